@@ -8,10 +8,11 @@
 //!
 //! Run with: cargo run --example basic_usage
 
+use privacy_cash::utils::parse_keypair;
 use privacy_cash::{PrivacyCash, Result, USDC_MINT};
 use solana_sdk::{
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::Signer,
 };
 use std::str::FromStr;
 
@@ -44,11 +45,12 @@ async fn sol_example(client: &PrivacyCash, recipient: &Pubkey) -> Result<()> {
     println!("\nWithdrawing 0.005 SOL...");
     let withdraw_result = client.withdraw(5_000_000, Some(recipient)).await?;
     println!(
-        "Withdrawal successful! Tx: {}\n  Recipient: {}\n  Amount: {} lamports\n  Fee: {} lamports",
+        "Withdrawal successful! Tx: {}\n  Recipient: {}\n  Amount: {} lamports\n  Fee: {} lamports\n  Expected remaining: {} lamports",
         withdraw_result.signature,
         withdraw_result.recipient,
         withdraw_result.amount_in_lamports,
-        withdraw_result.fee_in_lamports
+        withdraw_result.fee_in_lamports,
+        withdraw_result.expected_remaining_lamports
     );
 
     // Check final balance
@@ -148,16 +150,7 @@ async fn main() -> Result<()> {
         .expect("Please set SOLANA_PRIVATE_KEY environment variable");
 
     // Parse private key (supports base58 or JSON array format)
-    let keypair = if private_key.starts_with('[') {
-        let bytes: Vec<u8> = serde_json::from_str(&private_key)
-            .expect("Invalid private key format");
-        Keypair::from_bytes(&bytes).expect("Invalid keypair bytes")
-    } else {
-        let bytes = bs58::decode(&private_key)
-            .into_vec()
-            .expect("Invalid base58 private key");
-        Keypair::from_bytes(&bytes).expect("Invalid keypair bytes")
-    };
+    let keypair = parse_keypair(&private_key).expect("Invalid private key");
 
     println!("Using wallet: {}", keypair.pubkey());
     println!("RPC URL: {}", rpc_url);
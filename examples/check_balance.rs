@@ -6,8 +6,8 @@
 //! Or using a JSON keypair file:
 //!   SOLANA_PRIVATE_KEY=$(cat ~/.config/solana/id.json) cargo run --example check_balance
 
+use privacy_cash::utils::parse_keypair;
 use privacy_cash::{PrivacyCash, Signer};
-use solana_sdk::signature::Keypair;
 use std::str::FromStr;
 
 #[tokio::main]
@@ -21,16 +21,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("❌ Please set SOLANA_PRIVATE_KEY environment variable");
 
     // Parse private key (supports base58 or JSON array format)
-    let keypair = if private_key.trim().starts_with('[') {
-        // JSON array format
-        let bytes: Vec<u8> = serde_json::from_str(&private_key)
-            .expect("Invalid JSON private key format");
-        Keypair::from_bytes(&bytes)?
-    } else {
-        // Base58 format
-        let key_bytes = bs58::decode(&private_key).into_vec()?;
-        Keypair::from_bytes(&key_bytes)?
-    };
+    let keypair = parse_keypair(&private_key)?;
 
     println!("Wallet: {}", keypair.pubkey());
 
@@ -12,9 +12,9 @@
 //!   # Send 10 USDC to a recipient
 //!   SOLANA_PRIVATE_KEY=<key> cargo run --release --example send_privately -- 10 usdc RecipientPubkey
 
-use privacy_cash::{PrivacyCash, Signer};
+use privacy_cash::utils::parse_keypair;
+use privacy_cash::{Cluster, PrivacyCash, Signer};
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::Keypair;
 use std::env;
 use std::io::{self, Write};
 use std::str::FromStr;
@@ -165,11 +165,7 @@ fn step_box(step: u32, total: u32, title: &str) {
 }
 
 fn format_amount(amount: u64, token: &str) -> String {
-    match token {
-        "sol" => format!("{:.6}", amount as f64 / 1_000_000_000.0),
-        "usdc" | "usdt" => format!("{:.2}", amount as f64 / 1_000_000.0),
-        _ => amount.to_string(),
-    }
+    privacy_cash::utils::format_token_amount(amount, token).unwrap_or_else(|_| amount.to_string())
 }
 
 fn format_duration(duration: Duration) -> String {
@@ -209,9 +205,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     // Parse keypair to get pubkey for display
-    let key_bytes = bs58::decode(&private_key).into_vec()?;
-    #[allow(deprecated)]
-    let keypair = Keypair::from_bytes(&key_bytes)?;
+    let keypair = parse_keypair(&private_key)?;
     let self_pubkey = keypair.pubkey();
 
     // Parse command line arguments
@@ -243,6 +237,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Get RPC URL from environment or use default
     let rpc_url = env::var("SOLANA_RPC_URL")
         .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string());
+    let cluster = Cluster::from_rpc_url(&rpc_url);
 
     // Display configuration
     println!("{BOLD}{WHITE}Configuration:{RESET}");
@@ -329,7 +324,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("  {DIM}├─{RESET} {GREEN}Deposited:{RESET} {} {} into shielded pool", format_amount(deposited_amount, &token), token.to_uppercase());
     println!("  {DIM}├─{RESET} {BLUE}TX Signature:{RESET} {DIM}{}{RESET}", shorten_sig(&deposit_sig));
-    println!("  {DIM}└─{RESET} {MAGENTA}Solscan:{RESET} {CYAN}https://solscan.io/tx/{}{RESET}", deposit_sig);
+    println!("  {DIM}└─{RESET} {MAGENTA}Explorer:{RESET} {CYAN}{}{RESET}", cluster.explorer_tx_url(&deposit_sig));
 
     // ============ STEP 2: WAIT FOR UTXO INDEXING ============
     step_box(2, 3, "Confirming UTXO in Merkle Tree");
@@ -380,7 +375,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "ZK proof generated & withdrawal submitted ({CYAN}{}{RESET})",
                         format_duration(withdraw_start.elapsed())
                     ));
-                    (r.signature, r.amount_in_lamports)
+                    let sig = r.last().map(|w| w.signature.clone()).unwrap_or_default();
+                    (sig, r.iter().map(|w| w.amount_in_lamports).sum())
                 }
                 Err(e) => {
                     spinner.fail("Withdrawal failed");
@@ -397,7 +393,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "ZK proof generated & withdrawal submitted ({CYAN}{}{RESET})",
                         format_duration(withdraw_start.elapsed())
                     ));
-                    (r.signature, r.base_units)
+                    let sig = r.last().map(|w| w.signature.clone()).unwrap_or_default();
+                    (sig, r.iter().map(|w| w.base_units).sum())
                 }
                 Err(e) => {
                     spinner.fail("Withdrawal failed");
@@ -414,7 +411,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "ZK proof generated & withdrawal submitted ({CYAN}{}{RESET})",
                         format_duration(withdraw_start.elapsed())
                     ));
-                    (r.signature, r.base_units)
+                    let sig = r.last().map(|w| w.signature.clone()).unwrap_or_default();
+                    (sig, r.iter().map(|w| w.base_units).sum())
                 }
                 Err(e) => {
                     spinner.fail("Withdrawal failed");
@@ -431,7 +429,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("  {DIM}├─{RESET} {GREEN}Withdrawn:{RESET} {} {} to recipient", format_amount(received_amount, &token), token.to_uppercase());
     println!("  {DIM}├─{RESET} {BLUE}TX Signature:{RESET} {DIM}{}{RESET}", shorten_sig(&withdraw_sig));
-    println!("  {DIM}└─{RESET} {MAGENTA}Solscan:{RESET} {CYAN}https://solscan.io/tx/{}{RESET}", withdraw_sig);
+    println!("  {DIM}└─{RESET} {MAGENTA}Explorer:{RESET} {CYAN}{}{RESET}", cluster.explorer_tx_url(&withdraw_sig));
 
     // ============ FINAL SUMMARY ============
     print_success_box();
@@ -456,8 +454,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("{DIM}─────────────────────────────────────────────────────────────────{RESET}");
     println!("  {BOLD}Transaction Links:{RESET}");
-    println!("  {DIM}Deposit:{RESET}  {CYAN}https://solscan.io/tx/{}{RESET}", deposit_sig);
-    println!("  {DIM}Withdraw:{RESET} {CYAN}https://solscan.io/tx/{}{RESET}", withdraw_sig);
+    println!("  {DIM}Deposit:{RESET}  {CYAN}{}{RESET}", cluster.explorer_tx_url(&deposit_sig));
+    println!("  {DIM}Withdraw:{RESET} {CYAN}{}{RESET}", cluster.explorer_tx_url(&withdraw_sig));
     println!("{DIM}─────────────────────────────────────────────────────────────────{RESET}");
     println!();
 
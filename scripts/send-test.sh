@@ -333,5 +333,5 @@ case "${confirm}" in
     ;;
 esac
 
-# Run with warnings suppressed
-exec env RUSTFLAGS="-Awarnings" cargo run --release --example send_privately -- "$AMOUNT" "$TOKEN" "$RECIPIENT"
+# Run with warnings suppressed (the confirmation above is the mainnet opt-in)
+exec env RUSTFLAGS="-Awarnings" ALLOW_MAINNET=true cargo run --release --example send_privately -- "$AMOUNT" "$TOKEN" "$RECIPIENT"
@@ -0,0 +1,86 @@
+//! Instruction data hook for audits (enabled with the `audit` feature)
+//!
+//! Register a hook with [`set_instruction_hook`] to receive the exact
+//! instruction data of every deposit and withdrawal right after it is
+//! serialized, before it is sent. Security reviews can diff it against the
+//! program's expected layout.
+//!
+//! The data holds the instruction discriminator, the proof, the public
+//! signals and the external data (recipient, amounts, fee recipient and the
+//! encrypted output notes). It contains no private keys or blindings; the
+//! encrypted outputs can only be read with the wallet's encryption key.
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+type Hook = Arc<dyn Fn(&InstructionAudit) + Send + Sync>;
+
+/// Hook registered for this process
+static INSTRUCTION_HOOK: Lazy<RwLock<Option<Hook>>> = Lazy::new(|| RwLock::new(None));
+
+/// Serialized instruction data of one deposit or withdrawal
+#[derive(Debug, Clone)]
+pub struct InstructionAudit {
+    /// `"deposit"`, `"deposit_spl"`, `"withdraw"` or `"withdraw_spl"`
+    pub operation: &'static str,
+    /// Whether the transaction is only proven, not submitted
+    pub dry_run: bool,
+    /// Instruction data exactly as submitted (or relayed)
+    pub instruction_data: Vec<u8>,
+}
+
+impl InstructionAudit {
+    /// Instruction data as lowercase hex
+    pub fn instruction_data_hex(&self) -> String {
+        hex::encode(&self.instruction_data)
+    }
+}
+
+/// Call `hook` with the instruction data of every deposit and withdrawal
+///
+/// Replaces any previous hook. The hook runs on the transaction's task, so
+/// keep it quick.
+pub fn set_instruction_hook(hook: impl Fn(&InstructionAudit) + Send + Sync + 'static) {
+    *INSTRUCTION_HOOK.write() = Some(Arc::new(hook));
+}
+
+/// Remove the instruction hook
+pub fn clear_instruction_hook() {
+    *INSTRUCTION_HOOK.write() = None;
+}
+
+/// Pass instruction data to the hook, if one is registered
+pub(crate) fn emit(operation: &'static str, dry_run: bool, instruction_data: &[u8]) {
+    let Some(hook) = INSTRUCTION_HOOK.read().clone() else {
+        return;
+    };
+    hook(&InstructionAudit {
+        operation,
+        dry_run,
+        instruction_data: instruction_data.to_vec(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[test]
+    fn test_instruction_hook() {
+        // Without a hook nothing happens
+        emit("deposit", false, &[1, 2]);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        set_instruction_hook(move |audit| {
+            sink.lock().push((audit.operation, audit.instruction_data_hex()))
+        });
+        emit("withdraw", true, &[0xde, 0xad]);
+        clear_instruction_hook();
+        emit("withdraw", false, &[0xbe, 0xef]);
+
+        assert_eq!(*seen.lock(), vec![("withdraw", "dead".to_string())]);
+    }
+}
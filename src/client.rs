@@ -2,35 +2,123 @@
 //!
 //! Provides a high-level interface for interacting with Privacy Cash.
 
+use crate::cluster::Cluster;
+use crate::config::Config;
 use crate::constants::{
-    get_supported_tokens, LSK_ENCRYPTED_OUTPUTS, LSK_FETCH_OFFSET, 
-    PARTNER_FEE_RATE, PARTNER_FEE_WALLET, PARTNER_REFERRER, USDC_MINT,
+    find_token_by_mint, get_supported_tokens, resolve_token, ALT_ADDRESS,
+    DEFAULT_DEPOSIT_COMMITMENT_TIMEOUT, DEPOSIT_COMMITMENT, LSK_INTERRUPTED_OPERATIONS, DEPOSIT_COMPUTE_UNIT_LIMIT, DEPOSIT_RECIPIENT, FEE_RECIPIENT, LAMPORTS_PER_SOL, LSK_ENCRYPTED_OUTPUTS, LSK_FETCH_OFFSET,
+    LSK_RECEIVE_CURSORS, LSK_SEEN_COMMITMENTS, PARTNER_FEE_RATE, PARTNER_FEE_WALLET, ALLOW_MAINNET, PARTNER_REFERRER,
+    PRIORITY_FEE_MICRO_LAMPORTS, PROGRAM_ID, RELAYER_API_URL, SIGNATURE_FEE_LAMPORTS, SOL_MINT,
+    TokenInfo, USDC_MINT, WITHDRAW_OVERHEAD_ESTIMATE,
 };
 use crate::deposit::{deposit, DepositParams, DepositResult};
 use crate::deposit_spl::{deposit_spl, DepositSplParams, DepositSplResult};
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::{get_private_balance, localstorage_key};
-use crate::get_utxos_spl::get_private_balance_spl;
+use crate::get_utxos::{
+    get_private_balance, get_utxos, get_utxos_from, get_utxos_with_options, localstorage_key, ScanDiagnostics,
+    ScanOptions,
+};
+use crate::get_utxos_spl::{get_private_balance_spl, get_utxos_spl, get_utxos_spl_with_options};
+use crate::history::{get_history, HistoryEntry};
+use crate::in_flight::{load_interrupted, save_interrupted, InFlight, PendingOperation};
+use crate::keypair::{DomainTags, ZkKeypair};
+use crate::prover::{ensure_circuit_files, CircuitSpec, Proof};
+use crate::prover_rust::RustProver;
+use crate::receive::ReceiveAddress;
+use crate::record::{Operation, OperationRecord, OperationTimer, RecordDetails, RecordSink};
+use crate::self_test::{self, SelfTestReport};
 use crate::storage::Storage;
-use crate::utxo::{Balance, SplBalance};
-use crate::withdraw::{withdraw, WithdrawParams, WithdrawResult};
+use crate::token_registry::{TokenMetadata, TokenRegistry};
+use crate::utils::{
+    calculate_deposit_fee, calculate_partner_fee, calculate_transaction_fee, calculate_withdraw_fee_rounded, check_utxo_exists,
+    checked_total, ensure_balance_covers_fee, ensure_deposit_debited, ensure_spl_pool_initialized,
+    fetch_merkle_proofs, get_lookup_table,
+    get_program_accounts, query_remote_tree_state,
+    refresh_lookup_table, token_account_owner, usd_value, validate_program_accounts, wait_for_commitment,
+    withdrawal_nullifier_pdas, FeeRounding, SubmitMode,
+};
+use crate::utxo::{Balance, SplBalance, Utxo, UtxoVersion};
+use crate::watch::{watch_incoming, IncomingNote};
+use crate::withdraw::{
+    prepare_withdraw, submit_verified_withdraw, verify_external_proof, withdraw, WithdrawContext, WithdrawParams,
+    WithdrawResult,
+};
 use crate::withdraw_spl::{withdraw_spl, WithdrawSplParams, WithdrawSplResult};
+use crate::{SendCostEstimate, ShieldResult, WithdrawalPlan};
+use futures::Stream;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
+    commitment_config::CommitmentLevel,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
     system_instruction,
     transaction::Transaction,
 };
 use spl_associated_token_account::get_associated_token_address;
+use spl_token::solana_program::program_pack::Pack;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// A partner fee transfer made for a withdrawal
+///
+/// Passed to the callback set with
+/// [`PrivacyCash::set_partner_fee_callback`] once the transfer confirms.
+#[derive(Debug, Clone)]
+pub struct PartnerFeeEvent {
+    /// Token the fee was paid in (`"sol"`, `"usdc"`, ...)
+    pub token: String,
+    /// Fee in base units of `token`
+    pub amount: u64,
+    /// Wallet receiving the fee
+    pub destination: Pubkey,
+    /// Signature of the fee transfer
+    pub signature: String,
+}
+
+type PartnerFeeCallback = Arc<dyn Fn(&PartnerFeeEvent) + Send + Sync>;
+
+/// Addresses and endpoints a client uses, from [`PrivacyCash::addresses`]
+///
+/// Reflects environment overrides and the client's own settings, for
+/// diagnosing network and configuration mismatches.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct AddressBook {
+    /// Cluster guessed from the RPC URL
+    pub cluster: Cluster,
+    /// RPC endpoint
+    pub rpc_url: String,
+    /// Relayer API endpoint
+    pub relayer_url: String,
+    /// Privacy Cash program
+    pub program_id: Pubkey,
+    /// SOL Merkle tree account
+    pub tree_account: Pubkey,
+    /// Program's global config account
+    pub global_config_account: Pubkey,
+    /// Address lookup table used by transactions
+    pub lookup_table: Pubkey,
+    /// Privacy Cash protocol fee recipient
+    pub fee_recipient: Pubkey,
+    /// Placeholder recipient in deposit instructions
+    pub deposit_recipient: Pubkey,
+    /// Wallet receiving the partner fee
+    pub partner_fee_wallet: Pubkey,
+    /// Referrer sent with deposits and withdrawals, if any
+    pub referrer: Option<String>,
+    /// Mint of each built-in token, by name
+    pub token_mints: Vec<(&'static str, Pubkey)>,
+}
 
 /// Main Privacy Cash client
 pub struct PrivacyCash {
-    /// Solana RPC connection
-    connection: RpcClient,
+    /// Solana RPC connection (shared with clients from `with_keypair`)
+    connection: Arc<RpcClient>,
 
     /// User's keypair
     keypair: Arc<Keypair>,
@@ -38,11 +126,78 @@ pub struct PrivacyCash {
     /// Encryption service
     encryption_service: EncryptionService,
 
-    /// Local storage for caching
-    storage: Storage,
+    /// Local storage for caching (keys are scoped per public key)
+    storage: Arc<Storage>,
 
     /// Path to circuit files
     circuit_path: String,
+
+    /// Prover for `estimate_proof_time`, kept so its zkey is loaded once
+    benchmark_prover: RustProver,
+
+    /// Client-wide referrer (overrides `PARTNER_REFERRER`)
+    referrer: Option<String>,
+
+    /// Relayer deposits and withdrawals go through, and fees come from
+    relayer_url: String,
+
+    /// Cluster the RPC URL points at
+    cluster: Cluster,
+
+    /// Whether fund-moving operations may run on mainnet
+    allow_mainnet: bool,
+
+    /// Wallet receiving the partner/platform fee
+    partner_fee_wallet: Pubkey,
+
+    /// Partner/platform fee rate on withdrawals
+    partner_fee_rate: f64,
+
+    /// Skip the partner fee on withdrawals to the client's own wallet
+    waive_self_withdraw_fee: bool,
+
+    /// Partner fees below this many base units are not collected
+    partner_fee_dust_threshold: u64,
+
+    /// Pay the SOL partner fee out of withdrawals to the client's own wallet
+    deduct_partner_fee: bool,
+
+    /// Largest share of a withdrawal that fees may take
+    max_fee_ratio: Option<f64>,
+
+    /// Largest total fee per withdrawal, in base units, by token name
+    max_total_fees: HashMap<&'static str, u64>,
+
+    /// How protocol withdrawal fees are rounded to whole base units
+    fee_rounding: FeeRounding,
+
+    /// Called after each partner fee transfer
+    partner_fee_callback: Option<PartnerFeeCallback>,
+
+    /// Receives an audit record after each deposit and withdrawal
+    operation_sink: Option<RecordSink>,
+
+    /// Sub-key index for withdrawal change (`None` = base key)
+    change_key_index: Option<u32>,
+
+    /// Commitment `send_privately` waits for on the deposit, and for how long
+    deposit_commitment: Option<CommitmentLevel>,
+    deposit_commitment_timeout: Duration,
+
+    /// Check that SOL deposits took the amount from the public balance
+    verify_deposit_balance: bool,
+
+    /// Where signed deposit transactions are sent
+    submit_mode: SubmitMode,
+
+    /// Deposits and withdrawals running now
+    in_flight: Arc<InFlight>,
+
+    /// Stops scans and proofs in deposits and withdrawals once set
+    abort_signal: Option<Arc<Mutex<bool>>>,
+
+    /// Resolved token metadata (shared with clients from `with_keypair`)
+    token_registry: Arc<TokenRegistry>,
 }
 
 impl std::fmt::Debug for PrivacyCash {
@@ -89,6 +244,7 @@ impl PrivacyCash {
         circuit_path: Option<String>,
     ) -> Result<Self> {
         let connection = RpcClient::new(rpc_url.to_string());
+        let cluster = Cluster::from_rpc_url(rpc_url);
 
         let storage = if let Some(dir) = cache_dir {
             Storage::file(dir)?
@@ -96,23 +252,175 @@ impl PrivacyCash {
             Storage::default_file()?
         };
 
+        Ok(Self::from_parts(connection, cluster, keypair, storage, circuit_path))
+    }
+
+    /// Create a client around an existing RPC client
+    ///
+    /// Use this to tune RPC timeouts. `RpcClient::new` waits up to 30s per
+    /// request, which suits one-shot tools; long-running servers usually
+    /// want `RpcClient::new_with_timeouts_and_commitment` with a shorter
+    /// request timeout (10-15s) so a stale connection fails fast and the
+    /// retry reconnects. Relayer timeouts are process-wide, see
+    /// [`relayer::set_http_timeouts`](crate::relayer::set_http_timeouts).
+    /// The cluster is guessed from the client's URL.
+    pub fn with_rpc_client(
+        connection: RpcClient,
+        keypair: Keypair,
+        cache_dir: Option<PathBuf>,
+        circuit_path: Option<String>,
+    ) -> Result<Self> {
+        let cluster = Cluster::from_rpc_url(&connection.url());
+        let storage = match cache_dir {
+            Some(dir) => Storage::file(dir)?,
+            None => Storage::default_file()?,
+        };
+        Ok(Self::from_parts(connection, cluster, keypair, storage, circuit_path))
+    }
+
+    /// Create a client from environment variables
+    ///
+    /// Reads the RPC URL from `rpc_env` and the wallet private key from
+    /// `key_env`. The key may be base58 or the JSON byte array of a Solana CLI
+    /// keypair file. Missing variables are a `ConfigError`, malformed keys an
+    /// `InvalidInput` error.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::PrivacyCash;
+    ///
+    /// let client = PrivacyCash::from_env("SOLANA_RPC_URL", "SOLANA_PRIVATE_KEY").unwrap();
+    /// ```
+    pub fn from_env(rpc_env: &str, key_env: &str) -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|e| {
+                PrivacyCashError::ConfigError(format!("Environment variable {}: {}", name, e))
+            })
+        };
+        let rpc_url = var(rpc_env)?;
+        let keypair = crate::utils::parse_keypair(&var(key_env)?)?;
+        Self::new(&rpc_url, keypair)
+    }
+
+    /// Create a client for unit tests, without any network access
+    ///
+    /// Uses a mock RPC client, in-memory storage, `mock_relayer` for
+    /// relayer requests and `mock_config` as the relayer config. The relayer
+    /// mock and config are installed for the calling thread (see
+    /// [`install_thread_mocks`](crate::testing::install_thread_mocks)), so
+    /// tests building clients with different mocks can run in parallel.
+    #[cfg(feature = "testing")]
+    pub fn for_testing(
+        keypair: Keypair,
+        mock_relayer: crate::testing::MockRelayer,
+        mock_config: Config,
+    ) -> Self {
+        crate::testing::install_thread_mocks(mock_relayer, mock_config);
+
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        Self::from_parts(connection, Cluster::Localnet, keypair, Storage::memory(), None)
+    }
+
+    fn from_parts(
+        connection: RpcClient,
+        cluster: Cluster,
+        keypair: Keypair,
+        storage: Storage,
+        circuit_path: Option<String>,
+    ) -> Self {
         let mut encryption_service = EncryptionService::new();
         encryption_service.derive_encryption_key_from_wallet(&keypair);
 
         // Default circuit path - users need to download circuit files
         let circuit_path = circuit_path.unwrap_or_else(|| {
+            let name = CircuitSpec::default().name;
             std::env::current_dir()
-                .map(|p| p.join("circuit").join("transaction2").to_string_lossy().to_string())
-                .unwrap_or_else(|_| "./circuit/transaction2".to_string())
+                .map(|p| p.join("circuit").join(name).to_string_lossy().to_string())
+                .unwrap_or_else(|_| format!("./circuit/{}", name))
         });
 
-        Ok(Self {
-            connection,
+        warn_if_circuit_missing(&circuit_path);
+
+        Self {
+            connection: Arc::new(connection),
             keypair: Arc::new(keypair),
             encryption_service,
-            storage,
+            storage: Arc::new(storage),
+            benchmark_prover: RustProver::new(&circuit_path),
             circuit_path,
-        })
+            referrer: None,
+            relayer_url: RELAYER_API_URL.trim_end_matches('/').to_string(),
+            cluster,
+            allow_mainnet: *ALLOW_MAINNET,
+            partner_fee_wallet: *PARTNER_FEE_WALLET,
+            partner_fee_rate: *PARTNER_FEE_RATE,
+            waive_self_withdraw_fee: false,
+            partner_fee_dust_threshold: 0,
+            deduct_partner_fee: false,
+            max_fee_ratio: None,
+            max_total_fees: HashMap::new(),
+            fee_rounding: FeeRounding::default(),
+            partner_fee_callback: None,
+            operation_sink: None,
+            change_key_index: None,
+            deposit_commitment: *DEPOSIT_COMMITMENT,
+            deposit_commitment_timeout: DEFAULT_DEPOSIT_COMMITMENT_TIMEOUT,
+            verify_deposit_balance: false,
+            submit_mode: SubmitMode::default(),
+            in_flight: Arc::new(InFlight::default()),
+            abort_signal: None,
+            token_registry: Arc::new(TokenRegistry::new()),
+        }
+    }
+
+    /// Create a client for another account, reusing this client's connection
+    ///
+    /// The new client shares the RPC connection and cache storage (cache keys
+    /// are scoped per public key, so accounts don't see each other's data) and
+    /// copies all settings. Only the encryption keys are derived afresh.
+    pub fn with_keypair(&self, keypair: Keypair) -> PrivacyCash {
+        let mut encryption_service = EncryptionService::new();
+        encryption_service.derive_encryption_key_from_wallet(&keypair);
+        // The encryption key was just derived, so these can't fail
+        let _ = encryption_service.set_receive_indices(self.encryption_service.receive_indices());
+        encryption_service.set_domain_tags(self.encryption_service.domain_tags().clone());
+        let storage = if self.storage.is_encrypted() {
+            let cache_key = encryption_service.derive_cache_key().ok();
+            Arc::new(self.storage.with_cache_key(cache_key))
+        } else {
+            Arc::clone(&self.storage)
+        };
+
+        PrivacyCash {
+            connection: Arc::clone(&self.connection),
+            keypair: Arc::new(keypair),
+            encryption_service,
+            storage,
+            circuit_path: self.circuit_path.clone(),
+            benchmark_prover: self.benchmark_prover.clone(),
+            referrer: self.referrer.clone(),
+            relayer_url: self.relayer_url.clone(),
+            cluster: self.cluster,
+            allow_mainnet: self.allow_mainnet,
+            partner_fee_wallet: self.partner_fee_wallet,
+            partner_fee_rate: self.partner_fee_rate,
+            waive_self_withdraw_fee: self.waive_self_withdraw_fee,
+            partner_fee_dust_threshold: self.partner_fee_dust_threshold,
+            deduct_partner_fee: self.deduct_partner_fee,
+            max_fee_ratio: self.max_fee_ratio,
+            max_total_fees: self.max_total_fees.clone(),
+            fee_rounding: self.fee_rounding,
+            partner_fee_callback: self.partner_fee_callback.clone(),
+            operation_sink: self.operation_sink.clone(),
+            change_key_index: self.change_key_index,
+            deposit_commitment: self.deposit_commitment,
+            deposit_commitment_timeout: self.deposit_commitment_timeout,
+            verify_deposit_balance: self.verify_deposit_balance,
+            submit_mode: self.submit_mode,
+            in_flight: Arc::new(InFlight::default()),
+            abort_signal: self.abort_signal.clone(),
+            token_registry: Arc::clone(&self.token_registry),
+        }
     }
 
     /// Get the user's public key
@@ -120,6 +428,35 @@ impl PrivacyCash {
         self.keypair.pubkey()
     }
 
+    /// UTXO keypair this client uses for notes of the given version
+    ///
+    /// Both versions are derived from the wallet keypair, through the same
+    /// wallet signature as the encryption keys. New notes are V2; V1 is only
+    /// needed for notes created by older SDKs. Sub-keys for change (see
+    /// [`set_change_key_index`](Self::set_change_key_index)) derive from the
+    /// V2 keypair via [`ZkKeypair::derive_child`].
+    ///
+    /// **Highly sensitive**: the private key spends every note of this
+    /// version. Only export it to trusted backup tooling, never log it.
+    pub fn zk_keypair(&self, version: UtxoVersion) -> Result<ZkKeypair> {
+        self.encryption_service.utxo_keypair(version)
+    }
+
+    /// Use `domain_tags` for this wallet's UTXO keypairs
+    ///
+    /// Only for a circuit that separates hash domains; the current one uses
+    /// the default (zero) tags, and notes hashed under other tags can't be
+    /// spent with it. New notes, scans and decrypted notes all use the tags.
+    /// See [`DomainTags`] for which tag covers which hash.
+    pub fn set_domain_tags(&mut self, domain_tags: DomainTags) {
+        self.encryption_service.set_domain_tags(domain_tags);
+    }
+
+    /// Domain tags of this wallet's UTXO keypairs
+    pub fn domain_tags(&self) -> &DomainTags {
+        self.encryption_service.domain_tags()
+    }
+
     // ============ SOL Operations ============
 
     /// Deposit SOL into Privacy Cash
@@ -137,10 +474,12 @@ impl PrivacyCash {
     /// # }
     /// ```
     pub async fn deposit(&self, lamports: u64) -> Result<DepositResult> {
-        // Use partner referrer for revenue sharing
-        let referrer = PARTNER_REFERRER.as_deref();
+        self.ensure_cluster_allowed()?;
+        let config = self.get_config().await?;
+        // Use the client's referrer for revenue sharing
+        let referrer = self.referrer();
         
-        deposit(DepositParams {
+        let operation = deposit(DepositParams {
             connection: &self.connection,
             keypair: &self.keypair,
             encryption_service: &self.encryption_service,
@@ -148,8 +487,16 @@ impl PrivacyCash {
             amount_in_lamports: lamports,
             key_base_path: &self.circuit_path,
             referrer,
-        })
-        .await
+            input_utxos: None,
+            receive_address: None,
+            dry_run: false,
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            submit_mode: self.submit_mode,
+            abort_signal: self.abort_signal.clone(),
+        });
+        let operation = self.checked_deposit(lamports, operation);
+        self.recorded(Operation::Deposit, "sol", lamports, operation).await
     }
 
     /// Deposit SOL with a referrer
@@ -158,7 +505,9 @@ impl PrivacyCash {
         lamports: u64,
         referrer: &str,
     ) -> Result<DepositResult> {
-        deposit(DepositParams {
+        self.ensure_cluster_allowed()?;
+        let config = self.get_config().await?;
+        let operation = deposit(DepositParams {
             connection: &self.connection,
             keypair: &self.keypair,
             encryption_service: &self.encryption_service,
@@ -166,8 +515,78 @@ impl PrivacyCash {
             amount_in_lamports: lamports,
             key_base_path: &self.circuit_path,
             referrer: Some(referrer),
-        })
-        .await
+            input_utxos: None,
+            receive_address: None,
+            dry_run: false,
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            submit_mode: self.submit_mode,
+            abort_signal: self.abort_signal.clone(),
+        });
+        let operation = self.checked_deposit(lamports, operation);
+        self.recorded(Operation::Deposit, "sol", lamports, operation).await
+    }
+
+    /// Deposit SOL as a private payment to a receive address
+    ///
+    /// The note is owned by `address`'s sub-key and encrypted under its key,
+    /// so only the wallet that derived the address finds and spends it (once
+    /// it scans that index, see
+    /// [`set_receive_scan_indices`](Self::set_receive_scan_indices)). The
+    /// payer needs no leaf index: the note is placed at the tree's next one.
+    /// None of this wallet's notes are spent, and
+    /// [`DepositResult::output_utxo`] is `None`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash, invoice: &str) -> privacy_cash::Result<()> {
+    /// let address: privacy_cash::ReceiveAddress = invoice.parse()?;
+    /// let result = client.deposit_to(10_000_000, &address).await?;
+    /// println!("Paid in {}", result.signature);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn deposit_to(&self, lamports: u64, address: &ReceiveAddress) -> Result<DepositResult> {
+        self.ensure_cluster_allowed()?;
+        let config = self.get_config().await?;
+        let operation = deposit(DepositParams {
+            connection: &self.connection,
+            keypair: &self.keypair,
+            encryption_service: &self.encryption_service,
+            storage: &self.storage,
+            amount_in_lamports: lamports,
+            key_base_path: &self.circuit_path,
+            referrer: self.referrer(),
+            input_utxos: None,
+            receive_address: Some(address),
+            dry_run: false,
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            submit_mode: self.submit_mode,
+            abort_signal: self.abort_signal.clone(),
+        });
+        let operation = self.checked_deposit(lamports, operation);
+        self.recorded(Operation::Deposit, "sol", lamports, operation).await
+    }
+
+    /// Run a SOL deposit, checking the public balance if
+    /// [`set_verify_deposit_balance`](Self::set_verify_deposit_balance) is on
+    async fn checked_deposit(
+        &self,
+        lamports: u64,
+        operation: impl std::future::Future<Output = Result<DepositResult>>,
+    ) -> Result<DepositResult> {
+        if !self.verify_deposit_balance {
+            return operation.await;
+        }
+
+        let pubkey = self.keypair.pubkey();
+        let before = self.connection.get_balance(&pubkey)?;
+        let result = operation.await?;
+        let after = self.connection.get_balance(&pubkey)?;
+        let network_fee = calculate_transaction_fee(DEPOSIT_COMPUTE_UNIT_LIMIT, *PRIORITY_FEE_MICRO_LAMPORTS);
+        ensure_deposit_debited(before, after, lamports, network_fee, &result.signature)?;
+        Ok(result)
     }
 
     /// Withdraw SOL from Privacy Cash
@@ -190,46 +609,63 @@ impl PrivacyCash {
         lamports: u64,
         recipient: Option<&Pubkey>,
     ) -> Result<WithdrawResult> {
+        self.withdraw_inputs(lamports, recipient, None).await
+    }
+
+    /// Withdraw the full value of specific notes, skipping the UTXO scan
+    ///
+    /// Meant for notes the client just created, e.g. the `output_utxo` of a
+    /// [`DepositResult`]. At most as many notes as the circuit spends (two)
+    /// may be given; more fail with `InvalidInput`. Fees are taken from the
+    /// notes' total, as in [`withdraw_all`](Self::withdraw_all).
+    pub async fn withdraw_notes(
+        &self,
+        notes: Vec<Utxo>,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawResult> {
+        let total = notes_total(&notes)?;
+        let config = self.get_config().await?;
+        let fee = self.withdraw_fee(
+            total,
+            config.withdraw_fee_rate,
+            config.withdraw_rent_fee,
+            LAMPORTS_PER_SOL,
+        )?;
+        ensure_balance_covers_fee(total, fee)?;
+
+        self.withdraw_inputs(total, recipient, Some(notes)).await
+    }
+
+    /// Withdraw, optionally from given notes, and record the outcome
+    pub(crate) async fn withdraw_inputs(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+        input_utxos: Option<Vec<Utxo>>,
+    ) -> Result<WithdrawResult> {
+        let operation = self.collect_fee_and_withdraw(lamports, recipient, input_utxos, None);
+        self.recorded(Operation::Withdraw, "sol", lamports, operation).await
+    }
+
+    /// Collect the partner fee and withdraw, optionally from given notes
+    ///
+    /// `referrer` overrides the client referrer.
+    async fn collect_fee_and_withdraw(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+        input_utxos: Option<Vec<Utxo>>,
+        referrer: Option<&str>,
+    ) -> Result<WithdrawResult> {
+        self.ensure_cluster_allowed()?;
         let self_pubkey = self.keypair.pubkey();
         let recipient = recipient.unwrap_or(&self_pubkey);
-        
-        // Calculate and collect partner/platform fee
-        let partner_fee = (lamports as f64 * *PARTNER_FEE_RATE) as u64;
-        
-        if partner_fee > 0 {
-            // Check user has enough public SOL for the fee
-            let public_balance = self.connection.get_balance(&self_pubkey)?;
-            if public_balance < partner_fee + 5000 {
-                // 5000 lamports for tx fee
-                return Err(PrivacyCashError::InsufficientBalance {
-                    need: partner_fee + 5000,
-                    have: public_balance,
-                });
-            }
-            
-            // Transfer partner fee
-            let transfer_ix = system_instruction::transfer(
-                &self_pubkey,
-                &PARTNER_FEE_WALLET,
-                partner_fee,
-            );
-            
-            let recent_blockhash = self.connection.get_latest_blockhash()?;
-            let tx = Transaction::new_signed_with_payer(
-                &[transfer_ix],
-                Some(&self_pubkey),
-                &[&*self.keypair],
-                recent_blockhash,
-            );
-            
-            self.connection.send_and_confirm_transaction(&tx)?;
-            log::info!("Partner fee collected: {} lamports", partner_fee);
-        }
-        
-        // Use partner referrer for revenue sharing
-        let referrer = PARTNER_REFERRER.as_deref();
+        let config = self.get_config().await?;
+
+        // Use the client's referrer for revenue sharing
+        let referrer = referrer.or(self.referrer());
 
-        withdraw(WithdrawParams {
+        let withdrawal = withdraw(WithdrawParams {
             connection: &self.connection,
             keypair: &self.keypair,
             encryption_service: &self.encryption_service,
@@ -238,8 +674,109 @@ impl PrivacyCash {
             recipient,
             key_base_path: &self.circuit_path,
             referrer,
-        })
-        .await
+            change_key_index: self.change_key_index,
+            input_utxos,
+            dry_run: false,
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            fee_rounding: self.fee_rounding,
+            abort_signal: self.abort_signal.clone(),
+        });
+        self.with_sol_partner_fee(lamports, recipient, &config, withdrawal).await
+    }
+
+    /// Collect the partner fee for a SOL withdrawal of `lamports` to
+    /// `recipient` around `withdrawal`
+    ///
+    /// Like the SPL fee, the fee is transferred from the public wallet before
+    /// the withdrawal. A fee deducted from a withdrawal to the own wallet
+    /// (see [`set_deduct_partner_fee`](Self::set_deduct_partner_fee)) is
+    /// transferred once it lands; if that fails, the result is
+    /// `PartnerFeeNotCollected`.
+    async fn with_sol_partner_fee(
+        &self,
+        lamports: u64,
+        recipient: &Pubkey,
+        config: &Config,
+        withdrawal: impl std::future::Future<Output = Result<WithdrawResult>>,
+    ) -> Result<WithdrawResult> {
+        let self_pubkey = self.keypair.pubkey();
+
+        // Calculate and collect partner/platform fee
+        let partner_fee = self.partner_fee_for(lamports, recipient)?;
+        if self.has_fee_limits("sol") {
+            let protocol_fee = self.withdraw_fee(
+                lamports,
+                config.withdraw_fee_rate,
+                config.withdraw_rent_fee,
+                LAMPORTS_PER_SOL,
+            )?;
+            // The partner fee transfer pays its own signature fee
+            let network_fee = if partner_fee > 0 { SIGNATURE_FEE_LAMPORTS } else { 0 };
+            let fees = checked_total(checked_total(protocol_fee, partner_fee)?, network_fee)?;
+            self.ensure_fee_limits("sol", lamports, fees)?;
+        }
+        
+        // Withdrawals to our own wallet can pay the fee from what they bring in
+        let deduct_fee = partner_fee > 0 && self.deduct_partner_fee && recipient == &self_pubkey;
+        let mut partner_fee_signature = None;
+        if partner_fee > 0 && !deduct_fee {
+            self.ensure_sol_for_partner_fee(partner_fee)?;
+            partner_fee_signature = Some(self.transfer_partner_fee(partner_fee)?);
+        }
+
+        let mut result = withdrawal.await?;
+        result.partner_fee_signature = partner_fee_signature;
+        if deduct_fee {
+            let signature = self.transfer_partner_fee(partner_fee).map_err(|e| {
+                PrivacyCashError::PartnerFeeNotCollected {
+                    signature: result.signature.clone(),
+                    fee: partner_fee,
+                    error: Box::new(e),
+                }
+            })?;
+            result.partner_fee_signature = Some(signature);
+        }
+        Ok(result)
+    }
+
+    /// Fail unless the public wallet can pay a SOL partner fee transfer
+    ///
+    /// The transfer has no compute budget instructions, so it pays only the
+    /// signature fee, and the wallet must stay rent-exempt after it.
+    fn ensure_sol_for_partner_fee(&self, partner_fee: u64) -> Result<()> {
+        let have = self.connection.get_balance(&self.keypair.pubkey())?;
+        let network_fee = SIGNATURE_FEE_LAMPORTS;
+        let rent_reserve = self.connection.get_minimum_balance_for_rent_exemption(0)?;
+        let need = checked_total(checked_total(partner_fee, network_fee)?, rent_reserve)?;
+        if have < need {
+            return Err(PrivacyCashError::InsufficientSolForPartnerFee {
+                have,
+                need,
+                fee: partner_fee,
+                network_fee,
+                rent_reserve,
+            });
+        }
+        Ok(())
+    }
+
+    /// Transfer a SOL partner fee from the public wallet, returning the signature
+    fn transfer_partner_fee(&self, partner_fee: u64) -> Result<String> {
+        let self_pubkey = self.keypair.pubkey();
+        let transfer_ix = system_instruction::transfer(&self_pubkey, &self.partner_fee_wallet, partner_fee);
+
+        let recent_blockhash = self.connection.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &[transfer_ix],
+            Some(&self_pubkey),
+            &[&*self.keypair],
+            recent_blockhash,
+        );
+
+        let signature = self.connection.send_and_confirm_transaction(&tx)?;
+        log::info!("Partner fee collected: {} lamports", partner_fee);
+        Ok(self.report_partner_fee("sol", partner_fee, signature.to_string()))
     }
 
     /// Withdraw SOL with a referrer
@@ -249,10 +786,51 @@ impl PrivacyCash {
         recipient: Option<&Pubkey>,
         referrer: &str,
     ) -> Result<WithdrawResult> {
+        // Same partner fee and fee limits as any other withdrawal
+        let operation = self.collect_fee_and_withdraw(lamports, recipient, None, Some(referrer));
+        self.recorded(Operation::Withdraw, "sol", lamports, operation).await
+    }
+
+    /// Build a SOL withdrawal for proving on another machine
+    ///
+    /// Does everything [`withdraw`](Self::withdraw) does before proving and
+    /// returns the result; nothing is submitted and no fee is collected.
+    /// Prove `context.circuit_input` offline with [`RustProver::prove`], then
+    /// pass the context and proof to
+    /// [`submit_withdraw_with_proof`](Self::submit_withdraw_with_proof).
+    ///
+    /// **WARNING**: the context contains the input notes' private keys and
+    /// blindings; see [`WithdrawContext`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// // Online: build the withdrawal and carry it to the offline machine
+    /// let context = client.prepare_withdraw(10_000_000, None).await?;
+    /// let json = serde_json::to_string(&context).unwrap();
+    ///
+    /// // Offline: prove it
+    /// let context: privacy_cash::withdraw::WithdrawContext = serde_json::from_str(&json).unwrap();
+    /// let prover = privacy_cash::prover_rust::RustProver::new("./circuit/transaction2");
+    /// let (proof, _) = prover.prove(&context.circuit_input).await?;
+    ///
+    /// // Online again: submit
+    /// let result = client.submit_withdraw_with_proof(context, &proof).await?;
+    /// println!("Withdrawn in {}", result.signature);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn prepare_withdraw(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawContext> {
+        self.ensure_cluster_allowed()?;
         let self_pubkey = self.keypair.pubkey();
         let recipient = recipient.unwrap_or(&self_pubkey);
+        let config = self.get_config().await?;
 
-        withdraw(WithdrawParams {
+        prepare_withdraw(WithdrawParams {
             connection: &self.connection,
             keypair: &self.keypair,
             encryption_service: &self.encryption_service,
@@ -260,14 +838,63 @@ impl PrivacyCash {
             amount_in_lamports: lamports,
             recipient,
             key_base_path: &self.circuit_path,
-            referrer: Some(referrer),
+            referrer: self.referrer(),
+            change_key_index: self.change_key_index,
+            input_utxos: None,
+            dry_run: false,
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            fee_rounding: self.fee_rounding,
+            abort_signal: self.abort_signal.clone(),
         })
         .await
     }
 
+    /// Submit a withdrawal from [`prepare_withdraw`](Self::prepare_withdraw)
+    /// with a proof generated elsewhere
+    ///
+    /// Collects the partner fee as [`withdraw`](Self::withdraw) would. The
+    /// context must come from this wallet; a context changed since it was
+    /// prepared fails with `InvalidInput`, and a proof that doesn't verify
+    /// against the context's public signals with `ProofGenerationError`,
+    /// before anything is sent, including the partner fee. The result reports
+    /// [`ProofSource::External`](crate::ProofSource::External).
+    pub async fn submit_withdraw_with_proof(
+        &self,
+        context: WithdrawContext,
+        proof: &Proof,
+    ) -> Result<WithdrawResult> {
+        self.ensure_cluster_allowed()?;
+        if context.sender != self.keypair.pubkey().to_string() {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Withdrawal was prepared by {}, not this wallet",
+                context.sender
+            )));
+        }
+        let recipient = Pubkey::from_str(&context.recipient)
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid recipient: {}", e)))?;
+        let lamports = context.amount_in_lamports;
+        let config = self.get_config().await?;
+
+        let operation = async {
+            // Checked before the partner fee is paid
+            let public_signals = verify_external_proof(&context, proof, &self.circuit_path).await?;
+            let withdrawal = submit_verified_withdraw(context, proof, public_signals);
+            self.with_sol_partner_fee(lamports, &recipient, &config, withdrawal).await
+        };
+        self.recorded(Operation::Withdraw, "sol", lamports, operation).await
+    }
+
     /// Withdraw ALL private SOL to recipient
     ///
-    /// This is a convenience method that withdraws the entire private SOL balance.
+    /// A transaction spends at most two notes, so a balance spread over more
+    /// notes is withdrawn in several transactions, largest notes first, each
+    /// paying its own fees. Notes too small to cover their fees or the
+    /// relayer's minimum withdrawal are left behind. If the first transaction
+    /// fails, its error is returned; if a later one fails,
+    /// [`WithdrawAllIncomplete`](PrivacyCashError::WithdrawAllIncomplete)
+    /// lists the withdrawals that went through. Call again to withdraw the
+    /// rest.
     ///
     /// # Arguments
     /// * `recipient` - Optional recipient address (defaults to self)
@@ -276,27 +903,86 @@ impl PrivacyCash {
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
     /// // Withdraw all private SOL to self
-    /// let result = client.withdraw_all(None).await?;
-    /// println!("Withdrawn {} lamports", result.amount_in_lamports);
+    /// let results = client.withdraw_all(None).await?;
+    /// let total: u64 = results.iter().map(|r| r.amount_in_lamports).sum();
+    /// println!("Withdrawn {} lamports in {} transactions", total, results.len());
     /// # Ok(())
     /// # }
     /// ```
     pub async fn withdraw_all(
         &self,
         recipient: Option<&Pubkey>,
-    ) -> Result<WithdrawResult> {
-        // Get current private balance
-        let balance = self.get_private_balance().await?;
-        
-        if balance.lamports == 0 {
-            return Err(PrivacyCashError::InsufficientBalance {
-                need: 1,
-                have: 0,
-            });
-        }
+    ) -> Result<Vec<WithdrawResult>> {
+        let config = self.get_config().await?;
+        let batches = plan_withdraw_all(
+            self.list_utxos().await?,
+            config.withdraw_fee_rate,
+            config.withdraw_rent_fee,
+            LAMPORTS_PER_SOL,
+            minimum_withdrawal_units(&config, "sol", LAMPORTS_PER_SOL),
+            self.fee_rounding,
+        )?;
+        ensure_notes_provable(&batches, None).await?;
+
+        withdraw_batches(
+            batches,
+            |batch| self.withdraw_notes(batch, recipient),
+            |result| (&result.signature, result.amount_in_lamports),
+        )
+        .await
+    }
+
+    /// Work out what withdrawing the whole private balance of `token` takes
+    ///
+    /// Makes the same selection as [`withdraw_all`](Self::withdraw_all)
+    /// without sending anything, and reports the number of transactions,
+    /// their fees and a rough duration. `token` may be a name, alias or mint
+    /// address. The duration uses [`estimate_proof_time`](Self::estimate_proof_time),
+    /// which proves once on the first call.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let plan = client.withdrawal_plan("sol").await?;
+    /// println!(
+    ///     "{} transactions, {} lamports in fees, about {:.0} minutes",
+    ///     plan.transactions,
+    ///     plan.total_fees(),
+    ///     plan.estimated_duration.as_secs_f64() / 60.0
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn withdrawal_plan(&self, token: &str) -> Result<WithdrawalPlan> {
+        let token = resolve_token(token)?;
+        let config = self.get_config().await?;
+        let (notes, rent_fee) = if token.mint == *SOL_MINT {
+            (self.list_utxos().await?, config.withdraw_rent_fee)
+        } else {
+            (self.list_utxos_spl(&token.mint).await?, config.token_rent_fee(token.name)?)
+        };
+        let fee_rate = config.withdraw_fee_rate;
+        let note_count = notes.len();
+        let batches = plan_withdraw_all(
+            notes,
+            fee_rate,
+            rent_fee,
+            token.units_per_token,
+            minimum_withdrawal_units(&config, token.name, token.units_per_token),
+            self.fee_rounding,
+        )?;
 
-        // Withdraw the full balance
-        self.withdraw(balance.lamports, recipient).await
+        let per_transaction = self.estimate_proof_time().await? + WITHDRAW_OVERHEAD_ESTIMATE;
+        let self_pubkey = self.keypair.pubkey();
+        let mut plan = price_withdrawal_plan(&batches, per_transaction, |amount| {
+            Ok((
+                self.withdraw_fee(amount, fee_rate, rent_fee, token.units_per_token)?,
+                self.partner_fee_for(amount, &self_pubkey)?,
+            ))
+        })?;
+        plan.token = token.name.to_string();
+        plan.notes_left = note_count - batches.iter().map(Vec::len).sum::<usize>();
+        Ok(plan)
     }
 
     /// Get private SOL balance
@@ -322,15 +1008,238 @@ impl PrivacyCash {
         .await
     }
 
-    // ============ SPL Token Operations ============
+    /// List unspent private SOL notes
+    ///
+    /// Each [`Utxo`] carries its `version`; see [`UtxoVersion`] for what the
+    /// versions mean.
+    pub async fn list_utxos(&self) -> Result<Vec<Utxo>> {
+        get_utxos(
+            &self.connection,
+            &self.keypair.pubkey(),
+            &self.encryption_service,
+            &self.storage,
+            None,
+        )
+        .await
+    }
 
-    /// Deposit SPL tokens into Privacy Cash
+    /// List unspent private notes for an SPL token
+    pub async fn list_utxos_spl(&self, mint_address: &Pubkey) -> Result<Vec<Utxo>> {
+        get_utxos_spl(
+            &self.connection,
+            &self.keypair.pubkey(),
+            &self.encryption_service,
+            &self.storage,
+            mint_address,
+            None,
+        )
+        .await
+    }
+
+    /// Scan for private notes with explicit [`ScanOptions`]
     ///
-    /// # Arguments
-    /// * `base_units` - Amount in base units (e.g., 1 USDC = 1_000_000 base units)
-    /// * `mint_address` - Token mint address
+    /// `token` is `None` for SOL or an SPL mint. Notes found already spent
+    /// on-chain are left out and counted in the returned [`ScanDiagnostics`],
+    /// so a note that was spent from another device shows up there rather
+    /// than failing a later withdrawal. Turn off
+    /// [`check_spent`](ScanOptions::check_spent) to save the RPC calls when
+    /// only a rough balance is needed.
+    pub async fn scan_utxos(
+        &self,
+        token: Option<&Pubkey>,
+        options: ScanOptions,
+    ) -> Result<(Vec<Utxo>, ScanDiagnostics)> {
+        match token {
+            Some(mint) if *mint != *SOL_MINT => {
+                get_utxos_spl_with_options(
+                    &self.connection,
+                    &self.keypair.pubkey(),
+                    &self.encryption_service,
+                    &self.storage,
+                    mint,
+                    self.abort_signal.clone(),
+                    options,
+                )
+                .await
+            }
+            _ => {
+                get_utxos_with_options(
+                    &self.connection,
+                    &self.keypair.pubkey(),
+                    &self.encryption_service,
+                    &self.storage,
+                    self.abort_signal.clone(),
+                    options,
+                )
+                .await
+            }
+        }
+    }
+
+    /// List unspent notes created at or after leaf `start_index`
     ///
-    /// # Example
+    /// For incremental sync and audits: only the range from `start_index` on
+    /// is fetched, and the stored scan cursor and cache are left alone (see
+    /// [`scan_cursor`](Self::scan_cursor)). `token` is `None` for SOL or an
+    /// SPL mint.
+    pub async fn list_utxos_from(&self, token: Option<&Pubkey>, start_index: u64) -> Result<Vec<Utxo>> {
+        let token_name = match token {
+            Some(mint) if *mint != *SOL_MINT => Some(
+                find_token_by_mint(mint)
+                    .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint.to_string()))?
+                    .name,
+            ),
+            _ => None,
+        };
+        get_utxos_from(&self.connection, &self.encryption_service, start_index, token_name).await
+    }
+
+    /// Nullifier PDAs the next withdrawal of `token` will create
+    ///
+    /// Picks the same notes `withdraw`/`withdraw_spl` would (the two largest
+    /// unspent ones) and derives their nullifier PDAs without generating a
+    /// proof; see [`withdrawal_nullifier_pdas`] for the order. Any of these
+    /// accounts appearing on-chain means the notes were spent. `token` may be
+    /// a name, alias or mint address.
+    pub async fn next_withdrawal_nullifiers(&self, token: &str) -> Result<Vec<Pubkey>> {
+        let token = resolve_token(token)?;
+        let mut utxos = if token.mint == *SOL_MINT {
+            self.list_utxos().await?
+        } else {
+            self.list_utxos_spl(&token.mint).await?
+        };
+
+        utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
+        utxos.truncate(2);
+        withdrawal_nullifier_pdas(&utxos)
+    }
+
+    /// Whether the relayer has indexed the note with this encrypted output
+    ///
+    /// A single check against the indexer, for building custom confirmation
+    /// polling; deposits and withdrawals already wait for it internally.
+    /// `token` is `None` for SOL or a token name, alias or mint address.
+    pub async fn commitment_indexed(&self, encrypted_output: &[u8], token: Option<&str>) -> Result<bool> {
+        let token_name = match token {
+            Some(token) => {
+                let token = resolve_token(token)?;
+                (token.mint != *SOL_MINT).then_some(token.name)
+            }
+            None => None,
+        };
+        check_utxo_exists(encrypted_output, token_name).await
+    }
+
+    /// Sweep all V1 SOL notes into V2 notes
+    ///
+    /// Spends V1 notes two at a time in zero-amount deposits, each producing a
+    /// single V2 note with the combined value. Only the network fee is paid;
+    /// nothing enters or leaves the pool. Returns one result per transaction
+    /// (empty if there was nothing to migrate).
+    ///
+    /// Each transaction is tracked like a deposit, so
+    /// [`shutdown`](Self::shutdown) waits for the one in progress and stops
+    /// the migration before the next. The operation sink gets an
+    /// [`Operation::Migration`] record per transaction.
+    pub async fn migrate_v1_to_v2(&self) -> Result<Vec<DepositResult>> {
+        self.ensure_cluster_allowed()?;
+        if self.in_flight.is_shutting_down() {
+            return Err(PrivacyCashError::ShuttingDown);
+        }
+        let v1_utxos: Vec<Utxo> = self
+            .list_utxos()
+            .await?
+            .into_iter()
+            .filter(|utxo| utxo.version == UtxoVersion::V1 && !utxo.is_dummy())
+            .collect();
+
+        log::info!("Migrating {} V1 notes to V2", v1_utxos.len());
+        let pairs: Vec<Vec<Utxo>> = v1_utxos.chunks(2).map(<[Utxo]>::to_vec).collect();
+        ensure_notes_provable(&pairs, None).await?;
+
+        let mut results = Vec::new();
+        for pair in pairs {
+            let pair_total: u64 = pair.iter().map(|utxo| utxo.amount_u64()).sum();
+            let migration = deposit(DepositParams {
+                connection: &self.connection,
+                keypair: &self.keypair,
+                encryption_service: &self.encryption_service,
+                storage: &self.storage,
+                amount_in_lamports: 0,
+                key_base_path: &self.circuit_path,
+                referrer: self.referrer(),
+                input_utxos: Some(pair),
+                receive_address: None,
+                dry_run: false,
+                // Migrations deposit nothing, so no minimum applies
+                config: None,
+                relayer_url: Some(&self.relayer_url),
+                submit_mode: self.submit_mode,
+                abort_signal: self.abort_signal.clone(),
+            });
+            let result = self
+                .recorded(Operation::Migration, "sol", pair_total, migration)
+                .await?;
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Decrypt a single encrypted output with this wallet's keys
+    ///
+    /// Returns `Ok(None)` if the note belongs to someone else.
+    pub fn decrypt_utxo(&self, encrypted_output: &[u8]) -> Result<Option<Utxo>> {
+        self.encryption_service.try_decrypt_utxo(encrypted_output)
+    }
+
+    /// Watch for incoming private payments
+    ///
+    /// Polls the relayer every `interval` and yields each newly received note
+    /// exactly once. Already-reported notes are remembered in the client's
+    /// storage, so restarting the watcher doesn't repeat them.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use futures::StreamExt;
+    /// use std::time::Duration;
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let mut incoming = Box::pin(client.watch_incoming(Duration::from_secs(30)));
+    /// while let Some(note) = incoming.next().await {
+    ///     println!("Received {} at index {}", note.amount, note.index);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn watch_incoming(&self, interval: Duration) -> impl Stream<Item = IncomingNote> + '_ {
+        watch_incoming(
+            &self.connection,
+            self.keypair.pubkey(),
+            &self.encryption_service,
+            &self.storage,
+            interval,
+        )
+    }
+
+    /// Transaction history of the shielded account
+    ///
+    /// Pass `None` for SOL or a mint address for an SPL token. Entries are
+    /// sorted by leaf index. Only what the relayer and chain expose is
+    /// available: no timestamps, change notes appear as incoming and spent
+    /// entries report whole note amounts (see [`crate::history`]).
+    pub async fn history(&self, token: Option<&Pubkey>) -> Result<Vec<HistoryEntry>> {
+        get_history(&self.connection, &self.encryption_service, token).await
+    }
+
+    // ============ SPL Token Operations ============
+
+    /// Deposit SPL tokens into Privacy Cash
+    ///
+    /// # Arguments
+    /// * `base_units` - Amount in base units (e.g., 1 USDC = 1_000_000 base units)
+    /// * `mint_address` - Token mint address
+    ///
+    /// # Example
     /// ```rust,no_run
     /// use solana_sdk::pubkey::Pubkey;
     /// use std::str::FromStr;
@@ -346,20 +1255,58 @@ impl PrivacyCash {
         base_units: u64,
         mint_address: &Pubkey,
     ) -> Result<DepositSplResult> {
-        // Use partner referrer for revenue sharing
-        let referrer = PARTNER_REFERRER.as_deref();
+        self.ensure_cluster_allowed()?;
+        let token = self.token_metadata(mint_address)?;
+        let config = self.get_config().await?;
+        // Use the client's referrer for revenue sharing
+        let referrer = self.referrer();
         
-        deposit_spl(DepositSplParams {
+        let operation = deposit_spl(DepositSplParams {
             connection: &self.connection,
             keypair: &self.keypair,
             encryption_service: &self.encryption_service,
             storage: &self.storage,
             base_units,
-            mint_address,
+            token: &token,
             key_base_path: &self.circuit_path,
             referrer,
-        })
-        .await
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            submit_mode: self.submit_mode,
+            abort_signal: self.abort_signal.clone(),
+            dry_run: false,
+        });
+        self.recorded(Operation::Deposit, &token_label(mint_address), base_units, operation)
+            .await
+    }
+
+    /// Deposit SPL tokens with a referrer
+    pub async fn deposit_spl_with_referrer(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        referrer: &str,
+    ) -> Result<DepositSplResult> {
+        self.ensure_cluster_allowed()?;
+        let token = self.token_metadata(mint_address)?;
+        let config = self.get_config().await?;
+        let operation = deposit_spl(DepositSplParams {
+            connection: &self.connection,
+            keypair: &self.keypair,
+            encryption_service: &self.encryption_service,
+            storage: &self.storage,
+            base_units,
+            token: &token,
+            key_base_path: &self.circuit_path,
+            referrer: Some(referrer),
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            submit_mode: self.submit_mode,
+            abort_signal: self.abort_signal.clone(),
+            dry_run: false,
+        });
+        self.recorded(Operation::Deposit, &token_label(mint_address), base_units, operation)
+            .await
     }
 
     /// Deposit USDC (convenience method)
@@ -379,22 +1326,118 @@ impl PrivacyCash {
         mint_address: &Pubkey,
         recipient: Option<&Pubkey>,
     ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl_inputs(base_units, mint_address, recipient, None, None)
+            .await
+    }
+
+    /// Withdraw SPL tokens to a specific token account
+    ///
+    /// Like [`withdraw_spl`](Self::withdraw_spl), but pays out to
+    /// `token_account` instead of deriving the recipient's associated token
+    /// account. Use it for exchange deposit addresses and program-owned
+    /// accounts that aren't ATAs. The account must already exist and hold
+    /// `mint_address`; its owner counts as the recipient.
+    pub async fn withdraw_spl_to_token_account(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        token_account: &Pubkey,
+    ) -> Result<WithdrawSplResult> {
+        let owner = token_account_owner(&self.connection, token_account, mint_address)?;
+        self.withdraw_spl_inputs(base_units, mint_address, Some(&owner), Some(token_account), None)
+            .await
+    }
+
+    /// Withdraw the full value of specific SPL notes, skipping the UTXO scan
+    ///
+    /// The SPL counterpart of [`withdraw_notes`](Self::withdraw_notes).
+    pub async fn withdraw_notes_spl(
+        &self,
+        notes: Vec<Utxo>,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawSplResult> {
+        let token = self.token_metadata(mint_address)?.info;
+        let total = notes_total(&notes)?;
+        let config = self.get_config().await?;
+        let fee = self.withdraw_fee(
+            total,
+            config.withdraw_fee_rate,
+            config.token_rent_fee(token.name)?,
+            token.units_per_token,
+        )?;
+        ensure_balance_covers_fee(total, fee)?;
+
+        self.withdraw_spl_inputs(total, mint_address, recipient, None, Some(notes))
+            .await
+    }
+
+    /// Withdraw SPL tokens, optionally from given notes, and record the outcome
+    pub(crate) async fn withdraw_spl_inputs(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+        recipient_token_account: Option<&Pubkey>,
+        input_utxos: Option<Vec<Utxo>>,
+    ) -> Result<WithdrawSplResult> {
+        let operation = self.collect_fee_and_withdraw_spl(
+            base_units,
+            mint_address,
+            recipient,
+            recipient_token_account,
+            input_utxos,
+            None,
+        );
+        self.recorded(Operation::Withdraw, &token_label(mint_address), base_units, operation)
+            .await
+    }
+
+    /// Collect the partner fee and withdraw SPL tokens, optionally from given notes
+    ///
+    /// `referrer` overrides the client referrer.
+    async fn collect_fee_and_withdraw_spl(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+        recipient_token_account: Option<&Pubkey>,
+        input_utxos: Option<Vec<Utxo>>,
+        referrer: Option<&str>,
+    ) -> Result<WithdrawSplResult> {
+        self.ensure_cluster_allowed()?;
         let self_pubkey = self.keypair.pubkey();
         let recipient = recipient.unwrap_or(&self_pubkey);
         
         // Calculate partner/platform fee
-        let partner_fee = (base_units as f64 * *PARTNER_FEE_RATE) as u64;
+        let partner_fee = self.partner_fee_for(base_units, recipient)?;
+        let metadata = self.token_metadata(mint_address)?;
+        let token = &metadata.info;
+        let config = self.get_config().await?;
+        if self.has_fee_limits(token.name) {
+            let protocol_fee = self.withdraw_fee(
+                base_units,
+                config.withdraw_fee_rate,
+                config.token_rent_fee(token.name)?,
+                token.units_per_token,
+            )?;
+            let fees = checked_total(protocol_fee, partner_fee)?;
+            self.ensure_fee_limits(token.name, base_units, fees)?;
+        }
+        // An uninitialized pool would fail the withdrawal after the fee is paid
+        ensure_spl_pool_initialized(&self.connection, mint_address, token.name)?;
         
+        let mut partner_fee_signature = None;
         if partner_fee > 0 {
             // Transfer partner fee in SPL tokens
             let user_ata = get_associated_token_address(&self_pubkey, mint_address);
-            let partner_ata = get_associated_token_address(&PARTNER_FEE_WALLET, mint_address);
+            let partner_ata = get_associated_token_address(&self.partner_fee_wallet, mint_address);
             
             // Check if partner ATA exists, create if needed
             if self.connection.get_account(&partner_ata).is_err() {
                 let create_ata_ix = spl_associated_token_account::instruction::create_associated_token_account(
                     &self_pubkey,
-                    &PARTNER_FEE_WALLET,
+                    &self.partner_fee_wallet,
                     mint_address,
                     &spl_token::id(),
                 );
@@ -427,25 +1470,58 @@ impl PrivacyCash {
                 recent_blockhash,
             );
             
-            self.connection.send_and_confirm_transaction(&tx)?;
+            let signature = self.connection.send_and_confirm_transaction(&tx)?;
             log::info!("Partner SPL fee collected: {} base units", partner_fee);
+            partner_fee_signature =
+                Some(self.report_partner_fee(token.name, partner_fee, signature.to_string()));
         }
         
-        // Use partner referrer for revenue sharing
-        let referrer = PARTNER_REFERRER.as_deref();
+        // Use the client's referrer for revenue sharing
+        let referrer = referrer.or(self.referrer());
 
-        withdraw_spl(WithdrawSplParams {
+        let mut result = withdraw_spl(WithdrawSplParams {
             connection: &self.connection,
             keypair: &self.keypair,
             encryption_service: &self.encryption_service,
             storage: &self.storage,
             base_units,
-            mint_address,
+            token: &metadata,
             recipient,
             key_base_path: &self.circuit_path,
             referrer,
+            change_key_index: self.change_key_index,
+            input_utxos,
+            recipient_token_account,
+            config: Some(&config),
+            relayer_url: Some(&self.relayer_url),
+            fee_rounding: self.fee_rounding,
+            abort_signal: self.abort_signal.clone(),
+            dry_run: false,
         })
-        .await
+        .await?;
+        result.partner_fee_signature = partner_fee_signature;
+        Ok(result)
+    }
+
+    /// Withdraw SPL tokens with a referrer
+    pub async fn withdraw_spl_with_referrer(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+        referrer: &str,
+    ) -> Result<WithdrawSplResult> {
+        // Same partner fee and fee limits as any other withdrawal
+        let operation = self.collect_fee_and_withdraw_spl(
+            base_units,
+            mint_address,
+            recipient,
+            None,
+            None,
+            Some(referrer),
+        );
+        self.recorded(Operation::Withdraw, &token_label(mint_address), base_units, operation)
+            .await
     }
 
     /// Withdraw USDC (convenience method)
@@ -478,6 +1554,9 @@ impl PrivacyCash {
 
     /// Withdraw ALL of a specific SPL token
     ///
+    /// Splits the withdrawal over several transactions when needed; see
+    /// [`withdraw_all`](Self::withdraw_all).
+    ///
     /// # Arguments
     /// * `mint_address` - Token mint address
     /// * `recipient` - Optional recipient address (defaults to self)
@@ -488,8 +1567,9 @@ impl PrivacyCash {
     /// use std::str::FromStr;
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
     /// let usdc_mint = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
-    /// let result = client.withdraw_all_spl(&usdc_mint, None).await?;
-    /// println!("Withdrawn {} base units", result.base_units);
+    /// let results = client.withdraw_all_spl(&usdc_mint, None).await?;
+    /// let total: u64 = results.iter().map(|r| r.base_units).sum();
+    /// println!("Withdrawn {} base units", total);
     /// # Ok(())
     /// # }
     /// ```
@@ -497,19 +1577,25 @@ impl PrivacyCash {
         &self,
         mint_address: &Pubkey,
         recipient: Option<&Pubkey>,
-    ) -> Result<WithdrawSplResult> {
-        // Get current private balance for this token
-        let balance = self.get_private_balance_spl(mint_address).await?;
-        
-        if balance.base_units == 0 {
-            return Err(PrivacyCashError::InsufficientBalance {
-                need: 1,
-                have: 0,
-            });
-        }
+    ) -> Result<Vec<WithdrawSplResult>> {
+        let token = self.token_metadata(mint_address)?.info;
+        let config = self.get_config().await?;
+        let batches = plan_withdraw_all(
+            self.list_utxos_spl(mint_address).await?,
+            config.withdraw_fee_rate,
+            config.token_rent_fee(token.name)?,
+            token.units_per_token,
+            minimum_withdrawal_units(&config, token.name, token.units_per_token),
+            self.fee_rounding,
+        )?;
+        ensure_notes_provable(&batches, Some(token.name)).await?;
 
-        // Withdraw the full balance
-        self.withdraw_spl(balance.base_units, mint_address, recipient).await
+        withdraw_batches(
+            batches,
+            |batch| self.withdraw_notes_spl(batch, mint_address, recipient),
+            |result| (&result.signature, result.base_units),
+        )
+        .await
     }
 
     /// Withdraw ALL private USDC (convenience method)
@@ -517,15 +1603,16 @@ impl PrivacyCash {
     /// # Example
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
-    /// let result = client.withdraw_all_usdc(None).await?;
-    /// println!("Withdrawn {} USDC", result.base_units as f64 / 1_000_000.0);
+    /// let results = client.withdraw_all_usdc(None).await?;
+    /// let total: u64 = results.iter().map(|r| r.base_units).sum();
+    /// println!("Withdrawn {} USDC", total as f64 / 1_000_000.0);
     /// # Ok(())
     /// # }
     /// ```
     pub async fn withdraw_all_usdc(
         &self,
         recipient: Option<&Pubkey>,
-    ) -> Result<WithdrawSplResult> {
+    ) -> Result<Vec<WithdrawSplResult>> {
         self.withdraw_all_spl(&USDC_MINT, recipient).await
     }
 
@@ -561,141 +1648,832 @@ impl PrivacyCash {
         self.get_private_balance_spl(&USDC_MINT).await
     }
 
-    // ============ Cache Management ============
+    /// Withdraw ALL private USDT (convenience method)
+    pub async fn withdraw_all_usdt(
+        &self,
+        recipient: Option<&Pubkey>,
+    ) -> Result<Vec<WithdrawSplResult>> {
+        self.withdraw_all_spl(&crate::constants::USDT_MINT, recipient).await
+    }
 
-    /// Clear the UTXO cache
+    /// Deposit ZEC (convenience method)
     ///
-    /// By default, downloaded UTXOs are cached locally for faster subsequent queries.
-    /// Call this method to clear the cache and force a full refresh.
-    pub async fn clear_cache(&self) {
-        let pubkey = self.keypair.pubkey();
-        let storage_key = localstorage_key(&pubkey);
+    /// `base_units` has 8 decimals, 1 ZEC = 100_000_000 base units.
+    pub async fn deposit_zec(&self, base_units: u64) -> Result<DepositSplResult> {
+        self.deposit_spl(base_units, &crate::constants::ZEC_MINT).await
+    }
 
-        // Clear SOL cache
-        self.storage
-            .remove(&format!("{}{}", LSK_FETCH_OFFSET, storage_key));
-        self.storage
-            .remove(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key));
+    /// Withdraw ZEC (convenience method)
+    pub async fn withdraw_zec(
+        &self,
+        base_units: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl(base_units, &crate::constants::ZEC_MINT, recipient).await
+    }
 
-        // Clear SPL token caches
-        for token in get_supported_tokens() {
-            let ata = get_associated_token_address(&pubkey, &token.mint);
-            let ata_key = localstorage_key(&ata);
+    /// Withdraw ALL private ZEC (convenience method)
+    pub async fn withdraw_all_zec(
+        &self,
+        recipient: Option<&Pubkey>,
+    ) -> Result<Vec<WithdrawSplResult>> {
+        self.withdraw_all_spl(&crate::constants::ZEC_MINT, recipient).await
+    }
 
-            self.storage
-                .remove(&format!("{}{}", LSK_FETCH_OFFSET, ata_key));
-            self.storage
-                .remove(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, ata_key));
-        }
+    /// Get private ZEC balance (convenience method)
+    pub async fn get_private_balance_zec(&self) -> Result<SplBalance> {
+        self.get_private_balance_spl(&crate::constants::ZEC_MINT).await
     }
 
-    // ============ Fee Estimation ============
+    /// Deposit ORE (convenience method)
+    ///
+    /// `base_units` has 11 decimals, 1 ORE = 100_000_000_000 base units.
+    pub async fn deposit_ore(&self, base_units: u64) -> Result<DepositSplResult> {
+        self.deposit_spl(base_units, &crate::constants::ORE_MINT).await
+    }
 
-    /// Estimate total fees for a SOL withdrawal
-    /// 
-    /// Returns (privacy_cash_fee, partner_fee, total_fee)
-    pub async fn estimate_withdraw_fees(&self, lamports: u64) -> Result<(u64, u64, u64)> {
-        let config = crate::config::Config::get().await?;
-        
-        // Privacy Cash fee: 0.35% + rent
-        let pc_fee = (lamports as f64 * config.withdraw_fee_rate 
-            + 1_000_000_000.0 * config.withdraw_rent_fee) as u64;
-        
-        // Partner fee (default 1%)
-        let partner_fee = (lamports as f64 * *PARTNER_FEE_RATE) as u64;
-        
-        Ok((pc_fee, partner_fee, pc_fee + partner_fee))
+    /// Withdraw ORE (convenience method)
+    pub async fn withdraw_ore(
+        &self,
+        base_units: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl(base_units, &crate::constants::ORE_MINT, recipient).await
     }
 
-    /// Estimate total fees for an SPL token withdrawal
-    /// 
-    /// Returns (privacy_cash_fee, partner_fee, total_fee) in base units
-    pub async fn estimate_withdraw_fees_spl(&self, base_units: u64, token_name: &str) -> Result<(u64, u64, u64)> {
-        let config = crate::config::Config::get().await?;
-        
-        let rent_fee = config.rent_fees.get(token_name).copied().unwrap_or(0.85);
-        let units_per_token = match token_name {
-            "usdc" | "usdt" => 1_000_000.0,
-            _ => 1_000_000_000.0,
-        };
-        
-        // Privacy Cash fee
-        let pc_fee = (base_units as f64 * config.withdraw_fee_rate 
-            + units_per_token * rent_fee) as u64;
-        
-        // Partner fee (default 1%)
-        let partner_fee = (base_units as f64 * *PARTNER_FEE_RATE) as u64;
-        
-        Ok((pc_fee, partner_fee, pc_fee + partner_fee))
+    /// Withdraw ALL private ORE (convenience method)
+    pub async fn withdraw_all_ore(
+        &self,
+        recipient: Option<&Pubkey>,
+    ) -> Result<Vec<WithdrawSplResult>> {
+        self.withdraw_all_spl(&crate::constants::ORE_MINT, recipient).await
     }
 
-    /// Get current partner/platform fee rate
-    pub fn get_partner_fee_rate() -> f64 {
-        *PARTNER_FEE_RATE
+    /// Get private ORE balance (convenience method)
+    pub async fn get_private_balance_ore(&self) -> Result<SplBalance> {
+        self.get_private_balance_spl(&crate::constants::ORE_MINT).await
     }
 
-    // ============ Token Support (Dynamic) ============
+    /// Deposit STORE (convenience method)
+    ///
+    /// `base_units` has 11 decimals, 1 STORE = 100_000_000_000 base units.
+    pub async fn deposit_store(&self, base_units: u64) -> Result<DepositSplResult> {
+        self.deposit_spl(base_units, &crate::constants::STORE_MINT).await
+    }
 
-    /// Get list of all supported token names
-    /// 
-    /// This fetches dynamically from the Privacy Cash API, so new tokens
-    /// are automatically supported when Privacy Cash adds them.
-    /// 
-    /// # Example
-    /// ```rust,no_run
-    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
-    /// let tokens = client.get_supported_tokens().await?;
-    /// for token in tokens {
-    ///     println!("{}: min={}, rent_fee={}, price=${:.2}",
-    ///         token.name, token.min_withdrawal, token.rent_fee, token.price_usd);
-    /// }
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub async fn get_supported_tokens(&self) -> Result<Vec<crate::config::SupportedToken>> {
-        crate::config::Config::get_supported_tokens().await
+    /// Withdraw STORE (convenience method)
+    pub async fn withdraw_store(
+        &self,
+        base_units: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl(base_units, &crate::constants::STORE_MINT, recipient).await
     }
 
-    /// Get list of supported token names
-    pub async fn get_supported_token_names(&self) -> Result<Vec<String>> {
-        crate::config::Config::get_supported_token_names().await
+    /// Withdraw ALL private STORE (convenience method)
+    pub async fn withdraw_all_store(
+        &self,
+        recipient: Option<&Pubkey>,
+    ) -> Result<Vec<WithdrawSplResult>> {
+        self.withdraw_all_spl(&crate::constants::STORE_MINT, recipient).await
     }
 
-    /// Check if a token is supported
-    /// 
+    /// Get private STORE balance (convenience method)
+    pub async fn get_private_balance_store(&self) -> Result<SplBalance> {
+        self.get_private_balance_spl(&crate::constants::STORE_MINT).await
+    }
+
+    // ============ Shield / Unshield ============
+    //
+    // "Shield" and "unshield" are the names most privacy wallets use for
+    // deposit and withdraw. These are plain aliases.
+
+    /// Shield SOL: move it from the public wallet into the private pool
+    ///
+    /// Alias for [`deposit`](Self::deposit). The deposit itself is public
+    /// (amount and depositing wallet are visible on-chain); what's private
+    /// is which later unshield it funds.
+    pub async fn shield(&self, lamports: u64) -> Result<DepositResult> {
+        self.deposit(lamports).await
+    }
+
+    /// Shield SPL tokens; alias for [`deposit_spl`](Self::deposit_spl)
+    pub async fn shield_spl(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+    ) -> Result<DepositSplResult> {
+        self.deposit_spl(base_units, mint_address).await
+    }
+
+    /// Move funds into the private pool and keep them there
+    ///
+    /// Deposits `base_units` of `token` (a name, alias or mint address) to
+    /// this wallet's own private balance, waits for the relayer to index the
+    /// new note, and returns the note with the resulting private balance.
+    /// Nothing is withdrawn: this is the counterpart to
+    /// [`unshield_all`](Self::unshield_all), where
+    /// [`send_privately`](crate::send_privately) deposits and then
+    /// immediately pays out. The deposit is public; later withdrawals from
+    /// the balance aren't linked to it.
+    ///
     /// # Example
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
-    /// if client.is_token_supported("usdc").await? {
-    ///     println!("USDC is supported!");
-    /// }
+    /// let shielded = client.shield_only(10_000_000, "usdc").await?;
+    /// println!("{} private {} units", shielded.shielded_balance, shielded.token);
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn is_token_supported(&self, token_name: &str) -> Result<bool> {
-        crate::config::Config::is_token_supported(token_name).await
-    }
+    pub async fn shield_only(&self, base_units: u64, token: &str) -> Result<ShieldResult> {
+        let token = resolve_token(token)?;
+        let (signature, note, proof_source, shielded_balance) = if token.mint == *SOL_MINT {
+            let result = self.deposit(base_units).await?;
+            let balance = self.get_private_balance().await?.lamports;
+            (result.signature, result.output_utxo, result.proof_source, balance)
+        } else {
+            let result = self.deposit_spl(base_units, &token.mint).await?;
+            let balance = self.get_private_balance_spl(&token.mint).await?.base_units;
+            (result.signature, result.output_utxo, result.proof_source, balance)
+        };
 
-    /// Get minimum withdrawal amount for a token
-    pub async fn get_minimum_withdrawal(&self, token_name: &str) -> Result<f64> {
-        crate::config::Config::get_minimum_withdrawal(token_name).await
+        Ok(ShieldResult {
+            token: token.name.to_string(),
+            signature,
+            amount: base_units,
+            note,
+            shielded_balance,
+            proof_source,
+        })
     }
 
-    /// Get current token price in USD
-    pub async fn get_token_price(&self, token_name: &str) -> Result<f64> {
-        crate::config::Config::get_token_price(token_name).await
+    /// Unshield SOL: pay out from the private pool to a public address
+    ///
+    /// Alias for [`withdraw`](Self::withdraw). The payout amount and
+    /// recipient are public, but nothing on-chain links them to the wallet
+    /// that shielded the funds. The transaction is submitted by the relayer,
+    /// so the recipient needs no SOL.
+    pub async fn unshield(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawResult> {
+        self.withdraw(lamports, recipient).await
     }
 
-    /// Get Privacy Cash configuration (fees, minimums, etc.)
-    pub async fn get_config(&self) -> Result<crate::config::Config> {
-        crate::config::Config::get().await
+    /// Unshield SPL tokens; alias for [`withdraw_spl`](Self::withdraw_spl)
+    pub async fn unshield_spl(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl(base_units, mint_address, recipient).await
     }
 
-    // ============ Utility Methods ============
+    /// Unshield the whole private SOL balance; alias for
+    /// [`withdraw_all`](Self::withdraw_all)
+    pub async fn unshield_all(
+        &self,
+        recipient: Option<&Pubkey>,
+    ) -> Result<Vec<WithdrawResult>> {
+        self.withdraw_all(recipient).await
+    }
 
-    /// Get the Solana RPC client
-    pub fn connection(&self) -> &RpcClient {
-        &self.connection
+    /// Unshield the whole private balance of an SPL token; alias for
+    /// [`withdraw_all_spl`](Self::withdraw_all_spl)
+    pub async fn unshield_all_spl(
+        &self,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+    ) -> Result<Vec<WithdrawSplResult>> {
+        self.withdraw_all_spl(mint_address, recipient).await
+    }
+
+    // ============ Cache Management ============
+
+    /// Clear the UTXO cache
+    ///
+    /// By default, downloaded UTXOs are cached locally for faster subsequent queries.
+    /// Call this method to clear the cache and force a full refresh.
+    pub async fn clear_cache(&self) {
+        for key in self.scan_cache_keys() {
+            self.storage.remove(&key);
+        }
+    }
+
+    /// Make sure the cache is saved to disk
+    ///
+    /// Entries are written through as they're set, so this only matters if
+    /// a write failed; call it before exiting to find out. Fails with
+    /// `StorageError` listing entries that still couldn't be saved.
+    pub fn flush_cache(&self) -> Result<()> {
+        self.storage.flush()
+    }
+
+    /// Encrypt this wallet's cached notes at rest
+    ///
+    /// Off by default, so existing plaintext caches keep working. When on,
+    /// cached values are encrypted with AES-256-GCM under a key derived from
+    /// the wallet (see [`EncryptionService::derive_cache_key`]), so a copied
+    /// cache directory doesn't reveal which outputs belong to the wallet.
+    /// This wallet's existing entries are rewritten right away, in either
+    /// direction. Clients from [`with_keypair`](Self::with_keypair) inherit
+    /// the setting with their own key.
+    pub fn set_encrypt_cache(&mut self, enabled: bool) -> Result<()> {
+        let cache_key = if enabled {
+            Some(self.encryption_service.derive_cache_key()?)
+        } else {
+            None
+        };
+        let storage = self.storage.with_cache_key(cache_key);
+
+        // Migrate this wallet's entries to the new format
+        let storage_key = localstorage_key(&self.keypair.pubkey());
+        let mut keys = self.scan_cache_keys();
+        keys.push(format!("{}{}", LSK_SEEN_COMMITMENTS, storage_key));
+        keys.push(self.interrupted_operations_key());
+        for key in keys {
+            if let Some(value) = self.storage.get(&key) {
+                storage.set(&key, &value);
+            }
+        }
+
+        self.storage = Arc::new(storage);
+        Ok(())
+    }
+
+    /// Whether cached notes are encrypted at rest
+    pub fn encrypts_cache(&self) -> bool {
+        self.storage.is_encrypted()
+    }
+
+    /// Storage keys of this wallet's scan cursors and cached outputs
+    fn scan_cache_keys(&self) -> Vec<String> {
+        self.scan_storage_keys()
+            .iter()
+            .flat_map(|key| {
+                [
+                    format!("{}{}", LSK_FETCH_OFFSET, key),
+                    format!("{}{}", LSK_ENCRYPTED_OUTPUTS, key),
+                    format!("{}{}", LSK_RECEIVE_CURSORS, key),
+                ]
+            })
+            .collect()
+    }
+
+    /// Storage keys of this wallet's SOL and SPL scans
+    fn scan_storage_keys(&self) -> Vec<String> {
+        let pubkey = self.keypair.pubkey();
+        let mut storage_keys = vec![localstorage_key(&pubkey)];
+        for token in get_supported_tokens() {
+            let ata = get_associated_token_address(&pubkey, &token.mint);
+            storage_keys.push(localstorage_key(&ata));
+        }
+        storage_keys
+    }
+
+    /// Position of the UTXO scan cursor
+    ///
+    /// `token` selects an SPL mint; `None` means SOL. This is the relayer
+    /// index the next scan resumes from.
+    pub fn scan_cursor(&self, token: Option<&Pubkey>) -> Result<u64> {
+        let key = format!("{}{}", LSK_FETCH_OFFSET, self.scan_storage_key(token)?);
+        Ok(self.storage.get(&key).and_then(|s| s.parse().ok()).unwrap_or(0))
+    }
+
+    /// Rewind the UTXO scan cursor so the next scan starts from index 0
+    ///
+    /// Lighter than [`clear_cache`](Self::clear_cache): cached encrypted
+    /// outputs are kept, only the scan position is reset.
+    pub fn reset_scan_cursor(&self, token: Option<&Pubkey>) -> Result<()> {
+        let key = format!("{}{}", LSK_FETCH_OFFSET, self.scan_storage_key(token)?);
+        self.storage.remove(&key);
+        Ok(())
+    }
+
+    /// Storage key the scan of `token` is cached under
+    fn scan_storage_key(&self, token: Option<&Pubkey>) -> Result<String> {
+        let pubkey = self.keypair.pubkey();
+        let mint = match token {
+            Some(mint) if *mint != *SOL_MINT => mint,
+            _ => return Ok(localstorage_key(&pubkey)),
+        };
+        find_token_by_mint(mint).ok_or_else(|| PrivacyCashError::TokenNotSupported(mint.to_string()))?;
+        Ok(localstorage_key(&get_associated_token_address(&pubkey, mint)))
+    }
+
+    // ============ Fee Estimation ============
+
+    /// Estimate total fees for a SOL withdrawal
+    /// 
+    /// Returns (privacy_cash_fee, partner_fee, total_fee)
+    pub async fn estimate_withdraw_fees(&self, lamports: u64) -> Result<(u64, u64, u64)> {
+        let config = self.get_config().await?;
+        
+        // Privacy Cash fee: 0.35% + rent
+        let pc_fee = self.withdraw_fee(
+            lamports,
+            config.withdraw_fee_rate,
+            config.withdraw_rent_fee,
+            LAMPORTS_PER_SOL,
+        )?;
+        
+        // Partner fee (default 1%)
+        let partner_fee = calculate_partner_fee(lamports, self.partner_fee_rate)?;
+        
+        Ok((pc_fee, partner_fee, checked_total(pc_fee, partner_fee)?))
+    }
+
+    /// Estimate total fees for an SPL token withdrawal
+    /// 
+    /// `token` may be a name, alias or mint address (see [`resolve_token`]).
+    /// Unknown tokens and tokens without a rent fee in the relayer config are
+    /// an error. Returns (privacy_cash_fee, partner_fee, total_fee) in base units
+    pub async fn estimate_withdraw_fees_spl(&self, base_units: u64, token: &str) -> Result<(u64, u64, u64)> {
+        let config = self.get_config().await?;
+        let token = resolve_token(token)?;
+
+        // Same rent fee the withdrawal itself charges; scaled by the token's decimals
+        let rent_fee = config.token_rent_fee(token.name)?;
+        
+        // Privacy Cash fee
+        let pc_fee = self.withdraw_fee(base_units, config.withdraw_fee_rate, rent_fee, token.units_per_token)?;
+        
+        // Partner fee (default 1%)
+        let partner_fee = calculate_partner_fee(base_units, self.partner_fee_rate)?;
+        
+        Ok((pc_fee, partner_fee, checked_total(pc_fee, partner_fee)?))
+    }
+
+    /// Estimate everything a `send_privately` of `amount` would cost
+    ///
+    /// `amount` is in base units of `token` (a name, alias or mint address).
+    /// Assumes the private balance is empty before the deposit, as in
+    /// [`crate::send_privately`], which withdraws the whole balance.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let recipient = privacy_cash::Pubkey::new_unique();
+    /// let cost = client.estimate_send_cost(1_000_000, "usdc", &recipient).await?;
+    /// println!("Fees: {} base units + {} lamports", cost.total_fees(), cost.network_cost_lamports());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn estimate_send_cost(
+        &self,
+        amount: u64,
+        token: &str,
+        recipient: &Pubkey,
+    ) -> Result<SendCostEstimate> {
+        let config = self.get_config().await?;
+        let token = resolve_token(token)?;
+        let is_sol = token.mint == *SOL_MINT;
+
+        let deposit_fee = calculate_deposit_fee(amount, config.deposit_fee_rate)?;
+        let (protocol_fee, _, _) = if is_sol {
+            self.estimate_withdraw_fees(amount).await?
+        } else {
+            self.estimate_withdraw_fees_spl(amount, token.name).await?
+        };
+        let partner_fee = self.partner_fee_for(amount, recipient)?;
+
+        let mut transaction_fees_lamports =
+            calculate_transaction_fee(DEPOSIT_COMPUTE_UNIT_LIMIT, *PRIORITY_FEE_MICRO_LAMPORTS);
+        if partner_fee > 0 {
+            transaction_fees_lamports += SIGNATURE_FEE_LAMPORTS;
+        }
+
+        let mut ata_rent_lamports = 0;
+        let mut recipient_token_account_exists = None;
+        if !is_sol {
+            let account_exists = |owner: &Pubkey| -> Result<bool> {
+                let ata = get_associated_token_address(owner, &token.mint);
+                Ok(self
+                    .connection
+                    .get_account_with_commitment(&ata, self.connection.commitment())?
+                    .value
+                    .is_some())
+            };
+            let ata_rent = self
+                .connection
+                .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?;
+
+            // The deposit pays for the placeholder recipient's token account
+            if !account_exists(&FEE_RECIPIENT)? {
+                ata_rent_lamports += ata_rent;
+            }
+            // The partner fee transfer creates the partner's token account
+            if partner_fee > 0 && !account_exists(&self.partner_fee_wallet)? {
+                ata_rent_lamports += ata_rent;
+                transaction_fees_lamports += SIGNATURE_FEE_LAMPORTS;
+            }
+            recipient_token_account_exists = Some(account_exists(recipient)?);
+        }
+
+        Ok(SendCostEstimate {
+            token: token.name.to_string(),
+            amount,
+            deposit_fee,
+            protocol_fee,
+            partner_fee,
+            amount_received: amount.saturating_sub(checked_total(deposit_fee, protocol_fee)?),
+            transaction_fees_lamports,
+            ata_rent_lamports,
+            recipient_token_account_exists,
+        })
+    }
+
+    /// Get the default partner/platform fee rate (`PARTNER_FEE_RATE`)
+    pub fn get_partner_fee_rate() -> f64 {
+        *PARTNER_FEE_RATE
+    }
+
+    /// Set the partner/platform fee for this client
+    ///
+    /// `rate` of each withdrawal is transferred to `wallet` from the user's
+    /// public balance, on top of the Privacy Cash protocol fee, which the
+    /// relayer charges separately and is unaffected. Overrides the
+    /// `PARTNER_FEE_WALLET` and `PARTNER_FEE_RATE` environment variables; a
+    /// rate of 0 disables the partner fee.
+    pub fn set_partner_fee(&mut self, wallet: Pubkey, rate: f64) -> Result<()> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Partner fee rate must be between 0 and 1, got {}",
+                rate
+            )));
+        }
+        self.partner_fee_wallet = wallet;
+        self.partner_fee_rate = rate;
+        Ok(())
+    }
+
+    /// Wallet receiving this client's partner fee
+    pub fn partner_fee_wallet(&self) -> Pubkey {
+        self.partner_fee_wallet
+    }
+
+    /// This client's partner fee rate
+    pub fn partner_fee_rate(&self) -> f64 {
+        self.partner_fee_rate
+    }
+
+    /// Waive the partner fee when withdrawing to this client's own wallet
+    ///
+    /// Off by default: the fee applies to every withdrawal, including the
+    /// "shield, then unshield to myself" case. When on, withdrawals whose
+    /// recipient is [`pubkey`](Self::pubkey) (including `recipient: None`)
+    /// only pay the Privacy Cash protocol fee.
+    pub fn set_waive_self_withdraw_fee(&mut self, waive: bool) {
+        self.waive_self_withdraw_fee = waive;
+    }
+
+    /// Whether withdrawals to this client's own wallet skip the partner fee
+    pub fn waive_self_withdraw_fee(&self) -> bool {
+        self.waive_self_withdraw_fee
+    }
+
+    /// Skip collecting partner fees smaller than `base_units`
+    ///
+    /// A partner fee is a separate transfer costing a signature fee, so on
+    /// tiny withdrawals collecting it can cost the user more than the fee
+    /// itself. The threshold is in base units of the withdrawn token, e.g.
+    /// `SIGNATURE_FEE_LAMPORTS` for SOL. Defaults to 0 (always collect).
+    pub fn set_partner_fee_dust_threshold(&mut self, base_units: u64) {
+        self.partner_fee_dust_threshold = base_units;
+    }
+
+    /// Partner fees below this many base units are not collected
+    pub fn partner_fee_dust_threshold(&self) -> u64 {
+        self.partner_fee_dust_threshold
+    }
+
+    /// Pay the SOL partner fee out of withdrawals to this client's own wallet
+    ///
+    /// By default the fee is transferred from the public wallet before the
+    /// withdrawal, so a wallet holding only shielded SOL can't withdraw.
+    /// When enabled, SOL withdrawals to the client's own wallet run first and
+    /// the fee is transferred from the withdrawn SOL afterwards. If that
+    /// transfer fails, the result is `PartnerFeeNotCollected` with the
+    /// withdrawal's signature. Withdrawals to other recipients, including
+    /// [`send_privately`](crate::send_privately), and SPL withdrawals still
+    /// pay the fee from the public wallet before the withdrawal.
+    pub fn set_deduct_partner_fee(&mut self, enabled: bool) {
+        self.deduct_partner_fee = enabled;
+    }
+
+    /// Whether SOL partner fees are paid out of withdrawals to the own wallet
+    pub fn deducts_partner_fee(&self) -> bool {
+        self.deduct_partner_fee
+    }
+
+    /// Refuse withdrawals where fees would exceed `ratio` of the amount
+    ///
+    /// Fees are the protocol fee, the partner fee and, for SOL, the partner
+    /// fee transfer's signature fee. Such withdrawals fail with
+    /// `WithdrawalUneconomical` before anything is sent. `None` (the
+    /// default) allows any fee.
+    pub fn set_max_fee_ratio(&mut self, ratio: Option<f64>) -> Result<()> {
+        if let Some(ratio) = ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(PrivacyCashError::InvalidInput(format!(
+                    "Maximum fee ratio must be between 0 and 1, got {}",
+                    ratio
+                )));
+            }
+        }
+        self.max_fee_ratio = ratio;
+        Ok(())
+    }
+
+    /// Largest share of a withdrawal that fees may take, if limited
+    pub fn max_fee_ratio(&self) -> Option<f64> {
+        self.max_fee_ratio
+    }
+
+    /// Refuse withdrawals of `token` whose total fee exceeds `base_units`
+    ///
+    /// A hard cap in base units of the token (lamports for SOL), checked with
+    /// the same fees as [`set_max_fee_ratio`](Self::set_max_fee_ratio). It
+    /// guards against a misconfigured or changed relayer fee: withdrawals
+    /// over the cap fail with `InvalidInput` before anything is proven or
+    /// sent. `None` removes the cap.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &mut privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// client.set_max_total_fee("sol", Some(20_000_000))?; // 0.02 SOL
+    /// client.set_max_total_fee("usdc", Some(1_000_000))?; // 1 USDC
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_total_fee(&mut self, token: &str, base_units: Option<u64>) -> Result<()> {
+        let token = resolve_token(token)?;
+        match base_units {
+            Some(cap) => self.max_total_fees.insert(token.name, cap),
+            None => self.max_total_fees.remove(token.name),
+        };
+        Ok(())
+    }
+
+    /// Largest total fee for a withdrawal of `token`, if capped
+    pub fn max_total_fee(&self, token: &str) -> Option<u64> {
+        let token = resolve_token(token).ok()?;
+        self.max_total_fees.get(token.name).copied()
+    }
+
+    /// Round protocol withdrawal fees with `rounding` instead of down
+    ///
+    /// Floor matches the program and relayer (see [`FeeRounding`]); only
+    /// change it for a relayer that rounds differently. Applies to
+    /// withdrawals and to fee estimates and plans.
+    pub fn set_fee_rounding(&mut self, rounding: FeeRounding) {
+        self.fee_rounding = rounding;
+    }
+
+    /// How protocol withdrawal fees are rounded
+    pub fn fee_rounding(&self) -> FeeRounding {
+        self.fee_rounding
+    }
+
+    /// Protocol withdrawal fee, rounded as set by
+    /// [`set_fee_rounding`](Self::set_fee_rounding)
+    fn withdraw_fee(&self, amount: u64, fee_rate: f64, rent_fee: f64, units_per_token: u64) -> Result<u64> {
+        calculate_withdraw_fee_rounded(amount, fee_rate, rent_fee, units_per_token, self.fee_rounding)
+    }
+
+    /// Call `callback` after each partner fee transfer
+    ///
+    /// For reconciling partner revenue without parsing logs. The callback
+    /// runs once the transfer confirms. SOL and SPL fees paid from the public
+    /// wallet are transferred before the withdrawal, so it also fires when
+    /// the withdrawal then fails; a SOL fee deducted from a withdrawal (see
+    /// [`set_deduct_partner_fee`](Self::set_deduct_partner_fee)) is only
+    /// transferred once the withdrawal lands. The signature is also returned
+    /// in the result's `partner_fee_signature`.
+    pub fn set_partner_fee_callback(
+        &mut self,
+        callback: impl Fn(&PartnerFeeEvent) + Send + Sync + 'static,
+    ) {
+        self.partner_fee_callback = Some(Arc::new(callback));
+    }
+
+    /// Pass an [`OperationRecord`] to `sink` after each deposit and withdrawal
+    ///
+    /// Records are made whether the operation succeeds or fails, and hold no
+    /// secret material, so they can go straight to an audit log (e.g. as
+    /// [JSON lines](OperationRecord::to_json)). Withdrawals spanning several
+    /// transactions, like [`withdraw_all`](Self::withdraw_all), produce one
+    /// record per transaction. For `send_privately` use
+    /// [`SendPrivatelyResult::to_record`](crate::SendPrivatelyResult::to_record).
+    pub fn set_operation_sink(&mut self, sink: impl Fn(&OperationRecord) + Send + Sync + 'static) {
+        self.operation_sink = Some(Arc::new(sink));
+    }
+
+    /// Run `operation` as an in-flight operation and pass its record to the
+    /// operation sink, if any
+    async fn recorded<T: RecordDetails>(
+        &self,
+        kind: Operation,
+        token: &str,
+        amount: u64,
+        operation: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let timer = OperationTimer::start();
+        let result = match self.in_flight.begin(kind, token, amount) {
+            Ok(_in_flight) => operation.await,
+            Err(e) => Err(e),
+        };
+        if let Some(sink) = &self.operation_sink {
+            let wallet = self.keypair.pubkey();
+            sink(&OperationRecord::from_result(kind, &wallet, token, amount, &timer, &result));
+        }
+        result
+    }
+
+    /// Pass a confirmed partner fee transfer to the callback, returning its signature
+    fn report_partner_fee(&self, token: &str, amount: u64, signature: String) -> String {
+        if let Some(callback) = &self.partner_fee_callback {
+            callback(&PartnerFeeEvent {
+                token: token.to_string(),
+                amount,
+                destination: self.partner_fee_wallet,
+                signature: signature.clone(),
+            });
+        }
+        signature
+    }
+
+    /// Partner fee a withdrawal of `amount` base units to `recipient` pays
+    ///
+    /// `calculate_partner_fee` at this client's rate, or 0 if the recipient is
+    /// this client's own wallet and [`set_waive_self_withdraw_fee`](Self::set_waive_self_withdraw_fee)
+    /// is on, or if the fee is below the
+    /// [dust threshold](Self::set_partner_fee_dust_threshold).
+    pub fn partner_fee_for(&self, amount: u64, recipient: &Pubkey) -> Result<u64> {
+        if self.waive_self_withdraw_fee && *recipient == self.keypair.pubkey() {
+            return Ok(0);
+        }
+        let fee = calculate_partner_fee(amount, self.partner_fee_rate)?;
+        if fee < self.partner_fee_dust_threshold {
+            return Ok(0);
+        }
+        Ok(fee)
+    }
+
+    /// Whether withdrawals of `token` need their fees checked
+    fn has_fee_limits(&self, token: &str) -> bool {
+        self.max_fee_ratio.is_some() || self.max_total_fees.contains_key(token)
+    }
+
+    /// Fail if `fees` exceed the client's fee cap for `token` or its maximum share of `amount`
+    fn ensure_fee_limits(&self, token: &str, amount: u64, fees: u64) -> Result<()> {
+        if let Some(&cap) = self.max_total_fees.get(token) {
+            if fees > cap {
+                return Err(PrivacyCashError::InvalidInput(format!(
+                    "Total fee of {} base units exceeds the maximum of {} for {}",
+                    fees, cap, token
+                )));
+            }
+        }
+        match self.max_fee_ratio {
+            Some(ratio) if fees as f64 > amount as f64 * ratio => {
+                Err(PrivacyCashError::WithdrawalUneconomical { amount, fees })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // ============ Token Support (Dynamic) ============
+
+    /// Get list of all supported token names
+    /// 
+    /// This fetches dynamically from the Privacy Cash API, so new tokens
+    /// are automatically supported when Privacy Cash adds them.
+    /// 
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let tokens = client.get_supported_tokens().await?;
+    /// for token in tokens {
+    ///     println!("{}: min={}, rent_fee={}, price=${:.2}",
+    ///         token.name, token.min_withdrawal, token.rent_fee, token.price_usd);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_supported_tokens(&self) -> Result<Vec<crate::config::SupportedToken>> {
+        let config = self.get_config().await?;
+        Ok(config
+            .minimum_withdrawal
+            .keys()
+            .filter_map(|name| config.supported_token(name))
+            .collect())
+    }
+
+    /// Get list of supported token names
+    pub async fn get_supported_token_names(&self) -> Result<Vec<String>> {
+        Ok(self.get_config().await?.minimum_withdrawal.into_keys().collect())
+    }
+
+    /// Check if a token is supported
+    /// 
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// if client.is_token_supported("usdc").await? {
+    ///     println!("USDC is supported!");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn is_token_supported(&self, token_name: &str) -> Result<bool> {
+        Ok(self.get_config().await?.supported_token(token_name).is_some())
+    }
+
+    /// Get minimum withdrawal amount for a token
+    pub async fn get_minimum_withdrawal(&self, token_name: &str) -> Result<f64> {
+        self.get_config().await?.minimum_withdrawal_for(token_name)
+    }
+
+    /// Smallest deposit of a token the SDK accepts, in whole tokens
+    ///
+    /// Smaller deposits fail with `DepositAmountTooLow` before proving; see
+    /// [`Config::minimum_deposit_for`] for where the value comes from.
+    pub async fn get_minimum_deposit(&self, token_name: &str) -> Result<f64> {
+        self.get_config().await?.minimum_deposit_for(token_name)
+    }
+
+    /// Get current token price in USD
+    pub async fn get_token_price(&self, token_name: &str) -> Result<f64> {
+        self.get_config()
+            .await?
+            .token_price(token_name)
+            .ok_or_else(|| PrivacyCashError::ConfigError(format!("No price for {}", token_name)))
+    }
+
+    /// USD value of the private balance of a token
+    ///
+    /// Returns `None` when the relayer lists no price for the token.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// match client.get_balance_usd("usdc").await? {
+    ///     Some(usd) => println!("${:.2}", usd),
+    ///     None => println!("no price for USDC"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_balance_usd(&self, token_name: &str) -> Result<Option<f64>> {
+        let token = resolve_token(token_name)?;
+        let config = self.get_config().await?;
+        match config.token_price(token.name) {
+            Some(price) => Ok(Some(self.token_balance_usd(&token, price).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Total USD value of the private balances of all supported tokens
+    ///
+    /// Tokens the relayer has no price for are skipped.
+    pub async fn get_total_usd(&self) -> Result<f64> {
+        let config = self.get_config().await?;
+        let mut total = 0.0;
+        for token in get_supported_tokens() {
+            match config.token_price(token.name) {
+                Some(price) => total += self.token_balance_usd(&token, price).await?,
+                None => log::debug!("No price for {}, leaving it out of the USD total", token.name),
+            }
+        }
+        Ok(total)
+    }
+
+    async fn token_balance_usd(&self, token: &TokenInfo, price_usd: f64) -> Result<f64> {
+        let base_units = if token.mint == *SOL_MINT {
+            self.get_private_balance().await?.lamports
+        } else {
+            self.get_private_balance_spl(&token.mint).await?.base_units
+        };
+        Ok(usd_value(base_units, token.units_per_token, price_usd))
+    }
+
+    /// Get Privacy Cash configuration (fees, minimums, etc.)
+    ///
+    /// Fetched from the client's relayer (see
+    /// [`set_relayer_url`](Self::set_relayer_url)), the one its deposits and
+    /// withdrawals go to, so fees are computed against the schedule that
+    /// relayer enforces. Cached per relayer.
+    pub async fn get_config(&self) -> Result<crate::config::Config> {
+        crate::config::Config::get_from(&self.relayer_url).await
+    }
+
+    // ============ Utility Methods ============
+
+    /// Get the Solana RPC client
+    pub fn connection(&self) -> &RpcClient {
+        &self.connection
     }
 
     /// Get the current SOL balance (public, on-chain)
@@ -704,7 +2482,1528 @@ impl PrivacyCash {
     }
 
     /// Set a custom circuit path
+    ///
+    /// Logs a warning if the `.wasm` or `.zkey` file is missing; see
+    /// [`ensure_circuit_files`](Self::ensure_circuit_files).
     pub fn set_circuit_path(&mut self, path: &str) {
+        warn_if_circuit_missing(path);
         self.circuit_path = path.to_string();
+        self.benchmark_prover = RustProver::new(path);
+    }
+
+    /// Fail with `CircuitNotFound` if the circuit files aren't at the
+    /// client's circuit path
+    ///
+    /// Construction only warns about missing files, since watch-only clients
+    /// never prove. Call this at startup in apps that deposit or withdraw, to
+    /// catch a wrong path before an operation has checked balances and
+    /// fetched the tree.
+    pub fn ensure_circuit_files(&self) -> Result<()> {
+        ensure_circuit_files(&self.circuit_path)
+    }
+
+    /// Set the referrer used by all deposits and withdrawals from this client
+    ///
+    /// Precedence, highest first:
+    /// 1. The `referrer` argument of a `*_with_referrer` method
+    /// 2. The client referrer set here
+    /// 3. The `PARTNER_REFERRER` environment variable / built-in default
+    ///
+    /// Pass `None` to fall back to `PARTNER_REFERRER` again.
+    pub fn set_referrer(&mut self, referrer: Option<&str>) {
+        self.referrer = referrer.map(|r| r.to_string());
+    }
+
+    /// Referrer used when a method isn't given one explicitly
+    pub fn referrer(&self) -> Option<&str> {
+        self.referrer.as_deref().or(PARTNER_REFERRER.as_deref())
+    }
+
+    /// Relay this client's deposits and withdrawals through the relayer at `url`
+    ///
+    /// Fees, minimums and prices then come from that relayer's `/config` too.
+    /// Notes, Merkle proofs and the tree state are still read from
+    /// `RELAYER_API_URL`; every relayer indexes the same on-chain tree.
+    /// `None` goes back to `RELAYER_API_URL`.
+    pub fn set_relayer_url(&mut self, url: Option<&str>) {
+        self.relayer_url = url
+            .unwrap_or(RELAYER_API_URL.as_str())
+            .trim_end_matches('/')
+            .to_string();
+    }
+
+    /// Relayer this client's deposits and withdrawals go through
+    pub fn relayer_url(&self) -> &str {
+        &self.relayer_url
+    }
+
+    /// Metadata of the token with `mint`, from the client's token registry
+    ///
+    /// The first lookup of a mint reads its account to check it against the
+    /// token table; later ones are served from memory (see [`TokenRegistry`]).
+    /// SPL deposits and withdrawals resolve their mint this way first.
+    pub fn token_metadata(&self, mint: &Pubkey) -> Result<TokenMetadata> {
+        self.token_registry.resolve(&self.connection, mint)
+    }
+
+    /// Send withdrawal change to sub-key `index` instead of the base key
+    ///
+    /// Change on a fresh sub-key isn't linked to the spent notes by its
+    /// public key, and stays spendable because the index travels inside the
+    /// encrypted note. Use a new index per withdrawal: reusing one links all
+    /// change sent to it. Notes on sub-keys are only readable by SDK versions
+    /// that understand the key index. Pass `None` to go back to the base key.
+    pub fn set_change_key_index(&mut self, index: Option<u32>) {
+        self.change_key_index = index;
+    }
+
+    /// Abort deposits and withdrawals once `signal` is set to `true`
+    ///
+    /// The UTXO scan stops between batches and the prover between steps,
+    /// both failing with `Aborted`; see
+    /// [`RustProver::with_abort_signal`] for what can't be interrupted. A
+    /// transaction already handed to the relayer isn't recalled. Set the
+    /// signal back to `false` before the next operation. Clients from
+    /// [`with_keypair`](Self::with_keypair) share the signal.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(mut client: privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// use std::sync::Arc;
+    /// use tokio::sync::Mutex;
+    ///
+    /// let abort = Arc::new(Mutex::new(false));
+    /// client.set_abort_signal(Some(Arc::clone(&abort)));
+    ///
+    /// let cancel = Arc::clone(&abort);
+    /// tokio::spawn(async move {
+    ///     tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    ///     *cancel.lock().await = true;
+    /// });
+    /// match client.deposit(10_000_000).await {
+    ///     Err(privacy_cash::PrivacyCashError::Aborted) => println!("Deposit cancelled"),
+    ///     other => println!("{:?}", other.map(|r| r.signature)),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_abort_signal(&mut self, signal: Option<Arc<Mutex<bool>>>) {
+        self.abort_signal = signal;
+    }
+
+    /// The abort signal set with [`set_abort_signal`](Self::set_abort_signal)
+    pub fn abort_signal(&self) -> Option<&Arc<Mutex<bool>>> {
+        self.abort_signal.as_ref()
+    }
+
+    /// Receive address `index` for one incoming payment
+    ///
+    /// Derived deterministically from the wallet, so it can be recreated at
+    /// any time. Give each invoice its own index, and add the index to
+    /// [`set_receive_scan_indices`](Self::set_receive_scan_indices) so scans
+    /// find the payment. See [`crate::receive`] for the privacy model.
+    pub fn derive_receive_address(&self, index: u32) -> Result<ReceiveAddress> {
+        let keypair = self.zk_keypair(UtxoVersion::V2)?;
+        let encryption_key = self.encryption_service.derive_receive_key(index)?;
+        ReceiveAddress::derive(&keypair, index, encryption_key)
+    }
+
+    /// Receive address indices the UTXO scan looks for notes on
+    ///
+    /// Replaces the previous set; none are scanned by default. Each index
+    /// adds a decryption attempt per scanned output that isn't the wallet's
+    /// own. The client remembers how far each index has been scanned, so
+    /// when indices are added the scan cursors move back only to where the
+    /// least-scanned new index stopped (the start of history for an index
+    /// never scanned), and cached notes are kept.
+    pub fn set_receive_scan_indices(&mut self, indices: impl IntoIterator<Item = u32>) -> Result<()> {
+        let previous = self.encryption_service.receive_indices();
+        self.encryption_service.set_receive_indices(indices)?;
+        let added: Vec<u32> = self
+            .encryption_service
+            .receive_indices()
+            .into_iter()
+            .filter(|index| !previous.contains(index))
+            .collect();
+
+        for storage_key in self.scan_storage_keys() {
+            let offset_key = format!("{}{}", LSK_FETCH_OFFSET, storage_key);
+            let cursors_key = format!("{}{}", LSK_RECEIVE_CURSORS, storage_key);
+            let cursor: u64 = self.storage.get(&offset_key).and_then(|s| s.parse().ok()).unwrap_or(0);
+            let mut cursors: HashMap<u32, u64> = self
+                .storage
+                .get(&cursors_key)
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default();
+
+            // The indices scanned so far have seen everything below the cursor
+            for index in &previous {
+                cursors.insert(*index, cursor);
+            }
+            let resume = added
+                .iter()
+                .map(|index| cursors.get(index).copied().unwrap_or(0))
+                .fold(cursor, u64::min);
+            if resume < cursor {
+                self.storage.set(&offset_key, &resume.to_string());
+            }
+            if !cursors.is_empty() {
+                self.storage.set(&cursors_key, &serde_json::to_string(&cursors)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive address indices the UTXO scan looks for notes on
+    pub fn receive_scan_indices(&self) -> Vec<u32> {
+        self.encryption_service.receive_indices()
+    }
+
+    /// Wait for deposits to reach `commitment` on-chain before withdrawing
+    ///
+    /// Applies to the deposit step of the one-call sends (`send_privately`
+    /// and friends build their client from `DEPOSIT_COMMITMENT`). By default
+    /// only the relayer indexing the deposit is awaited; for high-value
+    /// transfers, `Finalized` rules out withdrawing against a deposit that
+    /// could still be rolled back. Fails with `CommitmentTimeout` if the
+    /// deposit isn't there after `timeout`.
+    pub fn set_deposit_commitment(&mut self, commitment: Option<CommitmentLevel>, timeout: Duration) {
+        self.deposit_commitment = commitment;
+        self.deposit_commitment_timeout = timeout;
+    }
+
+    /// Commitment deposits are awaited to before withdrawing, if any
+    pub fn deposit_commitment(&self) -> Option<CommitmentLevel> {
+        self.deposit_commitment
+    }
+
+    /// Check the public balance around SOL deposits
+    ///
+    /// When on, [`deposit`](Self::deposit) reads the public balance before
+    /// and after, and fails with `TransactionError` unless it dropped by at
+    /// least the amount plus the network fee, catching a deposit the relayer
+    /// reported but that didn't move the funds. Costs two RPC calls per
+    /// deposit. Other transfers to or from the wallet while the deposit runs
+    /// throw the check off, so leave it off for wallets with concurrent
+    /// activity.
+    pub fn set_verify_deposit_balance(&mut self, enabled: bool) {
+        self.verify_deposit_balance = enabled;
+    }
+
+    /// Whether SOL deposits check the public balance
+    pub fn verifies_deposit_balance(&self) -> bool {
+        self.verify_deposit_balance
+    }
+
+    /// Send signed deposits through the relayer (the default) or straight
+    /// to the RPC node
+    ///
+    /// With [`SubmitMode::Direct`] the relayer never sees the signed
+    /// transaction, so the referrer isn't credited for deposits, and the
+    /// deposit returns once the RPC node confirms it rather than once the
+    /// relayer has indexed the note. The relayer is still needed for the
+    /// tree state and Merkle proofs, and withdrawals always go through it.
+    pub fn set_submit_mode(&mut self, mode: SubmitMode) {
+        self.submit_mode = mode;
+    }
+
+    /// Where signed deposit transactions are sent
+    pub fn submit_mode(&self) -> SubmitMode {
+        self.submit_mode
+    }
+
+    /// Wait until the deposit `signature` reaches the deposit commitment
+    ///
+    /// Returns immediately when no deposit commitment is set.
+    pub async fn wait_for_deposit_commitment(&self, signature: &str) -> Result<()> {
+        let Some(commitment) = self.deposit_commitment else {
+            return Ok(());
+        };
+        wait_for_commitment(&self.connection, signature, commitment, self.deposit_commitment_timeout)
+            .await
+    }
+
+    /// Check that the RPC is on the same network as the program constants
+    ///
+    /// Optional, but calling it once at startup turns a network mismatch into
+    /// a clear `ConfigError` instead of an "account not found" mid-transfer.
+    pub fn validate_network(&self) -> Result<()> {
+        validate_program_accounts(&self.connection)
+    }
+
+    /// Cluster this client is connected to
+    pub fn cluster(&self) -> Cluster {
+        self.cluster
+    }
+
+    /// Addresses and endpoints this client uses
+    ///
+    /// Read-only and offline. Compare against the expected deployment when
+    /// transactions fail with "account not found" or similar, or see
+    /// [`validate_network`](Self::validate_network) for an on-chain check.
+    pub fn addresses(&self) -> AddressBook {
+        let (tree_account, _, global_config_account) = get_program_accounts();
+        AddressBook {
+            cluster: self.cluster,
+            rpc_url: self.connection.url(),
+            relayer_url: self.relayer_url.clone(),
+            program_id: *PROGRAM_ID,
+            tree_account,
+            global_config_account,
+            lookup_table: *ALT_ADDRESS,
+            fee_recipient: *FEE_RECIPIENT,
+            deposit_recipient: *DEPOSIT_RECIPIENT,
+            partner_fee_wallet: self.partner_fee_wallet,
+            referrer: self.referrer().map(str::to_string),
+            token_mints: get_supported_tokens()
+                .into_iter()
+                .map(|token| (token.name, token.mint))
+                .collect(),
+        }
+    }
+
+    /// Allow deposits and withdrawals on mainnet
+    ///
+    /// Off by default (unless `ALLOW_MAINNET=true` is set) so that testing
+    /// against the default mainnet RPC can't move real funds by accident.
+    /// Production code should opt in explicitly.
+    pub fn set_allow_mainnet(&mut self, allow: bool) {
+        self.allow_mainnet = allow;
+    }
+
+    /// Refuse fund-moving operations on mainnet unless opted in
+    fn ensure_cluster_allowed(&self) -> Result<()> {
+        if self.cluster == Cluster::MainnetBeta && !self.allow_mainnet {
+            return Err(PrivacyCashError::InvalidInput(
+                "mainnet operations require explicit opt-in".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Estimate how long proof generation takes on this device
+    ///
+    /// Runs a single dummy proof the first time and stores the result, so
+    /// later calls (and later launches with the same cache directory) return
+    /// immediately. Nothing is submitted on-chain. The proof runs on a
+    /// blocking thread, and the client keeps the loaded zkey for later
+    /// estimates.
+    pub async fn estimate_proof_time(&self) -> Result<Duration> {
+        self.benchmark_prover.benchmark_cached(&self.storage).await
+    }
+
+    /// Fetch the relayer config, tree state and lookup table ahead of the
+    /// first operation
+    ///
+    /// Everything is fetched concurrently, the tree state once per supported
+    /// token, so the next deposit or withdrawal starts on an open relayer
+    /// connection with the config and lookup table cached; call it at app
+    /// launch or when idle. Cached values are kept (the lookup table until
+    /// `LOOKUP_TABLE_TTL` expires), so calling it again is cheap. The tree
+    /// state itself isn't cached: its next index changes with every
+    /// transaction, so each operation still reads it fresh.
+    pub async fn prewarm(&self) -> Result<()> {
+        let tree_states = get_supported_tokens().into_iter().map(|token| {
+            let token_name = (token.mint != *SOL_MINT).then_some(token.name);
+            query_remote_tree_state(token_name)
+        });
+        let (config, tree_states, lookup_table) = tokio::join!(
+            self.get_config(),
+            futures::future::try_join_all(tree_states),
+            get_lookup_table(&self.connection)
+        );
+
+        config?;
+        tree_states?;
+        lookup_table?;
+        Ok(())
+    }
+
+    /// Wait for in-flight deposits and withdrawals before the app exits
+    ///
+    /// From now on the client refuses new deposits and withdrawals with
+    /// `ShuttingDown`. Operations already running get up to `timeout` to
+    /// finish; those still running then are saved to storage and returned,
+    /// and [`interrupted_operations`](Self::interrupted_operations) lists them
+    /// on the next launch.
+    ///
+    /// A transaction that was already submitted can't be rolled back: an
+    /// interrupted operation may still land on-chain after the process exits.
+    /// Refresh the balance or history on the next launch to find out.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let interrupted = client.shutdown(std::time::Duration::from_secs(30)).await?;
+    /// if !interrupted.is_empty() {
+    ///     eprintln!("{} operations didn't finish; check them on the next launch", interrupted.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn shutdown(&self, timeout: Duration) -> Result<Vec<PendingOperation>> {
+        self.in_flight.shut_down();
+        if self.in_flight.wait_idle(timeout).await {
+            return Ok(Vec::new());
+        }
+
+        let pending = self.in_flight.pending();
+        log::warn!("Shutting down with {} operations in flight", pending.len());
+        save_interrupted(&self.storage, &self.interrupted_operations_key(), &pending)?;
+        Ok(pending)
+    }
+
+    /// Whether [`shutdown`](Self::shutdown) was called
+    pub fn is_shutting_down(&self) -> bool {
+        self.in_flight.is_shutting_down()
+    }
+
+    /// Deposits and withdrawals this client is running now
+    pub fn in_flight_operations(&self) -> Vec<PendingOperation> {
+        self.in_flight.pending()
+    }
+
+    /// Operations a [`shutdown`](Self::shutdown) interrupted, oldest first
+    ///
+    /// They stay listed until
+    /// [`clear_interrupted_operations`](Self::clear_interrupted_operations).
+    pub fn interrupted_operations(&self) -> Vec<PendingOperation> {
+        load_interrupted(&self.storage, &self.interrupted_operations_key())
+    }
+
+    /// Forget interrupted operations once they've been checked
+    pub fn clear_interrupted_operations(&self) {
+        self.storage.remove(&self.interrupted_operations_key());
+    }
+
+    fn interrupted_operations_key(&self) -> String {
+        format!("{}{}", LSK_INTERRUPTED_OPERATIONS, localstorage_key(&self.keypair.pubkey()))
+    }
+
+    /// Re-fetch the address lookup table, replacing the cached copy
+    ///
+    /// The table is otherwise reused until `LOOKUP_TABLE_TTL` expires; call
+    /// this after it's extended so the next transaction can use the new
+    /// entries. Returns the number of addresses in the table.
+    pub async fn refresh_alt(&self) -> Result<usize> {
+        let table = refresh_lookup_table(&self.connection).await?;
+        Ok(table.addresses.len())
+    }
+
+    /// Check that this environment can make private transfers
+    ///
+    /// Runs non-destructive checks and reports each one instead of stopping
+    /// at the first failure: RPC reachable and on the expected cluster,
+    /// program and lookup table present, relayer `/config` reachable,
+    /// circuit files present and loadable, and a dummy proof that generates
+    /// and verifies. The proof check takes as long as a real proof.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) {
+    /// let report = client.self_test().await;
+    /// print!("{}", report);
+    /// if !report.passed() {
+    ///     std::process::exit(1);
+    /// }
+    /// # }
+    /// ```
+    pub async fn self_test(&self) -> SelfTestReport {
+        let mut report = SelfTestReport::default();
+
+        if report.run("rpc", self_test::check_rpc(&self.connection, self.cluster)).await {
+            report
+                .run("program", self_test::check_program_accounts(&self.connection))
+                .await;
+        } else {
+            report.skip("program", "RPC unreachable");
+        }
+        report.run("relayer", self_test::check_relayer()).await;
+
+        let prover = RustProver::new(&self.circuit_path);
+        if report
+            .run("circuit_files", self_test::check_circuit_files(&prover, &self.circuit_path))
+            .await
+        {
+            report.run("proof", self_test::check_proof(&prover)).await;
+        } else {
+            report.skip("proof", "circuit files unavailable");
+        }
+        report
+    }
+}
+
+/// Make the withdrawals `plan_withdraw_all` planned, one after another
+///
+/// Stops at the first failure. If nothing went through yet its error is
+/// returned as is, otherwise wrapped in `WithdrawAllIncomplete` along with
+/// the signatures and amount (from `receipt`) of the withdrawals made.
+pub(crate) async fn withdraw_batches<T, F>(
+    batches: Vec<Vec<Utxo>>,
+    mut withdraw: impl FnMut(Vec<Utxo>) -> F,
+    receipt: impl Fn(&T) -> (&String, u64),
+) -> Result<Vec<T>>
+where
+    F: std::future::Future<Output = Result<T>>,
+{
+    let transactions = batches.len();
+    let mut results = Vec::with_capacity(transactions);
+    for batch in batches {
+        match withdraw(batch).await {
+            Ok(result) => results.push(result),
+            Err(e) if results.is_empty() => return Err(e),
+            Err(e) => {
+                log::warn!("Stopping after {} of {} withdrawals: {}", results.len(), transactions, e);
+                let signatures = results.iter().map(|r| receipt(r).0.clone()).collect();
+                let withdrawn = results.iter().map(|r| receipt(r).1).sum();
+                return Err(PrivacyCashError::WithdrawAllIncomplete {
+                    signatures,
+                    withdrawn,
+                    transactions,
+                    error: Box::new(e),
+                });
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Fail unless the relayer can prove every note in `batches`
+///
+/// One concurrent round of proof requests before a multi-transaction
+/// withdrawal or migration, so a note the relayer hasn't indexed stops it
+/// before the first transaction rather than halfway. Each transaction still
+/// fetches fresh proofs: they change as its predecessors append leaves.
+async fn ensure_notes_provable(batches: &[Vec<Utxo>], token_name: Option<&str>) -> Result<()> {
+    let commitments = batches
+        .iter()
+        .flatten()
+        .map(Utxo::get_commitment)
+        .collect::<Result<Vec<_>>>()?;
+    fetch_merkle_proofs(&commitments, token_name).await?;
+    Ok(())
+}
+
+/// Split notes into the withdrawals `withdraw_all` makes, two notes each
+///
+/// Notes are paired largest first. Stops at the first pair that wouldn't
+/// cover its fee or would deliver less than `minimum` base units (later
+/// pairs are no larger), and fails if that's already the first pair.
+pub(crate) fn plan_withdraw_all(
+    mut notes: Vec<Utxo>,
+    fee_rate: f64,
+    rent_fee: f64,
+    units_per_token: u64,
+    minimum: u64,
+    rounding: FeeRounding,
+) -> Result<Vec<Vec<Utxo>>> {
+    if notes.is_empty() {
+        return Err(PrivacyCashError::InsufficientBalance { need: 1, have: 0 });
+    }
+    notes.sort_by(|a, b| b.amount.cmp(&a.amount));
+
+    let mut batches = Vec::new();
+    for pair in notes.chunks(2) {
+        let total = notes_total(pair)?;
+        let fee = calculate_withdraw_fee_rounded(total, fee_rate, rent_fee, units_per_token, rounding)?;
+        let usable = ensure_balance_covers_fee(total, fee).and_then(|_| {
+            if total - fee < minimum {
+                return Err(PrivacyCashError::WithdrawalAmountTooLow { minimum });
+            }
+            Ok(())
+        });
+
+        if let Err(e) = usable {
+            if batches.is_empty() {
+                return Err(e);
+            }
+            log::info!("Leaving {} notes too small to withdraw", notes.len() - 2 * batches.len());
+            break;
+        }
+        batches.push(pair.to_vec());
+    }
+    Ok(batches)
+}
+
+/// Amounts, fees and duration of the withdrawals in `batches`
+///
+/// `fees` gives the (protocol, partner) fees of a withdrawal of an amount.
+/// `token` and `notes_left` are left for the caller to fill in.
+pub(crate) fn price_withdrawal_plan(
+    batches: &[Vec<Utxo>],
+    per_transaction: Duration,
+    fees: impl Fn(u64) -> Result<(u64, u64)>,
+) -> Result<WithdrawalPlan> {
+    let mut plan = WithdrawalPlan {
+        token: String::new(),
+        transactions: batches.len(),
+        amount: 0,
+        protocol_fee: 0,
+        partner_fee: 0,
+        amount_received: 0,
+        notes_left: 0,
+        estimated_duration: per_transaction * batches.len() as u32,
+    };
+    for batch in batches {
+        let amount = notes_total(batch)?;
+        let (protocol_fee, partner_fee) = fees(amount)?;
+        plan.amount = checked_total(plan.amount, amount)?;
+        plan.protocol_fee = checked_total(plan.protocol_fee, protocol_fee)?;
+        plan.partner_fee = checked_total(plan.partner_fee, partner_fee)?;
+        plan.amount_received = checked_total(plan.amount_received, amount.saturating_sub(protocol_fee))?;
+    }
+    Ok(plan)
+}
+
+/// The relayer's minimum withdrawal for a token in base units (0 if unset)
+fn minimum_withdrawal_units(config: &Config, token_name: &str, units_per_token: u64) -> u64 {
+    config
+        .minimum_withdrawal_for(token_name)
+        .map_or(0, |minimum| (minimum * units_per_token as f64) as u64)
+}
+
+/// Name of the token with this mint, or the mint address if it's unknown
+fn token_label(mint_address: &Pubkey) -> String {
+    find_token_by_mint(mint_address).map_or_else(|| mint_address.to_string(), |t| t.name.to_string())
+}
+
+/// Warn at setup time about a circuit path that can't prove, instead of
+/// failing the first deposit or withdrawal halfway through
+fn warn_if_circuit_missing(circuit_path: &str) {
+    if let Err(e) = ensure_circuit_files(circuit_path) {
+        log::warn!("{}; deposits and withdrawals will fail until the circuit path is fixed", e);
+    }
+}
+
+/// Total value of notes to withdraw, which must share a mint and all fit
+/// in one proof
+fn notes_total(notes: &[Utxo]) -> Result<u64> {
+    if notes.is_empty() {
+        return Err(PrivacyCashError::NoUtxosAvailable);
+    }
+    CircuitSpec::default().ensure_input_count(notes.len())?;
+    if notes.iter().any(|n| n.mint_address != notes[0].mint_address) {
+        return Err(PrivacyCashError::InvalidInput(
+            "Notes to withdraw must all have the same mint".to_string(),
+        ));
+    }
+    notes.iter().try_fold(0u64, |total, note| {
+        total
+            .checked_add(note.amount_u64())
+            .ok_or_else(|| PrivacyCashError::InvalidInput("Note total overflows u64".to_string()))
+    })
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::testing::{install_thread_mocks, test_config, test_mock};
+
+    #[tokio::test]
+    async fn test_client_fees_follow_the_submitting_relayer() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(client.pubkey(), 0.0).unwrap();
+
+        // Two relayers with different schedules: fees follow whichever one
+        // the client submits to
+        let cheap = "https://cheap-relayer.example";
+        let pricey = "https://pricey-relayer.example/";
+        let mut config = test_config();
+        config.withdraw_fee_rate = 0.002;
+        Config::set_cached_for(cheap, config);
+        let mut config = test_config();
+        config.withdraw_fee_rate = 0.01;
+        Config::set_cached_for(pricey, config);
+
+        let (pc_fee, _, _) = client.estimate_withdraw_fees(1_000_000_000).await.unwrap();
+        assert_eq!(pc_fee, 3_500_000 + 6_000_000);
+
+        client.set_relayer_url(Some(cheap));
+        assert_eq!(client.relayer_url(), cheap);
+        let (pc_fee, _, _) = client.estimate_withdraw_fees(1_000_000_000).await.unwrap();
+        assert_eq!(pc_fee, 2_000_000 + 6_000_000);
+
+        client.set_relayer_url(Some(pricey));
+        assert_eq!(client.relayer_url(), pricey.trim_end_matches('/'));
+        let (pc_fee, _, _) = client.estimate_withdraw_fees(1_000_000_000).await.unwrap();
+        assert_eq!(pc_fee, 10_000_000 + 6_000_000);
+        assert_eq!(client.addresses().relayer_url, pricey.trim_end_matches('/'));
+
+        client.set_relayer_url(None);
+        let (pc_fee, _, _) = client.estimate_withdraw_fees(1_000_000_000).await.unwrap();
+        assert_eq!(pc_fee, 3_500_000 + 6_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_send_cost_sol() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(client.pubkey(), 0.01).unwrap();
+
+        let recipient = Keypair::new().pubkey();
+        let cost = client
+            .estimate_send_cost(1_000_000_000, "sol", &recipient)
+            .await
+            .unwrap();
+        assert_eq!(cost.protocol_fee, 3_500_000 + 6_000_000);
+        assert_eq!(cost.partner_fee, 10_000_000);
+        assert_eq!(cost.amount_received, 1_000_000_000 - cost.protocol_fee);
+        assert_eq!(cost.total_fees(), cost.protocol_fee + cost.partner_fee);
+        assert_eq!(cost.ata_rent_lamports, 0);
+        assert!(cost.recipient_token_account_exists.is_none());
+        assert!(cost.network_cost_lamports() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_commitment_indexed() {
+        let relayer = test_mock()
+            .respond("GET", "/utxos/check/abcd", 200, serde_json::json!({ "exists": true }))
+            .respond("GET", "/utxos/check/ef01", 200, serde_json::json!({ "exists": false }));
+        let client = PrivacyCash::for_testing(Keypair::new(), relayer, test_config());
+
+        assert!(client.commitment_indexed(&[0xab, 0xcd], None).await.unwrap());
+        assert!(client.commitment_indexed(&[0xab, 0xcd], Some("sol")).await.unwrap());
+        assert!(!client.commitment_indexed(&[0xef, 0x01], Some("USDC")).await.unwrap());
+        assert!(client.commitment_indexed(&[0xef, 0x01], Some("doge")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_balance_usd() {
+        let mut config = test_config();
+        config.prices = HashMap::from([("sol".to_string(), 150.0), ("usdc".to_string(), 1.0)]);
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), config);
+
+        // The mock relayer serves no decryptable notes, so priced balances are 0
+        assert_eq!(client.get_balance_usd("SOL").await.unwrap(), Some(0.0));
+        assert_eq!(client.get_balance_usd("usdc").await.unwrap(), Some(0.0));
+        // No price listed: no value rather than an error
+        assert_eq!(client.get_balance_usd("zec").await.unwrap(), None);
+        assert!(client.get_balance_usd("doge").await.is_err());
+        assert_eq!(client.get_total_usd().await.unwrap(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_scan_cursor() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+
+        // Neither output decrypts for this wallet, but both were scanned
+        assert!(client.list_utxos().await.unwrap().is_empty());
+        assert_eq!(client.scan_cursor(None).unwrap(), 2);
+        assert_eq!(client.scan_cursor(Some(&crate::USDC_MINT)).unwrap(), 0);
+
+        client.reset_scan_cursor(None).unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+        assert!(client.scan_cursor(Some(&Keypair::new().pubkey())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_utxos_without_spent_check() {
+        use crate::ScanOptions;
+
+        use crate::constants::LSK_ENCRYPTED_OUTPUTS;
+        use crate::get_utxos::localstorage_key;
+
+        let keypair = Keypair::new();
+        let cache_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, localstorage_key(&keypair.pubkey()));
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-unchecked-{}", keypair.pubkey()));
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        let client = PrivacyCash::with_rpc_client(connection, keypair, Some(cache_dir.clone()), None).unwrap();
+        install_thread_mocks(test_mock(), test_config());
+
+        let options = ScanOptions { check_spent: false };
+        let (utxos, diagnostics) = client.scan_utxos(None, options).await.unwrap();
+        assert!(utxos.is_empty());
+        assert_eq!(diagnostics.spent_notes, 0);
+        assert!(client.scan_utxos(Some(&Keypair::new().pubkey()), options).await.is_err());
+
+        // Unchecked notes stay out of the cache and the range is scanned again
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+        assert!(!cache_dir.join(&cache_key).exists());
+
+        client.scan_utxos(None, ScanOptions::default()).await.unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 2);
+        assert!(cache_dir.join(&cache_key).exists());
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_list_utxos_from_leaves_cursor() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.reset_scan_cursor(None).unwrap();
+
+        assert!(client.list_utxos_from(None, 0).await.unwrap().is_empty());
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+        assert!(client
+            .list_utxos_from(Some(&Keypair::new().pubkey()), 0)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_near_the_end_of_the_index_range() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        for token in [None, Some(&crate::USDC_MINT)] {
+            assert!(client.list_utxos_from(token, u64::MAX - 1).await.unwrap().is_empty());
+        }
+
+        // The cursor stops at the end of the range instead of overflowing
+        let cursor_key = format!("{}{}", LSK_FETCH_OFFSET, client.scan_storage_key(None).unwrap());
+        client.storage.set(&cursor_key, &(u64::MAX - 1).to_string());
+        assert!(client.list_utxos().await.unwrap().is_empty());
+        assert_eq!(client.scan_cursor(None).unwrap(), u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_notes_rejects_bad_inputs() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        assert!(matches!(
+            client.withdraw_notes(Vec::new(), None).await,
+            Err(PrivacyCashError::NoUtxosAvailable)
+        ));
+
+        let keypair = ZkKeypair::generate().unwrap();
+        // Only two notes fit in one proof, so a third isn't silently dropped
+        let three = (0..3)
+            .map(|i| Utxo::new(1_000u64, keypair.clone(), i, None, None))
+            .collect();
+        assert!(matches!(
+            client.withdraw_notes(three, None).await,
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+
+        let mixed = vec![
+            Utxo::new(1_000u64, keypair.clone(), 0, None, None),
+            Utxo::new(1_000u64, keypair, 1, Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), None),
+        ];
+        assert!(matches!(
+            client.withdraw_notes(mixed, None).await,
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_withdraw_fees_spl_scales_by_decimals() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(client.pubkey(), 0.0).unwrap();
+
+        // ZEC has 8 decimals: 1 ZEC = 10^8 base units
+        let (pc_fee, _, _) = client.estimate_withdraw_fees_spl(100_000_000, "zec").await.unwrap();
+        assert_eq!(pc_fee, 350_000 + 100_000);
+
+        // ORE has 11 decimals: 1 ORE = 10^11 base units
+        let (pc_fee, _, _) = client.estimate_withdraw_fees_spl(100_000_000_000, "ore").await.unwrap();
+        assert_eq!(pc_fee, 350_000_000 + 1_000_000_000);
+
+        assert!(client.estimate_withdraw_fees_spl(1_000, "doge").await.is_err());
+        // Known token but no rent fee configured
+        assert!(client.estimate_withdraw_fees_spl(1_000, "usdt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_with_keypair_copies_settings() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        let fee_wallet = Keypair::new().pubkey();
+        client.set_partner_fee(fee_wallet, 0.02).unwrap();
+        client.set_referrer(Some("referrer"));
+        client.set_verify_deposit_balance(true);
+        client.set_fee_rounding(FeeRounding::Ceil);
+        client.set_submit_mode(crate::SubmitMode::Direct);
+        let abort = std::sync::Arc::new(tokio::sync::Mutex::new(false));
+        client.set_abort_signal(Some(std::sync::Arc::clone(&abort)));
+
+        let other = client.with_keypair(Keypair::new());
+        assert_ne!(other.pubkey(), client.pubkey());
+        assert_eq!(other.partner_fee_wallet(), fee_wallet);
+        assert_eq!(other.partner_fee_rate(), 0.02);
+        assert_eq!(other.referrer(), Some("referrer"));
+        assert!(other.verifies_deposit_balance());
+        assert_eq!(other.fee_rounding(), FeeRounding::Ceil);
+        assert_eq!(other.submit_mode(), crate::SubmitMode::Direct);
+        // Aborting one aborts both
+        assert!(std::sync::Arc::ptr_eq(other.abort_signal().unwrap(), &abort));
+    }
+
+    #[tokio::test]
+    async fn test_zk_keypair_matches_encryption_service() {
+        let keypair = Keypair::new();
+        let mut service = crate::encryption::EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&keypair);
+        let client = PrivacyCash::for_testing(keypair, test_mock(), test_config());
+
+        let v1 = client.zk_keypair(UtxoVersion::V1).unwrap();
+        let v2 = client.zk_keypair(UtxoVersion::V2).unwrap();
+        assert_ne!(v1.pubkey(), v2.pubkey());
+
+        let expected = ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap();
+        assert_eq!(v2.pubkey(), expected.pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_next_withdrawal_nullifiers_without_notes() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        assert!(matches!(
+            client.next_withdrawal_nullifiers("sol").await,
+            Err(crate::PrivacyCashError::NoUtxosAvailable)
+        ));
+        assert!(client.next_withdrawal_nullifiers("doge").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notes_checked_for_proofs_up_front() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let indexed = Utxo::new(1_000u64, keypair.clone(), 0, None, None);
+        let unindexed = Utxo::new(2_000u64, keypair, 1, None, None);
+        let proof_path = format!("/merkle/proof/{}", indexed.get_commitment().unwrap());
+        install_thread_mocks(
+            test_mock().respond(
+                "GET",
+                &proof_path,
+                200,
+                serde_json::json!({ "pathElements": ["1"], "pathIndices": [0] }),
+            ),
+            test_config(),
+        );
+
+        assert!(ensure_notes_provable(&[vec![indexed.clone()]], None).await.is_ok());
+        // A later batch the relayer can't prove stops the whole plan
+        assert!(ensure_notes_provable(&[vec![indexed], vec![unindexed]], None).await.is_err());
+        assert!(ensure_notes_provable(&[], None).await.is_ok());
+    }
+
+    #[test]
+    fn test_plan_withdraw_all_splits_into_pairs() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let notes = |amounts: &[u64]| -> Vec<Utxo> {
+            amounts
+                .iter()
+                .enumerate()
+                .map(|(i, &a)| Utxo::new(a, keypair.clone(), i as u64, None, None))
+                .collect()
+        };
+        let sol = 1_000_000_000;
+
+        // Largest first, two per transaction; the dust note is left behind
+        let batches = plan_withdraw_all(
+            notes(&[100_000_000, sol, 1_000_000, 500_000_000, 200_000_000]),
+            0.0035, 0.006, sol, 10_000_000, FeeRounding::Floor,
+        )
+        .unwrap();
+        let amounts: Vec<Vec<u64>> = batches
+            .iter()
+            .map(|b| b.iter().map(|u| u.amount_u64()).collect())
+            .collect();
+        assert_eq!(amounts, vec![vec![sol, 500_000_000], vec![200_000_000, 100_000_000]]);
+
+        // Below the relayer minimum after fees
+        assert!(matches!(
+            plan_withdraw_all(notes(&[20_000_000]), 0.0035, 0.006, sol, 50_000_000, FeeRounding::Floor),
+            Err(PrivacyCashError::WithdrawalAmountTooLow { .. })
+        ));
+        assert!(matches!(
+            plan_withdraw_all(notes(&[1_000_000]), 0.0035, 0.006, sol, 0, FeeRounding::Floor),
+            Err(PrivacyCashError::BalanceTooSmallForFees { .. })
+        ));
+        assert!(plan_withdraw_all(Vec::new(), 0.0035, 0.006, sol, 0, FeeRounding::Floor).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_batches_reports_partial_failure() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let batches = || -> Vec<Vec<Utxo>> {
+            (0..3)
+                .map(|i| vec![Utxo::new(100 + i, keypair.clone(), i, None, None)])
+                .collect()
+        };
+        // Withdraws a batch's amount under its index, failing from `fail_at` on
+        let withdraw = |fail_at: u64| {
+            move |batch: Vec<Utxo>| async move {
+                let index = batch[0].index;
+                if index >= fail_at {
+                    return Err(PrivacyCashError::Aborted);
+                }
+                Ok((index.to_string(), batch[0].amount_u64()))
+            }
+        };
+        fn receipt(r: &(String, u64)) -> (&String, u64) {
+            (&r.0, r.1)
+        }
+
+        assert_eq!(withdraw_batches(batches(), withdraw(3), receipt).await.unwrap().len(), 3);
+        // Nothing went through: the error is passed on as is
+        assert!(matches!(
+            withdraw_batches(batches(), withdraw(0), receipt).await,
+            Err(PrivacyCashError::Aborted)
+        ));
+        match withdraw_batches(batches(), withdraw(2), receipt).await {
+            Err(PrivacyCashError::WithdrawAllIncomplete { signatures, withdrawn, transactions, error }) => {
+                assert_eq!(signatures, vec!["0".to_string(), "1".to_string()]);
+                assert_eq!(withdrawn, 201);
+                assert_eq!(transactions, 3);
+                assert!(matches!(*error, PrivacyCashError::Aborted));
+            }
+            other => panic!("expected WithdrawAllIncomplete, got {:?}", other.map(|r| r.len())),
+        }
+    }
+
+    #[test]
+    fn test_price_withdrawal_plan() {
+        use crate::utils::calculate_withdraw_fee;
+
+        let keypair = ZkKeypair::generate().unwrap();
+        let sol = 1_000_000_000;
+        let notes: Vec<Utxo> = [sol, 500_000_000, 200_000_000, 100_000_000, 1_000_000]
+            .iter()
+            .enumerate()
+            .map(|(i, &a)| Utxo::new(a, keypair.clone(), i as u64, None, None))
+            .collect();
+        let batches = plan_withdraw_all(notes, 0.0035, 0.006, sol, 10_000_000, FeeRounding::Floor).unwrap();
+
+        let plan = price_withdrawal_plan(&batches, Duration::from_secs(20), |amount| {
+            Ok((calculate_withdraw_fee(amount, 0.0035, 0.006, sol)?, amount / 100))
+        })
+        .unwrap();
+        assert_eq!(plan.transactions, 2);
+        assert_eq!(plan.amount, 1_800_000_000);
+        // 0.35% of each withdrawal plus 0.006 SOL rent per transaction
+        assert_eq!(plan.protocol_fee, 6_300_000 + 2 * 6_000_000);
+        assert_eq!(plan.partner_fee, 18_000_000);
+        assert_eq!(plan.total_fees(), plan.protocol_fee + plan.partner_fee);
+        assert_eq!(plan.amount_received, plan.amount - plan.protocol_fee);
+        assert_eq!(plan.estimated_duration, Duration::from_secs(40));
+    }
+
+    #[tokio::test]
+    async fn test_micro_withdrawal_fee_limits() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+        let other = Keypair::new().pubkey();
+
+        // A 4_000-lamport fee costs more to transfer than it's worth
+        client.set_partner_fee_dust_threshold(crate::SIGNATURE_FEE_LAMPORTS);
+        assert_eq!(client.partner_fee_for(400_000, &other).unwrap(), 0);
+        assert_eq!(client.partner_fee_for(1_000_000, &other).unwrap(), 10_000);
+
+        // 0.1 SOL pays 0.00635 SOL protocol fee plus 0.001 partner fee
+        assert!(client.set_max_fee_ratio(Some(1.5)).is_err());
+        client.set_max_fee_ratio(Some(0.05)).unwrap();
+        assert!(matches!(
+            client.withdraw(100_000_000, Some(&other)).await,
+            Err(PrivacyCashError::WithdrawalUneconomical { amount: 100_000_000, .. })
+        ));
+        assert_eq!(client.with_keypair(Keypair::new()).max_fee_ratio(), Some(0.05));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_total_fee() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+        let other = Keypair::new().pubkey();
+
+        // 0.1 SOL: 6_350_000 protocol + 1_000_000 partner + 5_000 signature fee
+        let total = 7_355_000;
+        assert!(client.set_max_total_fee("doge", Some(total)).is_err());
+        client.set_max_total_fee("SOL", Some(total)).unwrap();
+        assert_eq!(client.max_total_fee("sol"), Some(total));
+
+        // At the cap the guard passes; the 50-lamport mock wallet can't pay the partner fee
+        assert!(matches!(
+            client.withdraw(100_000_000, Some(&other)).await,
+            Err(PrivacyCashError::InsufficientSolForPartnerFee { .. })
+        ));
+
+        client.set_max_total_fee("sol", Some(total - 1)).unwrap();
+        assert!(matches!(
+            client.withdraw(100_000_000, Some(&other)).await,
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+        // A referrer doesn't get around the partner fee or its limits
+        assert!(matches!(
+            client.withdraw_with_referrer(100_000_000, Some(&other), "referrer").await,
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+        assert_eq!(client.with_keypair(Keypair::new()).max_total_fee("sol"), Some(total - 1));
+
+        client.set_max_total_fee("sol", None).unwrap();
+        assert_eq!(client.max_total_fee("sol"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_operation_sink_records_failures() {
+        use parking_lot::Mutex;
+
+        let keypair = Keypair::new();
+        let wallet = keypair.pubkey();
+        let mut client = PrivacyCash::for_testing(keypair, test_mock(), test_config());
+        client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+        let records: Arc<Mutex<Vec<OperationRecord>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        client.set_operation_sink(move |record| sink.lock().push(record.clone()));
+
+        // The 50-lamport mock wallet can't pay the partner fee
+        assert!(client.withdraw(100_000_000, None).await.is_err());
+
+        let records = records.lock();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].operation, Operation::Withdraw);
+        assert_eq!(records[0].wallet, wallet.to_string());
+        assert_eq!(records[0].token, "sol");
+        assert_eq!(records[0].amount, 100_000_000);
+        assert!(!records[0].succeeded());
+        assert!(records[0].signatures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_cache_migrates_entries() {
+        use crate::constants::LSK_FETCH_OFFSET;
+        use crate::get_utxos::localstorage_key;
+
+        let keypair = Keypair::new();
+        let key = format!("{}{}", LSK_FETCH_OFFSET, localstorage_key(&keypair.pubkey()));
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-encrypt-{}", keypair.pubkey()));
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        let mut client =
+            PrivacyCash::with_rpc_client(connection, keypair, Some(cache_dir.clone()), None).unwrap();
+        install_thread_mocks(test_mock(), test_config());
+
+        client.list_utxos().await.unwrap();
+        let cursor = client.scan_cursor(None).unwrap();
+        assert_eq!(std::fs::read_to_string(cache_dir.join(&key)).unwrap(), cursor.to_string());
+
+        // Existing entries are rewritten encrypted and still read back
+        client.set_encrypt_cache(true).unwrap();
+        assert!(client.encrypts_cache());
+        assert_eq!(client.scan_cursor(None).unwrap(), cursor);
+        let on_disk = std::fs::read_to_string(cache_dir.join(&key)).unwrap();
+        assert!(on_disk.starts_with("enc1:"));
+        assert!(client.with_keypair(Keypair::new()).encrypts_cache());
+
+        // And back to plaintext
+        client.set_encrypt_cache(false).unwrap();
+        assert_eq!(std::fs::read_to_string(cache_dir.join(&key)).unwrap(), cursor.to_string());
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[test]
+    fn test_address_book() {
+        use crate::constants::{FEE_RECIPIENT, PROGRAM_ID, USDC_MINT};
+
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        let fee_wallet = Keypair::new().pubkey();
+        client.set_partner_fee(fee_wallet, 0.01).unwrap();
+        client.set_referrer(Some("my-referrer"));
+
+        let addresses = client.addresses();
+        assert_eq!(addresses.program_id, *PROGRAM_ID);
+        assert_eq!(addresses.fee_recipient, *FEE_RECIPIENT);
+        assert_eq!(addresses.tree_account, crate::utils::get_program_accounts().0);
+        // Client settings win over the environment defaults
+        assert_eq!(addresses.partner_fee_wallet, fee_wallet);
+        assert_eq!(addresses.referrer.as_deref(), Some("my-referrer"));
+        assert!(addresses.token_mints.contains(&("usdc", *USDC_MINT)));
+    }
+
+    #[test]
+    fn test_validate_network() {
+        use solana_client::rpc_request::RpcRequest;
+
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-network-{}", std::process::id()));
+        let client = |accounts: serde_json::Value| {
+            let mut mocks = HashMap::new();
+            mocks.insert(
+                RpcRequest::GetMultipleAccounts,
+                serde_json::json!({ "context": { "slot": 1 }, "value": accounts }),
+            );
+            let connection = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+            PrivacyCash::with_rpc_client(connection, Keypair::new(), Some(cache_dir.clone()), None).unwrap()
+        };
+        let account = |owner: &str, executable: bool| {
+            serde_json::json!({
+                "data": ["", "base64"],
+                "lamports": 1_000_000,
+                "owner": owner,
+                "executable": executable,
+                "rentEpoch": 0,
+                "space": 0,
+            })
+        };
+
+        let deployed = serde_json::json!([
+            account("BPFLoaderUpgradeab1e11111111111111111111111", true),
+            account("AddressLookupTab1e1111111111111111111111111", false),
+        ]);
+        assert!(client(deployed).validate_network().is_ok());
+
+        // An RPC on another network has neither account
+        let mismatch = client(serde_json::json!([null, null])).validate_network();
+        assert!(matches!(mismatch, Err(PrivacyCashError::ConfigError(m)) if m.contains("different network")));
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_alt() {
+        use base64::Engine;
+        use solana_client::rpc_request::RpcRequest;
+
+        // Lookup table header followed by two addresses
+        let mut data = vec![0u8; 56];
+        data.extend_from_slice(Keypair::new().pubkey().as_ref());
+        data.extend_from_slice(Keypair::new().pubkey().as_ref());
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetAccountInfo,
+            serde_json::json!({
+                "context": { "slot": 1 },
+                "value": {
+                    "data": [base64::engine::general_purpose::STANDARD.encode(&data), "base64"],
+                    "lamports": 1_000_000,
+                    "owner": "AddressLookupTab1e1111111111111111111111111",
+                    "executable": false,
+                    "rentEpoch": 0,
+                    "space": data.len(),
+                },
+            }),
+        );
+        let connection = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let keypair = Keypair::new();
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-refresh-alt-{}", keypair.pubkey()));
+        let client = PrivacyCash::with_rpc_client(connection, keypair, Some(cache_dir.clone()), None).unwrap();
+
+        assert_eq!(client.refresh_alt().await.unwrap(), 2);
+        // The refreshed table is what the next operation gets
+        let table = crate::utils::get_lookup_table(client.connection()).await.unwrap();
+        assert_eq!(table.addresses.len(), 2);
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_partner_fee_needs_public_sol() {
+        let keypair = Keypair::new();
+        let own_wallet = keypair.pubkey();
+        let mut client = PrivacyCash::for_testing(keypair, test_mock(), test_config());
+        client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+        let other = Keypair::new().pubkey();
+
+        // The 50-lamport mock wallet can't pay the fee up front
+        match client.withdraw(100_000_000, Some(&other)).await {
+            Err(PrivacyCashError::InsufficientSolForPartnerFee {
+                have,
+                need,
+                fee,
+                network_fee,
+                rent_reserve,
+            }) => {
+                assert_eq!(have, 50);
+                assert_eq!(fee, 1_000_000);
+                assert_eq!(network_fee, crate::SIGNATURE_FEE_LAMPORTS);
+                assert_eq!(need, fee + network_fee + rent_reserve);
+            }
+            other => panic!("expected InsufficientSolForPartnerFee, got {:?}", other),
+        }
+
+        // Deducting only applies to withdrawals to the own wallet, which then
+        // get past the fee and fail at the (unmocked) tree state request
+        client.set_deduct_partner_fee(true);
+        assert!(client.deducts_partner_fee());
+        assert!(matches!(
+            client.withdraw(100_000_000, Some(&other)).await,
+            Err(PrivacyCashError::InsufficientSolForPartnerFee { .. })
+        ));
+        let result = client.withdraw(100_000_000, Some(&own_wallet)).await;
+        assert!(!matches!(result, Err(PrivacyCashError::InsufficientSolForPartnerFee { .. })));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_receive_addresses() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+
+        let address = client.derive_receive_address(9).unwrap();
+        assert_eq!(address.index, 9);
+        assert_eq!(client.derive_receive_address(9).unwrap(), address);
+        assert_ne!(client.derive_receive_address(10).unwrap().owner, address.owner);
+
+        assert!(client.receive_scan_indices().is_empty());
+        client.set_receive_scan_indices(0..3).unwrap();
+        assert_eq!(client.receive_scan_indices(), vec![0, 1, 2]);
+
+        // Another wallet scans the same indices of its own addresses
+        let other = client.with_keypair(Keypair::new());
+        assert_eq!(other.receive_scan_indices(), vec![0, 1, 2]);
+        assert_ne!(other.derive_receive_address(9).unwrap(), address);
+    }
+
+    #[test]
+    fn test_receive_scan_indices_merge_cursors() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        let storage_key = localstorage_key(&client.pubkey());
+        let offset_key = format!("{}{}", LSK_FETCH_OFFSET, storage_key);
+        let outputs_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+        client.storage.set(&offset_key, "100");
+        client.storage.set(&outputs_key, "[\"ab\"]");
+
+        // New indices were never scanned: back to the start, keeping the cache
+        client.set_receive_scan_indices([0, 1]).unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+        assert_eq!(client.storage.get(&outputs_key).as_deref(), Some("[\"ab\"]"));
+
+        // Dropping an index doesn't move the cursor, and re-adding it resumes
+        // where its scan stopped
+        client.storage.set(&offset_key, "150");
+        client.set_receive_scan_indices([0]).unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 150);
+        client.storage.set(&offset_key, "200");
+        client.set_receive_scan_indices([0, 1]).unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 150);
+
+        // Re-adding a known index while the others are further along
+        client.storage.set(&offset_key, "300");
+        client.set_receive_scan_indices([0, 1]).unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 300);
+        client.set_receive_scan_indices([0, 1, 2]).unwrap();
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_partner_fee_event() {
+        use parking_lot::Mutex;
+        use solana_client::rpc_request::RpcRequest;
+
+        // The default mock wallet holds 50 lamports, too little for the fee
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetBalance,
+            serde_json::json!({ "context": { "slot": 1 }, "value": 10_000_000_000u64 }),
+        );
+        let connection = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let keypair = Keypair::new();
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-fee-event-{}", keypair.pubkey()));
+        let mut client =
+            PrivacyCash::with_rpc_client(connection, keypair, Some(cache_dir.clone()), None).unwrap();
+        install_thread_mocks(test_mock(), test_config());
+
+        let fee_wallet = Keypair::new().pubkey();
+        client.set_allow_mainnet(true);
+        client.set_partner_fee(fee_wallet, 0.01).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        client.set_partner_fee_callback(move |event| sink.lock().push(event.clone()));
+
+        // The fee is collected before the withdrawal, which then fails at the
+        // (unmocked) tree state request
+        assert!(client.withdraw(1_000_000_000, None).await.is_err());
+
+        let events = events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].token, "sol");
+        assert_eq!(events[0].amount, 10_000_000);
+        assert_eq!(events[0].destination, fee_wallet);
+        assert!(!events[0].signature.is_empty());
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_uninitialized_spl_pool_collects_no_fee() {
+        use base64::Engine;
+        use parking_lot::Mutex;
+        use solana_client::rpc_request::RpcRequest;
+
+        // A valid USDC mint, but neither pool account exists
+        let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+        let mint = spl_token::state::Mint { decimals: 6, is_initialized: true, ..Default::default() };
+        spl_token::state::Mint::pack(mint, &mut mint_data).unwrap();
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::GetAccountInfo,
+            serde_json::json!({ "context": { "slot": 1 }, "value": {
+                "data": [base64::engine::general_purpose::STANDARD.encode(&mint_data), "base64"],
+                "executable": false,
+                "lamports": 1_000_000,
+                "owner": spl_token::id().to_string(),
+                "rentEpoch": 0,
+                "space": mint_data.len(),
+            } }),
+        );
+        mocks.insert(
+            RpcRequest::GetMultipleAccounts,
+            serde_json::json!({ "context": { "slot": 1 }, "value": [null, null] }),
+        );
+        let connection = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let keypair = Keypair::new();
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-spl-pool-{}", keypair.pubkey()));
+        let mut client =
+            PrivacyCash::with_rpc_client(connection, keypair, Some(cache_dir.clone()), None).unwrap();
+        install_thread_mocks(test_mock(), test_config());
+
+        client.set_allow_mainnet(true);
+        client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        client.set_partner_fee_callback(move |event| sink.lock().push(event.clone()));
+
+        let recipient = Keypair::new().pubkey();
+        let result = client.withdraw_spl(1_000_000, &USDC_MINT, Some(&recipient)).await;
+        assert!(matches!(result, Err(PrivacyCashError::TokenNotInitialized { .. })));
+        assert!(events.lock().is_empty());
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failed_partner_fee_transfer_fails_the_withdrawal() {
+        use solana_client::rpc_request::RpcRequest;
+
+        // Enough SOL for the fee, but no blockhash to transfer it with
+        let cache_dir = std::env::temp_dir().join(format!("privacy-cash-fee-failure-{}", std::process::id()));
+        let client = || {
+            let mut mocks = HashMap::new();
+            mocks.insert(
+                RpcRequest::GetBalance,
+                serde_json::json!({ "context": { "slot": 1 }, "value": 10_000_000_000u64 }),
+            );
+            mocks.insert(RpcRequest::GetLatestBlockhash, serde_json::json!("not a blockhash"));
+            let connection = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+            let mut client =
+                PrivacyCash::with_rpc_client(connection, Keypair::new(), Some(cache_dir.clone()), None)
+                    .unwrap();
+            client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+            client.set_deduct_partner_fee(true);
+            client
+        };
+        let landed = |recipient: &Pubkey| WithdrawResult {
+            signature: "withdrawal".to_string(),
+            recipient: recipient.to_string(),
+            amount_in_lamports: 1_000_000_000,
+            fee_in_lamports: 9_500_000,
+            is_partial: false,
+            expected_remaining_lamports: 0,
+            dry_run: None,
+            partner_fee_signature: None,
+            proof_source: crate::ProofSource::Rust,
+        };
+
+        // Deducted from a withdrawal to the own wallet: the withdrawal went
+        // through, so the error carries its signature
+        let client_own = client();
+        let own = client_own.pubkey();
+        let result = client_own
+            .with_sol_partner_fee(1_000_000_000, &own, &test_config(), async { Ok(landed(&own)) })
+            .await;
+        match result {
+            Err(PrivacyCashError::PartnerFeeNotCollected { signature, fee, .. }) => {
+                assert_eq!(signature, "withdrawal");
+                assert_eq!(fee, 10_000_000);
+            }
+            other => panic!("expected PartnerFeeNotCollected, got {:?}", other.map(|r| r.signature)),
+        }
+
+        // Paid from the public wallet for one to a third party, as in
+        // send_privately: the withdrawal never starts
+        let third_party = Keypair::new().pubkey();
+        let started = std::sync::atomic::AtomicBool::new(false);
+        let result = client()
+            .with_sol_partner_fee(1_000_000_000, &third_party, &test_config(), async {
+                started.store(true, std::sync::atomic::Ordering::SeqCst);
+                Ok(landed(&third_party))
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(!matches!(result, Err(PrivacyCashError::PartnerFeeNotCollected { .. })));
+        assert!(!started.load(std::sync::atomic::Ordering::SeqCst));
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_self_withdraw_fee_policy() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(Keypair::new().pubkey(), 0.01).unwrap();
+        let own = client.pubkey();
+        let other = Keypair::new().pubkey();
+
+        // Applied to self-withdrawals unless waived
+        assert!(!client.waive_self_withdraw_fee());
+        assert_eq!(client.partner_fee_for(1_000_000, &own).unwrap(), 10_000);
+
+        client.set_waive_self_withdraw_fee(true);
+        assert_eq!(client.partner_fee_for(1_000_000, &own).unwrap(), 0);
+        assert_eq!(client.partner_fee_for(1_000_000, &other).unwrap(), 10_000);
+        assert!(client.with_keypair(Keypair::new()).waive_self_withdraw_fee());
+
+        let cost = client
+            .estimate_send_cost(1_000_000_000, "sol", &own)
+            .await
+            .unwrap();
+        assert_eq!(cost.partner_fee, 0);
+        assert_eq!(cost.total_fees(), cost.protocol_fee);
+    }
+
+    #[tokio::test]
+    async fn test_shield_only_rejects_unknown_tokens() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        assert!(matches!(
+            client.shield_only(1_000_000, "doge").await,
+            Err(crate::PrivacyCashError::TokenNotSupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_deposit_below_minimum_rejected() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        assert_eq!(client.get_minimum_deposit("sol").await.unwrap(), 0.006 / (1.0 - 0.0035));
+        assert_eq!(client.get_minimum_deposit("ore").await.unwrap(), 0.01 / (1.0 - 0.0035));
+        // Rejected before touching the RPC node
+        assert!(matches!(
+            client.deposit(6_000_000).await,
+            Err(PrivacyCashError::DepositAmountTooLow { amount: 6_000_000, minimum: 6_021_074 })
+        ));
+
+        // A note of the minimum deposit can be withdrawn
+        let config = test_config();
+        let note = Utxo::new(6_021_074u64, ZkKeypair::generate().unwrap(), 0, None, None);
+        assert!(plan_withdraw_all(
+            vec![note],
+            config.withdraw_fee_rate,
+            config.withdraw_rent_fee,
+            LAMPORTS_PER_SOL,
+            minimum_withdrawal_units(&config, "sol", LAMPORTS_PER_SOL),
+            FeeRounding::Floor,
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_refuses_new_operations() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        assert!(!client.is_shutting_down());
+        assert!(client.shutdown(Duration::from_millis(10)).await.unwrap().is_empty());
+        assert!(client.is_shutting_down());
+        assert!(client.interrupted_operations().is_empty());
+
+        assert!(matches!(
+            client.deposit(10_000_000).await,
+            Err(crate::PrivacyCashError::ShuttingDown)
+        ));
+        assert!(client.in_flight_operations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_v1_migration() {
+        let client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.reset_scan_cursor(None).unwrap();
+        client.shutdown(Duration::from_millis(10)).await.unwrap();
+
+        // Refused before scanning for V1 notes
+        assert!(matches!(
+            client.migrate_v1_to_v2().await,
+            Err(crate::PrivacyCashError::ShuttingDown)
+        ));
+        assert_eq!(client.scan_cursor(None).unwrap(), 0);
+        assert!(client.in_flight_operations().is_empty());
+        assert!(client.interrupted_operations().is_empty());
     }
 }
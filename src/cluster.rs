@@ -0,0 +1,91 @@
+//! Solana cluster identification and explorer links
+
+use serde::{Deserialize, Serialize};
+
+/// Solana cluster an RPC endpoint belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cluster {
+    /// Mainnet beta
+    #[default]
+    MainnetBeta,
+    /// Devnet
+    Devnet,
+    /// Testnet
+    Testnet,
+    /// Local test validator
+    Localnet,
+}
+
+impl Cluster {
+    /// Guess the cluster from an RPC URL
+    ///
+    /// Anything not recognisably devnet, testnet or local is treated as
+    /// mainnet, since most third-party RPC providers don't name the cluster.
+    pub fn from_rpc_url(rpc_url: &str) -> Self {
+        let url = rpc_url.to_lowercase();
+        if url.contains("devnet") {
+            Cluster::Devnet
+        } else if url.contains("testnet") {
+            Cluster::Testnet
+        } else if url.contains("localhost") || url.contains("127.0.0.1") {
+            Cluster::Localnet
+        } else {
+            Cluster::MainnetBeta
+        }
+    }
+
+    /// Explorer URL for a transaction signature (Solscan)
+    pub fn explorer_tx_url(&self, signature: &str) -> String {
+        match self {
+            Cluster::MainnetBeta => format!("https://solscan.io/tx/{}", signature),
+            Cluster::Devnet => format!("https://solscan.io/tx/{}?cluster=devnet", signature),
+            Cluster::Testnet => format!("https://solscan.io/tx/{}?cluster=testnet", signature),
+            Cluster::Localnet => format!(
+                "https://explorer.solana.com/tx/{}?cluster=custom&customUrl=http%3A%2F%2Flocalhost%3A8899",
+                signature
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rpc_url() {
+        assert_eq!(
+            Cluster::from_rpc_url("https://api.mainnet-beta.solana.com"),
+            Cluster::MainnetBeta
+        );
+        assert_eq!(
+            Cluster::from_rpc_url("https://api.devnet.solana.com"),
+            Cluster::Devnet
+        );
+        assert_eq!(
+            Cluster::from_rpc_url("https://api.testnet.solana.com"),
+            Cluster::Testnet
+        );
+        assert_eq!(
+            Cluster::from_rpc_url("http://127.0.0.1:8899"),
+            Cluster::Localnet
+        );
+        assert_eq!(
+            Cluster::from_rpc_url("https://mainnet.helius-rpc.com/?api-key=x"),
+            Cluster::MainnetBeta
+        );
+    }
+
+    #[test]
+    fn test_explorer_tx_url() {
+        assert_eq!(
+            Cluster::MainnetBeta.explorer_tx_url("abc"),
+            "https://solscan.io/tx/abc"
+        );
+        assert_eq!(
+            Cluster::Devnet.explorer_tx_url("abc"),
+            "https://solscan.io/tx/abc?cluster=devnet"
+        );
+    }
+}
@@ -1,14 +1,69 @@
 //! Configuration fetching from the relayer API
 
-use crate::constants::RELAYER_API_URL;
+use crate::constants::{resolve_token, TokenInfo, RELAYER_API_URL};
 use crate::error::{PrivacyCashError, Result};
-use once_cell::sync::OnceCell;
-use parking_lot::RwLock;
+use crate::relayer;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
 
 /// Global cached configuration
-static CONFIG_CACHE: OnceCell<RwLock<Option<Config>>> = OnceCell::new();
+static CONFIG_CACHE: Lazy<ConfigCache> = Lazy::new(ConfigCache::new);
+
+/// Cached configurations of relayers other than `RELAYER_API_URL`
+static RELAYER_CONFIG_CACHES: Lazy<Mutex<HashMap<String, Arc<ConfigCache>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Cached config with single-flight fetching
+///
+/// The lock around the value is never held across an await. Callers that
+/// miss the cache queue on `fetch_lock`, so concurrent first callers share
+/// one relayer request instead of each sending their own.
+struct ConfigCache {
+    value: RwLock<Option<Config>>,
+    fetch_lock: tokio::sync::Mutex<()>,
+}
+
+impl ConfigCache {
+    fn new() -> Self {
+        Self {
+            value: RwLock::new(None),
+            fetch_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn cached(&self) -> Option<Config> {
+        self.value.read().clone()
+    }
+
+    fn set(&self, config: Option<Config>) {
+        *self.value.write() = config;
+    }
+
+    async fn get_or_fetch_with<F, Fut>(&self, fetch: F) -> Result<Config>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Config>>,
+    {
+        if let Some(config) = self.cached() {
+            return Ok(config);
+        }
+
+        let _guard = self.fetch_lock.lock().await;
+
+        // Another caller may have finished the fetch while we waited
+        if let Some(config) = self.cached() {
+            return Ok(config);
+        }
+
+        let config = fetch().await?;
+        self.set(Some(config.clone()));
+        Ok(config)
+    }
+}
 
 /// Configuration from the relayer API
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +89,10 @@ pub struct Config {
     #[serde(default)]
     pub minimum_withdrawal: HashMap<String, f64>,
 
+    /// Minimum deposit amounts per token, if the relayer publishes them
+    #[serde(default)]
+    pub minimum_deposit: HashMap<String, f64>,
+
     /// Token prices in USD
     #[serde(default)]
     pub prices: HashMap<String, f64>,
@@ -48,14 +107,43 @@ pub struct SupportedToken {
     pub price_usd: f64,
 }
 
+impl SupportedToken {
+    /// Static details (mint, decimals) for this token, if the SDK knows it
+    pub fn token_info(&self) -> Option<TokenInfo> {
+        resolve_token(&self.name).ok()
+    }
+
+    /// Mint address, if the SDK knows this token
+    pub fn mint(&self) -> Option<solana_sdk::pubkey::Pubkey> {
+        self.token_info().map(|t| t.mint)
+    }
+
+    /// Minimum withdrawal in base units, if the SDK knows this token's decimals
+    pub fn min_withdrawal_base_units(&self) -> Option<u64> {
+        self.token_info()
+            .map(|t| (self.min_withdrawal * t.units_per_token as f64).round() as u64)
+    }
+}
+
+impl TryFrom<&SupportedToken> for TokenInfo {
+    type Error = PrivacyCashError;
+
+    fn try_from(token: &SupportedToken) -> Result<Self> {
+        resolve_token(&token.name)
+    }
+}
+
 impl Config {
     /// Fetch configuration from the relayer API
     pub async fn fetch() -> Result<Self> {
-        let url = format!("{}/config", *RELAYER_API_URL);
+        Self::fetch_from(&RELAYER_API_URL).await
+    }
 
-        let response = reqwest::get(&url)
-            .await
-            .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch config: {}", e)))?;
+    /// Fetch configuration from the relayer at `relayer_url`
+    pub async fn fetch_from(relayer_url: &str) -> Result<Self> {
+        let url = format!("{}/config", relayer_url.trim_end_matches('/'));
+
+        let response = relayer::send(relayer::get(&url), "Failed to fetch config").await?;
 
         if !response.status().is_success() {
             return Err(PrivacyCashError::ApiError(format!(
@@ -64,42 +152,107 @@ impl Config {
             )));
         }
 
-        let config: Config = response
+        let mut config: Config = response
             .json()
             .await
             .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse config: {}", e)))?;
+        config.normalize();
+        for name in config.missing_maps() {
+            log::warn!("Relayer config has no {} entries; token lookups will fail", name);
+        }
 
         Ok(config)
     }
 
+    /// Rewrite the per-token map keys into the form lookups use
+    ///
+    /// Keys are matched like [`resolve_token`] matches names (case, `-`,
+    /// `_`, `$`, spaces and aliases don't matter) after dropping a namespace
+    /// prefix such as `spl:`. If two keys normalize to the same token, the
+    /// one already in canonical form wins. Done automatically by
+    /// [`fetch`](Self::fetch) and [`set_cached`](Self::set_cached).
+    pub fn normalize(&mut self) {
+        for map in [
+            &mut self.rent_fees,
+            &mut self.minimum_withdrawal,
+            &mut self.minimum_deposit,
+            &mut self.prices,
+        ] {
+            let mut normalized = HashMap::with_capacity(map.len());
+            for (raw, value) in map.drain() {
+                let key = token_key(raw.rsplit([':', '/']).next().unwrap_or(&raw));
+                if raw == key || !normalized.contains_key(&key) {
+                    normalized.insert(key, value);
+                } else {
+                    log::warn!("Ignoring duplicate relayer config key {}", raw);
+                }
+            }
+            *map = normalized;
+        }
+    }
+
+    /// Per-token maps that are required but came back empty
+    ///
+    /// The maps default to empty when the relayer leaves them out, which
+    /// would otherwise only surface later as "token not supported" errors.
+    pub fn missing_maps(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.minimum_withdrawal.is_empty() {
+            missing.push("minimum_withdrawal");
+        }
+        if self.rent_fees.is_empty() {
+            missing.push("rent_fees");
+        }
+        missing
+    }
+
     /// Get cached configuration or fetch if not cached
+    ///
+    /// Concurrent callers that miss the cache share a single fetch.
     pub async fn get_or_fetch() -> Result<Self> {
-        let cache = CONFIG_CACHE.get_or_init(|| RwLock::new(None));
-
-        // Try to read from cache first
-        {
-            let read_guard = cache.read();
-            if let Some(config) = read_guard.as_ref() {
-                return Ok(config.clone());
-            }
+        #[cfg(feature = "testing")]
+        if let Some(config) = crate::testing::thread_config() {
+            return Ok(config);
         }
+        CONFIG_CACHE.get_or_fetch_with(Self::fetch).await
+    }
 
-        // Fetch and cache
-        let config = Self::fetch().await?;
-        {
-            let mut write_guard = cache.write();
-            *write_guard = Some(config.clone());
+    /// Replace the cached configuration
+    ///
+    /// Later lookups use `config` instead of fetching from the relayer.
+    pub fn set_cached(mut config: Config) {
+        config.normalize();
+        CONFIG_CACHE.set(Some(config));
+    }
+
+    /// Cached configuration of the relayer at `relayer_url`, fetching it if needed
+    ///
+    /// Each relayer has its own cache; `RELAYER_API_URL` shares the one
+    /// [`get_or_fetch`](Self::get_or_fetch) uses.
+    pub async fn get_from(relayer_url: &str) -> Result<Self> {
+        let relayer_url = relayer_url.trim_end_matches('/');
+        if relayer_url == RELAYER_API_URL.trim_end_matches('/') {
+            return Self::get_or_fetch().await;
         }
+        relayer_cache(relayer_url)
+            .get_or_fetch_with(|| Self::fetch_from(relayer_url))
+            .await
+    }
 
-        Ok(config)
+    /// Replace the cached configuration of the relayer at `relayer_url`
+    pub fn set_cached_for(relayer_url: &str, mut config: Config) {
+        let relayer_url = relayer_url.trim_end_matches('/');
+        if relayer_url == RELAYER_API_URL.trim_end_matches('/') {
+            return Self::set_cached(config);
+        }
+        config.normalize();
+        relayer_cache(relayer_url).set(Some(config));
     }
 
-    /// Clear the cached configuration
+    /// Clear the cached configuration of every relayer
     pub fn clear_cache() {
-        if let Some(cache) = CONFIG_CACHE.get() {
-            let mut write_guard = cache.write();
-            *write_guard = None;
-        }
+        CONFIG_CACHE.set(None);
+        RELAYER_CONFIG_CACHES.lock().clear();
     }
 
     /// Get withdraw fee rate
@@ -122,10 +275,13 @@ impl Config {
 
     /// Get rent fee for a specific token
     pub async fn get_token_rent_fee(token_name: &str) -> Result<f64> {
-        let config = Self::get_or_fetch().await?;
-        config
-            .rent_fees
-            .get(token_name)
+        Self::get_or_fetch().await?.token_rent_fee(token_name)
+    }
+
+    /// Rent fee for a token in this config
+    pub fn token_rent_fee(&self, token_name: &str) -> Result<f64> {
+        self.rent_fees
+            .get(&token_key(token_name))
             .copied()
             .ok_or_else(|| PrivacyCashError::ConfigError(format!("No rent fee for {}", token_name)))
     }
@@ -139,46 +295,111 @@ impl Config {
     /// Check if a token is supported
     pub async fn is_token_supported(token_name: &str) -> Result<bool> {
         let config = Self::get_or_fetch().await?;
-        Ok(config.minimum_withdrawal.contains_key(&token_name.to_lowercase()))
+        Ok(config.minimum_withdrawal.contains_key(&token_key(token_name)))
     }
 
     /// Get minimum withdrawal for a token
     pub async fn get_minimum_withdrawal(token_name: &str) -> Result<f64> {
-        let config = Self::get_or_fetch().await?;
-        config
-            .minimum_withdrawal
-            .get(&token_name.to_lowercase())
+        Self::get_or_fetch().await?.minimum_withdrawal_for(token_name)
+    }
+
+    /// Minimum withdrawal for a token in this config
+    pub fn minimum_withdrawal_for(&self, token_name: &str) -> Result<f64> {
+        self.minimum_withdrawal
+            .get(&token_key(token_name))
             .copied()
             .ok_or_else(|| PrivacyCashError::ConfigError(format!("Token {} not supported", token_name)))
     }
 
+    /// Get minimum deposit for a token
+    pub async fn get_minimum_deposit(token_name: &str) -> Result<f64> {
+        Self::get_or_fetch().await?.minimum_deposit_for(token_name)
+    }
+
+    /// Minimum deposit for a token in this config, in whole tokens
+    ///
+    /// The relayer's `minimum_deposit` entry if it has one. Otherwise the
+    /// smallest deposit whose note can be withdrawn in full: after the
+    /// deposit fee, the withdraw fee rate and the token's rent fee
+    /// (`withdraw_rent_fee` for SOL), at least the minimum withdrawal is left.
+    pub fn minimum_deposit_for(&self, token_name: &str) -> Result<f64> {
+        let key = token_key(token_name);
+        if let Some(minimum) = self.minimum_deposit.get(&key) {
+            return Ok(*minimum);
+        }
+        let rent_fee = if key == "sol" {
+            self.withdraw_rent_fee
+        } else {
+            self.token_rent_fee(token_name)
+                .map_err(|_| PrivacyCashError::ConfigError(format!("No deposit minimum for {}", token_name)))?
+        };
+        let minimum_withdrawal = self.minimum_withdrawal.get(&key).copied().unwrap_or(0.0);
+        let kept = (1.0 - self.withdraw_fee_rate) * (1.0 - self.deposit_fee_rate);
+        if kept <= 0.0 {
+            return Err(PrivacyCashError::ConfigError(format!(
+                "Fee rates leave nothing of a {} deposit",
+                token_name
+            )));
+        }
+        Ok((rent_fee + minimum_withdrawal) / kept)
+    }
+
+    /// Fail with `DepositAmountTooLow` if `amount` base units of a token is
+    /// below its minimum deposit
+    ///
+    /// Passes if the config has no minimum for the token.
+    pub fn ensure_minimum_deposit(&self, token_name: &str, units_per_token: u64, amount: u64) -> Result<()> {
+        let Ok(minimum) = self.minimum_deposit_for(token_name) else {
+            log::warn!("No deposit minimum for {}, not checking", token_name);
+            return Ok(());
+        };
+        let minimum = (minimum * units_per_token as f64).round() as u64;
+        if amount < minimum {
+            return Err(PrivacyCashError::DepositAmountTooLow { amount, minimum });
+        }
+        Ok(())
+    }
+
     /// Get all supported tokens with their details
     pub async fn get_supported_tokens() -> Result<Vec<SupportedToken>> {
         let config = Self::get_or_fetch().await?;
-        
-        let mut tokens = Vec::new();
-        for (name, min_withdrawal) in &config.minimum_withdrawal {
-            let rent_fee = config.rent_fees.get(name).copied().unwrap_or(0.0);
-            let price_usd = config.prices.get(name).copied().unwrap_or(0.0);
-            
-            tokens.push(SupportedToken {
-                name: name.clone(),
-                min_withdrawal: *min_withdrawal,
-                rent_fee,
-                price_usd,
-            });
-        }
-        
-        Ok(tokens)
+        Ok(config
+            .minimum_withdrawal
+            .keys()
+            .filter_map(|name| config.supported_token(name))
+            .collect())
+    }
+
+    /// Live details for a token from this config
+    ///
+    /// `token` may be a name, alias or mint address. `None` if the relayer
+    /// doesn't list the token; missing rent fees and prices are 0.
+    pub fn supported_token(&self, token: &str) -> Option<SupportedToken> {
+        let name = token_key(token);
+        let min_withdrawal = *self.minimum_withdrawal.get(&name)?;
+        Some(SupportedToken {
+            rent_fee: self.rent_fees.get(&name).copied().unwrap_or(0.0),
+            price_usd: self.prices.get(&name).copied().unwrap_or(0.0),
+            name,
+            min_withdrawal,
+        })
+    }
+
+    /// Live details for a token the SDK knows statically
+    pub fn token_details(&self, token: &TokenInfo) -> Option<SupportedToken> {
+        self.supported_token(token.name)
+    }
+
+    /// Price of a token in USD, if the relayer lists one
+    pub fn token_price(&self, token: &str) -> Option<f64> {
+        self.prices.get(&token_key(token)).copied()
     }
 
     /// Get token price in USD
     pub async fn get_token_price(token_name: &str) -> Result<f64> {
         let config = Self::get_or_fetch().await?;
         config
-            .prices
-            .get(&token_name.to_lowercase())
-            .copied()
+            .token_price(token_name)
             .ok_or_else(|| PrivacyCashError::ConfigError(format!("No price for {}", token_name)))
     }
 
@@ -187,3 +408,196 @@ impl Config {
         Self::get_or_fetch().await
     }
 }
+
+fn relayer_cache(relayer_url: &str) -> Arc<ConfigCache> {
+    RELAYER_CONFIG_CACHES
+        .lock()
+        .entry(relayer_url.to_string())
+        .or_insert_with(|| Arc::new(ConfigCache::new()))
+        .clone()
+}
+
+/// Config map key for a token name, alias or mint address
+///
+/// Tokens the SDK doesn't know yet fall back to their name normalized the
+/// same way, so tokens newly added to the relayer config still resolve.
+fn token_key(token: &str) -> String {
+    resolve_token(token).map(|t| t.name.to_string()).unwrap_or_else(|_| {
+        token
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | '$') && !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase()
+    })
+}
+
+/// Relayer config the crate's unit tests run against
+///
+/// Tests that need other values override fields on a copy.
+#[cfg(test)]
+pub(crate) fn test_config() -> Config {
+    serde_json::from_value(serde_json::json!({
+        "withdraw_fee_rate": 0.0035,
+        "withdraw_rent_fee": 0.006,
+        "deposit_fee_rate": 0.0,
+        "rent_fees": { "zec": 0.001, "ore": 0.01 },
+    }))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_first_callers_share_one_fetch() {
+        let cache = Arc::new(ConfigCache::new());
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let cache = cache.clone();
+                let fetches = fetches.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch_with(|| async move {
+                            fetches.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(test_config())
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            let config = handle.await.unwrap().unwrap();
+            assert_eq!(config.withdraw_fee_rate, 0.0035);
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_is_not_cached() {
+        let cache = ConfigCache::new();
+        let result = cache
+            .get_or_fetch_with(|| async { Err(PrivacyCashError::ApiError("down".into())) })
+            .await;
+        assert!(result.is_err());
+        assert!(cache.cached().is_none());
+
+        let config = cache.get_or_fetch_with(|| async { Ok(test_config()) }).await.unwrap();
+        assert_eq!(config.deposit_fee_rate, 0.0);
+    }
+
+    #[test]
+    fn test_normalize_realistic_payload() {
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "withdraw_fee_rate": 0.0035,
+            "withdraw_rent_fee": 0.006,
+            "deposit_fee_rate": 0,
+            "rent_fees": { "SOL": 0.006, "USDC": 0.85, "spl:usdt": 0.85, "$ZEC": 0.001, "ore": 0.01 },
+            "minimum_withdrawal": {
+                "sol": 0.01, "Usdc": 2, "USDT": 2, "zec": 0.0001, "ORE": 0.001, "NEW-TOKEN": 5
+            },
+            "prices": { "SOL": 150.0, "usd-coin": 0.99, "usdc": 1.0 },
+        }))
+        .unwrap();
+        assert!(config.missing_maps().is_empty());
+        config.normalize();
+
+        for token in ["sol", "usdc", "usdt", "zec", "ore", "new_token"] {
+            assert!(
+                config.minimum_withdrawal.contains_key(&token_key(token)),
+                "minimum withdrawal for {}",
+                token
+            );
+        }
+        for token in ["sol", "usdc", "usdt", "zec", "ore"] {
+            assert!(config.rent_fees.contains_key(&token_key(token)), "rent fee for {}", token);
+        }
+        assert_eq!(config.minimum_withdrawal[&token_key("USDC")], 2.0);
+        assert_eq!(config.rent_fees[&token_key("usdt")], 0.85);
+        // "usd-coin" is an alias of usdc; the canonical key wins
+        assert_eq!(config.prices.len(), 2);
+        assert_eq!(config.prices[&token_key("usdc")], 1.0);
+    }
+
+    #[test]
+    fn test_static_and_dynamic_token_views() {
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "withdraw_fee_rate": 0.0035,
+            "withdraw_rent_fee": 0.006,
+            "deposit_fee_rate": 0.0,
+            "minimum_withdrawal": { "usdc": 2.0, "newcoin": 1.0 },
+            "rent_fees": { "usdc": 0.85 },
+        }))
+        .unwrap();
+        config.normalize();
+
+        let usdc_info = resolve_token("usdc").unwrap();
+        let usdc = config.token_details(&usdc_info).unwrap();
+        assert_eq!(usdc.rent_fee, 0.85);
+        assert_eq!(usdc.price_usd, 0.0);
+        assert_eq!(usdc.mint(), Some(usdc_info.mint));
+        assert_eq!(usdc.min_withdrawal_base_units(), Some(2_000_000));
+        assert_eq!(TokenInfo::try_from(&usdc).unwrap().decimals(), 6);
+        assert_eq!(config.supported_token(&usdc_info.mint.to_string()).unwrap().name, "usdc");
+
+        // Listed by the relayer but unknown to the SDK
+        let newcoin = config.supported_token("newcoin").unwrap();
+        assert!(newcoin.mint().is_none());
+        assert!(TokenInfo::try_from(&newcoin).is_err());
+        assert!(config.token_details(&resolve_token("sol").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_minimum_deposit() {
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "withdraw_fee_rate": 0.0035,
+            "withdraw_rent_fee": 0.006,
+            "deposit_fee_rate": 0.0,
+            "rent_fees": { "zec": 0.001, "usdc": 0.85 },
+            "minimum_deposit": { "USDC": 1.0 },
+            "minimum_withdrawal": { "zec": 0.01 },
+        }))
+        .unwrap();
+        config.normalize();
+
+        assert_eq!(config.minimum_deposit_for("usdc").unwrap(), 1.0);
+        // Falls back to the smallest note that can be withdrawn: the rent
+        // fee plus the minimum withdrawal, grossed up by the withdraw fee rate
+        assert_eq!(config.minimum_deposit_for("sol").unwrap(), 0.006 / (1.0 - 0.0035));
+        assert_eq!(config.minimum_deposit_for("zec").unwrap(), (0.001 + 0.01) / (1.0 - 0.0035));
+        assert!(config.minimum_deposit_for("ore").is_err());
+
+        assert!(config.ensure_minimum_deposit("sol", 1_000_000_000, 6_021_074).is_ok());
+        assert!(matches!(
+            config.ensure_minimum_deposit("sol", 1_000_000_000, 6_021_073),
+            Err(PrivacyCashError::DepositAmountTooLow { amount: 6_021_073, minimum: 6_021_074 })
+        ));
+        // The minimum note pays for its withdrawal and still meets the
+        // minimum withdrawal
+        let fee = crate::utils::calculate_withdraw_fee(1_103_864, 0.0035, 0.001, 100_000_000).unwrap();
+        assert!(1_103_864 - fee >= 1_000_000);
+        assert!(config.ensure_minimum_deposit("zec", 100_000_000, 1_103_863).is_err());
+
+        // A deposit fee shrinks the note, so the minimum grows with it
+        config.deposit_fee_rate = 0.01;
+        assert_eq!(config.minimum_deposit_for("sol").unwrap(), 0.006 / ((1.0 - 0.0035) * (1.0 - 0.01)));
+        assert!(config.ensure_minimum_deposit("usdc", 1_000_000, 999_999).is_err());
+        // No minimum known, nothing to enforce
+        assert!(config.ensure_minimum_deposit("ore", 100_000_000_000, 1).is_ok());
+    }
+
+    #[test]
+    fn test_missing_maps() {
+        let mut config = test_config();
+        assert_eq!(config.missing_maps(), vec!["minimum_withdrawal"]);
+        config.rent_fees.clear();
+        assert_eq!(config.missing_maps(), vec!["minimum_withdrawal", "rent_fees"]);
+    }
+}
@@ -1,5 +1,6 @@
 //! Constants used throughout the Privacy Cash SDK
 
+use crate::error::PrivacyCashError;
 use once_cell::sync::Lazy;
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
@@ -69,6 +70,68 @@ pub static ALT_ADDRESS: Lazy<Pubkey> = Lazy::new(|| {
         })
 });
 
+/// Priority fee in micro-lamports per compute unit (0 disables it)
+/// Set PRIORITY_FEE_MICRO_LAMPORTS env var to bid for inclusion under congestion
+pub static PRIORITY_FEE_MICRO_LAMPORTS: Lazy<u64> = Lazy::new(|| {
+    std::env::var("PRIORITY_FEE_MICRO_LAMPORTS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+});
+
+/// Default for `RustProver::with_constraint_check`
+/// Set STRICT_WITNESS_CHECK=true to check each witness against the circuit's
+/// R1CS constraints before proving (needs the `.r1cs` file next to the zkey)
+pub static STRICT_WITNESS_CHECK: Lazy<bool> = Lazy::new(|| {
+    std::env::var("STRICT_WITNESS_CHECK")
+        .map(|s| matches!(s.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+});
+
+/// Default for `RustProver::with_snarkjs_fallback`
+/// Set SNARKJS_FALLBACK_SECS to race the snarkjs CLI once a proof has taken
+/// that many seconds (needs `snarkjs` on the PATH)
+pub static SNARKJS_FALLBACK_AFTER: Lazy<Option<std::time::Duration>> = Lazy::new(|| {
+    std::env::var("SNARKJS_FALLBACK_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(std::time::Duration::from_secs)
+});
+
+/// Default for `Prover::with_temp_dir`
+/// Set SNARKJS_TEMP_DIR to write snarkjs input, witness and proof files
+/// there instead of the system temp directory
+pub static SNARKJS_TEMP_DIR: Lazy<Option<std::path::PathBuf>> = Lazy::new(|| {
+    std::env::var_os("SNARKJS_TEMP_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(std::path::PathBuf::from)
+});
+
+/// Default for `PrivacyCash::set_allow_mainnet`
+/// Set ALLOW_MAINNET=true to let deposits and withdrawals run against mainnet
+pub static ALLOW_MAINNET: Lazy<bool> = Lazy::new(|| {
+    std::env::var("ALLOW_MAINNET")
+        .map(|s| matches!(s.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+});
+
+/// Commitment `send_privately` waits for on the deposit before withdrawing
+/// Set DEPOSIT_COMMITMENT to "confirmed" or "finalized"; unset only waits for the relayer
+pub static DEPOSIT_COMMITMENT: Lazy<Option<solana_sdk::commitment_config::CommitmentLevel>> =
+    Lazy::new(|| {
+        std::env::var("DEPOSIT_COMMITMENT")
+            .ok()
+            .and_then(|s| s.trim().to_lowercase().parse().ok())
+    });
+
+/// How long to wait for a deposit to reach `DEPOSIT_COMMITMENT`
+pub const DEFAULT_DEPOSIT_COMMITMENT_TIMEOUT: std::time::Duration =
+    std::time::Duration::from_secs(90);
+
+/// Rough time a withdrawal takes besides proving: submitting to the relayer
+/// and waiting until the indexer sees its outputs
+pub const WITHDRAW_OVERHEAD_ESTIMATE: std::time::Duration = std::time::Duration::from_secs(15);
+
 /// Relayer API URL
 pub static RELAYER_API_URL: Lazy<String> = Lazy::new(|| {
     std::env::var("RELAYER_API_URL").unwrap_or_else(|_| "https://api3.privacycash.org".to_string())
@@ -109,11 +172,50 @@ pub static STORE_MINT: Lazy<Pubkey> = Lazy::new(|| {
     Pubkey::from_str("sTorERYB6xAZ1SSbwpK3zoK2EEwbBrc7TZAzg1uCGiH").unwrap()
 });
 
-/// SOL "mint" address (system program placeholder)
+/// Wrapped SOL mint address (identifies SOL in the token list)
 pub static SOL_MINT: Lazy<Pubkey> = Lazy::new(|| {
     Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap()
 });
 
+/// Mint placeholder the program and circuit use for native SOL
+///
+/// Used in `ExtData.mint_address` and as the mint field of SOL UTXOs. This is
+/// not the wrapped SOL mint; see [`SOL_MINT`] for that.
+pub const NATIVE_SOL_MINT_STR: &str = "11111111111111111111111111111112";
+
+/// Native SOL mint placeholder as a pubkey
+pub static NATIVE_SOL_MINT: Lazy<Pubkey> =
+    Lazy::new(|| Pubkey::from_str(NATIVE_SOL_MINT_STR).unwrap());
+
+/// Mint a token's pool uses in notes and `ExtData`
+///
+/// The token list identifies SOL by the wrapped SOL mint, but native SOL
+/// notes and `ExtData` use [`NATIVE_SOL_MINT`]; both map to the placeholder.
+/// Every other mint is its own pool mint.
+pub fn pool_mint(mint: &Pubkey) -> Pubkey {
+    if *mint == *SOL_MINT {
+        *NATIVE_SOL_MINT
+    } else {
+        *mint
+    }
+}
+
+/// ExtData recipient for deposits
+///
+/// Deposits don't pay anyone out, but the instruction still needs a recipient
+/// account. Set DEPOSIT_RECIPIENT to override it for differently configured
+/// deployments; an address that doesn't parse is logged and ignored.
+pub static DEPOSIT_RECIPIENT: Lazy<Pubkey> = Lazy::new(|| {
+    let default = Pubkey::from_str("AWexibGxNFKTa1b5R5MN4PJr9HWnWRwf8EW9g8cLx3dM").unwrap();
+    match std::env::var("DEPOSIT_RECIPIENT") {
+        Ok(s) => Pubkey::from_str(s.trim()).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid DEPOSIT_RECIPIENT {:?} ({}), using {}", s, e, default);
+            default
+        }),
+        Err(_) => default,
+    }
+});
+
 /// Number of UTXOs to fetch per batch
 pub const FETCH_UTXOS_GROUP_SIZE: u64 = 20_000;
 
@@ -135,11 +237,29 @@ pub const LSK_FETCH_OFFSET: &str = "fetch_offset";
 /// LocalStorage key prefix for encrypted outputs
 pub const LSK_ENCRYPTED_OUTPUTS: &str = "encrypted_outputs";
 
+/// LocalStorage key prefix for how far each receive address index has been scanned
+pub const LSK_RECEIVE_CURSORS: &str = "receive_cursors";
+
+/// LocalStorage key prefix for commitments already reported by the incoming-payment watcher
+pub const LSK_SEEN_COMMITMENTS: &str = "seen_commitments";
+
+/// LocalStorage key prefix for the measured proof generation time
+pub const LSK_PROOF_BENCHMARK: &str = "proof_benchmark";
+
+/// LocalStorage key prefix for operations a shutdown interrupted
+pub const LSK_INTERRUPTED_OPERATIONS: &str = "interrupted_operations";
+
+/// Base fee per transaction signature in lamports
+pub const SIGNATURE_FEE_LAMPORTS: u64 = 5_000;
+
+/// Compute unit limit requested by deposit transactions
+pub const DEPOSIT_COMPUTE_UNIT_LIMIT: u32 = 1_000_000;
+
 /// Lamports per SOL
 pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
 
 /// Supported token information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TokenInfo {
     pub name: &'static str,
     pub mint: Pubkey,
@@ -147,6 +267,13 @@ pub struct TokenInfo {
     pub units_per_token: u64,
 }
 
+impl TokenInfo {
+    /// Number of decimal places (e.g. 9 for SOL, 6 for USDC)
+    pub fn decimals(&self) -> u8 {
+        self.units_per_token.ilog10() as u8
+    }
+}
+
 /// Get list of all supported tokens
 pub fn get_supported_tokens() -> Vec<TokenInfo> {
     vec![
@@ -202,3 +329,107 @@ pub fn find_token_by_name(name: &str) -> Option<TokenInfo> {
         .into_iter()
         .find(|t| t.name == name.to_lowercase())
 }
+
+/// Alternative names accepted for supported tokens
+const TOKEN_ALIASES: &[(&str, &str)] = &[
+    ("solana", "sol"),
+    ("wsol", "sol"),
+    ("wrappedsol", "sol"),
+    ("usdcoin", "usdc"),
+    ("tether", "usdt"),
+    ("usdtether", "usdt"),
+    ("zcash", "zec"),
+];
+
+/// Resolve a token from a name, alias or mint address
+///
+/// Names are matched case-insensitively, ignoring `-`, `_`, `$` and spaces,
+/// so "SOL", "USD-C", "Tether" and "EPjFWdd5…Dt1v" all resolve.
+pub fn resolve_token(identifier: &str) -> std::result::Result<TokenInfo, PrivacyCashError> {
+    let trimmed = identifier.trim();
+
+    if let Ok(mint) = Pubkey::from_str(trimmed) {
+        if let Some(token) = find_token_by_mint(&mint) {
+            return Ok(token);
+        }
+    }
+
+    let normalized: String = trimmed
+        .chars()
+        .filter(|c| !matches!(c, '-' | '_' | '$' | ' '))
+        .collect::<String>()
+        .to_lowercase();
+    let name = TOKEN_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == normalized)
+        .map(|(_, name)| *name)
+        .unwrap_or(normalized.as_str());
+
+    find_token_by_name(name).ok_or_else(|| {
+        let names: Vec<_> = get_supported_tokens().iter().map(|t| t.name).collect();
+        PrivacyCashError::TokenNotSupported(format!(
+            "{}. Use one of: {}, or a supported mint address",
+            identifier,
+            names.join(", ")
+        ))
+    })
+}
+
+impl FromStr for TokenInfo {
+    type Err = PrivacyCashError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        resolve_token(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_sol_mint_placeholder() {
+        // The circuit expects the system program ID + 1, passed through as-is
+        assert_eq!(NATIVE_SOL_MINT.to_string(), "11111111111111111111111111111112");
+        assert_eq!(
+            crate::utils::get_mint_address_field(&NATIVE_SOL_MINT),
+            NATIVE_SOL_MINT_STR
+        );
+        assert_ne!(*NATIVE_SOL_MINT, *SOL_MINT);
+
+        // Wrapped SOL resolves to the same placeholder at the API boundary;
+        // the field of the wrapped mint itself is an SPL field
+        assert_eq!(pool_mint(&SOL_MINT), *NATIVE_SOL_MINT);
+        assert_eq!(pool_mint(&NATIVE_SOL_MINT), *NATIVE_SOL_MINT);
+        assert_eq!(pool_mint(&USDC_MINT), *USDC_MINT);
+        assert_eq!(crate::utils::get_mint_address_field(&pool_mint(&SOL_MINT)), NATIVE_SOL_MINT_STR);
+        assert_ne!(crate::utils::get_mint_address_field(&SOL_MINT), NATIVE_SOL_MINT_STR);
+    }
+
+    #[test]
+    fn test_resolve_token_names_and_aliases() {
+        assert_eq!(resolve_token("SOL").unwrap().name, "sol");
+        assert_eq!(resolve_token(" usd-c ").unwrap().name, "usdc");
+        assert_eq!(resolve_token("Tether").unwrap().name, "usdt");
+        assert_eq!(resolve_token("$ZEC").unwrap().name, "zec");
+    }
+
+    #[test]
+    fn test_resolve_token_by_mint() {
+        let token = resolve_token("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        assert_eq!(token.name, "usdc");
+        assert_eq!(token.mint, *USDC_MINT);
+    }
+
+    #[test]
+    fn test_resolve_token_unknown() {
+        match resolve_token("doge") {
+            Err(PrivacyCashError::TokenNotSupported(msg)) => {
+                assert!(msg.contains("doge"));
+                assert!(msg.contains("usdc"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(resolve_token("11111111111111111111111111111111").is_err());
+    }
+}
@@ -1,28 +1,34 @@
 //! Deposit functionality for native SOL
 
+use crate::config::Config;
 use crate::constants::{
-    ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, TRANSACT_IX_DISCRIMINATOR,
+    DEPOSIT_COMPUTE_UNIT_LIMIT, DEPOSIT_RECIPIENT, FEE_RECIPIENT, LAMPORTS_PER_SOL, NATIVE_SOL_MINT,
+    PRIORITY_FEE_MICRO_LAMPORTS, PROGRAM_ID, TRANSACT_IX_DISCRIMINATOR,
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::get_utxos;
-use crate::keypair::ZkKeypair;
-use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
-use crate::prover_rust::RustProver;
+use crate::get_utxos::{confirmed_output, get_utxos};
+use crate::prover::{
+    input_nullifiers, output_commitments, parse_proof_to_bytes, CircuitInput, CircuitSpec, DryRunOutput,
+    PublicSignals,
+};
+use crate::prover_rust::{ProofSource, RustProver};
+use crate::receive::ReceiveAddress;
+use crate::relayer;
 use crate::storage::Storage;
+use crate::telemetry;
 use crate::utxo::{Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, query_remote_tree_state,
-    ExtData,
+    calculate_deposit_fee, calculate_public_amount, ensure_output_amounts_in_range, ext_amount_for,
+    fetch_input_merkle_paths, find_cross_check_nullifier_pdas, find_nullifier_pdas, get_lookup_table,
+    get_mint_address_field, get_program_accounts, query_remote_tree_state, relayer_error,
+    submit_deposit, submit_direct, ExtData, SubmitMode, TreeState,
 };
 use num_bigint::BigUint;
 use num_traits::Zero;
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
-    address_lookup_table::AddressLookupTableAccount,
     compute_budget::ComputeBudgetInstruction,
     instruction::{AccountMeta, Instruction},
     message::{v0::Message as MessageV0, VersionedMessage},
@@ -32,13 +38,39 @@ use solana_sdk::{
     system_program,
     transaction::VersionedTransaction,
 };
-use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 /// Deposit result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepositResult {
     /// Transaction signature
     pub signature: String,
+
+    /// The note this deposit created, with its confirmed leaf index
+    ///
+    /// Pass it to `PrivacyCash::withdraw_notes` to spend it without a scan.
+    /// `None` if the relayer didn't report the index, or if the deposit paid
+    /// a receive address (the note isn't this wallet's).
+    #[serde(skip)]
+    pub output_utxo: Option<Utxo>,
+
+    /// Set by a dry run, which stops after proving and submits nothing
+    ///
+    /// `signature` is empty and `output_utxo` is `None` in that case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunOutput>,
+
+    /// Prover that produced the proof
+    #[serde(default)]
+    pub proof_source: ProofSource,
+}
+
+impl DepositResult {
+    /// Whether the deposit was actually submitted (i.e. not a dry run)
+    pub fn is_submitted(&self) -> bool {
+        self.dry_run.is_none()
+    }
 }
 
 /// Parameters for deposit
@@ -50,9 +82,36 @@ pub struct DepositParams<'a> {
     pub amount_in_lamports: u64,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+    /// Spend these notes as inputs (at most two) instead of the wallet's first unspent ones
+    pub input_utxos: Option<Vec<Utxo>>,
+    /// Pay the deposit to this receive address instead of the wallet. Such a
+    /// deposit spends none of the wallet's notes.
+    pub receive_address: Option<&'a ReceiveAddress>,
+    /// Stop after generating and locally verifying the proof, returning it in
+    /// [`DepositResult::dry_run`] without submitting anything
+    pub dry_run: bool,
+    /// Relayer config to take the minimum deposit from; `None` uses the
+    /// cached config of `relayer_url`
+    pub config: Option<&'a Config>,
+    /// Relayer to relay the deposit through; `None` uses `RELAYER_API_URL`
+    pub relayer_url: Option<&'a str>,
+    /// Relay the signed transaction or send it to the RPC node directly;
+    /// `referrer` is ignored with [`SubmitMode::Direct`]
+    pub submit_mode: SubmitMode,
+    /// Stop scanning and proving once this is set to `true` (see
+    /// [`RustProver::with_abort_signal`]), failing with `Aborted`
+    pub abort_signal: Option<Arc<Mutex<bool>>>,
 }
 
 /// Execute a deposit
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "deposit",
+        skip_all,
+        fields(token = "sol", amount = params.amount_in_lamports, signature = tracing::field::Empty)
+    )
+)]
 pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
     let DepositParams {
         connection,
@@ -62,13 +121,33 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         amount_in_lamports,
         key_base_path,
         referrer,
+        input_utxos,
+        receive_address,
+        dry_run,
+        config,
+        relayer_url,
+        submit_mode,
+        abort_signal,
     } = params;
+    let relayer_url = relayer::base_url(relayer_url);
 
     let public_key = keypair.pubkey();
-    let fee_amount = 0u64; // No deposit fee
 
     log::info!("Starting deposit of {} lamports", amount_in_lamports);
 
+    // A dust note couldn't pay for its own withdrawal. Deposits of nothing
+    // (V1 migrations) only merge existing notes and are exempt, and pay no fee.
+    let fee_amount = if amount_in_lamports > 0 {
+        let config = match config {
+            Some(config) => config.clone(),
+            None => Config::get_from(relayer_url).await?,
+        };
+        config.ensure_minimum_deposit("sol", LAMPORTS_PER_SOL, amount_in_lamports)?;
+        calculate_deposit_fee(amount_in_lamports, config.deposit_fee_rate)?
+    } else {
+        0
+    };
+
     // Check deposit limit
     let limit = check_deposit_limit(connection).await?;
     if let Some(max_lamports) = limit {
@@ -80,12 +159,12 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         }
     }
 
-    // Check balance
+    // Check balance; the fee comes out of the deposited amount
     let balance = connection.get_balance(&public_key)?;
-    if balance < amount_in_lamports + fee_amount {
+    if balance < amount_in_lamports {
         return Err(PrivacyCashError::InsufficientBalance {
             have: balance,
-            need: amount_in_lamports + fee_amount,
+            need: amount_in_lamports,
         });
     }
 
@@ -100,71 +179,240 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         tree_state.next_index
     );
 
-    // Get UTXO keypair
-    let utxo_private_key = encryption_service.get_utxo_private_key_v2()?;
-    let utxo_keypair = ZkKeypair::from_hex(&utxo_private_key)?;
+    // Fetch existing UTXOs, unless the caller picked the inputs
+    let circuit = CircuitSpec::default();
+    let existing_utxos = match input_utxos {
+        // The wallet's notes would go to the payee along with the deposit
+        Some(_) if receive_address.is_some() => {
+            return Err(PrivacyCashError::InvalidInput(
+                "A deposit to a receive address can't spend notes".to_string(),
+            ));
+        }
+        None if receive_address.is_some() => Vec::new(),
+        Some(utxos) if utxos.len() > circuit.inputs => {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "A deposit can spend at most {} notes, got {}",
+                circuit.inputs,
+                utxos.len()
+            )));
+        }
+        Some(utxos) => utxos,
+        None => {
+            get_utxos(connection, &public_key, encryption_service, storage, abort_signal.clone())
+                .await?
+        }
+    };
 
-    // Fetch existing UTXOs
-    let existing_utxos = get_utxos(connection, &public_key, encryption_service, storage, None).await?;
+    let DepositTransaction {
+        outputs,
+        ext_data,
+        circuit_input,
+    } = build_deposit(
+        encryption_service,
+        existing_utxos,
+        amount_in_lamports,
+        fee_amount,
+        &tree_state,
+        receive_address,
+    )
+    .await?;
+    let recipient = ext_data.recipient;
+    let encrypted_output1 = ext_data.encrypted_output1.clone();
 
-    // Build inputs and calculate amounts
-    let (inputs, input_merkle_paths, ext_amount, output_amount) = if existing_utxos.is_empty() {
-        // Fresh deposit - use dummy inputs
-        let inputs = vec![
-            Utxo::dummy(utxo_keypair.clone(), None),
-            Utxo::dummy(utxo_keypair.clone(), None),
-        ];
-        let paths = vec![MerkleTree::zero_path(), MerkleTree::zero_path()];
+    // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
+    telemetry::phase!("prove");
+    log::info!("Generating ZK proof using pure Rust prover...");
+    #[cfg(feature = "debug-export")]
+    crate::prover::export_circuit_input_if_enabled(&circuit_input, "deposit");
+    let prover = RustProver::new(key_base_path).with_abort_signal(abort_signal);
+    let (proof, public_signals, proof_source) = prover.prove_with_source(&circuit_input).await?;
 
-        let ext_amount = amount_in_lamports as i64;
-        let output_amount = BigUint::from(amount_in_lamports) - BigUint::from(fee_amount);
+    // Parse proof to bytes
+    let proof_bytes = parse_proof_to_bytes(&proof)?;
+    let signals = PublicSignals::parse(&public_signals)?;
+    
+    // Debug: log proof bytes and sizes
+    log::debug!("Proof A size: {} bytes", proof_bytes.proof_a.len());
+    log::debug!("Proof B size: {} bytes", proof_bytes.proof_b.len());
+    log::debug!("Proof C size: {} bytes", proof_bytes.proof_c.len());
+    log::debug!("Proof A (first 32 bytes): {:02x?}", &proof_bytes.proof_a[..32.min(proof_bytes.proof_a.len())]);
+    log::debug!("Proof B (first 32 bytes): {:02x?}", &proof_bytes.proof_b[..32.min(proof_bytes.proof_b.len())]);
+    log::debug!("Proof C (first 32 bytes): {:02x?}", &proof_bytes.proof_c[..32.min(proof_bytes.proof_c.len())]);
+    log::debug!("Signal root: {:02x?}", &signals.root);
+    log::debug!("Signal public_amount: {:02x?}", &signals.public_amount);
+    log::debug!("Signal ext_data_hash: {:02x?}", &signals.ext_data_hash);
 
-        (inputs, paths, ext_amount, output_amount)
-    } else {
-        // Consolidate with existing UTXOs
-        let first_utxo = &existing_utxos[0];
-        let second_utxo = if existing_utxos.len() > 1 {
-            existing_utxos[1].clone()
-        } else {
-            Utxo::dummy(utxo_keypair.clone(), None)
-        };
+    // Find nullifier PDAs
+    let (nullifier0_pda, nullifier1_pda) =
+        find_nullifier_pdas(&signals.nullifiers());
+    let (nullifier2_pda, nullifier3_pda) =
+        find_cross_check_nullifier_pdas(&signals.nullifiers());
 
-        // Fetch Merkle proofs
-        let first_commitment = first_utxo.get_commitment()?;
-        let first_proof = fetch_merkle_proof(&first_commitment, None).await?;
+    // Serialize instruction data
+    let instruction_data = serialize_deposit_instruction(
+        &proof_bytes,
+        &signals,
+        &ext_data,
+    )?;
+    #[cfg(feature = "audit")]
+    crate::audit::emit("deposit", dry_run, &instruction_data);
+
+    if dry_run {
+        log::info!("Dry run: proof generated, not submitting deposit");
+        return Ok(DepositResult {
+            signature: String::new(),
+            output_utxo: None,
+            dry_run: Some(DryRunOutput::new(&proof_bytes, public_signals, instruction_data)),
+            proof_source,
+        });
+    }
 
-        let second_proof = if !second_utxo.is_dummy() {
-            let second_commitment = second_utxo.get_commitment()?;
-            fetch_merkle_proof(&second_commitment, None).await?
-        } else {
-            MerkleTree::zero_path()
-        };
+    // Build deposit instruction
+    let deposit_instruction = Instruction {
+        program_id: *PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(tree_account, false),
+            AccountMeta::new(nullifier0_pda, false),
+            AccountMeta::new(nullifier1_pda, false),
+            AccountMeta::new_readonly(nullifier2_pda, false),
+            AccountMeta::new_readonly(nullifier3_pda, false),
+            AccountMeta::new(tree_token_account, false),
+            AccountMeta::new_readonly(global_config_account, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new(*FEE_RECIPIENT, false),
+            AccountMeta::new(public_key, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: instruction_data,
+    };
+
+    let mut compute_budget_ixs = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        DEPOSIT_COMPUTE_UNIT_LIMIT,
+    )];
+    if *PRIORITY_FEE_MICRO_LAMPORTS > 0 {
+        compute_budget_ixs.push(ComputeBudgetInstruction::set_compute_unit_price(
+            *PRIORITY_FEE_MICRO_LAMPORTS,
+        ));
+    }
 
-        let ext_amount = amount_in_lamports as i64;
-        let output_amount = first_utxo.amount.clone()
-            + second_utxo.amount.clone()
-            + BigUint::from(amount_in_lamports)
-            - BigUint::from(fee_amount);
+    // Address Lookup Table (cached across transactions)
+    let alt = get_lookup_table(connection).await?;
 
-        (
-            vec![first_utxo.clone(), second_utxo],
-            vec![first_proof, second_proof],
-            ext_amount,
-            output_amount,
-        )
+    let signature = submit_deposit(connection, submit_mode, &encrypted_output1, None, || {
+        // Get fresh blockhash for each attempt
+        let recent_blockhash = connection.get_latest_blockhash()?;
+
+        let message = MessageV0::try_compile(
+            &public_key,
+            &[compute_budget_ixs.as_slice(), &[deposit_instruction.clone()]].concat(),
+            &[alt.clone()],
+            recent_blockhash,
+        ).map_err(|e| PrivacyCashError::TransactionError(format!("Failed to compile message: {}", e)))?;
+
+        let versioned_message = VersionedMessage::V0(message);
+        let transaction = VersionedTransaction::try_new(versioned_message, &[keypair])
+            .map_err(|e| PrivacyCashError::TransactionError(format!("Failed to create transaction: {}", e)))?;
+        let signature = transaction.signatures[0];
+
+        telemetry::phase!("submit");
+        Ok((signature, async move {
+            match submit_mode {
+                SubmitMode::Relayer => {
+                    // Serialize transaction for relay
+                    use base64::Engine;
+                    let tx_bytes = bincode::serialize(&transaction).map_err(|e| {
+                        PrivacyCashError::SerializationError(format!("Failed to serialize transaction: {}", e))
+                    })?;
+                    let serialized = base64::engine::general_purpose::STANDARD.encode(&tx_bytes);
+
+                    log::info!("Submitting signed transaction to relayer...");
+                    relay_deposit_to_indexer(&serialized, &public_key, referrer, relayer_url).await
+                }
+                SubmitMode::Direct => {
+                    log::info!("Submitting signed transaction to the RPC node...");
+                    submit_direct(connection, &transaction)
+                }
+            }
+        }))
+    })
+    .await?;
+
+    telemetry::record_signature!("signature", signature);
+
+    // Wait for confirmation
+    telemetry::phase!("confirm");
+    // A direct submission is already confirmed on-chain; waiting for the
+    // relayer to index it would fail a landed deposit if the relayer is down
+    if submit_mode == SubmitMode::Relayer {
+        log::info!("Waiting for confirmation...");
+        wait_for_confirmation(&encrypted_output1, None, relayer_url).await?;
+    }
+    let output_utxo = match receive_address {
+        Some(_) => None,
+        None => confirmed_output(&outputs[0], &encrypted_output1, None).await,
     };
 
+    Ok(DepositResult {
+        signature,
+        output_utxo,
+        dry_run: None,
+        proof_source,
+    })
+}
+
+/// Notes, ext data and circuit input of a SOL deposit
+pub(crate) struct DepositTransaction {
+    pub(crate) outputs: Vec<Utxo>,
+    pub(crate) ext_data: ExtData,
+    pub(crate) circuit_input: CircuitInput,
+}
+
+/// Build a deposit of `amount_in_lamports` that consolidates `existing_utxos`
+///
+/// The first notes of `existing_utxos` (as many as the circuit spends) are
+/// spent alongside the deposit and the rest padded with dummy inputs. A
+/// wallet's first deposit has no notes, so it spends only dummies with zero
+/// Merkle paths and makes no Merkle proof requests. With `receive_address`,
+/// the first output pays that address instead of the wallet, at the tree's
+/// next index.
+pub(crate) async fn build_deposit(
+    encryption_service: &EncryptionService,
+    existing_utxos: Vec<Utxo>,
+    amount_in_lamports: u64,
+    fee_amount: u64,
+    tree_state: &TreeState,
+    receive_address: Option<&ReceiveAddress>,
+) -> Result<DepositTransaction> {
+    // Get UTXO keypair
+    let utxo_keypair = encryption_service.utxo_keypair(UtxoVersion::V2)?;
+
+    let circuit = CircuitSpec::default();
+
+    // Consolidate existing UTXOs into the deposit, padding with dummy inputs
+    // (a fresh deposit spends only dummies)
+    let spent: Vec<Utxo> = existing_utxos.into_iter().take(circuit.inputs).collect();
+    let inputs = circuit.pad_inputs(spent, &utxo_keypair, None)?;
+    let input_merkle_paths = fetch_input_merkle_paths(&inputs, None).await?;
+
+    let ext_amount = ext_amount_for(amount_in_lamports, true)?;
+    let input_amount = inputs.iter().fold(BigUint::zero(), |total, u| total + &u.amount);
+    let output_amount = input_amount + BigUint::from(amount_in_lamports) - BigUint::from(fee_amount);
+
     let public_amount = calculate_public_amount(ext_amount, fee_amount);
 
     // Create outputs
-    let outputs = vec![
-        Utxo::new(
+    let first_output = match receive_address {
+        Some(address) => address.note(output_amount.clone(), tree_state.next_index, &NATIVE_SOL_MINT),
+        None => Utxo::new(
             output_amount,
             utxo_keypair.clone(),
             tree_state.next_index,
             None,
             Some(UtxoVersion::V2),
         ),
+    };
+    let outputs = vec![
+        first_output,
         Utxo::new(
             0u64,
             utxo_keypair.clone(),
@@ -173,18 +421,21 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
             Some(UtxoVersion::V2),
         ),
     ];
+    ensure_output_amounts_in_range(&outputs)?;
 
     // Generate nullifiers and commitments
-    let input_nullifiers = vec![inputs[0].get_nullifier()?, inputs[1].get_nullifier()?];
-    let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
+    let input_nullifiers = input_nullifiers(&inputs)?;
+    let output_commitments = output_commitments(&outputs)?;
 
     // Encrypt outputs
-    let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
+    let encrypted_output1 = match receive_address {
+        Some(address) => address.encrypt_note(&outputs[0])?,
+        None => encryption_service.encrypt_utxo(&outputs[0])?,
+    };
     let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
 
     // Create ExtData
-    let recipient = Pubkey::from_str("AWexibGxNFKTa1b5R5MN4PJr9HWnWRwf8EW9g8cLx3dM").unwrap();
-    let sol_mint = Pubkey::from_str("11111111111111111111111111111112").unwrap();
+    let recipient = *DEPOSIT_RECIPIENT;
 
     let ext_data = ExtData {
         recipient,
@@ -193,11 +444,11 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         encrypted_output2: encrypted_output2.clone(),
         fee: fee_amount,
         fee_recipient: *FEE_RECIPIENT,
-        mint_address: sol_mint,
+        mint_address: *NATIVE_SOL_MINT,
     };
 
     let ext_data_hash = ext_data.hash();
-    
+
     // Debug: log extData values
     log::debug!("ExtData recipient: {}", ext_data.recipient);
     log::debug!("ExtData ext_amount: {}", ext_data.ext_amount);
@@ -229,135 +480,15 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         out_blinding: outputs.iter().map(|u| u.blinding.to_string()).collect(),
         out_pubkey: outputs.iter().map(|u| u.keypair.pubkey().clone()).collect(),
 
-        mint_address: get_mint_address_field(&sol_mint),
-    };
-
-    // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
-    log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
-
-    // Parse proof to bytes
-    let proof_bytes = parse_proof_to_bytes(&proof)?;
-    let signals_bytes = parse_public_signals_to_bytes(&public_signals)?;
-    
-    // Debug: log proof bytes and sizes
-    log::debug!("Proof A size: {} bytes", proof_bytes.proof_a.len());
-    log::debug!("Proof B size: {} bytes", proof_bytes.proof_b.len());
-    log::debug!("Proof C size: {} bytes", proof_bytes.proof_c.len());
-    log::debug!("Proof A (first 32 bytes): {:02x?}", &proof_bytes.proof_a[..32.min(proof_bytes.proof_a.len())]);
-    log::debug!("Proof B (first 32 bytes): {:02x?}", &proof_bytes.proof_b[..32.min(proof_bytes.proof_b.len())]);
-    log::debug!("Proof C (first 32 bytes): {:02x?}", &proof_bytes.proof_c[..32.min(proof_bytes.proof_c.len())]);
-    log::debug!("Signal 0 (root): {:02x?}", &signals_bytes[0]);
-    log::debug!("Signal 1 (amount): {:02x?}", &signals_bytes[1]);
-    log::debug!("Signal 2 (extDataHash): {:02x?}", &signals_bytes[2]);
-
-    // Find nullifier PDAs
-    let (nullifier0_pda, nullifier1_pda) =
-        find_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
-    let (nullifier2_pda, nullifier3_pda) =
-        find_cross_check_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
-
-    // Serialize instruction data
-    let instruction_data = serialize_deposit_instruction(
-        &proof_bytes,
-        &signals_bytes,
-        &ext_data,
-    );
-
-    // Build deposit instruction
-    let deposit_instruction = Instruction {
-        program_id: *PROGRAM_ID,
-        accounts: vec![
-            AccountMeta::new(tree_account, false),
-            AccountMeta::new(nullifier0_pda, false),
-            AccountMeta::new(nullifier1_pda, false),
-            AccountMeta::new_readonly(nullifier2_pda, false),
-            AccountMeta::new_readonly(nullifier3_pda, false),
-            AccountMeta::new(tree_token_account, false),
-            AccountMeta::new_readonly(global_config_account, false),
-            AccountMeta::new(recipient, false),
-            AccountMeta::new(*FEE_RECIPIENT, false),
-            AccountMeta::new(public_key, true),
-            AccountMeta::new_readonly(system_program::id(), false),
-        ],
-        data: instruction_data,
-    };
-
-    let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_000_000);
-
-    // Fetch Address Lookup Table
-    log::info!("Fetching Address Lookup Table...");
-    let alt_account = connection.get_account(&ALT_ADDRESS)?;
-    let alt = AddressLookupTableAccount {
-        key: *ALT_ADDRESS,
-        addresses: parse_alt_addresses(&alt_account.data)?,
+        mint_address: get_mint_address_field(&NATIVE_SOL_MINT),
     };
+    circuit.check_input(&circuit_input)?;
 
-    // Retry loop for transaction submission (handles blockhash expiration)
-    let max_retries = 3;
-    let mut last_error = None;
-    let mut signature = String::new();
-    
-    for attempt in 0..max_retries {
-        if attempt > 0 {
-            log::warn!("Retrying transaction (attempt {}/{}), fetching fresh blockhash...", attempt + 1, max_retries);
-            // Small delay before retry to allow network conditions to stabilize
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-        }
-
-        // Get fresh blockhash for each attempt
-        let recent_blockhash = connection.get_latest_blockhash()?;
-        
-        let message = MessageV0::try_compile(
-            &public_key,
-            &[compute_budget_ix.clone(), deposit_instruction.clone()],
-            &[alt.clone()],
-            recent_blockhash,
-        ).map_err(|e| PrivacyCashError::TransactionError(format!("Failed to compile message: {}", e)))?;
-
-        let versioned_message = VersionedMessage::V0(message);
-        let transaction = VersionedTransaction::try_new(versioned_message, &[keypair])
-            .map_err(|e| PrivacyCashError::TransactionError(format!("Failed to create transaction: {}", e)))?;
-
-        // Serialize transaction for relay
-        use base64::Engine;
-        let tx_bytes = bincode::serialize(&transaction)
-            .map_err(|e| PrivacyCashError::SerializationError(format!("Failed to serialize transaction: {}", e)))?;
-        let serialized = base64::engine::general_purpose::STANDARD.encode(&tx_bytes);
-
-        log::info!("Submitting signed transaction to relayer...");
-        
-        match relay_deposit_to_indexer(&serialized, &public_key, referrer).await {
-            Ok(sig) => {
-                signature = sig;
-                last_error = None;
-                break;
-            }
-            Err(e) => {
-                let error_str = format!("{}", e);
-                // Check if this is a blockhash expiration error
-                if error_str.contains("block height exceeded") || error_str.contains("expired") {
-                    log::warn!("Transaction blockhash expired, will retry with fresh blockhash");
-                    last_error = Some(e);
-                    continue;
-                }
-                // For other errors, fail immediately
-                return Err(e);
-            }
-        }
-    }
-    
-    // If we exhausted retries, return the last error
-    if let Some(err) = last_error {
-        return Err(err);
-    }
-
-    // Wait for confirmation
-    log::info!("Waiting for confirmation...");
-    wait_for_confirmation(&encrypted_output1, None).await?;
-
-    Ok(DepositResult { signature })
+    Ok(DepositTransaction {
+        outputs,
+        ext_data,
+        circuit_input,
+    })
 }
 
 /// Relay deposit to indexer backend
@@ -365,9 +496,8 @@ async fn relay_deposit_to_indexer(
     signed_transaction: &str,
     sender: &Pubkey,
     referrer: Option<&str>,
+    relayer_url: &str,
 ) -> Result<String> {
-    use crate::constants::RELAYER_API_URL;
-
     let mut body = serde_json::json!({
         "signedTransaction": signed_transaction,
         "senderAddress": sender.to_string()
@@ -377,20 +507,15 @@ async fn relay_deposit_to_indexer(
         body["referralWalletAddress"] = serde_json::Value::String(ref_addr.to_string());
     }
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/deposit", *RELAYER_API_URL))
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Relay failed: {}", e)))?;
+    let response = relayer::send(
+        relayer::post(&format!("{}/deposit", relayer_url)).json(&body),
+        "Relay failed",
+    )
+    .await?;
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        return Err(PrivacyCashError::ApiError(format!(
-            "Deposit relay failed: {}",
-            error_text
-        )));
+        return Err(relayer_error("Deposit relay failed", &error_text));
     }
 
     #[derive(Deserialize)]
@@ -407,9 +532,11 @@ async fn relay_deposit_to_indexer(
 }
 
 /// Wait for transaction confirmation
-async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>) -> Result<()> {
-    use crate::constants::RELAYER_API_URL;
-
+async fn wait_for_confirmation(
+    encrypted_output: &[u8],
+    token_name: Option<&str>,
+    relayer_url: &str,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
@@ -417,12 +544,12 @@ async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>
     loop {
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
-        let mut url = format!("{}/utxos/check/{}", *RELAYER_API_URL, encrypted_hex);
+        let mut url = format!("{}/utxos/check/{}", relayer_url, encrypted_hex);
         if let Some(token) = token_name {
             url = format!("{}?token={}", url, token);
         }
 
-        let response = reqwest::get(&url).await;
+        let response = relayer::send(relayer::get(&url), "Confirmation check").await;
 
         if let Ok(resp) = response {
             if let Ok(data) = resp.json::<serde_json::Value>().await {
@@ -461,41 +588,14 @@ async fn check_deposit_limit(connection: &RpcClient) -> Result<Option<u64>> {
     Ok(None)
 }
 
-/// Parse Address Lookup Table addresses from account data
-fn parse_alt_addresses(data: &[u8]) -> Result<Vec<Pubkey>> {
-    // ALT format: 56 bytes header + addresses (32 bytes each)
-    const HEADER_SIZE: usize = 56;
-    
-    if data.len() < HEADER_SIZE {
-        return Err(PrivacyCashError::TransactionError(
-            "Invalid ALT account data".to_string()
-        ));
-    }
-    
-    let addresses_data = &data[HEADER_SIZE..];
-    let num_addresses = addresses_data.len() / 32;
-    
-    let mut addresses = Vec::with_capacity(num_addresses);
-    for i in 0..num_addresses {
-        let start = i * 32;
-        let end = start + 32;
-        if end <= addresses_data.len() {
-            let pubkey_bytes: [u8; 32] = addresses_data[start..end]
-                .try_into()
-                .map_err(|_| PrivacyCashError::TransactionError("Invalid pubkey in ALT".to_string()))?;
-            addresses.push(Pubkey::new_from_array(pubkey_bytes));
-        }
-    }
-    
-    Ok(addresses)
-}
-
 /// Serialize deposit instruction data
 fn serialize_deposit_instruction(
     proof_bytes: &crate::prover::ProofBytes,
-    signals: &[[u8; 32]],
+    signals: &PublicSignals,
     ext_data: &ExtData,
-) -> Vec<u8> {
+) -> Result<Vec<u8>> {
+    proof_bytes.validate()?;
+
     use num_bigint::BigInt;
     use num_traits::ToPrimitive;
 
@@ -510,9 +610,7 @@ fn serialize_deposit_instruction(
     data.extend_from_slice(&proof_bytes.proof_c);
 
     // Public signals: root, publicAmount, extDataHash, nullifiers, commitments
-    for signal in signals.iter().take(7) {
-        data.extend_from_slice(signal);
-    }
+    data.extend_from_slice(&signals.to_bytes());
 
     // ExtData (minified): extAmount (i64), fee (u64)
     data.extend_from_slice(&ext_data.ext_amount.to_le_bytes());
@@ -524,5 +622,101 @@ fn serialize_deposit_instruction(
     data.extend_from_slice(&(ext_data.encrypted_output2.len() as u32).to_le_bytes());
     data.extend_from_slice(&ext_data.encrypted_output2);
 
-    data
+    Ok(data)
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::keypair::ZkKeypair;
+    use crate::testing::{install_thread_mocks, test_config, test_mock};
+
+    #[tokio::test]
+    async fn test_fresh_deposit_spends_dummy_inputs() {
+        // Dummy inputs need no Merkle proofs; a request would hit the mock's 404
+        install_thread_mocks(test_mock(), test_config());
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new());
+        let keypair = ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap();
+        let tree_state: TreeState =
+            serde_json::from_value(serde_json::json!({ "root": "12345", "nextIndex": 7 })).unwrap();
+
+        let amount = 50_000_000u64;
+        let deposit = build_deposit(&service, Vec::new(), amount, 0, &tree_state, None)
+            .await
+            .unwrap();
+        let input = &deposit.circuit_input;
+
+        // Both inputs are zero-value notes of the wallet key with zero paths
+        assert_eq!(input.in_amount, vec!["0", "0"]);
+        assert_eq!(input.in_path_indices, vec![0, 0]);
+        assert!(input
+            .in_path_elements
+            .iter()
+            .all(|path| path.iter().all(|element| element == "0")));
+        assert!(input.in_private_key.iter().all(|key| key == keypair.privkey()));
+        let nullifiers: Vec<String> = input
+            .in_blinding
+            .iter()
+            .map(|blinding| {
+                let blinding = BigUint::parse_bytes(blinding.as_bytes(), 10).unwrap();
+                Utxo::with_blinding(0u64, blinding, keypair.clone(), 0, None, Some(UtxoVersion::V2))
+                    .get_nullifier()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(input.input_nullifier, nullifiers);
+        assert_ne!(input.input_nullifier[0], input.input_nullifier[1]);
+
+        // The whole deposit lands in the first output, at the next leaves
+        assert_eq!(input.public_amount, amount.to_string());
+        assert_eq!(input.out_amount, vec![amount.to_string(), "0".to_string()]);
+        assert_eq!(deposit.outputs[0].index, 7);
+        assert_eq!(deposit.outputs[1].index, 8);
+        let commitments: Vec<String> = deposit
+            .outputs
+            .iter()
+            .map(|utxo| utxo.get_commitment().unwrap())
+            .collect();
+        assert_eq!(input.output_commitment, commitments);
+        let note = service.decrypt_utxo(&deposit.ext_data.encrypted_output1).unwrap();
+        assert_eq!(note.amount, BigUint::from(amount));
+
+        assert_eq!(input.root, "12345");
+        assert_eq!(input.ext_data_hash, deposit.ext_data.hash().to_vec());
+        assert_eq!(deposit.ext_data.ext_amount, amount as i64);
+    }
+
+    #[tokio::test]
+    async fn test_deposit_to_receive_address() {
+        install_thread_mocks(test_mock(), test_config());
+        let mut payer = EncryptionService::new();
+        payer.derive_encryption_key_from_wallet(&Keypair::new());
+        let mut payee = EncryptionService::new();
+        payee.derive_encryption_key_from_wallet(&Keypair::new());
+        let payee_key = payee.utxo_keypair(UtxoVersion::V2).unwrap();
+        let address = ReceiveAddress::derive(&payee_key, 5, payee.derive_receive_key(5).unwrap()).unwrap();
+        let tree_state: TreeState =
+            serde_json::from_value(serde_json::json!({ "root": "12345", "nextIndex": 7 })).unwrap();
+
+        // The payer never learns the leaf index; the note goes at the next one
+        let amount = 50_000_000u64;
+        let deposit = build_deposit(&payer, Vec::new(), amount, 0, &tree_state, Some(&address))
+            .await
+            .unwrap();
+        assert_eq!(deposit.circuit_input.out_pubkey[0], address.owner);
+        assert!(payer.try_decrypt_utxo(&deposit.ext_data.encrypted_output1).unwrap().is_none());
+
+        // The payee's scan finds it and can spend it
+        payee.set_receive_indices([5]).unwrap();
+        let note = payee
+            .try_decrypt_utxo(&deposit.ext_data.encrypted_output1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(note.amount, BigUint::from(amount));
+        assert_eq!(note.index, 7);
+        assert_eq!(note.key_index, Some(5));
+        assert_eq!(note.get_commitment().unwrap(), deposit.circuit_input.output_commitment[0]);
+        assert!(note.get_nullifier().is_ok());
+    }
 }
@@ -1,28 +1,36 @@
 //! Deposit functionality for SPL tokens
 
+use crate::config::Config;
 use crate::constants::{
-    find_token_by_mint, ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, RELAYER_API_URL,
-    TRANSACT_SPL_IX_DISCRIMINATOR,
+    DEPOSIT_COMPUTE_UNIT_LIMIT, FEE_RECIPIENT,
+    PRIORITY_FEE_MICRO_LAMPORTS, PROGRAM_ID, TRANSACT_SPL_IX_DISCRIMINATOR,
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::confirmed_output;
 use crate::get_utxos_spl::get_utxos_spl;
-use crate::keypair::ZkKeypair;
-use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
-use crate::prover_rust::RustProver;
+use crate::prover::{
+    input_nullifiers, output_commitments, parse_proof_to_bytes, CircuitInput, CircuitSpec, DryRunOutput,
+    PublicSignals,
+};
+use crate::prover_rust::{ProofSource, RustProver};
+use crate::relayer;
 use crate::storage::Storage;
+use crate::telemetry;
+use crate::token_registry::TokenMetadata;
 use crate::utxo::{Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, get_spl_tree_account,
-    query_remote_tree_state, ExtData,
+    calculate_deposit_fee, calculate_public_amount, calculate_transaction_fee, ensure_output_amounts_in_range,
+    ensure_spl_pool_initialized, ext_amount_for, fetch_input_merkle_paths,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_lookup_table, get_mint_address_field,
+    get_program_accounts, get_spl_tree_account, get_token_account_amount, query_remote_tree_state,
+    relayer_error, submit_deposit, submit_direct, ExtData, SubmitMode,
 };
 use num_bigint::BigUint;
+use num_traits::Zero;
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
-    address_lookup_table::AddressLookupTableAccount,
     compute_budget::ComputeBudgetInstruction,
     instruction::{AccountMeta, Instruction},
     message::{v0::Message as MessageV0, VersionedMessage},
@@ -34,11 +42,38 @@ use solana_sdk::{
 };
 use spl_associated_token_account::get_associated_token_address;
 use spl_token;
+use spl_token::solana_program::program_pack::Pack;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 /// SPL Deposit result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepositSplResult {
     pub signature: String,
+
+    /// The note this deposit created, with its confirmed leaf index
+    ///
+    /// Pass it to `PrivacyCash::withdraw_notes_spl` to spend it without a
+    /// scan. `None` if the relayer didn't report the index.
+    #[serde(skip)]
+    pub output_utxo: Option<Utxo>,
+
+    /// Set by a dry run, which stops after proving and submits nothing
+    ///
+    /// `signature` is empty and `output_utxo` is `None` in that case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunOutput>,
+
+    /// Prover that produced the proof
+    #[serde(default)]
+    pub proof_source: ProofSource,
+}
+
+impl DepositSplResult {
+    /// Whether the deposit was actually submitted (i.e. not a dry run)
+    pub fn is_submitted(&self) -> bool {
+        self.dry_run.is_none()
+    }
 }
 
 /// Parameters for SPL deposit
@@ -48,12 +83,39 @@ pub struct DepositSplParams<'a> {
     pub encryption_service: &'a EncryptionService,
     pub storage: &'a Storage,
     pub base_units: u64,
-    pub mint_address: &'a Pubkey,
+    /// Token to deposit, as resolved by [`PrivacyCash::token_metadata`](crate::PrivacyCash::token_metadata)
+    pub token: &'a TokenMetadata,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+    /// Relayer config to take the minimum deposit from; `None` uses the
+    /// cached config of `relayer_url`
+    pub config: Option<&'a Config>,
+    /// Relayer to relay the deposit through; `None` uses `RELAYER_API_URL`
+    pub relayer_url: Option<&'a str>,
+    /// Relay the signed transaction or send it to the RPC node directly;
+    /// `referrer` is ignored with [`SubmitMode::Direct`]
+    pub submit_mode: SubmitMode,
+    /// Stop scanning and proving once this is set to `true` (see
+    /// [`RustProver::with_abort_signal`]), failing with `Aborted`
+    pub abort_signal: Option<Arc<Mutex<bool>>>,
+    /// Stop after generating and locally verifying the proof, returning it in
+    /// [`DepositSplResult::dry_run`] without submitting anything
+    pub dry_run: bool,
 }
 
 /// Execute an SPL token deposit
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "deposit_spl",
+        skip_all,
+        fields(
+            token = %params.token.info.mint,
+            amount = params.base_units,
+            signature = tracing::field::Empty,
+        )
+    )
+)]
 pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResult> {
     let DepositSplParams {
         connection,
@@ -61,13 +123,17 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         encryption_service,
         storage,
         base_units,
-        mint_address,
+        token: TokenMetadata { info: token, .. },
         key_base_path,
         referrer,
+        config,
+        relayer_url,
+        submit_mode,
+        abort_signal,
+        dry_run,
     } = params;
-
-    let token = find_token_by_mint(mint_address)
-        .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
+    let relayer_url = relayer::base_url(relayer_url);
+    let mint_address = &token.mint;
 
     log::info!(
         "Starting {} deposit of {} base units",
@@ -75,8 +141,15 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         base_units
     );
 
+    // A dust note couldn't pay for its own withdrawal
+    let config = match config {
+        Some(config) => config.clone(),
+        None => Config::get_from(relayer_url).await?,
+    };
+    config.ensure_minimum_deposit(token.name, token.units_per_token, base_units)?;
+
     let public_key = keypair.pubkey();
-    let fee_base_units = 0u64;
+    let fee_base_units = calculate_deposit_fee(base_units, config.deposit_fee_rate)?;
 
     // Get token accounts
     let signer_token_account = get_associated_token_address(&public_key, mint_address);
@@ -87,26 +160,45 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
 
     // Get SPL tree account
     let tree_account = get_spl_tree_account(mint_address);
+    ensure_spl_pool_initialized(connection, mint_address, token.name)?;
 
-    // Check SPL balance
-    let account_info = connection.get_token_account_balance(&signer_token_account)?;
-    let balance: u64 = account_info.amount.parse().unwrap_or(0);
+    // Check SPL balance (a missing ATA means the wallet never held the token)
+    let balance = get_token_account_amount(connection, &signer_token_account)?.ok_or_else(|| {
+        PrivacyCashError::TokenAccountNotFound {
+            token: token.name.to_string(),
+            owner: public_key.to_string(),
+        }
+    })?;
 
-    if balance < base_units + fee_base_units {
+    // The fee comes out of the deposited amount
+    if balance < base_units {
         return Err(PrivacyCashError::InsufficientTokenBalance {
             token: token.name.to_string(),
             have: balance,
-            need: base_units + fee_base_units,
+            need: base_units,
         });
     }
 
-    // Check SOL for fees
+    // Check SOL for fees: transaction fee plus rent for the placeholder
+    // recipient's ATA, which the program creates at our expense if missing
+    let recipient_ata_exists = connection
+        .get_account_with_commitment(&recipient_ata, connection.commitment())?
+        .value
+        .is_some();
+    let ata_rent = if recipient_ata_exists {
+        0
+    } else {
+        log::debug!("Recipient ATA {} does not exist yet, it will be created", recipient_ata);
+        connection.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?
+    };
+    let required_sol =
+        calculate_transaction_fee(DEPOSIT_COMPUTE_UNIT_LIMIT, *PRIORITY_FEE_MICRO_LAMPORTS) + ata_rent;
+
     let sol_balance = connection.get_balance(&public_key)?;
-    if sol_balance < 2_000_000 {
-        // 0.002 SOL
+    if sol_balance < required_sol {
         return Err(PrivacyCashError::InsufficientBalance {
             have: sol_balance,
-            need: 2_000_000,
+            need: required_sol,
         });
     }
 
@@ -116,8 +208,7 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
     let tree_state = query_remote_tree_state(Some(token.name)).await?;
 
     // Get UTXO keypair
-    let utxo_private_key = encryption_service.get_utxo_private_key_v2()?;
-    let utxo_keypair = ZkKeypair::from_hex(&utxo_private_key)?;
+    let utxo_keypair = encryption_service.utxo_keypair(UtxoVersion::V2)?;
 
     // Fetch existing UTXOs
     let existing_utxos = get_utxos_spl(
@@ -126,53 +217,19 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         encryption_service,
         storage,
         mint_address,
-        None,
+        abort_signal.clone(),
     )
     .await?;
 
-    // Build inputs
-    let (inputs, input_merkle_paths, ext_amount, output_amount) = if existing_utxos.is_empty() {
-        let inputs = vec![
-            Utxo::dummy(utxo_keypair.clone(), Some(&mint_address.to_string())),
-            Utxo::dummy(utxo_keypair.clone(), Some(&mint_address.to_string())),
-        ];
-        let paths = vec![MerkleTree::zero_path(), MerkleTree::zero_path()];
+    // Consolidate existing UTXOs into the deposit, padding with dummy inputs
+    let circuit = CircuitSpec::default();
+    let spent: Vec<Utxo> = existing_utxos.into_iter().take(circuit.inputs).collect();
+    let inputs = circuit.pad_inputs(spent, &utxo_keypair, Some(&mint_address.to_string()))?;
+    let input_merkle_paths = fetch_input_merkle_paths(&inputs, Some(token.name)).await?;
 
-        let ext_amount = base_units as i64;
-        let output_amount = BigUint::from(base_units) - BigUint::from(fee_base_units);
-
-        (inputs, paths, ext_amount, output_amount)
-    } else {
-        let first_utxo = &existing_utxos[0];
-        let second_utxo = if existing_utxos.len() > 1 {
-            existing_utxos[1].clone()
-        } else {
-            Utxo::dummy(utxo_keypair.clone(), Some(&mint_address.to_string()))
-        };
-
-        let first_commitment = first_utxo.get_commitment()?;
-        let first_proof = fetch_merkle_proof(&first_commitment, Some(token.name)).await?;
-
-        let second_proof = if !second_utxo.is_dummy() {
-            let second_commitment = second_utxo.get_commitment()?;
-            fetch_merkle_proof(&second_commitment, Some(token.name)).await?
-        } else {
-            MerkleTree::zero_path()
-        };
-
-        let ext_amount = base_units as i64;
-        let output_amount = first_utxo.amount.clone()
-            + second_utxo.amount.clone()
-            + BigUint::from(base_units)
-            - BigUint::from(fee_base_units);
-
-        (
-            vec![first_utxo.clone(), second_utxo],
-            vec![first_proof, second_proof],
-            ext_amount,
-            output_amount,
-        )
-    };
+    let ext_amount = ext_amount_for(base_units, true)?;
+    let input_amount = inputs.iter().fold(BigUint::zero(), |total, u| total + &u.amount);
+    let output_amount = input_amount + BigUint::from(base_units) - BigUint::from(fee_base_units);
 
     let public_amount = calculate_public_amount(ext_amount, fee_base_units);
 
@@ -193,10 +250,11 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
             Some(UtxoVersion::V2),
         ),
     ];
+    ensure_output_amounts_in_range(&outputs)?;
 
     // Generate nullifiers and commitments
-    let input_nullifiers = vec![inputs[0].get_nullifier()?, inputs[1].get_nullifier()?];
-    let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
+    let input_nullifiers = input_nullifiers(&inputs)?;
+    let output_commitments = output_commitments(&outputs)?;
 
     // Encrypt outputs
     let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
@@ -249,23 +307,39 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
 
         mint_address: get_mint_address_field(mint_address),
     };
+    circuit.check_input(&circuit_input)?;
 
     // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
+    telemetry::phase!("prove");
     log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    #[cfg(feature = "debug-export")]
+    crate::prover::export_circuit_input_if_enabled(&circuit_input, "deposit_spl");
+    let prover = RustProver::new(key_base_path).with_abort_signal(abort_signal);
+    let (proof, public_signals, proof_source) = prover.prove_with_source(&circuit_input).await?;
 
     let proof_bytes = parse_proof_to_bytes(&proof)?;
-    let signals_bytes = parse_public_signals_to_bytes(&public_signals)?;
+    let signals = PublicSignals::parse(&public_signals)?;
 
     // Find nullifier PDAs
     let (nullifier0_pda, nullifier1_pda) =
-        find_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
+        find_nullifier_pdas(&signals.nullifiers());
     let (nullifier2_pda, nullifier3_pda) =
-        find_cross_check_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
+        find_cross_check_nullifier_pdas(&signals.nullifiers());
 
     // Serialize instruction data
-    let instruction_data = serialize_spl_instruction(&proof_bytes, &signals_bytes, &ext_data);
+    let instruction_data = serialize_spl_instruction(&proof_bytes, &signals, &ext_data)?;
+    #[cfg(feature = "audit")]
+    crate::audit::emit("deposit_spl", dry_run, &instruction_data);
+
+    if dry_run {
+        log::info!("Dry run: proof generated, not submitting {} deposit", token.name);
+        return Ok(DepositSplResult {
+            signature: String::new(),
+            output_utxo: None,
+            dry_run: Some(DryRunOutput::new(&proof_bytes, public_signals, instruction_data)),
+            proof_source,
+        });
+    }
 
     // Get SPL-specific accounts
     let signer_token_account = get_associated_token_address(&public_key, mint_address);
@@ -304,34 +378,25 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         data: instruction_data,
     };
 
-    let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_000_000);
-
-    // Fetch Address Lookup Table
-    log::info!("Fetching Address Lookup Table...");
-    let alt_account = connection.get_account(&ALT_ADDRESS)?;
-    let alt = AddressLookupTableAccount {
-        key: *ALT_ADDRESS,
-        addresses: parse_alt_addresses(&alt_account.data)?,
-    };
+    let mut compute_budget_ixs = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        DEPOSIT_COMPUTE_UNIT_LIMIT,
+    )];
+    if *PRIORITY_FEE_MICRO_LAMPORTS > 0 {
+        compute_budget_ixs.push(ComputeBudgetInstruction::set_compute_unit_price(
+            *PRIORITY_FEE_MICRO_LAMPORTS,
+        ));
+    }
 
-    // Retry loop for transaction submission (handles blockhash expiration)
-    let max_retries = 3;
-    let mut last_error = None;
-    let mut signature = String::new();
-    
-    for attempt in 0..max_retries {
-        if attempt > 0 {
-            log::warn!("Retrying transaction (attempt {}/{}), fetching fresh blockhash...", attempt + 1, max_retries);
-            // Small delay before retry to allow network conditions to stabilize
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-        }
+    // Address Lookup Table (cached across transactions)
+    let alt = get_lookup_table(connection).await?;
 
+    let signature = submit_deposit(connection, submit_mode, &encrypted_output1, Some(token.name), || {
         // Get fresh blockhash for each attempt
         let recent_blockhash = connection.get_latest_blockhash()?;
-        
+
         let message = MessageV0::try_compile(
             &public_key,
-            &[compute_budget_ix.clone(), deposit_instruction.clone()],
+            &[compute_budget_ixs.as_slice(), &[deposit_instruction.clone()]].concat(),
             &[alt.clone()],
             recent_blockhash,
         ).map_err(|e| PrivacyCashError::TransactionError(format!("Failed to compile message: {}", e)))?;
@@ -339,59 +404,59 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         let versioned_message = VersionedMessage::V0(message);
         let transaction = VersionedTransaction::try_new(versioned_message, &[keypair])
             .map_err(|e| PrivacyCashError::TransactionError(format!("Failed to create transaction: {}", e)))?;
-
-        // Serialize transaction for relay
-        use base64::Engine;
-        let tx_bytes = bincode::serialize(&transaction)
-            .map_err(|e| PrivacyCashError::SerializationError(format!("Failed to serialize transaction: {}", e)))?;
-        let serialized = base64::engine::general_purpose::STANDARD.encode(&tx_bytes);
-
-        // Relay to backend
-        log::info!("Submitting transaction to relayer...");
-        
-        match relay_spl_deposit_to_indexer(
-            &serialized,
-            &public_key,
-            mint_address,
-            referrer,
-        ).await {
-            Ok(sig) => {
-                signature = sig;
-                last_error = None;
-                break;
-            }
-            Err(e) => {
-                let error_str = format!("{}", e);
-                // Check if this is a blockhash expiration error
-                if error_str.contains("block height exceeded") || error_str.contains("expired") {
-                    log::warn!("Transaction blockhash expired, will retry with fresh blockhash");
-                    last_error = Some(e);
-                    continue;
+        let signature = transaction.signatures[0];
+
+        telemetry::phase!("submit");
+        Ok((signature, async move {
+            match submit_mode {
+                SubmitMode::Relayer => {
+                    // Serialize transaction for relay
+                    use base64::Engine;
+                    let tx_bytes = bincode::serialize(&transaction).map_err(|e| {
+                        PrivacyCashError::SerializationError(format!("Failed to serialize transaction: {}", e))
+                    })?;
+                    let serialized = base64::engine::general_purpose::STANDARD.encode(&tx_bytes);
+
+                    log::info!("Submitting transaction to relayer...");
+                    relay_spl_deposit_to_indexer(&serialized, &public_key, mint_address, referrer, relayer_url)
+                        .await
+                }
+                SubmitMode::Direct => {
+                    log::info!("Submitting transaction to the RPC node...");
+                    submit_direct(connection, &transaction)
                 }
-                // For other errors, fail immediately
-                return Err(e);
             }
-        }
-    }
-    
-    // If we exhausted retries, return the last error
-    if let Some(err) = last_error {
-        return Err(err);
-    }
+        }))
+    })
+    .await?;
 
-    // Wait for confirmation
-    log::info!("Waiting for confirmation...");
-    wait_for_spl_confirmation(&encrypted_output1, token.name).await?;
+    telemetry::record_signature!("signature", signature);
 
-    Ok(DepositSplResult { signature })
+    // Wait for confirmation
+    telemetry::phase!("confirm");
+    // A direct submission is already confirmed on-chain
+    if submit_mode == SubmitMode::Relayer {
+        log::info!("Waiting for confirmation...");
+        wait_for_spl_confirmation(&encrypted_output1, token.name, relayer_url).await?;
+    }
+    let output_utxo = confirmed_output(&outputs[0], &encrypted_output1, Some(token.name)).await;
+
+    Ok(DepositSplResult {
+        signature,
+        output_utxo,
+        dry_run: None,
+        proof_source,
+    })
 }
 
 /// Serialize SPL instruction data
 fn serialize_spl_instruction(
     proof_bytes: &crate::prover::ProofBytes,
-    signals: &[[u8; 32]],
+    signals: &PublicSignals,
     ext_data: &ExtData,
-) -> Vec<u8> {
+) -> Result<Vec<u8>> {
+    proof_bytes.validate()?;
+
     let mut data = Vec::new();
 
     data.extend_from_slice(&TRANSACT_SPL_IX_DISCRIMINATOR);
@@ -400,9 +465,7 @@ fn serialize_spl_instruction(
     data.extend_from_slice(&proof_bytes.proof_b);
     data.extend_from_slice(&proof_bytes.proof_c);
 
-    for signal in signals.iter().take(7) {
-        data.extend_from_slice(signal);
-    }
+    data.extend_from_slice(&signals.to_bytes());
 
     data.extend_from_slice(&ext_data.ext_amount.to_le_bytes());
     data.extend_from_slice(&ext_data.fee.to_le_bytes());
@@ -412,7 +475,7 @@ fn serialize_spl_instruction(
     data.extend_from_slice(&(ext_data.encrypted_output2.len() as u32).to_le_bytes());
     data.extend_from_slice(&ext_data.encrypted_output2);
 
-    data
+    Ok(data)
 }
 
 /// Relay SPL deposit to indexer
@@ -421,6 +484,7 @@ async fn relay_spl_deposit_to_indexer(
     sender: &Pubkey,
     mint_address: &Pubkey,
     referrer: Option<&str>,
+    relayer_url: &str,
 ) -> Result<String> {
     let mut body = serde_json::json!({
         "signedTransaction": signed_transaction,
@@ -432,20 +496,15 @@ async fn relay_spl_deposit_to_indexer(
         body["referralWalletAddress"] = serde_json::Value::String(ref_addr.to_string());
     }
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/deposit/spl", *RELAYER_API_URL))
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("SPL deposit relay failed: {}", e)))?;
+    let response = relayer::send(
+        relayer::post(&format!("{}/deposit/spl", relayer_url)).json(&body),
+        "SPL deposit relay failed",
+    )
+    .await?;
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        return Err(PrivacyCashError::ApiError(format!(
-            "SPL deposit failed: {}",
-            error_text
-        )));
+        return Err(relayer_error("SPL deposit failed", &error_text));
     }
 
     #[derive(Deserialize)]
@@ -462,7 +521,11 @@ async fn relay_spl_deposit_to_indexer(
 }
 
 /// Wait for SPL confirmation
-async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) -> Result<()> {
+async fn wait_for_spl_confirmation(
+    encrypted_output: &[u8],
+    token_name: &str,
+    relayer_url: &str,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
@@ -472,10 +535,10 @@ async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) ->
 
         let url = format!(
             "{}/utxos/check/{}?token={}",
-            *RELAYER_API_URL, encrypted_hex, token_name
+            relayer_url, encrypted_hex, token_name
         );
 
-        let response = reqwest::get(&url).await;
+        let response = relayer::send(relayer::get(&url), "Confirmation check").await;
 
         if let Ok(resp) = response {
             if let Ok(data) = resp.json::<serde_json::Value>().await {
@@ -493,32 +556,3 @@ async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) ->
         log::info!("Confirming SPL transaction... (retry {})", retries);
     }
 }
-
-/// Parse Address Lookup Table addresses from account data
-fn parse_alt_addresses(data: &[u8]) -> Result<Vec<Pubkey>> {
-    // ALT format: 56 bytes header + addresses (32 bytes each)
-    const HEADER_SIZE: usize = 56;
-    
-    if data.len() < HEADER_SIZE {
-        return Err(PrivacyCashError::TransactionError(
-            "Invalid ALT account data".to_string()
-        ));
-    }
-    
-    let addresses_data = &data[HEADER_SIZE..];
-    let num_addresses = addresses_data.len() / 32;
-    
-    let mut addresses = Vec::with_capacity(num_addresses);
-    for i in 0..num_addresses {
-        let start = i * 32;
-        let end = start + 32;
-        if end <= addresses_data.len() {
-            let pubkey_bytes: [u8; 32] = addresses_data[start..end]
-                .try_into()
-                .map_err(|_| PrivacyCashError::TransactionError("Invalid pubkey in ALT".to_string()))?;
-            addresses.push(Pubkey::new_from_array(pubkey_bytes));
-        }
-    }
-    
-    Ok(addresses)
-}
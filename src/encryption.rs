@@ -4,7 +4,7 @@
 
 use crate::constants::SIGN_MESSAGE;
 use crate::error::{PrivacyCashError, Result};
-use crate::keypair::ZkKeypair;
+use crate::keypair::{DomainTags, ZkKeypair};
 use crate::utxo::{Utxo, UtxoVersion};
 use aes_gcm::{
     aead::{Aead, KeyInit},
@@ -40,6 +40,12 @@ pub struct EncryptionService {
 
     /// V2 UTXO private key (cached)
     utxo_private_key_v2: Option<String>,
+
+    /// Keys of the receive addresses to scan for, by sub-key index
+    receive_keys: Vec<(u32, [u8; 32])>,
+
+    /// Domain tags of the UTXO keypairs, and so of every decrypted note
+    domain_tags: DomainTags,
 }
 
 impl std::fmt::Debug for EncryptionService {
@@ -47,6 +53,7 @@ impl std::fmt::Debug for EncryptionService {
         f.debug_struct("EncryptionService")
             .field("has_v1_key", &self.encryption_key_v1.is_some())
             .field("has_v2_key", &self.encryption_key_v2.is_some())
+            .field("receive_indices", &self.receive_indices())
             .finish()
     }
 }
@@ -59,6 +66,8 @@ impl EncryptionService {
             encryption_key_v2: None,
             utxo_private_key_v1: None,
             utxo_private_key_v2: None,
+            receive_keys: Vec::new(),
+            domain_tags: DomainTags::default(),
         }
     }
 
@@ -101,77 +110,58 @@ impl EncryptionService {
             .encryption_key_v2
             .as_ref()
             .ok_or_else(|| PrivacyCashError::EncryptionError("Encryption key not set".to_string()))?;
-
-        // Generate random 12-byte IV for GCM
-        let mut rng = rand::thread_rng();
-        let mut iv = [0u8; 12];
-        rng.fill(&mut iv);
-
-        // Create cipher
-        let cipher = Aes256Gcm::new_from_slice(key)
-            .map_err(|e| PrivacyCashError::EncryptionError(format!("Invalid key: {}", e)))?;
-
-        let nonce = Nonce::from_slice(&iv);
-
-        // Encrypt
-        let ciphertext = cipher
-            .encrypt(nonce, data)
-            .map_err(|e| PrivacyCashError::EncryptionError(format!("Encryption failed: {}", e)))?;
-
-        // V2 format: [version(8)] + [IV(12)] + [ciphertext with auth tag]
-        // Note: aes-gcm appends the 16-byte auth tag to the ciphertext
-        let mut result = Vec::with_capacity(8 + 12 + ciphertext.len());
-        result.extend_from_slice(&ENCRYPTION_VERSION_V2);
-        result.extend_from_slice(&iv);
-        result.extend_from_slice(&ciphertext);
-
-        Ok(result)
+        encrypt_v2(key, data)
     }
 
     /// Decrypt data (auto-detects V1 or V2 format)
     pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+        self.try_decrypt(encrypted_data)?.ok_or_else(|| {
+            PrivacyCashError::DecryptionError("Invalid key or corrupted data".to_string())
+        })
+    }
+
+    /// Decrypt data, returning `None` if it wasn't encrypted with this key
+    ///
+    /// Malformed input (too short, missing keys) is still an error.
+    pub fn try_decrypt(&self, encrypted_data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.try_decrypt_with_index(encrypted_data)?.map(|(data, _)| data))
+    }
+
+    /// Decrypt data with the wallet key or a receive address key
+    ///
+    /// Along with the plaintext, returns the receive address index whose key
+    /// opened it (`None` for the wallet key).
+    fn try_decrypt_with_index(&self, encrypted_data: &[u8]) -> Result<Option<(Vec<u8>, Option<u32>)>> {
         if encrypted_data.len() < 8 {
             return Err(PrivacyCashError::DecryptionError("Data too short".to_string()));
         }
 
         // Check if V2 format
-        if encrypted_data[..8] == ENCRYPTION_VERSION_V2 {
-            self.decrypt_v2(encrypted_data)
-        } else {
-            self.decrypt_v1(encrypted_data)
+        if encrypted_data[..8] != ENCRYPTION_VERSION_V2 {
+            return Ok(self.decrypt_v1(encrypted_data)?.map(|data| (data, None)));
+        }
+        if let Some(data) = self.decrypt_v2(encrypted_data)? {
+            return Ok(Some((data, None)));
+        }
+        for (index, key) in &self.receive_keys {
+            if let Some(data) = decrypt_v2_with_key(key, encrypted_data)? {
+                return Ok(Some((data, Some(*index))));
+            }
         }
+        Ok(None)
     }
 
-    /// Decrypt V2 format (AES-256-GCM)
-    fn decrypt_v2(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+    /// Decrypt V2 format (AES-256-GCM), `None` on authentication failure
+    fn decrypt_v2(&self, encrypted_data: &[u8]) -> Result<Option<Vec<u8>>> {
         let key = self
             .encryption_key_v2
             .as_ref()
             .ok_or_else(|| PrivacyCashError::DecryptionError("V2 encryption key not set".to_string()))?;
-
-        if encrypted_data.len() < 8 + 12 + 16 {
-            // version + iv + min auth tag
-            return Err(PrivacyCashError::DecryptionError("Data too short for V2".to_string()));
-        }
-
-        // Extract components
-        let iv = &encrypted_data[8..20]; // 12 bytes
-        let ciphertext = &encrypted_data[20..]; // rest (includes auth tag)
-
-        // Create cipher
-        let cipher = Aes256Gcm::new_from_slice(key)
-            .map_err(|e| PrivacyCashError::DecryptionError(format!("Invalid key: {}", e)))?;
-
-        let nonce = Nonce::from_slice(iv);
-
-        // Decrypt
-        cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|_| PrivacyCashError::DecryptionError("Invalid key or corrupted data".to_string()))
+        decrypt_v2_with_key(key, encrypted_data)
     }
 
-    /// Decrypt V1 format (AES-128-CTR with HMAC)
-    fn decrypt_v1(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+    /// Decrypt V1 format (AES-128-CTR with HMAC), `None` on authentication failure
+    fn decrypt_v1(&self, encrypted_data: &[u8]) -> Result<Option<Vec<u8>>> {
         let key = self
             .encryption_key_v1
             .as_ref()
@@ -196,7 +186,7 @@ impl EncryptionService {
         let calculated_tag = &mac.finalize().into_bytes()[..16];
 
         if !constant_time_eq(auth_tag, calculated_tag) {
-            return Err(PrivacyCashError::DecryptionError("Invalid key or corrupted data".to_string()));
+            return Ok(None);
         }
 
         // Decrypt using AES-128-CTR
@@ -210,7 +200,7 @@ impl EncryptionService {
         let mut plaintext = data.to_vec();
         cipher.apply_keystream(&mut plaintext);
 
-        Ok(plaintext)
+        Ok(Some(plaintext))
     }
 
     /// Encrypt a UTXO
@@ -221,16 +211,39 @@ impl EncryptionService {
 
     /// Decrypt a UTXO
     pub fn decrypt_utxo(&self, encrypted_data: &[u8]) -> Result<Utxo> {
+        self.try_decrypt_utxo(encrypted_data)?.ok_or_else(|| {
+            PrivacyCashError::DecryptionError("Invalid key or corrupted data".to_string())
+        })
+    }
+
+    /// Decrypt a UTXO received out-of-band (e.g. in a payment notification)
+    ///
+    /// Returns `Ok(None)` if the note isn't addressed to this wallet, and an
+    /// error if the data is malformed.
+    pub fn try_decrypt_utxo(&self, encrypted_data: &[u8]) -> Result<Option<Utxo>> {
         let version = self.get_encryption_version(encrypted_data);
-        let decrypted = self.decrypt(encrypted_data)?;
+        let (decrypted, receive_index) = match self.try_decrypt_with_index(encrypted_data)? {
+            Some(decrypted) => decrypted,
+            None => return Ok(None),
+        };
 
         let data_str = String::from_utf8(decrypted)
             .map_err(|_| PrivacyCashError::DecryptionError("Invalid UTF-8".to_string()))?;
 
-        let private_key = self.get_utxo_private_key_with_version(version)?;
-        let keypair = ZkKeypair::from_hex(&private_key)?;
-
-        Utxo::deserialize_from_encryption(&data_str, keypair, version)
+        let keypair = self.utxo_keypair(version)?;
+
+        let utxo = Utxo::deserialize_from_encryption(&data_str, keypair, version)?;
+        // A note sent to a receive address must be owned by that address's
+        // sub-key, or we couldn't spend it
+        if receive_index.is_some() && utxo.key_index != receive_index {
+            log::debug!(
+                "Ignoring note for receive address {:?} with key index {:?}",
+                receive_index,
+                utxo.key_index
+            );
+            return Ok(None);
+        }
+        Ok(Some(utxo))
     }
 
     /// Decrypt UTXO from hex string
@@ -261,6 +274,25 @@ impl EncryptionService {
         }
     }
 
+    /// UTXO keypair for a specific version, with this service's domain tags
+    pub fn utxo_keypair(&self, version: UtxoVersion) -> Result<ZkKeypair> {
+        ZkKeypair::from_hex(&self.get_utxo_private_key_with_version(version)?)?
+            .with_domain_tags(self.domain_tags.clone())
+    }
+
+    /// Use `domain_tags` for the UTXO keypairs
+    ///
+    /// Notes decrypted from then on carry the tags, so their commitments and
+    /// nullifiers are computed under them. See [`DomainTags`].
+    pub fn set_domain_tags(&mut self, domain_tags: DomainTags) {
+        self.domain_tags = domain_tags;
+    }
+
+    /// Domain tags of the UTXO keypairs
+    pub fn domain_tags(&self) -> &DomainTags {
+        &self.domain_tags
+    }
+
     /// Derive UTXO private key (V1 by default, or V2 if encrypted data is V2)
     pub fn derive_utxo_private_key(&self, encrypted_data: Option<&[u8]>) -> Result<String> {
         let version = encrypted_data
@@ -280,12 +312,65 @@ impl EncryptionService {
         self.get_utxo_private_key_with_version(UtxoVersion::V2)
     }
 
+    /// Key for encrypting the local cache at rest
+    ///
+    /// HMAC-SHA256 of a fixed label under the V2 key, so it is tied to the
+    /// wallet but never equal to the key that encrypts notes.
+    pub fn derive_cache_key(&self) -> Result<[u8; 32]> {
+        let key = self
+            .encryption_key_v2
+            .as_ref()
+            .ok_or_else(|| PrivacyCashError::EncryptionError("Encryption key not set".to_string()))?;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key)
+            .map_err(|e| PrivacyCashError::EncryptionError(format!("Invalid key: {}", e)))?;
+        mac.update(b"privacy-cash-cache");
+        Ok(mac.finalize().into_bytes().into())
+    }
+
+    /// Key that encrypts notes sent to receive address `index`
+    ///
+    /// HMAC-SHA256 of a label and the index under the V2 key, so each
+    /// address has its own key and none of them reveals the wallet key.
+    pub fn derive_receive_key(&self, index: u32) -> Result<[u8; 32]> {
+        let key = self
+            .encryption_key_v2
+            .as_ref()
+            .ok_or_else(|| PrivacyCashError::EncryptionError("Encryption key not set".to_string()))?;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key)
+            .map_err(|e| PrivacyCashError::EncryptionError(format!("Invalid key: {}", e)))?;
+        mac.update(b"privacy-cash-receive");
+        mac.update(&index.to_be_bytes());
+        Ok(mac.finalize().into_bytes().into())
+    }
+
+    /// Also decrypt notes sent to these receive addresses
+    ///
+    /// Replaces the previous set. Every output that the wallet key can't
+    /// open is tried against each of these keys, so decryption cost grows
+    /// linearly with their number.
+    pub fn set_receive_indices(&mut self, indices: impl IntoIterator<Item = u32>) -> Result<()> {
+        let mut indices: Vec<u32> = indices.into_iter().collect();
+        indices.sort_unstable();
+        indices.dedup();
+        self.receive_keys = indices
+            .into_iter()
+            .map(|index| Ok((index, self.derive_receive_key(index)?)))
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Receive address indices whose notes this service decrypts
+    pub fn receive_indices(&self) -> Vec<u32> {
+        self.receive_keys.iter().map(|(index, _)| *index).collect()
+    }
+
     /// Reset all keys
     pub fn reset(&mut self) {
         self.encryption_key_v1 = None;
         self.encryption_key_v2 = None;
         self.utxo_private_key_v1 = None;
         self.utxo_private_key_v2 = None;
+        self.receive_keys.clear();
     }
 }
 
@@ -295,6 +380,55 @@ impl Default for EncryptionService {
     }
 }
 
+/// Encrypt data in V2 format (AES-256-GCM) under `key`
+pub(crate) fn encrypt_v2(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    // Generate random 12-byte IV for GCM
+    let mut rng = rand::thread_rng();
+    let mut iv = [0u8; 12];
+    rng.fill(&mut iv);
+
+    // Create cipher
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|e| PrivacyCashError::EncryptionError(format!("Invalid key: {}", e)))?;
+
+    let nonce = Nonce::from_slice(&iv);
+
+    // Encrypt
+    let ciphertext = cipher
+        .encrypt(nonce, data)
+        .map_err(|e| PrivacyCashError::EncryptionError(format!("Encryption failed: {}", e)))?;
+
+    // V2 format: [version(8)] + [IV(12)] + [ciphertext with auth tag]
+    // Note: aes-gcm appends the 16-byte auth tag to the ciphertext
+    let mut result = Vec::with_capacity(8 + 12 + ciphertext.len());
+    result.extend_from_slice(&ENCRYPTION_VERSION_V2);
+    result.extend_from_slice(&iv);
+    result.extend_from_slice(&ciphertext);
+
+    Ok(result)
+}
+
+/// Decrypt V2 format under `key`, `None` on authentication failure
+fn decrypt_v2_with_key(key: &[u8], encrypted_data: &[u8]) -> Result<Option<Vec<u8>>> {
+    if encrypted_data.len() < 8 + 12 + 16 {
+        // version + iv + min auth tag
+        return Err(PrivacyCashError::DecryptionError("Data too short for V2".to_string()));
+    }
+
+    // Extract components
+    let iv = &encrypted_data[8..20]; // 12 bytes
+    let ciphertext = &encrypted_data[20..]; // rest (includes auth tag)
+
+    // Create cipher
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|e| PrivacyCashError::DecryptionError(format!("Invalid key: {}", e)))?;
+
+    let nonce = Nonce::from_slice(iv);
+
+    // Decrypt
+    Ok(cipher.decrypt(nonce, ciphertext).ok())
+}
+
 /// Constant-time comparison to prevent timing attacks
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
@@ -341,4 +475,47 @@ mod tests {
         assert_eq!(utxo.blinding, decrypted.blinding);
         assert_eq!(utxo.index, decrypted.index);
     }
+
+    #[test]
+    fn test_decrypted_utxo_domain_tags() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new());
+        let tags = DomainTags {
+            commitment: num_bigint::BigUint::from(2u8),
+            ..DomainTags::default()
+        };
+        service.set_domain_tags(tags.clone());
+
+        let zk_keypair = service.utxo_keypair(UtxoVersion::V2).unwrap();
+        assert_eq!(zk_keypair.domain_tags(), &tags);
+        let utxo = Utxo::new(1000u64, zk_keypair, 5, None, Some(UtxoVersion::V2));
+
+        // A scan recomputes the note's commitment under the same tags
+        let decrypted = service.decrypt_utxo(&service.encrypt_utxo(&utxo).unwrap()).unwrap();
+        assert_eq!(decrypted.keypair.domain_tags(), &tags);
+        assert_eq!(decrypted.get_commitment().unwrap(), utxo.get_commitment().unwrap());
+    }
+
+    #[test]
+    fn test_try_decrypt_utxo() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new());
+        let mut other = EncryptionService::new();
+        other.derive_encryption_key_from_wallet(&Keypair::new());
+
+        let zk_keypair = ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap();
+        let utxo = Utxo::new(2500u64, zk_keypair, 9, None, Some(UtxoVersion::V2));
+        let encrypted = service.encrypt_utxo(&utxo).unwrap();
+
+        // Addressed to us
+        let decrypted = service.try_decrypt_utxo(&encrypted).unwrap().unwrap();
+        assert_eq!(decrypted.amount, utxo.amount);
+        assert_eq!(decrypted.version, UtxoVersion::V2);
+
+        // Addressed to someone else
+        assert!(other.try_decrypt_utxo(&encrypted).unwrap().is_none());
+
+        // Malformed
+        assert!(service.try_decrypt_utxo(&[1, 2, 3]).is_err());
+    }
 }
@@ -20,6 +20,18 @@ pub enum PrivacyCashError {
     #[error("Insufficient balance: have {have} lamports, need {need} lamports")]
     InsufficientBalance { have: u64, need: u64 },
 
+    /// Public SOL doesn't cover a partner fee transfer
+    #[error(
+        "Insufficient SOL for the partner fee: have {have} lamports, need {need} ({fee} fee + {network_fee} network fee + {rent_reserve} rent-exempt reserve); top up the wallet or deduct the fee from the withdrawal"
+    )]
+    InsufficientSolForPartnerFee {
+        have: u64,
+        need: u64,
+        fee: u64,
+        network_fee: u64,
+        rent_reserve: u64,
+    },
+
     /// Insufficient SPL token balance
     #[error("Insufficient {token} balance: have {have}, need {need}")]
     InsufficientTokenBalance {
@@ -28,6 +40,10 @@ pub enum PrivacyCashError {
         need: u64,
     },
 
+    /// Wallet has no token account for an SPL token
+    #[error("No {token} token account for {owner}; the wallet has never held this token")]
+    TokenAccountNotFound { token: String, owner: String },
+
     /// No UTXOs available for withdrawal
     #[error("No UTXOs available for withdrawal")]
     NoUtxosAvailable,
@@ -40,6 +56,22 @@ pub enum PrivacyCashError {
     #[error("Withdrawal amount too low, minimum is {minimum}")]
     WithdrawalAmountTooLow { minimum: u64 },
 
+    /// Deposit too small to pay for its own withdrawal later (base units)
+    #[error("Deposit amount {amount} too low, minimum is {minimum}")]
+    DepositAmountTooLow { amount: u64, minimum: u64 },
+
+    /// Private balance is entirely consumed by withdrawal fees
+    #[error("Balance too small to cover fees: balance {balance}, fee {fee}")]
+    BalanceTooSmallForFees { balance: u64, fee: u64 },
+
+    /// Fees would take more of a withdrawal than the client allows
+    #[error("Fees of {fees} exceed the allowed share of the {amount} withdrawal; withdraw a larger amount")]
+    WithdrawalUneconomical { amount: u64, fees: u64 },
+
+    /// The protocol hasn't set up the pool for an SPL token on this network
+    #[error("The {token} pool is not initialized on this network (missing {missing}); the protocol hasn't set up this token yet")]
+    TokenNotInitialized { token: String, missing: String },
+
     /// Token not supported
     #[error("Token not supported: {0}")]
     TokenNotSupported(String),
@@ -68,10 +100,32 @@ pub enum PrivacyCashError {
     #[error("Transaction error: {0}")]
     TransactionError(String),
 
+    /// Transaction ran out of compute units
+    #[error(
+        "Compute budget exceeded ({}); try raising the compute unit limit",
+        describe_compute_units(*.consumed, *.limit)
+    )]
+    ComputeBudgetExceeded {
+        consumed: Option<u64>,
+        limit: Option<u64>,
+    },
+
+    /// Relayer rejected requests with 429 Too Many Requests
+    #[error("Rate limited by relayer ({})", describe_retry_after(*.retry_after_secs))]
+    RateLimited { retry_after_secs: Option<u64> },
+
     /// Transaction confirmation timeout
     #[error("Transaction confirmation timeout after {retries} retries")]
     ConfirmationTimeout { retries: u32 },
 
+    /// Transaction didn't reach the required commitment in time
+    #[error("Transaction {signature} did not reach {commitment} commitment within {timeout_secs}s")]
+    CommitmentTimeout {
+        signature: String,
+        commitment: String,
+        timeout_secs: u64,
+    },
+
     /// Solana client error
     #[error("Solana client error: {0}")]
     SolanaClientError(#[from] solana_client::client_error::ClientError),
@@ -104,7 +158,68 @@ pub enum PrivacyCashError {
     #[error("Circuit file not found: {0}")]
     CircuitNotFound(String),
 
+    /// A circuit file isn't the version this SDK pins
+    #[error("Circuit file {path} has SHA-256 {actual}, expected {expected}; it is corrupted or the wrong version")]
+    CircuitHashMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
     /// Operation aborted
     #[error("Operation aborted")]
     Aborted,
+
+    /// The witness doesn't satisfy the circuit, so the input is malformed
+    #[error("Witness fails circuit constraint {constraint} of {total}; the circuit input is malformed")]
+    WitnessConstraintFailed { constraint: usize, total: usize },
+
+    /// The client is shutting down and takes no new operations
+    #[error("Client is shutting down")]
+    ShuttingDown,
+
+    /// A multi-transaction withdrawal failed after some transactions went through
+    #[error(
+        "Withdrawal stopped after {} of {transactions} transactions ({withdrawn} base units withdrawn): {error}",
+        .signatures.len()
+    )]
+    WithdrawAllIncomplete {
+        /// Signatures of the withdrawals that went through
+        signatures: Vec<String>,
+        /// Amount they delivered, in the token's base units
+        withdrawn: u64,
+        /// Transactions planned in total
+        transactions: usize,
+        /// Why the next transaction failed
+        #[source]
+        error: Box<PrivacyCashError>,
+    },
+
+    /// A withdrawal went through but its partner fee transfer failed
+    #[error("Withdrawal {signature} went through, but its partner fee of {fee} lamports wasn't collected: {error}")]
+    PartnerFeeNotCollected {
+        /// Signature of the withdrawal
+        signature: String,
+        /// Fee that wasn't transferred, in lamports
+        fee: u64,
+        /// Why the transfer failed
+        #[source]
+        error: Box<PrivacyCashError>,
+    },
+}
+
+/// Describe consumed vs. requested compute units for error messages
+fn describe_compute_units(consumed: Option<u64>, limit: Option<u64>) -> String {
+    match (consumed, limit) {
+        (Some(consumed), Some(limit)) => format!("consumed {} of {} units", consumed, limit),
+        _ => "unit usage unknown".to_string(),
+    }
+}
+
+/// Describe when a rate-limited request may be retried
+fn describe_retry_after(retry_after_secs: Option<u64>) -> String {
+    match retry_after_secs {
+        Some(secs) => format!("retry after {}s", secs),
+        None => "try again later".to_string(),
+    }
 }
@@ -5,6 +5,7 @@ use crate::constants::{
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
+use crate::relayer;
 use crate::storage::Storage;
 use crate::utxo::{get_balance_from_utxos, Balance, Utxo};
 use num_bigint::BigUint;
@@ -47,6 +48,73 @@ struct IndicesResponse {
     indices: Vec<u64>,
 }
 
+/// What a UTXO scan had to skip
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanDiagnostics {
+    /// Entries that couldn't be decoded (bad hex, too short to be a note, or
+    /// decrypting to an unparseable note) and were skipped. Notes addressed
+    /// to other wallets aren't counted.
+    pub malformed_entries: usize,
+    /// Notes whose nullifier is already on-chain, excluded from the result.
+    /// Always 0 when [`ScanOptions::check_spent`] is off.
+    pub spent_notes: usize,
+    /// Total amount of the excluded spent notes, in base units
+    pub spent_amount: u64,
+}
+
+impl ScanDiagnostics {
+    pub(crate) fn add(&mut self, other: ScanDiagnostics) {
+        self.malformed_entries += other.malformed_entries;
+        self.spent_notes += other.spent_notes;
+        self.spent_amount = self.spent_amount.saturating_add(other.spent_amount);
+    }
+}
+
+/// How a UTXO scan treats the notes it finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Look up each note's nullifier PDAs on-chain and drop the notes that
+    /// were already spent. Costs one `getMultipleAccounts` call per batch of
+    /// notes; with it off the result may include spent notes, which fail
+    /// when withdrawn, and the scan cursor and note cache are left as they
+    /// were so spent notes never enter them.
+    pub check_spent: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self { check_spent: true }
+    }
+}
+
+/// Keep the notes that aren't spent, counting the rest in `diagnostics`
+///
+/// `spent` holds one flag per note, or `None` if the on-chain check was
+/// skipped, in which case every note is kept.
+pub(crate) fn filter_spent<'a>(
+    notes: Vec<(usize, &'a Utxo)>,
+    spent: Option<Vec<bool>>,
+    diagnostics: &mut ScanDiagnostics,
+) -> Vec<(usize, &'a Utxo)> {
+    let Some(spent) = spent else {
+        return notes;
+    };
+    notes
+        .into_iter()
+        .zip(spent)
+        .filter_map(|(note, is_spent)| {
+            if is_spent {
+                log::debug!("Skipping spent note at index {}", note.1.index);
+                diagnostics.spent_notes += 1;
+                diagnostics.spent_amount = diagnostics.spent_amount.saturating_add(note.1.amount_u64());
+                None
+            } else {
+                Some(note)
+            }
+        })
+        .collect()
+}
+
 /// Create a storage key for a public key
 pub fn localstorage_key(pubkey: &Pubkey) -> String {
     let program_prefix = PROGRAM_ID.to_string();
@@ -62,8 +130,49 @@ pub async fn get_utxos(
     storage: &Storage,
     abort_signal: Option<Arc<Mutex<bool>>>,
 ) -> Result<Vec<Utxo>> {
+    get_utxos_with_diagnostics(connection, public_key, encryption_service, storage, abort_signal)
+        .await
+        .map(|(utxos, _)| utxos)
+}
+
+/// Fetch all UTXOs for a user, reporting entries that had to be skipped
+///
+/// Malformed entries from the relayer are logged and skipped rather than
+/// failing the scan.
+pub async fn get_utxos_with_diagnostics(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    abort_signal: Option<Arc<Mutex<bool>>>,
+) -> Result<(Vec<Utxo>, ScanDiagnostics)> {
+    get_utxos_with_options(
+        connection,
+        public_key,
+        encryption_service,
+        storage,
+        abort_signal,
+        ScanOptions::default(),
+    )
+    .await
+}
+
+/// Fetch all UTXOs for a user with explicit [`ScanOptions`]
+///
+/// Spent notes that were excluded are counted in the returned
+/// [`ScanDiagnostics`].
+pub async fn get_utxos_with_options(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    abort_signal: Option<Arc<Mutex<bool>>>,
+    options: ScanOptions,
+) -> Result<(Vec<Utxo>, ScanDiagnostics)> {
+    let mut diagnostics = ScanDiagnostics::default();
     let mut valid_utxos = Vec::new();
     let mut valid_strings = Vec::new();
+    let mut seen_outputs = std::collections::HashSet::new();
     let mut history_indexes = Vec::new();
 
     let storage_key = localstorage_key(public_key);
@@ -82,13 +191,18 @@ pub async fn get_utxos(
             }
         }
 
-        let fetch_offset: u64 = storage
-            .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0)
-            .max(round_start_index);
-
-        let fetch_end = fetch_offset + FETCH_UTXOS_GROUP_SIZE;
+        // An unchecked scan doesn't move the stored offset (see below)
+        let fetch_offset: u64 = if options.check_spent {
+            storage
+                .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0)
+                .max(round_start_index)
+        } else {
+            round_start_index
+        };
+
+        let fetch_end = fetch_offset.saturating_add(FETCH_UTXOS_GROUP_SIZE);
         let url = format!(
             "{}/utxos/range?start={}&end={}",
             *RELAYER_API_URL, fetch_offset, fetch_end
@@ -96,8 +210,9 @@ pub async fn get_utxos(
 
         log::debug!("Fetching UTXOs from: {}", url);
 
-        let (fetched_utxos, encrypted_outputs, has_more, len) =
+        let (fetched_utxos, encrypted_outputs, has_more, len, batch_diagnostics) =
             fetch_user_utxos(&url, encryption_service, storage, &storage_key).await?;
+        diagnostics.add(batch_diagnostics);
 
         // Check which UTXOs are unspent
         let non_zero_utxos: Vec<_> = fetched_utxos
@@ -107,29 +222,44 @@ pub async fn get_utxos(
             .collect();
 
         if !non_zero_utxos.is_empty() {
-            let spent_flags = are_utxos_spent(
-                connection,
-                &non_zero_utxos.iter().map(|(_, u)| (*u).clone()).collect::<Vec<_>>(),
-            )
-            .await?;
-
-            for ((idx, utxo), is_spent) in non_zero_utxos.into_iter().zip(spent_flags) {
-                history_indexes.push(utxo.index);
-                if !is_spent {
-                    log::debug!("Found unspent UTXO: {:?}", encrypted_outputs.get(idx));
-                    valid_utxos.push(utxo.clone());
-                    if let Some(enc) = encrypted_outputs.get(idx) {
-                        valid_strings.push(enc.clone());
+            history_indexes.extend(non_zero_utxos.iter().map(|(_, utxo)| utxo.index));
+            let spent_flags = if options.check_spent {
+                Some(
+                    are_utxos_spent(
+                        connection,
+                        &non_zero_utxos.iter().map(|(_, u)| (*u).clone()).collect::<Vec<_>>(),
+                    )
+                    .await?,
+                )
+            } else {
+                None
+            };
+
+            for (idx, utxo) in filter_spent(non_zero_utxos, spent_flags, &mut diagnostics) {
+                log::debug!("Found unspent UTXO: {:?}", encrypted_outputs.get(idx));
+                if let Some(enc) = encrypted_outputs.get(idx) {
+                    // A cursor moved back for new receive indices finds
+                    // cached notes again
+                    if !seen_outputs.insert(enc.clone()) {
+                        continue;
                     }
+                    valid_strings.push(enc.clone());
                 }
+                valid_utxos.push(utxo.clone());
             }
         }
 
-        // Update storage offset
-        storage.set(
-            &format!("{}{}", LSK_FETCH_OFFSET, storage_key),
-            &(fetch_offset + len).to_string(),
-        );
+        // Update storage offset. Without the spent check the notes found
+        // may be spent, so neither the offset nor the cache below is
+        // updated: the next checked scan looks at this range again
+        if options.check_spent {
+            storage.set(
+                &format!("{}{}", LSK_FETCH_OFFSET, storage_key),
+                &fetch_offset.saturating_add(len).to_string(),
+            );
+        } else {
+            round_start_index = fetch_offset.saturating_add(len);
+        }
 
         if !has_more {
             break;
@@ -146,12 +276,74 @@ pub async fn get_utxos(
         .into_iter()
         .collect();
 
-    storage.set(
-        &format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key),
-        &serde_json::to_string(&unique_strings).unwrap_or_default(),
-    );
+    if options.check_spent {
+        storage.set(
+            &format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key),
+            &serde_json::to_string(&unique_strings).unwrap_or_default(),
+        );
+    }
+
+    if diagnostics.malformed_entries > 0 {
+        log::warn!("Skipped {} malformed UTXO entries", diagnostics.malformed_entries);
+    }
+    if diagnostics.spent_notes > 0 {
+        log::info!(
+            "Excluded {} spent notes ({} lamports)",
+            diagnostics.spent_notes,
+            diagnostics.spent_amount
+        );
+    }
 
-    Ok(valid_utxos)
+    Ok((valid_utxos, diagnostics))
+}
+
+/// Fetch a wallet's unspent UTXOs created at or after leaf `start_index`
+///
+/// Scans the relayer's range API from `start_index` without reading or
+/// moving the stored scan cursor or touching cached outputs, for audits and
+/// for wallets that persist their own last-seen index. `token_name` selects
+/// an SPL pool (`None` for SOL). Unspent notes below `start_index` are not
+/// returned.
+pub async fn get_utxos_from(
+    connection: &RpcClient,
+    encryption_service: &EncryptionService,
+    start_index: u64,
+    token_name: Option<&str>,
+) -> Result<Vec<Utxo>> {
+    let token_query = token_name.map(|t| format!("token={}&", t)).unwrap_or_default();
+    let mut offset = start_index;
+    let mut notes = Vec::new();
+
+    loop {
+        let url = format!(
+            "{}/utxos/range?{}start={}&end={}",
+            *RELAYER_API_URL,
+            token_query,
+            offset,
+            offset.saturating_add(FETCH_UTXOS_GROUP_SIZE)
+        );
+        let (encrypted_outputs, has_more) = fetch_range(&url).await?;
+        let (utxos, _, _) = decrypt_outputs(&encrypted_outputs, encryption_service, token_name).await?;
+        notes.extend(
+            utxos
+                .into_iter()
+                .filter(|u| u.amount_u64() > 0 && u.index >= start_index),
+        );
+
+        offset = offset.saturating_add(encrypted_outputs.len() as u64);
+        if !has_more || encrypted_outputs.is_empty() {
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    }
+
+    // Two nullifier PDAs per note; RPCs take up to 100 accounts per request
+    let mut unspent = Vec::new();
+    for batch in notes.chunks(50) {
+        let spent = are_utxos_spent(connection, batch).await?;
+        unspent.extend(batch.iter().zip(spent).filter(|(_, spent)| !spent).map(|(u, _)| u.clone()));
+    }
+    Ok(unspent)
 }
 
 /// Fetch UTXOs from API and decrypt
@@ -160,10 +352,38 @@ async fn fetch_user_utxos(
     encryption_service: &EncryptionService,
     storage: &Storage,
     storage_key: &str,
-) -> Result<(Vec<Utxo>, Vec<String>, bool, u64)> {
-    let response = reqwest::get(url)
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch UTXOs: {}", e)))?;
+) -> Result<(Vec<Utxo>, Vec<String>, bool, u64, ScanDiagnostics)> {
+    let (encrypted_outputs, has_more) = fetch_range(url).await?;
+    let len = encrypted_outputs.len() as u64;
+
+    // Decrypt outputs
+    let (utxos, decrypted_outputs, mut diagnostics) =
+        decrypt_outputs(&encrypted_outputs, encryption_service, None).await?;
+
+    // Also check cached outputs if no more to fetch
+    let mut all_utxos = utxos;
+    let mut all_outputs = decrypted_outputs;
+
+    if !has_more {
+        if let Some(cached) = storage.get(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key)) {
+            if let Ok(cached_outputs) = serde_json::from_str::<Vec<String>>(&cached) {
+                let (cached_utxos, cached_decrypted, cached_diagnostics) =
+                    decrypt_outputs(&cached_outputs, encryption_service, None).await?;
+                all_utxos.extend(cached_utxos);
+                all_outputs.extend(cached_decrypted);
+                diagnostics.add(cached_diagnostics);
+            }
+        }
+    }
+
+    Ok((all_utxos, all_outputs, has_more, len, diagnostics))
+}
+
+/// Fetch one page of encrypted outputs from the range API
+///
+/// Returns the outputs and whether more pages follow.
+async fn fetch_range(url: &str) -> Result<(Vec<String>, bool)> {
+    let response = relayer::send(relayer::get(url), "Failed to fetch UTXOs").await?;
 
     if !response.status().is_success() {
         return Err(PrivacyCashError::ApiError(format!(
@@ -177,11 +397,10 @@ async fn fetch_user_utxos(
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse UTXOs: {}", e)))?;
 
-    let (encrypted_outputs, has_more, total) = if let Some(outputs) = data.get("encrypted_outputs") {
+    if let Some(outputs) = data.get("encrypted_outputs") {
         let outputs: Vec<String> = serde_json::from_value(outputs.clone()).unwrap_or_default();
         let has_more = data.get("hasMore").and_then(|v| v.as_bool()).unwrap_or(false);
-        let total = data.get("total").and_then(|v| v.as_u64()).unwrap_or(0);
-        (outputs, has_more, total)
+        Ok((outputs, has_more))
     } else if data.is_array() {
         let utxos: Vec<ApiUtxo> = serde_json::from_value(data.clone()).unwrap_or_default();
         let outputs: Vec<String> = utxos
@@ -194,62 +413,58 @@ async fn fetch_user_utxos(
                 }
             })
             .collect();
-        let len = outputs.len() as u64;
-        (outputs, false, len)
+        Ok((outputs, false))
     } else {
-        return Err(PrivacyCashError::ApiError("Unexpected API response format".to_string()));
-    };
-
-    let len = encrypted_outputs.len() as u64;
-
-    // Decrypt outputs
-    let (utxos, decrypted_outputs) =
-        decrypt_outputs(&encrypted_outputs, encryption_service, None).await?;
-
-    // Also check cached outputs if no more to fetch
-    let mut all_utxos = utxos;
-    let mut all_outputs = decrypted_outputs;
-
-    if !has_more {
-        if let Some(cached) = storage.get(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key)) {
-            if let Ok(cached_outputs) = serde_json::from_str::<Vec<String>>(&cached) {
-                let (cached_utxos, cached_decrypted) =
-                    decrypt_outputs(&cached_outputs, encryption_service, None).await?;
-                all_utxos.extend(cached_utxos);
-                all_outputs.extend(cached_decrypted);
-            }
-        }
+        Err(PrivacyCashError::ApiError("Unexpected API response format".to_string()))
     }
-
-    Ok((all_utxos, all_outputs, has_more, len))
 }
 
-/// Decrypt encrypted outputs
-async fn decrypt_outputs(
+/// Decrypt the entries addressed to this wallet, skipping malformed ones
+///
+/// Returns the notes, their encrypted outputs and what was skipped. Entries
+/// for other wallets are silently ignored; entries that can't be decoded at
+/// all are logged and counted.
+pub(crate) fn decrypt_entries(
     encrypted_outputs: &[String],
     encryption_service: &EncryptionService,
-    token_name: Option<&str>,
-) -> Result<(Vec<Utxo>, Vec<String>)> {
+) -> (Vec<Utxo>, Vec<String>, ScanDiagnostics) {
     let mut utxos = Vec::new();
     let mut outputs = Vec::new();
+    let mut diagnostics = ScanDiagnostics::default();
 
     for encrypted in encrypted_outputs {
         if encrypted.is_empty() {
             continue;
         }
 
-        match encryption_service.decrypt_utxo_from_hex(encrypted) {
-            Ok(utxo) => {
+        let decoded = hex::decode(encrypted)
+            .map_err(|e| PrivacyCashError::DecryptionError(format!("Invalid hex: {}", e)))
+            .and_then(|data| encryption_service.try_decrypt_utxo(&data));
+        match decoded {
+            Ok(Some(utxo)) => {
                 utxos.push(utxo);
                 outputs.push(encrypted.clone());
             }
-            Err(_) => {
-                // UTXO doesn't belong to this user, skip
-                continue;
+            // UTXO doesn't belong to this user, skip
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!("Skipping malformed encrypted output: {}", e);
+                diagnostics.malformed_entries += 1;
             }
         }
     }
 
+    (utxos, outputs, diagnostics)
+}
+
+/// Decrypt encrypted outputs
+pub(crate) async fn decrypt_outputs(
+    encrypted_outputs: &[String],
+    encryption_service: &EncryptionService,
+    token_name: Option<&str>,
+) -> Result<(Vec<Utxo>, Vec<String>, ScanDiagnostics)> {
+    let (mut utxos, outputs, diagnostics) = decrypt_entries(encrypted_outputs, encryption_service);
+
     // Fetch real indices for decrypted UTXOs
     if !outputs.is_empty() {
         let indices = fetch_utxo_indices(&outputs, token_name).await?;
@@ -261,11 +476,11 @@ async fn decrypt_outputs(
         }
     }
 
-    Ok((utxos, outputs))
+    Ok((utxos, outputs, diagnostics))
 }
 
 /// Fetch UTXO indices from API
-async fn fetch_utxo_indices(encrypted_outputs: &[String], token_name: Option<&str>) -> Result<Vec<u64>> {
+pub(crate) async fn fetch_utxo_indices(encrypted_outputs: &[String], token_name: Option<&str>) -> Result<Vec<u64>> {
     let mut url = format!("{}/utxos/indices", *RELAYER_API_URL);
 
     let body = if let Some(token) = token_name {
@@ -279,13 +494,7 @@ async fn fetch_utxo_indices(encrypted_outputs: &[String], token_name: Option<&st
         })
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch indices: {}", e)))?;
+    let response = relayer::send(relayer::post(&url).json(&body), "Failed to fetch indices").await?;
 
     if !response.status().is_success() {
         return Err(PrivacyCashError::ApiError(format!(
@@ -302,8 +511,30 @@ async fn fetch_utxo_indices(encrypted_outputs: &[String], token_name: Option<&st
     Ok(data.indices)
 }
 
+/// A freshly created note with the leaf index the relayer assigned it
+///
+/// Returns `None` when the index can't be fetched, so callers can fall back
+/// to a full scan.
+pub(crate) async fn confirmed_output(
+    output: &Utxo,
+    encrypted_output: &[u8],
+    token_name: Option<&str>,
+) -> Option<Utxo> {
+    let encrypted = [hex::encode(encrypted_output)];
+    match fetch_utxo_indices(&encrypted, token_name).await {
+        Ok(indices) => indices.first().map(|&index| Utxo {
+            index,
+            ..output.clone()
+        }),
+        Err(e) => {
+            log::debug!("Could not fetch the leaf index of the new note: {}", e);
+            None
+        }
+    }
+}
+
 /// Check if UTXOs are spent
-async fn are_utxos_spent(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<bool>> {
+pub(crate) async fn are_utxos_spent(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<bool>> {
     let mut all_pdas = Vec::new();
 
     for (i, utxo) in utxos.iter().enumerate() {
@@ -337,6 +568,18 @@ async fn are_utxos_spent(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<b
     Ok(spent_flags)
 }
 
+/// Fail if any of `utxos` has been spent on-chain
+pub(crate) async fn ensure_unspent(connection: &RpcClient, utxos: &[Utxo]) -> Result<()> {
+    let spent = are_utxos_spent(connection, utxos).await?;
+    match utxos.iter().zip(spent).find(|(_, spent)| *spent) {
+        Some((utxo, _)) => Err(PrivacyCashError::InvalidInput(format!(
+            "Input note {} is already spent",
+            utxo.index
+        ))),
+        None => Ok(()),
+    }
+}
+
 /// Check if a single UTXO is spent
 pub async fn is_utxo_spent(connection: &RpcClient, utxo: &Utxo) -> Result<bool> {
     let result = are_utxos_spent(connection, &[utxo.clone()]).await?;
@@ -368,3 +611,65 @@ pub async fn get_private_balance(
     let utxos = get_utxos(connection, public_key, encryption_service, storage, None).await?;
     Ok(get_balance_from_utxos(&utxos))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    fn service() -> EncryptionService {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new());
+        service
+    }
+
+    #[test]
+    fn test_decrypt_entries_skips_corrupt_entry() {
+        let ours = service();
+        let keypair = crate::keypair::ZkKeypair::from_hex(&ours.get_utxo_private_key_v2().unwrap()).unwrap();
+        let note = Utxo::new(1_000u64, keypair, 0, None, None);
+        let valid = hex::encode(ours.encrypt_utxo(&note).unwrap());
+        let other_wallet = hex::encode(service().encrypt_utxo(&note).unwrap());
+        let garbled_note = hex::encode(ours.encrypt(b"not|a|note").unwrap());
+
+        let batch = vec![
+            valid.clone(),
+            "zz".to_string(),
+            other_wallet,
+            // Cut off inside the IV
+            valid[..40].to_string(),
+            String::new(),
+            garbled_note,
+            valid.clone(),
+        ];
+        let (utxos, outputs, diagnostics) = decrypt_entries(&batch, &ours);
+
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(outputs, vec![valid.clone(), valid]);
+        assert!(utxos.iter().all(|u| u.amount_u64() == 1_000));
+        assert_eq!(diagnostics.malformed_entries, 3);
+    }
+
+    #[test]
+    fn test_filter_spent() {
+        let keypair = crate::keypair::ZkKeypair::from_hex(&service().get_utxo_private_key_v2().unwrap()).unwrap();
+        let notes: Vec<Utxo> = [100u64, 200, 300]
+            .into_iter()
+            .enumerate()
+            .map(|(i, amount)| Utxo::new(amount, keypair.clone(), i as u64, None, None))
+            .collect();
+        let indexed = || notes.iter().enumerate().collect::<Vec<_>>();
+
+        let mut diagnostics = ScanDiagnostics::default();
+        let kept = filter_spent(indexed(), Some(vec![false, true, true]), &mut diagnostics);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].1.amount_u64(), 100);
+        assert_eq!(diagnostics.spent_notes, 2);
+        assert_eq!(diagnostics.spent_amount, 500);
+
+        // Check skipped: everything is kept and nothing is reported
+        let mut diagnostics = ScanDiagnostics::default();
+        assert_eq!(filter_spent(indexed(), None, &mut diagnostics).len(), 3);
+        assert_eq!(diagnostics, ScanDiagnostics::default());
+    }
+}
@@ -6,7 +6,8 @@ use crate::constants::{
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::localstorage_key;
+use crate::get_utxos::{decrypt_entries, filter_spent, localstorage_key, ScanDiagnostics, ScanOptions};
+use crate::relayer;
 use crate::storage::Storage;
 use crate::utxo::{get_balance_from_utxos_spl, SplBalance, Utxo};
 use num_bigint::BigUint;
@@ -26,6 +27,53 @@ pub async fn get_utxos_spl(
     mint_address: &Pubkey,
     abort_signal: Option<Arc<Mutex<bool>>>,
 ) -> Result<Vec<Utxo>> {
+    get_utxos_spl_with_diagnostics(
+        connection,
+        public_key,
+        encryption_service,
+        storage,
+        mint_address,
+        abort_signal,
+    )
+    .await
+    .map(|(utxos, _)| utxos)
+}
+
+/// Fetch all SPL token UTXOs for a user, reporting entries that had to be skipped
+///
+/// The SPL counterpart of [`get_utxos_with_diagnostics`](crate::get_utxos::get_utxos_with_diagnostics).
+pub async fn get_utxos_spl_with_diagnostics(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    mint_address: &Pubkey,
+    abort_signal: Option<Arc<Mutex<bool>>>,
+) -> Result<(Vec<Utxo>, ScanDiagnostics)> {
+    get_utxos_spl_with_options(
+        connection,
+        public_key,
+        encryption_service,
+        storage,
+        mint_address,
+        abort_signal,
+        ScanOptions::default(),
+    )
+    .await
+}
+
+/// Fetch all SPL token UTXOs for a user with explicit [`ScanOptions`]
+///
+/// The SPL counterpart of [`get_utxos_with_options`](crate::get_utxos::get_utxos_with_options).
+pub async fn get_utxos_spl_with_options(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    mint_address: &Pubkey,
+    abort_signal: Option<Arc<Mutex<bool>>>,
+    options: ScanOptions,
+) -> Result<(Vec<Utxo>, ScanDiagnostics)> {
     let token = find_token_by_mint(mint_address)
         .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
 
@@ -35,11 +83,13 @@ pub async fn get_utxos_spl(
     let ata = get_associated_token_address(public_key, mint_address);
     let storage_key = localstorage_key(&ata);
 
+    let mut diagnostics = ScanDiagnostics::default();
     let mut valid_utxos = Vec::new();
     let mut valid_strings = Vec::new();
+    let mut seen_outputs = std::collections::HashSet::new();
 
     // Get starting offset from storage
-    let round_start_index: u64 = storage
+    let mut round_start_index: u64 = storage
         .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
         .and_then(|s| s.parse().ok())
         .unwrap_or(0);
@@ -52,13 +102,18 @@ pub async fn get_utxos_spl(
             }
         }
 
-        let fetch_offset: u64 = storage
-            .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0)
-            .max(round_start_index);
+        // An unchecked scan doesn't move the stored offset (see below)
+        let fetch_offset: u64 = if options.check_spent {
+            storage
+                .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0)
+                .max(round_start_index)
+        } else {
+            round_start_index
+        };
 
-        let fetch_end = fetch_offset + FETCH_UTXOS_GROUP_SIZE;
+        let fetch_end = fetch_offset.saturating_add(FETCH_UTXOS_GROUP_SIZE);
         let url = format!(
             "{}/utxos/range?token={}&start={}&end={}",
             *RELAYER_API_URL, token.name, fetch_offset, fetch_end
@@ -66,9 +121,10 @@ pub async fn get_utxos_spl(
 
         log::debug!("Fetching SPL UTXOs from: {}", url);
 
-        let (fetched_utxos, encrypted_outputs, has_more, len) =
+        let (fetched_utxos, encrypted_outputs, has_more, len, batch_diagnostics) =
             fetch_user_utxos_spl(&url, encryption_service, storage, &storage_key, token.name)
                 .await?;
+        diagnostics.add(batch_diagnostics);
 
         // Check which UTXOs are unspent
         let non_zero_utxos: Vec<_> = fetched_utxos
@@ -78,31 +134,46 @@ pub async fn get_utxos_spl(
             .collect();
 
         if !non_zero_utxos.is_empty() {
-            let spent_flags = are_utxos_spent_spl(
-                connection,
-                &non_zero_utxos
-                    .iter()
-                    .map(|(_, u)| (*u).clone())
-                    .collect::<Vec<_>>(),
-            )
-            .await?;
-
-            for ((idx, utxo), is_spent) in non_zero_utxos.into_iter().zip(spent_flags) {
-                if !is_spent {
-                    log::debug!("Found unspent SPL UTXO: {:?}", encrypted_outputs.get(idx));
-                    valid_utxos.push(utxo.clone());
-                    if let Some(enc) = encrypted_outputs.get(idx) {
-                        valid_strings.push(enc.clone());
+            let spent_flags = if options.check_spent {
+                Some(
+                    are_utxos_spent_spl(
+                        connection,
+                        &non_zero_utxos
+                            .iter()
+                            .map(|(_, u)| (*u).clone())
+                            .collect::<Vec<_>>(),
+                    )
+                    .await?,
+                )
+            } else {
+                None
+            };
+
+            for (idx, utxo) in filter_spent(non_zero_utxos, spent_flags, &mut diagnostics) {
+                log::debug!("Found unspent SPL UTXO: {:?}", encrypted_outputs.get(idx));
+                if let Some(enc) = encrypted_outputs.get(idx) {
+                    // A cursor moved back for new receive indices finds
+                    // cached notes again
+                    if !seen_outputs.insert(enc.clone()) {
+                        continue;
                     }
+                    valid_strings.push(enc.clone());
                 }
+                valid_utxos.push(utxo.clone());
             }
         }
 
-        // Update storage offset
-        storage.set(
-            &format!("{}{}", LSK_FETCH_OFFSET, storage_key),
-            &(fetch_offset + len).to_string(),
-        );
+        // Update storage offset. Without the spent check the notes found
+        // may be spent, so neither the offset nor the cache below is
+        // updated: the next checked scan looks at this range again
+        if options.check_spent {
+            storage.set(
+                &format!("{}{}", LSK_FETCH_OFFSET, storage_key),
+                &fetch_offset.saturating_add(len).to_string(),
+            );
+        } else {
+            round_start_index = fetch_offset.saturating_add(len);
+        }
 
         if !has_more {
             break;
@@ -118,10 +189,12 @@ pub async fn get_utxos_spl(
         .into_iter()
         .collect();
 
-    storage.set(
-        &format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key),
-        &serde_json::to_string(&unique_strings).unwrap_or_default(),
-    );
+    if options.check_spent {
+        storage.set(
+            &format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key),
+            &serde_json::to_string(&unique_strings).unwrap_or_default(),
+        );
+    }
 
     // Filter UTXOs to only include those matching the mint address
     let filtered_utxos: Vec<_> = valid_utxos
@@ -129,7 +202,23 @@ pub async fn get_utxos_spl(
         .filter(|u| u.mint_address == mint_address.to_string())
         .collect();
 
-    Ok(filtered_utxos)
+    if diagnostics.malformed_entries > 0 {
+        log::warn!(
+            "Skipped {} malformed {} UTXO entries",
+            diagnostics.malformed_entries,
+            token.name
+        );
+    }
+    if diagnostics.spent_notes > 0 {
+        log::info!(
+            "Excluded {} spent {} notes ({} base units)",
+            diagnostics.spent_notes,
+            token.name,
+            diagnostics.spent_amount
+        );
+    }
+
+    Ok((filtered_utxos, diagnostics))
 }
 
 /// Fetch SPL UTXOs from API and decrypt
@@ -139,10 +228,8 @@ async fn fetch_user_utxos_spl(
     storage: &Storage,
     storage_key: &str,
     token_name: &str,
-) -> Result<(Vec<Utxo>, Vec<String>, bool, u64)> {
-    let response = reqwest::get(url)
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch SPL UTXOs: {}", e)))?;
+) -> Result<(Vec<Utxo>, Vec<String>, bool, u64, ScanDiagnostics)> {
+    let response = relayer::send(relayer::get(url), "Failed to fetch SPL UTXOs").await?;
 
     if !response.status().is_success() {
         return Err(PrivacyCashError::ApiError(format!(
@@ -193,7 +280,7 @@ async fn fetch_user_utxos_spl(
     let len = encrypted_outputs.len() as u64;
 
     // Decrypt outputs
-    let (utxos, decrypted_outputs) =
+    let (utxos, decrypted_outputs, mut diagnostics) =
         decrypt_outputs_spl(&encrypted_outputs, encryption_service, token_name).await?;
 
     // Also check cached outputs if no more to fetch
@@ -203,15 +290,16 @@ async fn fetch_user_utxos_spl(
     if !has_more {
         if let Some(cached) = storage.get(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key)) {
             if let Ok(cached_outputs) = serde_json::from_str::<Vec<String>>(&cached) {
-                let (cached_utxos, cached_decrypted) =
+                let (cached_utxos, cached_decrypted, cached_diagnostics) =
                     decrypt_outputs_spl(&cached_outputs, encryption_service, token_name).await?;
                 all_utxos.extend(cached_utxos);
                 all_outputs.extend(cached_decrypted);
+                diagnostics.add(cached_diagnostics);
             }
         }
     }
 
-    Ok((all_utxos, all_outputs, has_more, len))
+    Ok((all_utxos, all_outputs, has_more, len, diagnostics))
 }
 
 /// Decrypt encrypted SPL outputs
@@ -219,25 +307,8 @@ async fn decrypt_outputs_spl(
     encrypted_outputs: &[String],
     encryption_service: &EncryptionService,
     token_name: &str,
-) -> Result<(Vec<Utxo>, Vec<String>)> {
-    let mut utxos = Vec::new();
-    let mut outputs = Vec::new();
-
-    for encrypted in encrypted_outputs {
-        if encrypted.is_empty() {
-            continue;
-        }
-
-        match encryption_service.decrypt_utxo_from_hex(encrypted) {
-            Ok(utxo) => {
-                utxos.push(utxo);
-                outputs.push(encrypted.clone());
-            }
-            Err(_) => {
-                continue;
-            }
-        }
-    }
+) -> Result<(Vec<Utxo>, Vec<String>, ScanDiagnostics)> {
+    let (mut utxos, outputs, diagnostics) = decrypt_entries(encrypted_outputs, encryption_service);
 
     // Fetch real indices
     if !outputs.is_empty() {
@@ -250,7 +321,7 @@ async fn decrypt_outputs_spl(
         }
     }
 
-    Ok((utxos, outputs))
+    Ok((utxos, outputs, diagnostics))
 }
 
 /// Fetch UTXO indices for SPL tokens
@@ -262,13 +333,11 @@ async fn fetch_utxo_indices_spl(encrypted_outputs: &[String], token_name: &str)
         "token": token_name
     });
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch SPL indices: {}", e)))?;
+    let response = relayer::send(
+        relayer::post(&url).json(&body),
+        "Failed to fetch SPL indices",
+    )
+    .await?;
 
     if !response.status().is_success() {
         return Err(PrivacyCashError::ApiError(format!(
@@ -0,0 +1,191 @@
+//! Transaction history for the shielded account
+//!
+//! History is reconstructed from what the relayer and the chain expose: every
+//! encrypted output that decrypts with the user's key is a note the account
+//! received, and a note whose nullifier account exists on-chain was spent.
+//!
+//! Limits:
+//! - Notes carry no timestamps or signatures, so entries are ordered by leaf
+//!   index only.
+//! - Change outputs of the user's own withdrawals show up as incoming notes,
+//!   and a spent entry reports the full note amount, not the net amount that
+//!   left the pool.
+//! - Zero-value notes are skipped.
+
+use crate::constants::{
+    find_token_by_mint, FETCH_UTXOS_GROUP_SIZE, NATIVE_SOL_MINT_STR, RELAYER_API_URL,
+};
+use crate::encryption::EncryptionService;
+use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::{are_utxos_spent, decrypt_outputs};
+use crate::relayer;
+use crate::utxo::Utxo;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+/// Notes checked per `getMultipleAccounts` call (two nullifier PDAs each)
+const SPENT_CHECK_BATCH: usize = 50;
+
+/// Whether a history entry added or removed value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryDirection {
+    /// A note was received (deposit, incoming payment or change)
+    Incoming,
+    /// A note was spent (withdrawal or re-deposit)
+    Spent,
+}
+
+/// A single event in the shielded account history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Incoming or spent
+    pub direction: HistoryDirection,
+
+    /// Note amount in base units
+    pub amount: u64,
+
+    /// Note commitment (decimal string)
+    pub commitment: String,
+
+    /// Note nullifier, set for spent entries
+    pub nullifier: Option<String>,
+
+    /// Leaf index of the note in the Merkle tree
+    pub index: u64,
+
+    /// Mint address of the note
+    pub mint_address: String,
+}
+
+/// Reconstruct the history of the shielded account
+///
+/// `token` selects an SPL mint; `None` means SOL. This always scans the full
+/// relayer range, so it's slower than a balance query.
+pub async fn get_history(
+    connection: &RpcClient,
+    encryption_service: &EncryptionService,
+    token: Option<&Pubkey>,
+) -> Result<Vec<HistoryEntry>> {
+    let (token_name, mint_address) = match token {
+        None => (None, NATIVE_SOL_MINT_STR.to_string()),
+        Some(mint) => {
+            let info = find_token_by_mint(mint)
+                .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint.to_string()))?;
+            if info.name == "sol" {
+                (None, NATIVE_SOL_MINT_STR.to_string())
+            } else {
+                (Some(info.name), mint.to_string())
+            }
+        }
+    };
+
+    let mut notes = Vec::new();
+    let mut start = 0u64;
+    loop {
+        let mut url = format!(
+            "{}/utxos/range?start={}&end={}",
+            *RELAYER_API_URL,
+            start,
+            start.saturating_add(FETCH_UTXOS_GROUP_SIZE)
+        );
+        if let Some(name) = token_name {
+            url = format!("{}&token={}", url, name);
+        }
+
+        let response = relayer::send(relayer::get(&url), "Failed to fetch history").await?;
+        if !response.status().is_success() {
+            return Err(PrivacyCashError::ApiError(format!(
+                "UTXO API returned status: {}",
+                response.status()
+            )));
+        }
+
+        let data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse history: {}", e)))?;
+        let outputs: Vec<String> = data
+            .get("encrypted_outputs")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default();
+        let has_more = data.get("hasMore").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let (utxos, _, _) = decrypt_outputs(&outputs, encryption_service, token_name).await?;
+        notes.extend(
+            utxos
+                .into_iter()
+                .filter(|u| !u.is_dummy() && u.mint_address == mint_address),
+        );
+
+        start += outputs.len() as u64;
+        if !has_more || outputs.is_empty() {
+            break;
+        }
+    }
+
+    let mut spent = Vec::with_capacity(notes.len());
+    for batch in notes.chunks(SPENT_CHECK_BATCH) {
+        spent.extend(are_utxos_spent(connection, batch).await?);
+    }
+
+    build_history(&notes, &spent)
+}
+
+/// Turn notes and their spent flags into sorted history entries
+fn build_history(notes: &[Utxo], spent: &[bool]) -> Result<Vec<HistoryEntry>> {
+    let mut entries = Vec::new();
+
+    for (note, &is_spent) in notes.iter().zip(spent) {
+        let commitment = note.get_commitment()?;
+        let entry = HistoryEntry {
+            direction: HistoryDirection::Incoming,
+            amount: note.amount_u64(),
+            commitment,
+            nullifier: None,
+            index: note.index,
+            mint_address: note.mint_address.clone(),
+        };
+
+        if is_spent {
+            entries.push(HistoryEntry {
+                direction: HistoryDirection::Spent,
+                nullifier: Some(note.get_nullifier()?),
+                ..entry.clone()
+            });
+        }
+        entries.push(entry);
+    }
+
+    // A note's receipt sorts before its spend
+    entries.sort_by_key(|e| (e.index, e.direction == HistoryDirection::Spent));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::ZkKeypair;
+
+    #[test]
+    fn test_build_history() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let notes = vec![
+            Utxo::new(300u64, keypair.clone(), 7, None, None),
+            Utxo::new(100u64, keypair, 2, None, None),
+        ];
+
+        let entries = build_history(&notes, &[true, false]).unwrap();
+        let summary: Vec<_> = entries.iter().map(|e| (e.index, e.direction)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, HistoryDirection::Incoming),
+                (7, HistoryDirection::Incoming),
+                (7, HistoryDirection::Spent),
+            ]
+        );
+        assert!(entries[2].nullifier.is_some());
+        assert_eq!(entries[2].commitment, entries[1].commitment);
+    }
+}
@@ -0,0 +1,193 @@
+//! In-flight deposits and withdrawals, for graceful shutdown
+//!
+//! The client registers every deposit and withdrawal while it runs, so
+//! `PrivacyCash::shutdown` can wait for them. Operations still running when
+//! the wait times out are saved to storage as [`PendingOperation`]s; on the
+//! next launch, `PrivacyCash::interrupted_operations` lists them so the app
+//! can check what happened (e.g. with `history` or a balance refresh).
+//!
+//! Shutting down can't undo anything: a transaction already submitted may
+//! still land after the process exits.
+
+use crate::error::{PrivacyCashError, Result};
+use crate::record::Operation;
+use crate::storage::Storage;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+/// A deposit or withdrawal that was started but hadn't finished
+///
+/// Amounts are in base units of `token`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PendingOperation {
+    pub operation: Operation,
+    /// Token name, e.g. `"sol"` or `"usdc"`
+    pub token: String,
+    /// Amount requested
+    pub amount: u64,
+    /// Start time, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+}
+
+/// Operations a client is running
+#[derive(Default)]
+pub(crate) struct InFlight {
+    operations: Mutex<HashMap<u64, PendingOperation>>,
+    next_id: AtomicU64,
+    shutting_down: AtomicBool,
+    idle: Notify,
+}
+
+impl InFlight {
+    /// Register an operation until the returned guard is dropped
+    ///
+    /// Fails with `ShuttingDown` once [`shut_down`](Self::shut_down) was called.
+    pub(crate) fn begin(
+        self: &Arc<Self>,
+        operation: Operation,
+        token: &str,
+        amount: u64,
+    ) -> Result<InFlightGuard> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(PrivacyCashError::ShuttingDown);
+        }
+
+        let started_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.operations.lock().insert(
+            id,
+            PendingOperation {
+                operation,
+                token: token.to_string(),
+                amount,
+                started_at_ms,
+            },
+        );
+        Ok(InFlightGuard {
+            tracker: Arc::clone(self),
+            id,
+        })
+    }
+
+    /// Refuse new operations from now on
+    pub(crate) fn shut_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Operations running now, oldest first
+    pub(crate) fn pending(&self) -> Vec<PendingOperation> {
+        let mut pending: Vec<PendingOperation> = self.operations.lock().values().cloned().collect();
+        pending.sort_by_key(|op| op.started_at_ms);
+        pending
+    }
+
+    /// Wait up to `timeout` for all operations to finish; true if they did
+    pub(crate) async fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Created before the check, so a finish in between still wakes us
+            let idle = self.idle.notified();
+            if self.operations.lock().is_empty() {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, idle).await.is_err() {
+                return self.operations.lock().is_empty();
+            }
+        }
+    }
+}
+
+/// Keeps an operation registered while it runs
+pub(crate) struct InFlightGuard {
+    tracker: Arc<InFlight>,
+    id: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut operations = self.tracker.operations.lock();
+        operations.remove(&self.id);
+        if operations.is_empty() {
+            self.tracker.idle.notify_waiters();
+        }
+    }
+}
+
+/// Interrupted operations saved under `key`
+pub(crate) fn load_interrupted(storage: &Storage, key: &str) -> Vec<PendingOperation> {
+    storage
+        .get(key)
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Add `operations` to the interrupted operations saved under `key`
+pub(crate) fn save_interrupted(
+    storage: &Storage,
+    key: &str,
+    operations: &[PendingOperation],
+) -> Result<()> {
+    let mut saved = load_interrupted(storage, key);
+    saved.extend_from_slice(operations);
+    storage.set(key, &serde_json::to_string(&saved)?);
+    storage.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_idle() {
+        let tracker = Arc::new(InFlight::default());
+        assert!(tracker.wait_idle(Duration::ZERO).await);
+
+        let first = tracker.begin(Operation::Deposit, "sol", 1_000).unwrap();
+        let second = tracker.begin(Operation::Withdraw, "usdc", 5).unwrap();
+        assert_eq!(tracker.pending().len(), 2);
+        assert!(!tracker.wait_idle(Duration::from_millis(10)).await);
+
+        drop(first);
+        let finish = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(second);
+        });
+        assert!(tracker.wait_idle(Duration::from_secs(5)).await);
+        finish.await.unwrap();
+        assert!(tracker.pending().is_empty());
+
+        tracker.shut_down();
+        assert!(matches!(
+            tracker.begin(Operation::Deposit, "sol", 1),
+            Err(PrivacyCashError::ShuttingDown)
+        ));
+    }
+
+    #[test]
+    fn test_interrupted_operations_persist() {
+        let storage = Storage::memory();
+        let tracker = Arc::new(InFlight::default());
+        let _guard = tracker.begin(Operation::Withdraw, "sol", 42).unwrap();
+
+        save_interrupted(&storage, "key", &tracker.pending()).unwrap();
+        save_interrupted(&storage, "key", &tracker.pending()).unwrap();
+        let saved = load_interrupted(&storage, "key");
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].operation, Operation::Withdraw);
+        assert_eq!(saved[0].amount, 42);
+        assert!(load_interrupted(&storage, "other").is_empty());
+    }
+}
@@ -12,6 +12,34 @@ use ark_bn254::Fr;
 use ark_ff::{BigInteger, PrimeField};
 use num_bigint::BigUint;
 
+/// Poseidon domain tags for the hashes a keypair and its notes compute
+///
+/// Poseidon starts its state with a domain tag, so the same inputs hashed
+/// under different tags give unrelated outputs. The current circuit uses
+/// tag 0 everywhere, which is what [`Default`] gives; set other tags only
+/// for a circuit that separates these domains, since notes hashed with tags
+/// the circuit doesn't expect can't be spent.
+///
+/// | Tag | Hashes |
+/// |-----|--------|
+/// | `keypair` | public key `Poseidon(privkey)`, sub-key `Poseidon(privkey, index)` |
+/// | `commitment` | note commitment `Poseidon(amount, pubkey, blinding, mint)` |
+/// | `signature` | `Poseidon(privkey, commitment, index)` |
+/// | `nullifier` | `Poseidon(commitment, index, signature)` |
+///
+/// Merkle tree nodes always use tag 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainTags {
+    /// Public key and sub-key derivation
+    pub keypair: BigUint,
+    /// Note commitments
+    pub commitment: BigUint,
+    /// Spend signatures
+    pub signature: BigUint,
+    /// Nullifiers
+    pub nullifier: BigUint,
+}
+
 /// ZK Keypair for UTXO ownership
 ///
 /// This keypair uses Poseidon hashing for the public key derivation,
@@ -22,6 +50,8 @@ pub struct ZkKeypair {
     privkey: BigUint,
     /// Public key = Poseidon(privkey)
     pubkey: BigUint,
+    /// Domain tags for this key's hashes
+    domain_tags: DomainTags,
 }
 
 impl std::fmt::Debug for ZkKeypair {
@@ -47,17 +77,33 @@ impl ZkKeypair {
         let privkey = raw_decimal % &*FIELD_SIZE;
 
         // Compute public key using native Poseidon hash
-        let pubkey = Self::poseidon_hash(&[privkey.clone()])?;
+        let pubkey = Self::poseidon_hash(std::slice::from_ref(&privkey))?;
 
-        Ok(Self { privkey, pubkey })
+        Ok(Self { privkey, pubkey, domain_tags: DomainTags::default() })
     }
 
     /// Create a new keypair from raw bytes
+    ///
+    /// `bytes` is the big-endian private key. The key gets the default
+    /// domain tags; [`Utxo::from_bytes`](crate::utxo::Utxo::from_bytes)
+    /// restores a note's own tags with [`with_domain_tags`](Self::with_domain_tags).
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
         let raw_decimal = BigUint::from_bytes_be(bytes);
         let privkey = raw_decimal % &*FIELD_SIZE;
-        let pubkey = Self::poseidon_hash(&[privkey.clone()])?;
-        Ok(Self { privkey, pubkey })
+        let pubkey = Self::poseidon_hash(std::slice::from_ref(&privkey))?;
+        Ok(Self { privkey, pubkey, domain_tags: DomainTags::default() })
+    }
+
+    /// Keypair that knows only `pubkey`, for notes paid to someone else
+    ///
+    /// It can own outputs (commitments need only the public key) but can't
+    /// sign, so its notes can't be spent through it.
+    pub(crate) fn from_pubkey(pubkey: BigUint) -> Self {
+        Self {
+            privkey: BigUint::default(),
+            pubkey,
+            domain_tags: DomainTags::default(),
+        }
     }
 
     /// Generate a new random keypair
@@ -72,6 +118,40 @@ impl ZkKeypair {
         Self::from_hex(&hex_str)
     }
 
+    /// Derive the sub-key at `index`
+    ///
+    /// privkey = Poseidon(privkey, index). Sub-keys are deterministic, so a
+    /// wallet can always recover them from its base key, but their public
+    /// keys can't be linked to the base public key without the private key.
+    /// The sub-key keeps this key's domain tags.
+    pub fn derive_child(&self, index: u32) -> Result<Self> {
+        let tag = &self.domain_tags.keypair;
+        let privkey =
+            Self::poseidon_hash_with_domain(&[self.privkey.clone(), BigUint::from(index)], tag)?;
+        let pubkey = Self::poseidon_hash_with_domain(std::slice::from_ref(&privkey), tag)?;
+        Ok(Self {
+            privkey,
+            pubkey,
+            domain_tags: self.domain_tags.clone(),
+        })
+    }
+
+    /// Use `domain_tags` for this key's hashes
+    ///
+    /// Recomputes the public key under the new keypair tag. See
+    /// [`DomainTags`] for which tag covers which hash.
+    pub fn with_domain_tags(mut self, domain_tags: DomainTags) -> Result<Self> {
+        self.pubkey =
+            Self::poseidon_hash_with_domain(std::slice::from_ref(&self.privkey), &domain_tags.keypair)?;
+        self.domain_tags = domain_tags;
+        Ok(self)
+    }
+
+    /// Domain tags used for this key's hashes
+    pub fn domain_tags(&self) -> &DomainTags {
+        &self.domain_tags
+    }
+
     /// Get the private key as a BigUint
     pub fn privkey(&self) -> &BigUint {
         &self.privkey
@@ -96,6 +176,9 @@ impl ZkKeypair {
     ///
     /// signature = Poseidon(privkey, commitment, merklePath)
     pub fn sign(&self, commitment: &str, merkle_path: &str) -> Result<String> {
+        if self.privkey == BigUint::default() {
+            return Err(PrivacyCashError::InvalidKeypair("Keypair has no private key".to_string()));
+        }
         let inputs = vec![
             self.privkey.clone(),
             BigUint::parse_bytes(commitment.as_bytes(), 10)
@@ -104,7 +187,7 @@ impl ZkKeypair {
                 .ok_or_else(|| PrivacyCashError::InvalidKeypair("Invalid merkle path".to_string()))?,
         ];
 
-        let result = Self::poseidon_hash(&inputs)?;
+        let result = Self::poseidon_hash_with_domain(&inputs, &self.domain_tags.signature)?;
         Ok(result.to_string())
     }
 
@@ -112,6 +195,13 @@ impl ZkKeypair {
     ///
     /// This uses the circom-compatible Poseidon hash with BN254 curve parameters.
     pub fn poseidon_hash(inputs: &[BigUint]) -> Result<BigUint> {
+        Self::poseidon_hash_with_domain(inputs, &BigUint::default())
+    }
+
+    /// Compute Poseidon hash of multiple inputs under `domain_tag`
+    ///
+    /// A zero tag gives the same result as [`ZkKeypair::poseidon_hash`].
+    pub fn poseidon_hash_with_domain(inputs: &[BigUint], domain_tag: &BigUint) -> Result<BigUint> {
         let num_inputs = inputs.len();
         if num_inputs == 0 || num_inputs > 12 {
             return Err(PrivacyCashError::InvalidKeypair(
@@ -120,20 +210,10 @@ impl ZkKeypair {
         }
 
         // Convert BigUint inputs to Fr field elements
-        let fr_inputs: Vec<Fr> = inputs
-            .iter()
-            .map(|input| {
-                let bytes = input.to_bytes_be();
-                let mut padded = [0u8; 32];
-                let start = 32usize.saturating_sub(bytes.len());
-                let len = bytes.len().min(32);
-                padded[start..start + len].copy_from_slice(&bytes[..len]);
-                Fr::from_be_bytes_mod_order(&padded)
-            })
-            .collect();
+        let fr_inputs: Vec<Fr> = inputs.iter().map(to_field).collect();
 
         // Create Poseidon hasher and compute hash
-        let mut poseidon = Poseidon::<Fr>::new_circom(num_inputs)
+        let mut poseidon = Poseidon::<Fr>::with_domain_tag_circom(num_inputs, to_field(domain_tag))
             .map_err(|e| PrivacyCashError::InvalidKeypair(format!("Poseidon error: {:?}", e)))?;
         
         let hash = poseidon.hash(&fr_inputs)
@@ -146,6 +226,11 @@ impl ZkKeypair {
 
     /// Compute Poseidon hash from string inputs (for compatibility with JS SDK)
     pub fn poseidon_hash_strings(inputs: &[&str]) -> Result<String> {
+        Self::poseidon_hash_strings_with_domain(inputs, &BigUint::default())
+    }
+
+    /// Compute Poseidon hash from string inputs under `domain_tag`
+    pub fn poseidon_hash_strings_with_domain(inputs: &[&str], domain_tag: &BigUint) -> Result<String> {
         let biguint_inputs: Vec<BigUint> = inputs
             .iter()
             .map(|s| {
@@ -154,11 +239,23 @@ impl ZkKeypair {
             })
             .collect::<Result<Vec<_>>>()?;
 
-        let result = Self::poseidon_hash(&biguint_inputs)?;
+        let result = Self::poseidon_hash_with_domain(&biguint_inputs, domain_tag)?;
         Ok(result.to_string())
     }
 }
 
+/// Field element for `value`, reduced modulo the field size
+///
+/// Only the low 32 bytes are used.
+fn to_field(value: &BigUint) -> Fr {
+    let bytes = value.to_bytes_be();
+    let mut padded = [0u8; 32];
+    let start = 32usize.saturating_sub(bytes.len());
+    let len = bytes.len().min(32);
+    padded[start..start + len].copy_from_slice(&bytes[..len]);
+    Fr::from_be_bytes_mod_order(&padded)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,6 +276,16 @@ mod tests {
         assert!(!keypair.pubkey().is_zero());
     }
 
+    #[test]
+    fn test_derive_child() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let child = keypair.derive_child(1).unwrap();
+
+        assert_eq!(child.pubkey(), keypair.derive_child(1).unwrap().pubkey());
+        assert_ne!(child.pubkey(), keypair.pubkey());
+        assert_ne!(child.pubkey(), keypair.derive_child(2).unwrap().pubkey());
+    }
+
     #[test]
     fn test_poseidon_hash_consistency() {
         // Test that poseidon hash produces consistent output
@@ -199,6 +306,78 @@ mod tests {
         assert!(parsed.is_some());
     }
 
+    #[test]
+    fn test_poseidon_hash_pinned_vectors() {
+        // circomlib poseidon([1]) .. poseidon([1, 2, 3, 4]), zero domain tag
+        let expected = [
+            "18586133768512220936620570745912940619677854269274689475585506675881198879027",
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+            "6542985608222806190361240322586112750744169038454362455181422643027100751666",
+            "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+        ];
+        for (n, hash) in expected.iter().enumerate() {
+            let inputs: Vec<BigUint> = (1..=n as u64 + 1).map(BigUint::from).collect();
+            assert_eq!(ZkKeypair::poseidon_hash(&inputs).unwrap().to_string(), *hash);
+            assert_eq!(
+                ZkKeypair::poseidon_hash_with_domain(&inputs, &BigUint::zero())
+                    .unwrap()
+                    .to_string(),
+                *hash
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_domain_tags_pinned() {
+        let keypair = ZkKeypair::from_hex("0x1").unwrap();
+        assert_eq!(keypair.domain_tags(), &DomainTags::default());
+        assert_eq!(
+            keypair.pubkey_string(),
+            "18586133768512220936620570745912940619677854269274689475585506675881198879027"
+        );
+
+        let child = keypair.derive_child(7).unwrap();
+        assert_eq!(
+            child.privkey_string(),
+            "2324422178138999802353597641701330110253732970029014650284828039388354214723"
+        );
+        assert_eq!(
+            child.pubkey_string(),
+            "4102721927888003240711402722958027925973651841812991258393403812879520034607"
+        );
+
+        let commitment =
+            "7184444811215116501090144094268518375109594839859746171892905847822799375342";
+        assert_eq!(
+            keypair.sign(commitment, "3").unwrap(),
+            "4517236728993996170433944730710066900178405777640499033053087117800710761747"
+        );
+    }
+
+    #[test]
+    fn test_domain_tags() {
+        let keypair = ZkKeypair::from_hex("0x1").unwrap();
+        let tags = DomainTags {
+            keypair: BigUint::from(1u8),
+            ..DomainTags::default()
+        };
+        let tagged = keypair.clone().with_domain_tags(tags.clone()).unwrap();
+        assert_eq!(tagged.privkey(), keypair.privkey());
+        assert_eq!(
+            tagged.pubkey_string(),
+            "7027819679231713986199003991063430595247146474200488173808208579716508981912"
+        );
+
+        // Sub-keys keep the tags
+        let child = tagged.derive_child(7).unwrap();
+        assert_eq!(child.domain_tags(), &tags);
+        assert_ne!(child.pubkey(), keypair.derive_child(7).unwrap().pubkey());
+
+        // Back to the default tags restores the original key
+        let restored = tagged.with_domain_tags(DomainTags::default()).unwrap();
+        assert_eq!(restored.pubkey(), keypair.pubkey());
+    }
+
     #[test]
     fn test_keypair_deterministic() {
         // Same private key should produce same public key
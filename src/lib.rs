@@ -22,7 +22,7 @@
 //!         "your_base58_private_key",  // Private key
 //!         "recipient_pubkey",          // Recipient address  
 //!         0.1,                         // Amount to send
-//!         "sol",                       // Token: "sol", "usdc", "usdt"
+//!         "sol",                       // Token: "sol", "usdc", "usdt", ...
 //!         None,                        // Optional RPC URL
 //!     ).await?;
 //!     
@@ -33,7 +33,10 @@
 //! }
 //! ```
 
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod client;
+pub mod cluster;
 pub mod config;
 pub mod constants;
 pub mod deposit;
@@ -42,24 +45,49 @@ pub mod encryption;
 pub mod error;
 pub mod get_utxos;
 pub mod get_utxos_spl;
+pub mod history;
+pub mod in_flight;
 pub mod keypair;
 pub mod merkle_tree;
+pub mod payment_request;
 pub mod poseidon;
+pub mod proof_format;
 pub mod prover;
 pub mod prover_rust;
+pub mod receive;
+pub mod record;
+pub mod relayer;
+pub mod self_test;
 pub mod storage;
+mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod token_registry;
 pub mod utxo;
 pub mod utils;
+pub mod watch;
 pub mod withdraw;
 pub mod withdraw_spl;
 
 // Re-export main types
-pub use client::PrivacyCash;
+pub use client::{AddressBook, PartnerFeeEvent, PrivacyCash};
+pub use in_flight::PendingOperation;
+pub use cluster::Cluster;
 pub use config::{Config, SupportedToken};
 pub use constants::*;
 pub use error::{PrivacyCashError, Result};
-pub use keypair::ZkKeypair;
-pub use utxo::{Utxo, Balance, SplBalance};
+pub use get_utxos::{ScanDiagnostics, ScanOptions};
+pub use history::{HistoryDirection, HistoryEntry};
+pub use keypair::{DomainTags, ZkKeypair};
+pub use payment_request::{parse_payment_request, PaymentRequest};
+pub use receive::ReceiveAddress;
+pub use record::{Operation, OperationOutcome, OperationRecord};
+pub use prover_rust::ProofSource;
+pub use self_test::{CheckStatus, SelfTestCheck, SelfTestReport};
+pub use token_registry::{TokenMetadata, TokenRegistry};
+pub use utils::{FeeRounding, SubmitMode};
+pub use utxo::{Utxo, UtxoVersion, Balance, SplBalance};
+pub use watch::IncomingNote;
 
 // Re-export Solana types for convenience
 pub use solana_sdk::{
@@ -72,9 +100,12 @@ pub use solana_sdk::{
 // ============================================================================
 
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Result of a send_privately operation
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct SendPrivatelyResult {
     /// Deposit transaction signature
     pub deposit_signature: String,
@@ -90,6 +121,140 @@ pub struct SendPrivatelyResult {
     pub recipient: String,
     /// Token type
     pub token: String,
+    /// Time spent depositing (proof generation + confirmation)
+    pub deposit_duration: Duration,
+    /// Time spent waiting for the indexer after the deposit (near zero when
+    /// the deposit reported its note's leaf index and the note was reused)
+    pub index_wait_duration: Duration,
+    /// Time spent withdrawing (proof generation + relay)
+    pub withdraw_duration: Duration,
+    /// Total wall-clock time
+    pub total_duration: Duration,
+    /// Explorer link for the deposit transaction
+    pub deposit_url: String,
+    /// Explorer link for the withdraw transaction
+    pub withdraw_url: String,
+}
+
+impl SendPrivatelyResult {
+    /// Amount deposited as a human-readable decimal (e.g. "0.1")
+    pub fn amount_deposited_display(&self) -> String {
+        self.format(self.amount_deposited)
+    }
+
+    /// Amount received as a human-readable decimal
+    pub fn amount_received_display(&self) -> String {
+        self.format(self.amount_received)
+    }
+
+    /// Total fees as a human-readable decimal
+    pub fn total_fees_display(&self) -> String {
+        self.format(self.total_fees)
+    }
+
+    fn format(&self, base_units: u64) -> String {
+        utils::format_token_amount(base_units, &self.token).unwrap_or_else(|_| base_units.to_string())
+    }
+}
+
+/// All-in cost of a private send, from [`PrivacyCash::estimate_send_cost`]
+///
+/// Token amounts are in the sent token's base units; SOL costs are in
+/// lamports. For SOL sends both are lamports but are kept apart so the
+/// protocol fees and network costs can be shown separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SendCostEstimate {
+    /// Token being sent
+    pub token: String,
+    /// Amount deposited
+    pub amount: u64,
+    /// Privacy Cash deposit fee
+    pub deposit_fee: u64,
+    /// Privacy Cash withdraw fee (rate plus rent), paid by the relayer and
+    /// deducted from the withdrawn amount
+    pub protocol_fee: u64,
+    /// Nova Shield partner fee, transferred from the public wallet
+    pub partner_fee: u64,
+    /// Amount the recipient should receive
+    pub amount_received: u64,
+    /// Network fees for the deposit and partner fee transactions (lamports)
+    pub transaction_fees_lamports: u64,
+    /// Rent for token accounts the sender has to create (lamports)
+    pub ata_rent_lamports: u64,
+    /// Whether the recipient already has a token account (`None` for SOL)
+    ///
+    /// A missing account is created during the withdrawal and paid for by
+    /// the rent part of `protocol_fee`.
+    pub recipient_token_account_exists: Option<bool>,
+}
+
+impl SendCostEstimate {
+    /// Deposit, protocol and partner fees, in token base units
+    pub fn total_fees(&self) -> u64 {
+        self.deposit_fee + self.protocol_fee + self.partner_fee
+    }
+
+    /// SOL needed on top of the token amount and fees (lamports)
+    pub fn network_cost_lamports(&self) -> u64 {
+        self.transaction_fees_lamports + self.ata_rent_lamports
+    }
+}
+
+/// What withdrawing a whole private balance of a token would take
+///
+/// Returned by [`PrivacyCash::withdrawal_plan`]. Amounts are in the token's
+/// base units. Partner fees are those of withdrawals to the client's own
+/// wallet, the default recipient of `withdraw_all`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WithdrawalPlan {
+    /// Token being withdrawn
+    pub token: String,
+    /// Number of withdrawal transactions, each with its own proof
+    pub transactions: usize,
+    /// Value of the notes withdrawn
+    pub amount: u64,
+    /// Privacy Cash withdraw fees, deducted from the withdrawn amounts
+    pub protocol_fee: u64,
+    /// Partner fees, paid as configured on the client
+    pub partner_fee: u64,
+    /// Amount the withdrawals deliver, after protocol fees
+    pub amount_received: u64,
+    /// Notes too small to withdraw, which are left behind
+    pub notes_left: usize,
+    /// Rough time for all transactions, from this device's proof benchmark
+    pub estimated_duration: Duration,
+}
+
+impl WithdrawalPlan {
+    /// Protocol and partner fees, in token base units
+    pub fn total_fees(&self) -> u64 {
+        self.protocol_fee + self.partner_fee
+    }
+}
+
+/// Outcome of [`PrivacyCash::shield_only`]
+///
+/// Amounts are in the token's base units.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ShieldResult {
+    /// Token shielded
+    pub token: String,
+    /// Deposit transaction signature
+    pub signature: String,
+    /// Amount moved into the pool
+    pub amount: u64,
+    /// The note the deposit created, with its leaf index
+    ///
+    /// `None` if the relayer didn't report the index; the note is still in
+    /// `shielded_balance`.
+    pub note: Option<Utxo>,
+    /// Private balance of the token once the deposit was indexed
+    pub shielded_balance: u64,
+    /// Prover that produced the deposit proof
+    pub proof_source: ProofSource,
 }
 
 /// 🚀 SEND PRIVATELY - The ONE function you need!
@@ -103,9 +268,12 @@ pub struct SendPrivatelyResult {
 /// * `private_key` - Your wallet's private key (base58 encoded)
 /// * `recipient` - Recipient's public key (base58 encoded)
 /// * `amount` - Amount to send (e.g., 0.1 for 0.1 SOL or 10.0 for 10 USDC)
-/// * `token` - Token type: "sol", "usdc", or "usdt"
+/// * `token` - Token name, alias or mint address (e.g. "sol", "USDC", "tether")
 /// * `rpc_url` - Optional RPC URL (defaults to mainnet)
 ///
+/// On mainnet this fails unless `ALLOW_MAINNET=true` is set, see
+/// [`PrivacyCash::set_allow_mainnet`].
+///
 /// # Example
 /// ```rust,no_run
 /// use privacy_cash::send_privately;
@@ -123,7 +291,8 @@ pub struct SendPrivatelyResult {
 ///     println!("✅ Sent privately!");
 ///     println!("Deposit TX: {}", result.deposit_signature);
 ///     println!("Withdraw TX: {}", result.withdraw_signature);
-///     println!("Recipient received: {} lamports", result.amount_received);
+///     println!("Recipient received: {} SOL", result.amount_received_display());
+///     println!("Took {:?} ({})", result.total_duration, result.withdraw_url);
 ///     Ok(())
 /// }
 /// ```
@@ -135,107 +304,487 @@ pub async fn send_privately(
     rpc_url: Option<&str>,
 ) -> Result<SendPrivatelyResult> {
     // Parse private key
-    let key_bytes = bs58::decode(private_key)
-        .into_vec()
-        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid private key: {}", e)))?;
-    let keypair = Keypair::from_bytes(&key_bytes)
-        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid keypair: {}", e)))?;
+    let keypair = utils::parse_keypair(private_key)?;
 
     // Parse recipient
     let recipient_pubkey = Pubkey::from_str(recipient)
         .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid recipient: {}", e)))?;
 
-    // Create client
+    // Resolve token (name, alias or mint address)
+    let token_info = resolve_token(token)?;
+    let base_units = (amount * token_info.units_per_token as f64) as u64;
+
+    send_privately_inner(keypair, recipient_pubkey, base_units, token_info, rpc_url, None).await
+}
+
+/// [`send_privately`] with an explicit referrer
+///
+/// The referrer is used for both the deposit and the withdrawal, overriding
+/// the `PARTNER_REFERRER` environment variable.
+pub async fn send_privately_with_referrer(
+    private_key: &str,
+    recipient: &str,
+    amount: f64,
+    token: &str,
+    rpc_url: Option<&str>,
+    referrer: &str,
+) -> Result<SendPrivatelyResult> {
+    let keypair = utils::parse_keypair(private_key)?;
+    let recipient_pubkey = Pubkey::from_str(recipient)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid recipient: {}", e)))?;
+    let token_info = resolve_token(token)?;
+    let base_units = (amount * token_info.units_per_token as f64) as u64;
+
+    send_privately_inner(keypair, recipient_pubkey, base_units, token_info, rpc_url, Some(referrer)).await
+}
+
+/// [`send_privately`] that stops once `abort_signal` is set to `true`
+///
+/// Fails with `Aborted` if the signal is seen during either step's UTXO scan
+/// or proof generation (see [`PrivacyCash::set_abort_signal`]). If it's set
+/// after the deposit landed, the deposited funds stay in the private balance
+/// and can be withdrawn later.
+pub async fn send_privately_with_abort(
+    private_key: &str,
+    recipient: &str,
+    amount: f64,
+    token: &str,
+    rpc_url: Option<&str>,
+    abort_signal: Arc<tokio::sync::Mutex<bool>>,
+) -> Result<SendPrivatelyResult> {
+    let keypair = utils::parse_keypair(private_key)?;
+    let recipient_pubkey = Pubkey::from_str(recipient)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid recipient: {}", e)))?;
+    let token_info = resolve_token(token)?;
+    let base_units = (amount * token_info.units_per_token as f64) as u64;
+
+    let mut client = send_client(keypair, rpc_url, None)?;
+    client.set_abort_signal(Some(abort_signal));
+    send_with_client(&client, recipient_pubkey, base_units, token_info).await
+}
+
+/// Pay a [`PaymentRequest`] privately
+///
+/// Same as [`send_privately`], with recipient, token and amount taken from
+/// the request. Fails if the request doesn't specify an amount.
+///
+/// # Example
+/// ```rust,no_run
+/// use privacy_cash::{parse_payment_request, send_payment_request};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let request = parse_payment_request("privacycash:<recipient>?token=usdc&amount=5000000")?;
+///     let result = send_payment_request("your_private_key_base58", &request, None).await?;
+///     println!("Paid: {}", result.withdraw_url);
+///     Ok(())
+/// }
+/// ```
+pub async fn send_payment_request(
+    private_key: &str,
+    request: &PaymentRequest,
+    rpc_url: Option<&str>,
+) -> Result<SendPrivatelyResult> {
+    let keypair = utils::parse_keypair(private_key)?;
+    let token_info = request.token()?;
+    let base_units = request.amount.ok_or_else(|| {
+        PrivacyCashError::InvalidInput("Payment request has no amount".to_string())
+    })?;
+
+    send_privately_inner(keypair, request.recipient, base_units, token_info, rpc_url, None).await
+}
+
+/// Deposit, wait for the indexer, then withdraw everything to the recipient
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "send_privately",
+        skip_all,
+        fields(
+            token = token_info.name,
+            amount = base_units,
+            deposit_signature = tracing::field::Empty,
+            withdraw_signature = tracing::field::Empty,
+        )
+    )
+)]
+async fn send_privately_inner(
+    keypair: Keypair,
+    recipient_pubkey: Pubkey,
+    base_units: u64,
+    token_info: TokenInfo,
+    rpc_url: Option<&str>,
+    referrer: Option<&str>,
+) -> Result<SendPrivatelyResult> {
+    let client = send_client(keypair, rpc_url, referrer)?;
+    send_with_client(&client, recipient_pubkey, base_units, token_info).await
+}
+
+/// Client for the one-call send functions
+fn send_client(keypair: Keypair, rpc_url: Option<&str>, referrer: Option<&str>) -> Result<PrivacyCash> {
     let rpc = rpc_url.unwrap_or("https://api.mainnet-beta.solana.com");
-    let client = PrivacyCash::new(rpc, keypair)?;
-
-    let token_lower = token.to_lowercase();
-    
-    match token_lower.as_str() {
-        "sol" => {
-            let lamports = (amount * 1_000_000_000.0) as u64;
-            
-            // Step 1: Deposit
-            log::info!("Step 1/3: Depositing {} SOL...", amount);
-            let deposit_result = client.deposit(lamports).await?;
-            log::info!("Deposit TX: {}", deposit_result.signature);
-            
-            // Step 2: Wait for indexer
-            log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            
-            // Step 3: Withdraw ALL to recipient
-            log::info!("Step 3/3: Withdrawing to recipient...");
-            let withdraw_result = client.withdraw_all(Some(&recipient_pubkey)).await?;
-            log::info!("Withdraw TX: {}", withdraw_result.signature);
-            
-            Ok(SendPrivatelyResult {
-                deposit_signature: deposit_result.signature,
-                withdraw_signature: withdraw_result.signature,
-                amount_deposited: lamports,
-                amount_received: withdraw_result.amount_in_lamports,
-                total_fees: lamports.saturating_sub(withdraw_result.amount_in_lamports),
-                recipient: recipient.to_string(),
-                token: "sol".to_string(),
-            })
+    let mut client = PrivacyCash::new(rpc, keypair)?;
+    if referrer.is_some() {
+        client.set_referrer(referrer);
+    }
+    Ok(client)
+}
+
+/// Deposit with `client`, then withdraw the deposit to the recipient
+async fn send_with_client(
+    client: &PrivacyCash,
+    recipient_pubkey: Pubkey,
+    base_units: u64,
+    token_info: TokenInfo,
+) -> Result<SendPrivatelyResult> {
+    let cluster = client.cluster();
+    let is_sol = token_info.mint == *SOL_MINT;
+    let total_start = Instant::now();
+
+    // Step 1: Deposit
+    telemetry::phase!("deposit");
+    log::info!(
+        "Step 1/3: Depositing {} {}...",
+        utils::format_amount(base_units, token_info.decimals()),
+        token_info.name.to_uppercase()
+    );
+    let deposit_start = Instant::now();
+    let (deposit_signature, deposited_note) = if is_sol {
+        let result = client.deposit(base_units).await?;
+        (result.signature, result.output_utxo)
+    } else {
+        let result = client.deposit_spl(base_units, &token_info.mint).await?;
+        (result.signature, result.output_utxo)
+    };
+    telemetry::record_signature!("deposit_signature", deposit_signature);
+    log::info!("Deposit TX: {}", deposit_signature);
+    if let Some(commitment) = client.deposit_commitment() {
+        log::info!("Waiting for the deposit to be {:?}...", commitment);
+        client.wait_for_deposit_commitment(&deposit_signature).await?;
+    }
+    let deposit_duration = deposit_start.elapsed();
+
+    // Step 2: Wait for indexer, unless the deposit already reported the
+    // note's leaf index (it has waited for the note to be indexed)
+    telemetry::phase!("index_wait");
+    let index_start = Instant::now();
+    if deposited_note.is_none() {
+        log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    } else {
+        log::info!("Step 2/3: Deposit indexed, reusing the new note");
+    }
+    let index_wait_duration = index_start.elapsed();
+
+    // Step 3: Withdraw the deposit to the recipient
+    telemetry::phase!("withdraw");
+    log::info!("Step 3/3: Withdrawing to recipient...");
+    let withdraw_start = Instant::now();
+    // The deposit fee came out of the deposited amount
+    let deposit_fee = utils::calculate_deposit_fee(base_units, client.get_config().await?.deposit_fee_rate)?;
+    let (amount, inputs) = match SendWithdrawal::new(deposited_note, base_units - deposit_fee) {
+        SendWithdrawal::Note { note, amount } => (amount, Some(vec![*note])),
+        SendWithdrawal::Amount(amount) => (amount, None),
+    };
+    let (withdraw_signature, amount_received, protocol_fee, partner_fee_signature) = if is_sol {
+        let result = client
+            .withdraw_inputs(amount, Some(&recipient_pubkey), inputs)
+            .await?;
+        (result.signature, result.amount_in_lamports, result.fee_in_lamports, result.partner_fee_signature)
+    } else {
+        let result = client
+            .withdraw_spl_inputs(amount, &token_info.mint, Some(&recipient_pubkey), None, inputs)
+            .await?;
+        (result.signature, result.base_units, result.fee_base_units, result.partner_fee_signature)
+    };
+    // Fees paid out of a consolidated note's change don't reduce what the
+    // recipient gets, so count them from the withdrawal itself
+    let partner_fee = match partner_fee_signature {
+        Some(_) => client.partner_fee_for(amount, &recipient_pubkey)?,
+        None => 0,
+    };
+    let total_fees = deposit_fee.saturating_add(protocol_fee).saturating_add(partner_fee);
+    let withdraw_duration = withdraw_start.elapsed();
+    telemetry::record_signature!("withdraw_signature", withdraw_signature);
+    log::info!(
+        "Withdraw TX: {} ({} {} received)",
+        withdraw_signature,
+        utils::format_amount(amount_received, token_info.decimals()),
+        token_info.name.to_uppercase()
+    );
+
+    Ok(SendPrivatelyResult {
+        deposit_url: cluster.explorer_tx_url(&deposit_signature),
+        withdraw_url: cluster.explorer_tx_url(&withdraw_signature),
+        deposit_signature,
+        withdraw_signature,
+        amount_deposited: base_units,
+        amount_received,
+        total_fees,
+        recipient: recipient_pubkey.to_string(),
+        token: token_info.name.to_string(),
+        deposit_duration,
+        index_wait_duration,
+        withdraw_duration,
+        total_duration: total_start.elapsed(),
+    })
+}
+
+/// Outcome of [`send_privately_multi`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct MultiSendResult {
+    /// Completed sends, in the order they were requested
+    pub sends: Vec<SendPrivatelyResult>,
+    /// Tokens whose send failed or wasn't started, with the reason
+    pub failures: Vec<(String, PrivacyCashError)>,
+}
+
+impl MultiSendResult {
+    /// Whether every requested send completed
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Total received by the recipient in `token`, in base units
+    pub fn amount_received(&self, token: &str) -> u64 {
+        self.sends
+            .iter()
+            .filter(|send| send.token == token)
+            .map(|send| send.amount_received)
+            .sum()
+    }
+}
+
+impl std::fmt::Display for MultiSendResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for send in &self.sends {
+            writeln!(
+                f,
+                "{}: sent {}, received {} ({})",
+                send.token.to_uppercase(),
+                send.amount_deposited_display(),
+                send.amount_received_display(),
+                send.withdraw_signature
+            )?;
         }
-        "usdc" => {
-            let base_units = (amount * 1_000_000.0) as u64;
-            
-            // Step 1: Deposit
-            log::info!("Step 1/3: Depositing {} USDC...", amount);
-            let deposit_result = client.deposit_usdc(base_units).await?;
-            log::info!("Deposit TX: {}", deposit_result.signature);
-            
-            // Step 2: Wait for indexer
-            log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            
-            // Step 3: Withdraw ALL to recipient
-            log::info!("Step 3/3: Withdrawing to recipient...");
-            let withdraw_result = client.withdraw_all_usdc(Some(&recipient_pubkey)).await?;
-            log::info!("Withdraw TX: {}", withdraw_result.signature);
-            
-            Ok(SendPrivatelyResult {
-                deposit_signature: deposit_result.signature,
-                withdraw_signature: withdraw_result.signature,
-                amount_deposited: base_units,
-                amount_received: withdraw_result.base_units,
-                total_fees: base_units.saturating_sub(withdraw_result.base_units),
-                recipient: recipient.to_string(),
-                token: "usdc".to_string(),
-            })
+        for (token, error) in &self.failures {
+            writeln!(f, "{}: failed: {}", token.to_uppercase(), error)?;
         }
-        "usdt" => {
-            let base_units = (amount * 1_000_000.0) as u64;
-            
-            // Step 1: Deposit
-            log::info!("Step 1/3: Depositing {} USDT...", amount);
-            let deposit_result = client.deposit_usdt(base_units).await?;
-            log::info!("Deposit TX: {}", deposit_result.signature);
-            
-            // Step 2: Wait for indexer
-            log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            
-            // Step 3: Withdraw ALL to recipient
-            log::info!("Step 3/3: Withdrawing to recipient...");
-            let withdraw_result = client.withdraw_all_spl(&USDT_MINT, Some(&recipient_pubkey)).await?;
-            log::info!("Withdraw TX: {}", withdraw_result.signature);
-            
-            Ok(SendPrivatelyResult {
-                deposit_signature: deposit_result.signature,
-                withdraw_signature: withdraw_result.signature,
-                amount_deposited: base_units,
-                amount_received: withdraw_result.base_units,
-                total_fees: base_units.saturating_sub(withdraw_result.base_units),
-                recipient: recipient.to_string(),
-                token: "usdt".to_string(),
-            })
+        Ok(())
+    }
+}
+
+/// Send several tokens privately to one recipient
+///
+/// Each `(token, amount)` pair is a full [`send_privately`]. Different
+/// tokens use separate Merkle trees and run concurrently; repeated tokens
+/// run one after another. Before anything is sent, the SOL each send needs
+/// from the public wallet (network fees, token account rent, and for SOL the
+/// amount and partner fee) is reserved against the wallet's balance; sends
+/// that don't fit are reported as `InsufficientBalance` failures and not
+/// started, so the wallet can't run out of SOL halfway through.
+///
+/// A failed send doesn't stop the others; see [`MultiSendResult::failures`].
+/// Only an invalid key, recipient or token, or an unreachable RPC, fails the
+/// whole call.
+///
+/// # Example
+/// ```rust,no_run
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let result = privacy_cash::send_privately_multi(
+///         "your_private_key_base58",
+///         "recipient_pubkey",
+///         &[("sol", 0.05), ("usdc", 10.0)],
+///         None,
+///     ).await?;
+///     print!("{}", result);
+///     Ok(())
+/// }
+/// ```
+pub async fn send_privately_multi(
+    private_key: &str,
+    recipient: &str,
+    amounts: &[(&str, f64)],
+    rpc_url: Option<&str>,
+) -> Result<MultiSendResult> {
+    let keypair = utils::parse_keypair(private_key)?;
+    let recipient_pubkey = Pubkey::from_str(recipient)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid recipient: {}", e)))?;
+    let sends = amounts
+        .iter()
+        .map(|(token, amount)| {
+            let token_info = resolve_token(token)?;
+            let base_units = (amount * token_info.units_per_token as f64) as u64;
+            Ok((token_info, base_units))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let client = send_client(keypair, rpc_url, None)?;
+
+    // Reserve the public SOL each send needs, in request order
+    let mut sol_needs = Vec::with_capacity(sends.len());
+    for (token_info, base_units) in &sends {
+        let need = client
+            .estimate_send_cost(*base_units, token_info.name, &recipient_pubkey)
+            .await
+            .and_then(|estimate| sol_needed(&estimate, token_info.mint == *SOL_MINT));
+        sol_needs.push(need);
+    }
+    let budget = reserve_sol(client.get_sol_balance()?, sol_needs);
+
+    // One task per token, running that token's sends in order
+    let mut by_token: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (i, (token_info, _)) in sends.iter().enumerate() {
+        if budget[i].is_err() {
+            continue;
+        }
+        match by_token.iter_mut().find(|(name, _)| *name == token_info.name) {
+            Some((_, indices)) => indices.push(i),
+            None => by_token.push((token_info.name, vec![i])),
+        }
+    }
+    let client = &client;
+    let sends = &sends;
+    let tasks = by_token.into_iter().map(|(_, indices)| async move {
+        let mut outcomes = Vec::with_capacity(indices.len());
+        for i in indices {
+            let (token_info, base_units) = sends[i].clone();
+            let token_name = token_info.name;
+            let outcome = send_with_client(client, recipient_pubkey, base_units, token_info).await;
+            if let Err(e) = &outcome {
+                log::warn!("Private send of {} failed: {}", token_name, e);
+            }
+            outcomes.push((i, outcome));
+        }
+        outcomes
+    });
+    let mut outcomes: Vec<(usize, Result<SendPrivatelyResult>)> =
+        futures::future::join_all(tasks).await.into_iter().flatten().collect();
+    for (i, reserved) in budget.into_iter().enumerate() {
+        if let Err(e) = reserved {
+            outcomes.push((i, Err(e)));
         }
-        _ => Err(PrivacyCashError::InvalidInput(format!(
-            "Unsupported token: {}. Use 'sol', 'usdc', or 'usdt'",
-            token
-        ))),
+    }
+    outcomes.sort_by_key(|(i, _)| *i);
+
+    let mut result = MultiSendResult {
+        sends: Vec::new(),
+        failures: Vec::new(),
+    };
+    for (i, outcome) in outcomes {
+        match outcome {
+            Ok(send) => result.sends.push(send),
+            Err(e) => result.failures.push((sends[i].0.name.to_string(), e)),
+        }
+    }
+    Ok(result)
+}
+
+/// Public SOL a send takes from the wallet, in lamports
+fn sol_needed(estimate: &SendCostEstimate, is_sol: bool) -> Result<u64> {
+    let mut need = estimate.network_cost_lamports();
+    if is_sol {
+        // SOL sends also deposit the amount and pay the partner fee in SOL
+        need = utils::checked_total(need, estimate.amount)?;
+        need = utils::checked_total(need, estimate.partner_fee)?;
+    }
+    Ok(need)
+}
+
+/// Reserve each need against `balance` in order
+///
+/// A need that doesn't fit into what's left fails with
+/// `InsufficientBalance`; later, smaller needs may still fit. Needs that
+/// couldn't be estimated pass their error through.
+fn reserve_sol(balance: u64, needs: Vec<Result<u64>>) -> Vec<Result<()>> {
+    let mut left = balance;
+    needs
+        .into_iter()
+        .map(|need| {
+            let need = need?;
+            if need > left {
+                return Err(PrivacyCashError::InsufficientBalance { need, have: left });
+            }
+            left -= need;
+            Ok(())
+        })
+        .collect()
+}
+
+/// What the withdrawal step of a send spends
+#[derive(Debug)]
+enum SendWithdrawal {
+    /// The deposited `amount`, from the note the deposit created, spent
+    /// without a scan. The note also holds any older notes the deposit
+    /// consolidated; they stay in the pool as change.
+    Note { note: Box<Utxo>, amount: u64 },
+    /// The deposited amount, from the notes a scan finds. Used when the
+    /// deposit didn't report the note's leaf index; the rest of the private
+    /// balance stays put.
+    Amount(u64),
+}
+
+impl SendWithdrawal {
+    fn new(deposited_note: Option<Utxo>, base_units: u64) -> Self {
+        match deposited_note {
+            Some(note) => Self::Note {
+                note: Box::new(note),
+                amount: base_units,
+            },
+            None => Self::Amount(base_units),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_sol() {
+        let reserved = reserve_sol(
+            100,
+            vec![
+                Ok(60),
+                Ok(50),
+                Err(PrivacyCashError::ConfigError("no rent fee".to_string())),
+                Ok(40),
+            ],
+        );
+        assert!(reserved[0].is_ok());
+        assert!(matches!(
+            reserved[1],
+            Err(PrivacyCashError::InsufficientBalance { need: 50, have: 40 })
+        ));
+        assert!(matches!(reserved[2], Err(PrivacyCashError::ConfigError(_))));
+        assert!(reserved[3].is_ok());
+    }
+
+    #[test]
+    fn test_send_withdraws_only_the_deposit() {
+        let service = {
+            let mut service = encryption::EncryptionService::new();
+            service.derive_encryption_key_from_wallet(&Keypair::new());
+            service
+        };
+        let keypair = keypair::ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap();
+        let note = Utxo::new(5_000u64, keypair.clone(), 3, None, None);
+
+        assert!(matches!(
+            SendWithdrawal::new(Some(note), 5_000),
+            SendWithdrawal::Note { note, amount: 5_000 } if note.index == 3
+        ));
+        // A deposit that consolidated a pre-existing note creates a larger
+        // one; only the deposit is sent, the older note's value stays private
+        let consolidated = Utxo::new(12_000u64, keypair, 4, None, None);
+        assert!(matches!(
+            SendWithdrawal::new(Some(consolidated), 5_000),
+            SendWithdrawal::Note { note, amount: 5_000 } if note.index == 4
+        ));
+        // No leaf index (e.g. a direct deposit): withdraw the deposited
+        // amount, never the whole balance
+        assert!(matches!(SendWithdrawal::new(None, 5_000), SendWithdrawal::Amount(5_000)));
     }
 }
@@ -0,0 +1,141 @@
+//! Shareable payment requests
+//!
+//! A payment request bundles a recipient address, a token and an optional
+//! amount into a single URI-style string that can be pasted or put in a QR
+//! code, e.g. `privacycash:<recipient>?token=usdc&amount=2500000`.
+
+use crate::constants::{find_token_by_mint, resolve_token, TokenInfo};
+use crate::error::{PrivacyCashError, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+/// URI scheme for payment requests
+pub const PAYMENT_REQUEST_SCHEME: &str = "privacycash";
+
+/// A request to be paid privately
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// Address that receives the withdrawal
+    pub recipient: Pubkey,
+
+    /// Mint of the requested token
+    pub mint: Pubkey,
+
+    /// Requested amount in base units (None lets the payer choose)
+    pub amount: Option<u64>,
+}
+
+impl PaymentRequest {
+    /// Create a payment request
+    ///
+    /// `token` may be a name, alias or mint address (see [`resolve_token`]).
+    pub fn new(recipient: Pubkey, token: &str, amount: Option<u64>) -> Result<Self> {
+        let token = resolve_token(token)?;
+        Ok(Self {
+            recipient,
+            mint: token.mint,
+            amount,
+        })
+    }
+
+    /// Token information for the requested mint
+    pub fn token(&self) -> Result<TokenInfo> {
+        find_token_by_mint(&self.mint)
+            .ok_or_else(|| PrivacyCashError::TokenNotSupported(self.mint.to_string()))
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = find_token_by_mint(&self.mint)
+            .map(|t| t.name.to_string())
+            .unwrap_or_else(|| self.mint.to_string());
+
+        write!(f, "{}:{}?token={}", PAYMENT_REQUEST_SCHEME, self.recipient, token)?;
+        if let Some(amount) = self.amount {
+            write!(f, "&amount={}", amount)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = PrivacyCashError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |msg: &str| PrivacyCashError::InvalidInput(format!("Invalid payment request: {}", msg));
+
+        let rest = s
+            .trim()
+            .strip_prefix(PAYMENT_REQUEST_SCHEME)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .ok_or_else(|| invalid("missing privacycash: prefix"))?;
+
+        let (recipient, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let recipient = Pubkey::from_str(recipient).map_err(|_| invalid("bad recipient address"))?;
+
+        let mut token = "sol";
+        let mut amount = None;
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            match pair.split_once('=') {
+                Some(("token", value)) => token = value,
+                Some(("amount", value)) => {
+                    amount = Some(value.parse::<u64>().map_err(|_| invalid("bad amount"))?);
+                }
+                // Ignore unknown parameters for forward compatibility
+                _ => {}
+            }
+        }
+
+        Self::new(recipient, token, amount)
+    }
+}
+
+/// Parse a payment request string
+pub fn parse_payment_request(s: &str) -> Result<PaymentRequest> {
+    s.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::USDC_MINT;
+
+    #[test]
+    fn test_payment_request_roundtrip() {
+        let recipient = Pubkey::new_unique();
+        let request = PaymentRequest::new(recipient, "USDC", Some(2_500_000)).unwrap();
+        let encoded = request.to_string();
+
+        assert_eq!(
+            encoded,
+            format!("privacycash:{}?token=usdc&amount=2500000", recipient)
+        );
+        assert_eq!(parse_payment_request(&encoded).unwrap(), request);
+    }
+
+    #[test]
+    fn test_payment_request_defaults() {
+        let recipient = Pubkey::new_unique();
+        let request = parse_payment_request(&format!("privacycash:{}", recipient)).unwrap();
+        assert_eq!(request.token().unwrap().name, "sol");
+        assert_eq!(request.amount, None);
+
+        let request = parse_payment_request(&format!(
+            "privacycash:{}?token={}&label=coffee",
+            recipient, *USDC_MINT
+        ))
+        .unwrap();
+        assert_eq!(request.mint, *USDC_MINT);
+    }
+
+    #[test]
+    fn test_payment_request_invalid() {
+        assert!(parse_payment_request("solana:abc").is_err());
+        assert!(parse_payment_request("privacycash:not-a-key").is_err());
+        let recipient = Pubkey::new_unique();
+        assert!(parse_payment_request(&format!("privacycash:{}?amount=1.5", recipient)).is_err());
+        assert!(parse_payment_request(&format!("privacycash:{}?token=doge", recipient)).is_err());
+    }
+}
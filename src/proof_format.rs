@@ -0,0 +1,250 @@
+//! Proof encodings other than the on-chain layout
+//!
+//! [`ProofBytes`] holds a proof in the transact instruction's layout (see
+//! [`g1_to_onchain`](crate::prover::g1_to_onchain) and
+//! [`g2_to_onchain`](crate::prover::g2_to_onchain)). For storing proofs or
+//! verifying them off-chain it converts to and from:
+//!
+//! - snarkjs JSON (`pi_a`, `pi_b`, `pi_c`), with each Fq2 coordinate in
+//!   snarkjs' `[c0, c1]` order, as `snarkjs groth16 verify` expects
+//! - arkworks' compressed `ark_groth16::Proof<Bn254>` encoding (128 bytes),
+//!   readable by Rust verifiers built on arkworks
+//!
+//! Conversions are lossless: converting back gives the same on-chain bytes.
+
+use crate::error::{PrivacyCashError, Result};
+use crate::prover::{parse_proof_to_bytes, Proof, ProofBytes};
+use ark_bn254::{Bn254, Fq, Fq2, G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_ff::{BigInteger, PrimeField};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use num_bigint::BigUint;
+
+/// Length of [`ProofBytes::to_groth16_bytes`]
+pub const GROTH16_COMPRESSED_LEN: usize = 128;
+
+impl ProofBytes {
+    /// The proof as snarkjs JSON (`pi_a`, `pi_b`, `pi_c`, `protocol`, `curve`)
+    pub fn to_snarkjs_json(&self) -> Result<serde_json::Value> {
+        self.validate()?;
+
+        let g1 = |bytes: &[u8]| {
+            vec![
+                decimal(&bytes[..32]),
+                decimal(&bytes[32..]),
+                "1".to_string(),
+            ]
+        };
+        // On-chain: x.c1 || x.c0 || y.c1 || y.c0
+        let b = &self.proof_b;
+        let proof = Proof {
+            pi_a: g1(&self.proof_a),
+            pi_b: vec![
+                vec![decimal(&b[32..64]), decimal(&b[..32])],
+                vec![decimal(&b[96..]), decimal(&b[64..96])],
+                vec!["1".to_string(), "0".to_string()],
+            ],
+            pi_c: g1(&self.proof_c),
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+        };
+        serde_json::to_value(proof).map_err(|e| PrivacyCashError::SerializationError(e.to_string()))
+    }
+
+    /// Parse a proof from snarkjs JSON, as produced by `snarkjs groth16 prove`
+    pub fn from_snarkjs_json(json: &serde_json::Value) -> Result<Self> {
+        let mut proof: Proof = serde_json::from_value(json.clone()).map_err(|e| {
+            PrivacyCashError::SerializationError(format!("Invalid snarkjs proof: {}", e))
+        })?;
+        if proof.protocol != "groth16" || proof.curve != "bn128" {
+            return Err(PrivacyCashError::SerializationError(format!(
+                "Expected a groth16 bn128 proof, got {} {}",
+                proof.protocol, proof.curve
+            )));
+        }
+
+        // `parse_proof_to_bytes` takes coordinates as [c1, c0]
+        for coord in proof.pi_b.iter_mut() {
+            coord.reverse();
+        }
+        let bytes = parse_proof_to_bytes(&proof)?;
+        // Reject points the program's verifier would
+        bytes.to_ark()?;
+        Ok(bytes)
+    }
+
+    /// The proof in arkworks' compressed Groth16 encoding
+    ///
+    /// Fails if a point isn't on the curve or in the right subgroup.
+    pub fn to_groth16_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(GROTH16_COMPRESSED_LEN);
+        self.to_ark()?
+            .serialize_compressed(&mut bytes)
+            .map_err(|e| PrivacyCashError::SerializationError(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    /// Parse a proof from arkworks' compressed Groth16 encoding
+    pub fn from_groth16_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != GROTH16_COMPRESSED_LEN {
+            return Err(PrivacyCashError::SerializationError(format!(
+                "Groth16 proof is {} bytes, expected {}",
+                bytes.len(),
+                GROTH16_COMPRESSED_LEN
+            )));
+        }
+        let proof = ark_groth16::Proof::<Bn254>::deserialize_compressed(bytes).map_err(|e| {
+            PrivacyCashError::SerializationError(format!("Invalid Groth16 proof: {}", e))
+        })?;
+
+        let mut proof_b = Vec::with_capacity(128);
+        match proof.b.xy() {
+            Some((x, y)) => {
+                for coord in [x, y] {
+                    proof_b.extend(fq_to_be(&coord.c1));
+                    proof_b.extend(fq_to_be(&coord.c0));
+                }
+            }
+            None => proof_b.resize(128, 0),
+        }
+        Ok(Self {
+            proof_a: g1_to_bytes(&proof.a),
+            proof_b,
+            proof_c: g1_to_bytes(&proof.c),
+        })
+    }
+
+    /// The proof as arkworks points, checked to be on the curve and in the
+    /// right subgroup
+    pub(crate) fn to_ark(&self) -> Result<ark_groth16::Proof<Bn254>> {
+        self.validate()?;
+
+        let b = &self.proof_b;
+        let b = if b.iter().all(|&byte| byte == 0) {
+            G2Affine::zero()
+        } else {
+            let x = Fq2::new(fq_from_be(&b[32..64])?, fq_from_be(&b[..32])?);
+            let y = Fq2::new(fq_from_be(&b[96..])?, fq_from_be(&b[64..96])?);
+            let point = G2Affine::new_unchecked(x, y);
+            if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+                return Err(invalid_point("B"));
+            }
+            point
+        };
+
+        Ok(ark_groth16::Proof {
+            a: g1_from_bytes(&self.proof_a, "A")?,
+            b,
+            c: g1_from_bytes(&self.proof_c, "C")?,
+        })
+    }
+}
+
+/// 32 big-endian bytes as a decimal string
+fn decimal(bytes: &[u8]) -> String {
+    BigUint::from_bytes_be(bytes).to_string()
+}
+
+/// Base field element from 32 big-endian bytes, rejecting non-canonical values
+fn fq_from_be(bytes: &[u8]) -> Result<Fq> {
+    let n = BigUint::from_bytes_be(bytes);
+    if n >= BigUint::from(Fq::MODULUS) {
+        return Err(PrivacyCashError::SerializationError(
+            "Proof coordinate is not below the field modulus".to_string(),
+        ));
+    }
+    Ok(Fq::from(n))
+}
+
+fn fq_to_be(f: &Fq) -> Vec<u8> {
+    f.into_bigint().to_bytes_be()
+}
+
+/// G1 point from `x || y`; all zeros is the point at infinity
+fn g1_from_bytes(bytes: &[u8], name: &str) -> Result<G1Affine> {
+    if bytes.iter().all(|&byte| byte == 0) {
+        return Ok(G1Affine::zero());
+    }
+    let point = G1Affine::new_unchecked(fq_from_be(&bytes[..32])?, fq_from_be(&bytes[32..])?);
+    if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(invalid_point(name));
+    }
+    Ok(point)
+}
+
+fn g1_to_bytes(point: &G1Affine) -> Vec<u8> {
+    match point.xy() {
+        Some((x, y)) => [fq_to_be(x), fq_to_be(y)].concat(),
+        None => vec![0; 64],
+    }
+}
+
+fn invalid_point(name: &str) -> PrivacyCashError {
+    PrivacyCashError::SerializationError(format!("Proof point {} is not a valid curve point", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ec::CurveGroup;
+
+    fn proof() -> ProofBytes {
+        let g1 = G1Affine::generator();
+        let g2 = G2Affine::generator();
+        let (x, y) = g2.xy().unwrap();
+        let proof_b = [
+            fq_to_be(&x.c1),
+            fq_to_be(&x.c0),
+            fq_to_be(&y.c1),
+            fq_to_be(&y.c0),
+        ]
+        .concat();
+        ProofBytes {
+            proof_a: g1_to_bytes(&g1),
+            proof_b,
+            proof_c: g1_to_bytes(&(g1.into_group() + g1).into_affine()),
+        }
+    }
+
+    #[test]
+    fn test_snarkjs_json_roundtrip() {
+        let proof = proof();
+        let json = proof.to_snarkjs_json().unwrap();
+        assert_eq!(json["pi_a"][0], "1");
+        assert_eq!(json["pi_a"][1], "2");
+        // snarkjs order: c0 first
+        let g2 = G2Affine::generator();
+        let (x, _) = g2.xy().unwrap();
+        assert_eq!(json["pi_b"][0][0], decimal(&fq_to_be(&x.c0)));
+        assert_eq!(json["protocol"], "groth16");
+
+        let parsed = ProofBytes::from_snarkjs_json(&json).unwrap();
+        assert_eq!(parsed.proof_a, proof.proof_a);
+        assert_eq!(parsed.proof_b, proof.proof_b);
+        assert_eq!(parsed.proof_c, proof.proof_c);
+
+        let mut wrong_curve = json.clone();
+        wrong_curve["curve"] = "bls12381".into();
+        assert!(ProofBytes::from_snarkjs_json(&wrong_curve).is_err());
+        let mut off_curve = json;
+        off_curve["pi_a"][1] = "3".into();
+        assert!(ProofBytes::from_snarkjs_json(&off_curve).is_err());
+    }
+
+    #[test]
+    fn test_groth16_bytes_roundtrip() {
+        let proof = proof();
+        let bytes = proof.to_groth16_bytes().unwrap();
+        assert_eq!(bytes.len(), GROTH16_COMPRESSED_LEN);
+
+        let parsed = ProofBytes::from_groth16_bytes(&bytes).unwrap();
+        assert_eq!(parsed.proof_a, proof.proof_a);
+        assert_eq!(parsed.proof_b, proof.proof_b);
+        assert_eq!(parsed.proof_c, proof.proof_c);
+        assert!(ProofBytes::from_groth16_bytes(&bytes[1..]).is_err());
+
+        let mut off_curve = proof;
+        off_curve.proof_a[63] = 3;
+        assert!(off_curve.to_groth16_bytes().is_err());
+    }
+}
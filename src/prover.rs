@@ -2,13 +2,22 @@
 //!
 //! Uses snarkjs WASM for proof generation, compatible with the TypeScript SDK.
 
+use crate::constants::{FIELD_SIZE, NATIVE_SOL_MINT, SNARKJS_TEMP_DIR};
 use crate::error::{PrivacyCashError, Result};
-use crate::utils::biguint_to_bytes_le;
+use crate::keypair::ZkKeypair;
+use crate::merkle_tree::MerkleTree;
+use crate::utils::{biguint_to_bytes_le, get_mint_address_field};
+use crate::utxo::Utxo;
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often a cancellable snarkjs run checks whether it was cancelled
+const SNARKJS_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// Groth16 proof structure (compatible with snarkjs)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +39,106 @@ fn default_curve() -> String {
     "bn128".to_string()
 }
 
+/// Byte length of proof point A (G1) in the transact instruction
+pub const PROOF_A_LEN: usize = 64;
+/// Byte length of proof point B (G2) in the transact instruction
+pub const PROOF_B_LEN: usize = 128;
+/// Byte length of proof point C (G1) in the transact instruction
+pub const PROOF_C_LEN: usize = 64;
+
+/// Transact instruction data length before the encrypted outputs
+///
+/// Discriminator, proof, public signals, `ext_amount` and fee. Each
+/// encrypted output follows with a 4-byte length prefix.
+pub const TRANSACT_IX_FIXED_LEN: usize =
+    8 + PROOF_A_LEN + PROOF_B_LEN + PROOF_C_LEN + 32 * PUBLIC_SIGNAL_COUNT + 8 + 8;
+
+/// Shape of a transaction circuit
+///
+/// Deposits and withdrawals pad their inputs and check the circuit input
+/// against this. The rest is still fixed at two inputs and two outputs: the
+/// two output notes and encrypted outputs, the four nullifier PDAs and
+/// [`PUBLIC_SIGNAL_COUNT`] in the instruction layout. A larger circuit needs
+/// those changed too, not just a new spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitSpec {
+    /// Circuit file name, e.g. `"transaction2"`
+    pub name: &'static str,
+    /// Notes spent per transaction, including dummies
+    pub inputs: usize,
+    /// Notes created per transaction
+    pub outputs: usize,
+}
+
+impl CircuitSpec {
+    /// The two-input, two-output circuit the program verifies today
+    pub const TRANSACTION2: CircuitSpec = CircuitSpec {
+        name: "transaction2",
+        inputs: 2,
+        outputs: 2,
+    };
+
+    /// Root, public amount and ext data hash, then one nullifier per input
+    /// and one commitment per output
+    pub const fn public_signal_count(&self) -> usize {
+        3 + self.inputs + self.outputs
+    }
+
+    /// Fail with `InvalidInput` if `count` notes don't fit the input arity
+    pub fn ensure_input_count(&self, count: usize) -> Result<()> {
+        if count > self.inputs {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "The {} circuit spends at most {} notes, got {}",
+                self.name, self.inputs, count
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fill `utxos` up to the input arity with dummy notes
+    pub fn pad_inputs(
+        &self,
+        mut utxos: Vec<Utxo>,
+        keypair: &ZkKeypair,
+        mint_address: Option<&str>,
+    ) -> Result<Vec<Utxo>> {
+        self.ensure_input_count(utxos.len())?;
+        utxos.resize_with(self.inputs, || Utxo::dummy(keypair.clone(), mint_address));
+        Ok(utxos)
+    }
+
+    /// Fail unless every per-note field of `input` matches this arity
+    pub fn check_input(&self, input: &CircuitInput) -> Result<()> {
+        let fields = [
+            ("inputNullifier", input.input_nullifier.len(), self.inputs),
+            ("inAmount", input.in_amount.len(), self.inputs),
+            ("inPrivateKey", input.in_private_key.len(), self.inputs),
+            ("inBlinding", input.in_blinding.len(), self.inputs),
+            ("inPathIndices", input.in_path_indices.len(), self.inputs),
+            ("inPathElements", input.in_path_elements.len(), self.inputs),
+            ("outputCommitment", input.output_commitment.len(), self.outputs),
+            ("outAmount", input.out_amount.len(), self.outputs),
+            ("outBlinding", input.out_blinding.len(), self.outputs),
+            ("outPubkey", input.out_pubkey.len(), self.outputs),
+        ];
+        for (field, len, expected) in fields {
+            if len != expected {
+                return Err(PrivacyCashError::ProofGenerationError(format!(
+                    "{} has {} entries, the {} circuit takes {}",
+                    field, len, self.name, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for CircuitSpec {
+    fn default() -> Self {
+        Self::TRANSACTION2
+    }
+}
+
 /// Parsed proof in bytes for on-chain submission
 #[derive(Debug, Clone)]
 pub struct ProofBytes {
@@ -38,8 +147,61 @@ pub struct ProofBytes {
     pub proof_c: Vec<u8>,
 }
 
+impl ProofBytes {
+    /// Fail unless the proof points have the lengths the program expects
+    ///
+    /// The program reads them at fixed offsets, so a wrong length would
+    /// shift everything after it and build a malformed instruction.
+    pub fn validate(&self) -> Result<()> {
+        let points = [
+            ("A", self.proof_a.len(), PROOF_A_LEN),
+            ("B", self.proof_b.len(), PROOF_B_LEN),
+            ("C", self.proof_c.len(), PROOF_C_LEN),
+        ];
+        for (name, len, expected) in points {
+            if len != expected {
+                return Err(PrivacyCashError::SerializationError(format!(
+                    "Proof point {} is {} bytes, expected {}",
+                    name, len, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a dry run produced instead of a transaction
+///
+/// Nothing in here was submitted: no relayer call was made and no funds
+/// moved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunOutput {
+    /// Proof point A, on-chain encoding
+    pub proof_a: Vec<u8>,
+    /// Proof point B, on-chain encoding
+    pub proof_b: Vec<u8>,
+    /// Proof point C, on-chain encoding
+    pub proof_c: Vec<u8>,
+    /// Public signals as decimal strings
+    pub public_signals: Vec<String>,
+    /// Program instruction data the transaction would have carried
+    pub instruction_data: Vec<u8>,
+}
+
+impl DryRunOutput {
+    pub(crate) fn new(proof: &ProofBytes, public_signals: Vec<String>, instruction_data: Vec<u8>) -> Self {
+        Self {
+            proof_a: proof.proof_a.clone(),
+            proof_b: proof.proof_b.clone(),
+            proof_c: proof.proof_c.clone(),
+            public_signals,
+            instruction_data,
+        }
+    }
+}
+
 /// Circuit input for proof generation
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CircuitInput {
     // Common transaction data
     pub root: String,
@@ -77,7 +239,71 @@ pub struct CircuitInput {
     pub mint_address: String,
 }
 
+/// Nullifiers of `inputs`, in circuit order
+pub(crate) fn input_nullifiers(inputs: &[Utxo]) -> Result<Vec<String>> {
+    inputs.iter().map(Utxo::get_nullifier).collect()
+}
+
+/// Commitments of `outputs`, in circuit order
+pub(crate) fn output_commitments(outputs: &[Utxo]) -> Result<Vec<String>> {
+    outputs.iter().map(Utxo::get_commitment).collect()
+}
+
 impl CircuitInput {
+    /// Zero-value transaction with dummy inputs and zero outputs
+    ///
+    /// This is the same shape as a fresh deposit, but moves no funds. Useful
+    /// for benchmarking the prover; the resulting proof is never submitted.
+    pub fn dummy() -> Result<Self> {
+        let circuit = CircuitSpec::default();
+        let keypair = ZkKeypair::generate()?;
+        let inputs = circuit.pad_inputs(Vec::new(), &keypair, None)?;
+        let outputs: Vec<Utxo> = (0..circuit.outputs)
+            .map(|_| Utxo::dummy(keypair.clone(), None))
+            .collect();
+        let path = MerkleTree::zero_path();
+
+        Ok(Self {
+            root: "0".to_string(),
+            input_nullifier: input_nullifiers(&inputs)?,
+            output_commitment: output_commitments(&outputs)?,
+            public_amount: "0".to_string(),
+            ext_data_hash: vec![0u8; 32],
+            in_amount: inputs.iter().map(|u| u.amount.to_string()).collect(),
+            in_private_key: inputs.iter().map(|u| u.keypair.privkey().clone()).collect(),
+            in_blinding: inputs.iter().map(|u| u.blinding.to_string()).collect(),
+            in_path_indices: inputs.iter().map(|u| u.index).collect(),
+            in_path_elements: vec![path.path_elements; circuit.inputs],
+            out_amount: outputs.iter().map(|u| u.amount.to_string()).collect(),
+            out_blinding: outputs.iter().map(|u| u.blinding.to_string()).collect(),
+            out_pubkey: outputs.iter().map(|u| u.keypair.pubkey().clone()).collect(),
+            mint_address: get_mint_address_field(&NATIVE_SOL_MINT),
+        })
+    }
+
+    /// Public signals of a proof for this input, in circuit order
+    ///
+    /// Root, public amount, ext data hash (read little-endian, as the Rust
+    /// prover passes it to the witness), nullifiers, commitments.
+    pub fn public_signals(&self) -> Vec<String> {
+        let mut signals = vec![
+            self.root.clone(),
+            self.public_amount.clone(),
+            self.ext_data_hash_field().to_string(),
+        ];
+        signals.extend(self.input_nullifier.iter().cloned());
+        signals.extend(self.output_commitment.iter().cloned());
+        signals
+    }
+
+    /// The ext data hash as a field element
+    ///
+    /// The bytes are read little-endian, as snarkjs' `fromRprLE` does, so
+    /// every prover sees the same value.
+    fn ext_data_hash_field(&self) -> BigUint {
+        BigUint::from_bytes_le(&self.ext_data_hash) % &*FIELD_SIZE
+    }
+
     /// Convert to JSON for snarkjs
     pub fn to_json(&self) -> Result<String> {
         // Convert BigUint fields to strings for JSON serialization
@@ -98,7 +324,7 @@ impl CircuitInput {
         );
         input_map.insert(
             "extDataHash".to_string(),
-            serde_json::json!(BigUint::from_bytes_be(&self.ext_data_hash).to_string()),
+            serde_json::json!(self.ext_data_hash_field().to_string()),
         );
 
         input_map.insert("inAmount".to_string(), serde_json::json!(self.in_amount));
@@ -145,6 +371,68 @@ impl CircuitInput {
         serde_json::to_string(&input_map)
             .map_err(|e| PrivacyCashError::SerializationError(e.to_string()))
     }
+
+    /// Write the complete circuit input to `path` as pretty-printed JSON
+    ///
+    /// **WARNING**: the output contains private keys and blindings. Anyone
+    /// holding the file can spend the input notes. Only use this to reproduce
+    /// proof failures, and delete the file afterwards.
+    #[cfg(feature = "debug-export")]
+    pub fn to_debug_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        log::warn!(
+            "!!! Writing circuit input WITH PRIVATE KEYS to {} - do not share this file !!!",
+            path.display()
+        );
+
+        let mut value: serde_json::Value = serde_json::from_str(&self.to_json()?)?;
+        // Also keep the raw hash bytes, the decimal form hides byte-order mistakes
+        value["extDataHashHex"] = serde_json::json!(hex::encode(&self.ext_data_hash));
+
+        let json = serde_json::to_string_pretty(&value)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+/// Environment variable naming the directory for circuit input dumps
+#[cfg(feature = "debug-export")]
+pub const DEBUG_EXPORT_DIR_ENV: &str = "PRIVACY_CASH_DEBUG_EXPORT_DIR";
+
+/// Dump the circuit input if `PRIVACY_CASH_DEBUG_EXPORT_DIR` is set
+///
+/// Called by the deposit and withdraw flows right before proving. Failures
+/// are logged and never abort the transaction.
+#[cfg(feature = "debug-export")]
+pub fn export_circuit_input_if_enabled(input: &CircuitInput, operation: &str) {
+    let Ok(dir) = std::env::var(DEBUG_EXPORT_DIR_ENV) else {
+        return;
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let path = Path::new(&dir).join(format!("{}-{}.json", operation, timestamp));
+
+    if let Err(e) = input.to_debug_json(&path) {
+        log::warn!("Failed to export circuit input: {}", e);
+    }
+}
+
+/// Fail with `CircuitNotFound` unless `<key_base_path>.wasm` and
+/// `<key_base_path>.zkey` both exist
+pub fn ensure_circuit_files(key_base_path: &str) -> Result<()> {
+    for (kind, ext) in [("WASM", "wasm"), ("zkey", "zkey")] {
+        let path = format!("{}.{}", key_base_path, ext);
+        if !Path::new(&path).is_file() {
+            return Err(PrivacyCashError::CircuitNotFound(format!(
+                "{} file not found: {}. Please download circuit files from the Privacy Cash SDK.",
+                kind, path
+            )));
+        }
+    }
+    Ok(())
 }
 
 /// Prover for generating ZK proofs
@@ -157,45 +445,82 @@ impl CircuitInput {
 pub struct Prover {
     /// Base path for circuit files (.wasm and .zkey)
     key_base_path: String,
+    /// Directory for snarkjs input, witness and output files
+    temp_dir: PathBuf,
 }
 
 impl Prover {
     /// Create a new prover with circuit files at the given path
+    ///
+    /// Intermediate files go to `SNARKJS_TEMP_DIR` if set, otherwise the
+    /// system temp directory; see [`with_temp_dir`](Self::with_temp_dir) to
+    /// change that.
     pub fn new(key_base_path: &str) -> Self {
         Self {
             key_base_path: key_base_path.to_string(),
+            temp_dir: SNARKJS_TEMP_DIR.clone().unwrap_or_else(std::env::temp_dir),
         }
     }
 
+    /// Write intermediate files to `dir` instead of the system temp directory
+    ///
+    /// The directory is created if missing. Fails with `ConfigError` if it
+    /// can't be created or written to, so a read-only location is reported
+    /// here rather than halfway through a proof.
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        let unusable = |e: std::io::Error| {
+            PrivacyCashError::ConfigError(format!(
+                "Prover temp directory {} is not writable: {}",
+                dir.display(),
+                e
+            ))
+        };
+
+        std::fs::create_dir_all(&dir).map_err(unusable)?;
+        let probe = dir.join(format!(".privacy_cash_probe_{}", std::process::id()));
+        std::fs::write(&probe, b"").map_err(unusable)?;
+        let _ = std::fs::remove_file(&probe);
+
+        self.temp_dir = dir;
+        Ok(self)
+    }
+
+    /// Directory intermediate files are written to
+    pub fn temp_dir(&self) -> &Path {
+        &self.temp_dir
+    }
+
     /// Generate a ZK proof using snarkjs CLI
     ///
     /// This method shells out to snarkjs which must be installed globally.
     /// For production use, consider using the TypeScript SDK for proof generation
     /// or implementing a native WASM-based prover.
     pub async fn prove(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        self.prove_cancellable(input, None)
+    }
+
+    /// Like [`prove`](Self::prove), killing snarkjs once `cancel` is set
+    ///
+    /// Blocks until snarkjs exits; returns `Aborted` if it was cancelled.
+    pub(crate) fn prove_cancellable(
+        &self,
+        input: &CircuitInput,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(Proof, Vec<String>)> {
         let wasm_path = format!("{}.wasm", self.key_base_path);
         let zkey_path = format!("{}.zkey", self.key_base_path);
 
-        // Check that circuit files exist
-        if !Path::new(&wasm_path).exists() {
-            return Err(PrivacyCashError::CircuitNotFound(format!(
-                "WASM file not found: {}. Please download circuit files from the Privacy Cash SDK.",
-                wasm_path
-            )));
-        }
-        if !Path::new(&zkey_path).exists() {
-            return Err(PrivacyCashError::CircuitNotFound(format!(
-                "zkey file not found: {}. Please download circuit files from the Privacy Cash SDK.",
-                zkey_path
-            )));
-        }
+        ensure_circuit_files(&self.key_base_path)?;
 
-        // Create temporary files for input and output
-        let temp_dir = std::env::temp_dir();
-        let input_path = temp_dir.join("privacy_cash_input.json");
-        let witness_path = temp_dir.join("privacy_cash_witness.wtns");
-        let proof_path = temp_dir.join("privacy_cash_proof.json");
-        let public_path = temp_dir.join("privacy_cash_public.json");
+        // Temporary files for input and output, unique to this proof so
+        // concurrent proofs don't overwrite each other's files
+        std::fs::create_dir_all(&self.temp_dir)?;
+        let mut files = TempFiles::new(&self.temp_dir);
+        let input_path = files.path("input.json");
+        let witness_path = files.path("witness.wtns");
+        let proof_path = files.path("proof.json");
+        let public_path = files.path("public.json");
 
         // Write input to file
         let input_json = input.to_json()?;
@@ -204,21 +529,22 @@ impl Prover {
 
         // Generate witness using snarkjs
         log::debug!("Generating witness...");
-        let witness_output = Command::new("snarkjs")
-            .args([
+        let witness_output = run_snarkjs(
+            &[
                 "wtns",
                 "calculate",
                 &wasm_path,
                 input_path.to_str().unwrap(),
                 witness_path.to_str().unwrap(),
-            ])
-            .output()
-            .map_err(|e| {
+            ],
+            cancel,
+            |e| {
                 PrivacyCashError::ProofGenerationError(format!(
                     "Failed to run snarkjs witness calculation. Is snarkjs installed? (npm install -g snarkjs): {}",
                     e
                 ))
-            })?;
+            },
+        )?;
 
         if !witness_output.status.success() {
             let stderr = String::from_utf8_lossy(&witness_output.stderr);
@@ -230,22 +556,23 @@ impl Prover {
 
         // Generate proof
         log::debug!("Generating proof...");
-        let proof_output = Command::new("snarkjs")
-            .args([
+        let proof_output = run_snarkjs(
+            &[
                 "groth16",
                 "prove",
                 &zkey_path,
                 witness_path.to_str().unwrap(),
                 proof_path.to_str().unwrap(),
                 public_path.to_str().unwrap(),
-            ])
-            .output()
-            .map_err(|e| {
+            ],
+            cancel,
+            |e| {
                 PrivacyCashError::ProofGenerationError(format!(
                     "Failed to run snarkjs proof generation: {}",
                     e
                 ))
-            })?;
+            },
+        )?;
 
         if !proof_output.status.success() {
             let stderr = String::from_utf8_lossy(&proof_output.stderr);
@@ -269,12 +596,6 @@ impl Prover {
         let public_signals: Vec<String> = serde_json::from_str(&public_json)
             .map_err(|e| PrivacyCashError::SerializationError(format!("Failed to parse public signals: {}", e)))?;
 
-        // Clean up temporary files
-        let _ = std::fs::remove_file(&input_path);
-        let _ = std::fs::remove_file(&witness_path);
-        let _ = std::fs::remove_file(&proof_path);
-        let _ = std::fs::remove_file(&public_path);
-
         log::debug!("Proof generated successfully");
         Ok((proof, public_signals))
     }
@@ -289,62 +610,220 @@ impl Prover {
     }
 }
 
-/// Parse proof to bytes array for on-chain submission
-/// 
-/// Matches the TypeScript SDK's parseProofToBytesArray function:
-/// - pi_a, pi_c: Each coordinate is LE bytes then reversed to BE
-/// - pi_b: Each coordinate is LE bytes, then the entire 64-byte chunk is reversed
-pub fn parse_proof_to_bytes(proof: &Proof) -> Result<ProofBytes> {
-    // For pi_a and pi_c: convert to LE then reverse to BE
-    let parse_coord_be = |s: &str| -> Result<Vec<u8>> {
-        let n = BigUint::parse_bytes(s.as_bytes(), 10)
-            .ok_or_else(|| PrivacyCashError::SerializationError("Invalid coordinate".to_string()))?;
-        let bytes = biguint_to_bytes_le(&n);
-        // Reverse for big-endian format
-        Ok(bytes.iter().rev().cloned().collect())
+/// Per-proof file names in a temp directory, removed on drop
+///
+/// The files of a proof are deleted whether it succeeds or fails, as the
+/// input holds private keys.
+struct TempFiles {
+    prefix: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl TempFiles {
+    fn new(dir: &Path) -> Self {
+        Self {
+            prefix: dir.join(format!(
+                "privacy_cash_{}_{:016x}",
+                std::process::id(),
+                rand::random::<u64>()
+            )),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Path for the file ending in `suffix`
+    fn path(&mut self, suffix: &str) -> PathBuf {
+        let mut path = self.prefix.clone().into_os_string();
+        path.push("_");
+        path.push(suffix);
+        let path = PathBuf::from(path);
+        self.paths.push(path.clone());
+        path
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Run snarkjs with `args` to completion, or kill it once `cancel` is set
+///
+/// Without `cancel` this is a plain blocking run. Otherwise stdout is
+/// discarded (only stderr is read, for error messages) and the child is polled
+/// so it can be killed; a killed run returns `Aborted`.
+fn run_snarkjs(
+    args: &[&str],
+    cancel: Option<&AtomicBool>,
+    spawn_error: impl FnOnce(std::io::Error) -> PrivacyCashError,
+) -> Result<Output> {
+    let mut command = Command::new("snarkjs");
+    command.args(args);
+    let Some(cancel) = cancel else {
+        return command.output().map_err(spawn_error);
+    };
+
+    let mut child = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(PrivacyCashError::Aborted);
+        }
+        if child.try_wait()?.is_some() {
+            return Ok(child.wait_with_output()?);
+        }
+        std::thread::sleep(SNARKJS_POLL_INTERVAL);
+    }
+}
+
+/// Decimal field element as 32 big-endian bytes, the on-chain encoding
+///
+/// Fails on values that don't fit in 32 bytes rather than truncating them.
+pub fn to_be_32(decimal: &str) -> Result<[u8; 32]> {
+    let n = BigUint::parse_bytes(decimal.as_bytes(), 10)
+        .ok_or_else(|| PrivacyCashError::SerializationError("Invalid coordinate".to_string()))?;
+    if n.bits() > 256 {
+        return Err(PrivacyCashError::SerializationError(format!(
+            "Coordinate {} doesn't fit in 32 bytes",
+            decimal
+        )));
+    }
+    let mut bytes = biguint_to_bytes_le(&n);
+    bytes.reverse();
+    Ok(bytes)
+}
+
+/// G1 point `[x, y, z]` as `x || y` (affine, `z` is ignored)
+pub fn g1_to_onchain(point: &[String]) -> Result<[u8; 64]> {
+    let [x, y, ..] = point else {
+        return Err(PrivacyCashError::SerializationError(
+            "G1 point needs x and y coordinates".to_string(),
+        ));
     };
-    
-    // For pi_b: convert to LE, keep as LE (no reverse per element)
-    let parse_coord_le = |s: &str| -> Result<Vec<u8>> {
-        let n = BigUint::parse_bytes(s.as_bytes(), 10)
-            .ok_or_else(|| PrivacyCashError::SerializationError("Invalid coordinate".to_string()))?;
-        Ok(biguint_to_bytes_le(&n).to_vec())
+
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(&to_be_32(x)?);
+    bytes[32..].copy_from_slice(&to_be_32(y)?);
+    Ok(bytes)
+}
+
+/// G2 point `[[x.c1, x.c0], [y.c1, y.c0], ..]` as `x.c1 || x.c0 || y.c1 || y.c0`
+///
+/// The on-chain verifier (EIP-197 layout) wants the imaginary part first.
+/// [`RustProver`](crate::prover_rust::RustProver) already emits each Fq2
+/// coordinate as `[c1, c0]`, so only the limbs are converted to big-endian.
+/// The TypeScript SDK reaches the same bytes from snarkjs' `[c0, c1]` order by
+/// reversing each 64-byte little-endian chunk.
+pub fn g2_to_onchain(point: &[Vec<String>]) -> Result<[u8; 128]> {
+    let [x, y, ..] = point else {
+        return Err(PrivacyCashError::SerializationError(
+            "G2 point needs x and y coordinates".to_string(),
+        ));
     };
 
-    // Proof A: [x, y] flattened, each coord is BE
-    let mut proof_a = Vec::new();
-    proof_a.extend(parse_coord_be(&proof.pi_a[0])?);
-    proof_a.extend(parse_coord_be(&proof.pi_a[1])?);
-
-    // Proof B: The on-chain verifier uses change_endianness which reverses EACH 32-byte chunk
-    // snarkjs pi_b format: [[x.c1, x.c0], [y.c1, y.c0], [1, 0]]
-    // On-chain expects: [x.c1_be, x.c0_be, y.c1_be, y.c0_be] (each 32 bytes in BE)
-    let mut proof_b = Vec::new();
-    
-    // Process x coordinate (pi_b[0] = [c1, c0] in snarkjs format)
-    // Output order: c1, c0 (same as input, but each in BE)
-    for coord in &proof.pi_b[0] {
-        proof_b.extend(parse_coord_be(coord)?);
-    }
-    
-    // Process y coordinate (pi_b[1] = [c1, c0] in snarkjs format)
-    // Output order: c1, c0 (same as input, but each in BE)
-    for coord in &proof.pi_b[1] {
-        proof_b.extend(parse_coord_be(coord)?);
-    }
-
-    // Proof C: [x, y] flattened, each coord is BE
-    let mut proof_c = Vec::new();
-    proof_c.extend(parse_coord_be(&proof.pi_c[0])?);
-    proof_c.extend(parse_coord_be(&proof.pi_c[1])?);
+    let mut bytes = [0u8; 128];
+    for (chunk, coord) in bytes.chunks_exact_mut(64).zip([x, y]) {
+        let [c1, c0] = coord.as_slice() else {
+            return Err(PrivacyCashError::SerializationError(
+                "G2 coordinate needs exactly two limbs".to_string(),
+            ));
+        };
+        chunk[..32].copy_from_slice(&to_be_32(c1)?);
+        chunk[32..].copy_from_slice(&to_be_32(c0)?);
+    }
+    Ok(bytes)
+}
 
+/// Parse proof to bytes array for on-chain submission
+///
+/// Produces the same bytes as the TypeScript SDK's `parseProofToBytesArray`;
+/// see [`g1_to_onchain`] and [`g2_to_onchain`] for the layouts.
+pub fn parse_proof_to_bytes(proof: &Proof) -> Result<ProofBytes> {
     Ok(ProofBytes {
-        proof_a,
-        proof_b,
-        proof_c,
+        proof_a: g1_to_onchain(&proof.pi_a)?.to_vec(),
+        proof_b: g2_to_onchain(&proof.pi_b)?.to_vec(),
+        proof_c: g1_to_onchain(&proof.pi_c)?.to_vec(),
     })
 }
 
+/// Number of public signals in the on-chain instruction
+pub const PUBLIC_SIGNAL_COUNT: usize = CircuitSpec::TRANSACTION2.public_signal_count();
+
+/// Circuit public signals with named fields (32-byte big-endian each)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicSignals {
+    pub root: [u8; 32],
+    pub public_amount: [u8; 32],
+    pub ext_data_hash: [u8; 32],
+    pub nullifier0: [u8; 32],
+    pub nullifier1: [u8; 32],
+    pub commitment0: [u8; 32],
+    pub commitment1: [u8; 32],
+}
+
+impl PublicSignals {
+    /// Parse the prover's decimal public signals
+    pub fn parse(signals: &[String]) -> Result<Self> {
+        Self::from_bytes(&parse_public_signals_to_bytes(signals)?)
+    }
+
+    /// Build from byte signals in circuit order
+    ///
+    /// Requires exactly [`PUBLIC_SIGNAL_COUNT`] signals: any other count
+    /// means the proof is for a different circuit.
+    pub fn from_bytes(signals: &[[u8; 32]]) -> Result<Self> {
+        match signals {
+            [root, public_amount, ext_data_hash, nullifier0, nullifier1, commitment0, commitment1] => {
+                Ok(Self {
+                    root: *root,
+                    public_amount: *public_amount,
+                    ext_data_hash: *ext_data_hash,
+                    nullifier0: *nullifier0,
+                    nullifier1: *nullifier1,
+                    commitment0: *commitment0,
+                    commitment1: *commitment1,
+                })
+            }
+            _ => Err(PrivacyCashError::SerializationError(format!(
+                "Expected {} public signals, got {}",
+                PUBLIC_SIGNAL_COUNT,
+                signals.len()
+            ))),
+        }
+    }
+
+    /// Input nullifiers, used to derive the nullifier PDAs
+    pub fn nullifiers(&self) -> [[u8; 32]; 2] {
+        [self.nullifier0, self.nullifier1]
+    }
+
+    /// Signals in instruction order, as written into the transact instruction
+    pub fn to_bytes(&self) -> [u8; 32 * PUBLIC_SIGNAL_COUNT] {
+        let mut data = [0u8; 32 * PUBLIC_SIGNAL_COUNT];
+        let fields = [
+            &self.root,
+            &self.public_amount,
+            &self.ext_data_hash,
+            &self.nullifier0,
+            &self.nullifier1,
+            &self.commitment0,
+            &self.commitment1,
+        ];
+        for (chunk, field) in data.chunks_exact_mut(32).zip(fields) {
+            chunk.copy_from_slice(field);
+        }
+        data
+    }
+}
+
 /// Parse public signals to bytes arrays
 pub fn parse_public_signals_to_bytes(signals: &[String]) -> Result<Vec<[u8; 32]>> {
     signals
@@ -376,6 +855,220 @@ pub fn parse_public_signals_to_bytes(signals: &[String]) -> Result<Vec<[u8; 32]>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::FIELD_SIZE;
+    use proptest::prelude::*;
+
+    /// Uniform-ish elements of the BN254 scalar field
+    fn field_element() -> impl Strategy<Value = BigUint> {
+        any::<[u8; 32]>().prop_map(|bytes| BigUint::from_bytes_be(&bytes) % &*FIELD_SIZE)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_public_signals_round_trip(values in prop::collection::vec(field_element(), 1..12)) {
+            let signals: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            let bytes = parse_public_signals_to_bytes(&signals).unwrap();
+
+            prop_assert_eq!(bytes.len(), values.len());
+            for (value, bytes) in values.iter().zip(&bytes) {
+                prop_assert_eq!(&BigUint::from_bytes_be(bytes), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prover_temp_dir() {
+        let default_dir = SNARKJS_TEMP_DIR.clone().unwrap_or_else(std::env::temp_dir);
+        assert_eq!(Prover::new("circuit/transaction2").temp_dir(), default_dir);
+
+        let base = std::env::temp_dir().join(format!("privacy_cash_prover_test_{}", std::process::id()));
+        let nested = base.join("nested");
+        let prover = Prover::new("circuit/transaction2").with_temp_dir(&nested).unwrap();
+        assert_eq!(prover.temp_dir(), nested);
+        assert!(nested.is_dir());
+
+        // A path below a regular file can't be created
+        let file = base.join("file");
+        std::fs::write(&file, b"").unwrap();
+        assert!(matches!(
+            Prover::new("circuit/transaction2").with_temp_dir(file.join("sub")),
+            Err(PrivacyCashError::ConfigError(_))
+        ));
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_ensure_circuit_files() {
+        let dir = std::env::temp_dir().join(format!("privacy_cash_circuit_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("transaction2").to_string_lossy().to_string();
+
+        let missing = |e: Result<()>| match e {
+            Err(PrivacyCashError::CircuitNotFound(message)) => message,
+            other => panic!("expected CircuitNotFound, got {:?}", other),
+        };
+        assert!(missing(ensure_circuit_files(&base)).contains(".wasm"));
+        std::fs::write(format!("{}.wasm", base), b"").unwrap();
+        assert!(missing(ensure_circuit_files(&base)).contains(".zkey"));
+        std::fs::write(format!("{}.zkey", base), b"").unwrap();
+        assert!(ensure_circuit_files(&base).is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dummy_circuit_input() {
+        let input = CircuitInput::dummy().unwrap();
+        assert_eq!(input.public_amount, "0");
+        assert!(input.in_amount.iter().all(|a| a == "0"));
+        assert!(input.out_amount.iter().all(|a| a == "0"));
+        assert_ne!(input.input_nullifier[0], input.input_nullifier[1]);
+        assert_eq!(input.in_path_elements.len(), 2);
+    }
+
+    #[test]
+    fn test_temp_files_are_unique_and_removed() {
+        let dir = std::env::temp_dir();
+        let (mut first, mut second) = (TempFiles::new(&dir), TempFiles::new(&dir));
+        let path = first.path("input.json");
+        assert_ne!(path, second.path("input.json"));
+
+        std::fs::write(&path, b"{}").unwrap();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_to_json_matches_public_signals() {
+        let mut input = CircuitInput::dummy().unwrap();
+        input.root = "12345".to_string();
+        input.public_amount = "678".to_string();
+        // Not a palindrome, so reading it in the wrong byte order shows
+        input.ext_data_hash = (1..=32).collect();
+
+        let json: serde_json::Value = serde_json::from_str(&input.to_json().unwrap()).unwrap();
+        let signals = input.public_signals();
+        assert_eq!(json["root"], signals[0]);
+        assert_eq!(json["publicAmount"], signals[1]);
+        assert_eq!(json["extDataHash"], signals[2]);
+        assert_eq!(json["inputNullifier"][0], signals[3]);
+        assert_eq!(json["inputNullifier"][1], signals[4]);
+        assert_eq!(json["outputCommitment"][0], signals[5]);
+        assert_eq!(json["outputCommitment"][1], signals[6]);
+        assert_eq!(
+            signals[2],
+            "14528991250861404666834535435384615765856667510756806797353855100662256435713"
+        );
+    }
+
+    #[cfg(feature = "debug-export")]
+    #[test]
+    fn test_to_debug_json() {
+        let input = CircuitInput::dummy().unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "privacy_cash_debug_input_test_{}_{:016x}.json",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        input.to_debug_json(&path).unwrap();
+
+        let value: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["inPrivateKey"][0], input.in_private_key[0].to_string());
+        assert_eq!(value["extDataHashHex"], "00".repeat(32));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_spec() {
+        let circuit = CircuitSpec::default();
+        assert_eq!(circuit, CircuitSpec::TRANSACTION2);
+        assert_eq!(circuit.public_signal_count(), PUBLIC_SIGNAL_COUNT);
+
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = Utxo::new(5u64, keypair.clone(), 0, None, None);
+        let inputs = circuit.pad_inputs(vec![note], &keypair, None).unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert!(!inputs[0].is_dummy() && inputs[1].is_dummy());
+        assert!(circuit.pad_inputs(vec![inputs[0].clone(); 3], &keypair, None).is_err());
+        circuit.ensure_input_count(2).unwrap();
+        assert!(matches!(
+            circuit.ensure_input_count(3),
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+
+        let mut input = CircuitInput::dummy().unwrap();
+        circuit.check_input(&input).unwrap();
+        input.out_pubkey.pop();
+        assert!(circuit.check_input(&input).is_err());
+
+        let wide = CircuitSpec { name: "transaction16", inputs: 16, outputs: 2 };
+        assert_eq!(wide.public_signal_count(), 21);
+        assert_eq!(wide.pad_inputs(Vec::new(), &keypair, None).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_public_signals_layout() {
+        let signals: Vec<String> = (1..=7).map(|i| i.to_string()).collect();
+        let parsed = PublicSignals::parse(&signals).unwrap();
+
+        assert_eq!(parsed.root[31], 1);
+        assert_eq!(parsed.public_amount[31], 2);
+        assert_eq!(parsed.ext_data_hash[31], 3);
+        assert_eq!(parsed.nullifiers(), [parsed.nullifier0, parsed.nullifier1]);
+        assert_eq!(parsed.nullifier0[31], 4);
+        assert_eq!(parsed.nullifier1[31], 5);
+        assert_eq!(parsed.commitment0[31], 6);
+        assert_eq!(parsed.commitment1[31], 7);
+
+        // Instruction layout is the raw signals concatenated in circuit order
+        let expected: Vec<u8> = parse_public_signals_to_bytes(&signals)
+            .unwrap()
+            .concat();
+        assert_eq!(parsed.to_bytes().to_vec(), expected);
+
+        assert!(PublicSignals::parse(&signals[..6]).is_err());
+        let too_many: Vec<String> = (1..=8).map(|i| i.to_string()).collect();
+        assert!(matches!(
+            PublicSignals::parse(&too_many),
+            Err(PrivacyCashError::SerializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_be_32() {
+        let bytes = to_be_32("258").unwrap();
+        assert_eq!(bytes[30..], [1, 2]);
+        assert!(bytes[..30].iter().all(|&b| b == 0));
+        assert!(to_be_32("0x12").is_err());
+
+        let max = (BigUint::from(1u8) << 256u32) - 1u8;
+        assert_eq!(to_be_32(&max.to_string()).unwrap(), [0xff; 32]);
+        assert!(to_be_32(&(max + 1u8).to_string()).is_err());
+    }
+
+    #[test]
+    fn test_g1_to_onchain() {
+        let point = vec!["1".to_string(), "2".to_string(), "1".to_string()];
+        let bytes = g1_to_onchain(&point).unwrap();
+        assert_eq!(bytes[31], 1);
+        assert_eq!(bytes[63], 2);
+        assert!(g1_to_onchain(&point[..1]).is_err());
+    }
+
+    #[test]
+    fn test_g2_to_onchain() {
+        let point = vec![
+            vec!["1".to_string(), "2".to_string()],
+            vec!["3".to_string(), "4".to_string()],
+            vec!["1".to_string(), "0".to_string()],
+        ];
+        let bytes = g2_to_onchain(&point).unwrap();
+        let last_bytes: Vec<u8> = bytes.chunks(32).map(|c| c[31]).collect();
+        assert_eq!(last_bytes, vec![1, 2, 3, 4]);
+        assert!(g2_to_onchain(&point[..1]).is_err());
+        assert!(g2_to_onchain(&[vec!["1".to_string()], vec!["2".to_string()]]).is_err());
+    }
 
     #[test]
     fn test_parse_public_signals() {
@@ -5,19 +5,36 @@
 //! 
 //! This is the iOS-compatible prover that doesn't require Node.js/snarkjs.
 
+use crate::constants::{LSK_PROOF_BENCHMARK, SNARKJS_FALLBACK_AFTER, STRICT_WITNESS_CHECK};
 use crate::error::{PrivacyCashError, Result};
-use crate::prover::{CircuitInput, Proof};
+use crate::prover::{parse_proof_to_bytes, CircuitInput, Proof, Prover};
+use crate::storage::Storage;
 use ark_bn254::{Bn254, Fr};
+use ark_circom_solana::circom::{Constraints, R1CSFile};
 use ark_circom_solana::{read_zkey, CircomReduction, WitnessCalculator};
-use ark_groth16::Groth16;
+use ark_groth16::{Groth16, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::ConstraintMatrices;
 use ark_std::rand::thread_rng;
 use num_bigint::BigUint;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// How often an async `prove` polls its abort signal
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 type GrothBn = Groth16<Bn254, CircomReduction>;
 
+/// Proving key and constraint matrices loaded from a .zkey file
+type CircuitKeys = (ProvingKey<Bn254>, ConstraintMatrices<Fr>);
+
 /// Proof result containing formatted proof data for on-chain submission
 #[derive(Debug, Clone)]
 pub struct RustProofResult {
@@ -32,9 +49,29 @@ pub struct RustProofResult {
 /// This prover uses ark-circom for native proof generation,
 /// making it compatible with iOS and other platforms that
 /// cannot run Node.js/snarkjs.
+///
+/// The zkey is loaded on first use and kept for the lifetime of the prover,
+/// so reuse one instance for repeated proofs. Clones share the loaded zkey.
+#[derive(Clone)]
 pub struct RustProver {
     /// Base path for circuit files (.wasm and .zkey)
     key_base_path: String,
+    /// Verify each proof against the verifying key before returning it
+    verify_locally: bool,
+    /// Check each witness against the R1CS constraints before proving
+    check_constraints: bool,
+    /// Hand the proof to snarkjs when proving takes longer than this
+    snarkjs_fallback: Option<Duration>,
+    /// Stop proving once this is set to `true`
+    abort_signal: Option<Arc<tokio::sync::Mutex<bool>>>,
+    /// Set once snarkjs won the race for this proof
+    race_lost: Option<Arc<AtomicBool>>,
+    /// Cached zkey contents
+    keys: Arc<OnceCell<CircuitKeys>>,
+    /// Cached R1CS constraints, for the constraint check
+    constraints: Arc<OnceCell<Vec<Constraints<Bn254>>>>,
+    /// Cached benchmark result
+    benchmark: OnceCell<Duration>,
 }
 
 impl RustProver {
@@ -42,16 +79,270 @@ impl RustProver {
     pub fn new(key_base_path: &str) -> Self {
         Self {
             key_base_path: key_base_path.to_string(),
+            verify_locally: true,
+            check_constraints: *STRICT_WITNESS_CHECK,
+            snarkjs_fallback: *SNARKJS_FALLBACK_AFTER,
+            abort_signal: None,
+            race_lost: None,
+            keys: Arc::new(OnceCell::new()),
+            constraints: Arc::new(OnceCell::new()),
+            benchmark: OnceCell::new(),
         }
     }
 
+    /// Enable or disable local proof verification (enabled by default)
+    ///
+    /// Skipping verification saves a pairing check per proof. Only do this
+    /// with trusted circuit files: a bad proof will then only be caught when
+    /// the transaction is rejected on-chain, after the relayer round-trip.
+    pub fn with_local_verification(mut self, verify_locally: bool) -> Self {
+        self.verify_locally = verify_locally;
+        self
+    }
+
+    /// Check each witness against the circuit's constraints before proving
+    ///
+    /// Off by default (or as set by `STRICT_WITNESS_CHECK`). A malformed
+    /// input can still give a witness, and the proof made from it only fails
+    /// local verification after the full 30-60s prove; with the check on it
+    /// fails right after witness calculation with `WitnessConstraintFailed`.
+    /// Needs the circuit's `.r1cs` file next to the zkey, and costs one pass
+    /// over the constraints per proof, so it's meant for debugging.
+    pub fn with_constraint_check(mut self, check_constraints: bool) -> Self {
+        self.check_constraints = check_constraints;
+        self
+    }
+
+    /// Race the snarkjs CLI once a proof has taken longer than `after`
+    ///
+    /// Off by default (or as set by `SNARKJS_FALLBACK_SECS`). For servers
+    /// with snarkjs installed (see [`Prover`]) where the Rust prover can be
+    /// slow: once `after` passes, snarkjs proves the same input and whichever
+    /// prover finishes first is used and the other is stopped. If snarkjs
+    /// fails, the Rust prover is awaited as usual. [`prove_with_source`](Self::prove_with_source) reports
+    /// which prover produced the proof.
+    pub fn with_snarkjs_fallback(mut self, after: Option<Duration>) -> Self {
+        self.snarkjs_fallback = after;
+        self
+    }
+
+    /// Stop proving once `signal` is set to `true`
+    ///
+    /// With a signal set, [`prove`](Self::prove) runs on a blocking thread
+    /// and returns `Aborted` as soon as the signal is seen. The prover itself
+    /// checks the signal between steps (zkey load, witness calculation,
+    /// constraint check, proof generation, local verification) and stops at
+    /// the next one. The Groth16 step can't be interrupted: if the signal is
+    /// set while it runs, it finishes in the background (up to the full
+    /// 30-60s) before the thread is released, but its proof is discarded.
+    pub fn with_abort_signal(mut self, signal: Option<Arc<tokio::sync::Mutex<bool>>>) -> Self {
+        self.abort_signal = signal;
+        self
+    }
+
+    /// Load the zkey now instead of on the first proof
+    pub fn warm_up(&self) -> Result<()> {
+        self.load_keys().map(|_| ())
+    }
+
     /// Generate a ZK proof using pure Rust (ark-circom)
     ///
     /// This method provides the same interface as the snarkjs-based Prover,
     /// but uses native Rust code for proof generation.
     pub async fn prove(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        let (proof, public_signals, _) = self.prove_with_source(input).await?;
+        Ok((proof, public_signals))
+    }
+
+    /// Like [`prove`](Self::prove), also reporting which prover produced the proof
+    ///
+    /// Always [`ProofSource::Rust`] unless a snarkjs fallback is set (see
+    /// [`with_snarkjs_fallback`](Self::with_snarkjs_fallback)).
+    pub async fn prove_with_source(
+        &self,
+        input: &CircuitInput,
+    ) -> Result<(Proof, Vec<String>, ProofSource)> {
+        let Some(signal) = self.abort_signal.clone() else {
+            return self.prove_to_completion(input).await;
+        };
+        tokio::select! {
+            result = self.prove_to_completion(input) => result,
+            _ = wait_for_abort(&signal) => {
+                log::info!("Proof generation aborted");
+                Err(PrivacyCashError::Aborted)
+            }
+        }
+    }
+
+    /// Prove on this thread, or on a blocking thread if the proof may need
+    /// to be abandoned (abort signal or snarkjs fallback set)
+    async fn prove_to_completion(
+        &self,
+        input: &CircuitInput,
+    ) -> Result<(Proof, Vec<String>, ProofSource)> {
+        let Some(after) = self.snarkjs_fallback else {
+            if self.abort_signal.is_none() {
+                let (proof, public_signals) = self.prove_blocking(input)?;
+                return Ok((proof, public_signals, ProofSource::Rust));
+            }
+            let prover = self.clone();
+            let task_input = input.clone();
+            return rust_output(
+                tokio::task::spawn_blocking(move || prover.prove_blocking(&task_input)).await,
+            );
+        };
+
+        let mut prover = self.clone();
+        let verifier = self.clone();
+        let rust_input = input.clone();
+        let key_base_path = self.key_base_path.clone();
+        let snarkjs_input = input.clone();
+        race_snarkjs(
+            after,
+            move |lost| {
+                prover.race_lost = Some(lost);
+                prover.prove_blocking(&rust_input)
+            },
+            move |lost| {
+                let (proof, public_signals) =
+                    Prover::new(&key_base_path).prove_cancellable(&snarkjs_input, Some(&lost))?;
+                // snarkjs is a separate tool with its own input encoding: only
+                // use its proof if it proves this input's statement
+                verifier.verify_for_input(
+                    &snarkjs_input,
+                    &from_snarkjs_order(proof.clone()),
+                    &public_signals,
+                )?;
+                Ok((proof, public_signals))
+            },
+        )
+        .await
+    }
+
+    /// Verify a proof this prover didn't make against the circuit's verifying key
+    ///
+    /// `proof` has B's coordinates in the order `RustProver` produces (see
+    /// [`parse_proof_to_bytes`]). Fails with `ProofGenerationError` if it
+    /// doesn't verify for `public_signals`. Loads the zkey if needed.
+    pub fn verify(&self, proof: &Proof, public_signals: &[String]) -> Result<()> {
+        #[cfg(feature = "testing")]
+        if crate::testing::mock_prover_installed() {
+            return Ok(());
+        }
+        let (params, _) = self.load_keys()?;
+        let proof = parse_proof_to_bytes(proof)?.to_ark()?;
+        let public_inputs = public_signals
+            .iter()
+            .map(|s| parse_fr(s))
+            .collect::<Result<Vec<_>>>()?;
+        verify_groth16(&params.vk, &public_inputs, &proof)
+    }
+
+    /// Like [`verify`](Self::verify), also checking the signals are those of `input`
+    pub(crate) fn verify_for_input(
+        &self,
+        input: &CircuitInput,
+        proof: &Proof,
+        public_signals: &[String],
+    ) -> Result<()> {
+        if public_signals != input.public_signals().as_slice() {
+            return Err(PrivacyCashError::ProofGenerationError(
+                "Proof public signals don't match the circuit input".to_string(),
+            ));
+        }
+        self.verify(proof, public_signals)
+    }
+
+    /// Measure how long one proof takes on this device
+    ///
+    /// Proves a zero-value dummy transaction (see [`CircuitInput::dummy`]);
+    /// nothing is sent to the relayer or the chain. Includes zkey loading if
+    /// this prover hasn't loaded it yet. The proof runs on a blocking thread.
+    /// The result is cached per prover.
+    pub async fn benchmark(&self) -> Result<Duration> {
+        if let Some(elapsed) = self.benchmark.get() {
+            return Ok(*elapsed);
+        }
+
+        let input = CircuitInput::dummy()?;
+        let prover = self.clone();
+        let elapsed = tokio::task::spawn_blocking(move || {
+            let start = Instant::now();
+            prover.prove_blocking(&input).map(|_| start.elapsed())
+        })
+        .await
+        .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Benchmark task failed: {}", e)))??;
+
+        log::info!("Proof benchmark: {:.2}s", elapsed.as_secs_f64());
+        Ok(*self.benchmark.get_or_init(|| elapsed))
+    }
+
+    /// Like [`benchmark`](Self::benchmark), but persists the result in `storage`
+    ///
+    /// Later calls (including after an app restart) return the stored value
+    /// without proving again.
+    pub async fn benchmark_cached(&self, storage: &Storage) -> Result<Duration> {
+        let key = format!("{}{}", LSK_PROOF_BENCHMARK, self.key_base_path);
+
+        if let Some(millis) = storage.get(&key).and_then(|s| s.parse::<u64>().ok()) {
+            return Ok(Duration::from_millis(millis));
+        }
+
+        let elapsed = self.benchmark().await?;
+        storage.set(&key, &elapsed.as_millis().to_string());
+        Ok(elapsed)
+    }
+
+    /// Load the zkey file, or return the cached copy
+    fn load_keys(&self) -> Result<&CircuitKeys> {
+        self.keys.get_or_try_init(|| {
+            let zkey_path = format!("{}.zkey", self.key_base_path);
+            if !Path::new(&zkey_path).exists() {
+                return Err(PrivacyCashError::CircuitNotFound(format!(
+                    "zkey file not found: {}. Please download circuit files from the Privacy Cash SDK.",
+                    zkey_path
+                )));
+            }
+
+            log::info!("  [1/5] Loading zkey file ({})...", zkey_path);
+            let start = std::time::Instant::now();
+
+            let mut zkey_file = File::open(&zkey_path)?;
+            let keys = read_zkey(&mut zkey_file)
+                .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Failed to read zkey: {}", e)))?;
+
+            log::info!("  [1/5] Loaded zkey in {:.2}s (inputs: {}, constraints: {})",
+                start.elapsed().as_secs_f64(), keys.1.num_instance_variables, keys.1.num_constraints);
+            Ok(keys)
+        })
+    }
+
+    /// Load the R1CS constraints, or return the cached copy
+    fn load_constraints(&self) -> Result<&[Constraints<Bn254>]> {
+        let constraints = self.constraints.get_or_try_init(|| {
+            let r1cs_path = format!("{}.r1cs", self.key_base_path);
+            if !Path::new(&r1cs_path).exists() {
+                return Err(PrivacyCashError::CircuitNotFound(format!(
+                    "R1CS file not found: {}. The witness constraint check needs it; disable the check or add the file.",
+                    r1cs_path
+                )));
+            }
+
+            let file = R1CSFile::<Bn254>::new(std::io::BufReader::new(File::open(&r1cs_path)?))
+                .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Failed to read r1cs: {}", e)))?;
+            Ok(file.constraints)
+        })?;
+        Ok(constraints)
+    }
+
+    /// Synchronous proof generation, shared by `prove` and `ProverPool`
+    fn prove_blocking(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        self.check_abort()?;
+        #[cfg(feature = "testing")]
+        if let Some(output) = crate::testing::mock_proof(input) {
+            return Ok(output);
+        }
         let wasm_path = format!("{}.wasm", self.key_base_path);
-        let zkey_path = format!("{}.zkey", self.key_base_path);
 
         // Check that circuit files exist
         if !Path::new(&wasm_path).exists() {
@@ -60,28 +351,14 @@ impl RustProver {
                 wasm_path
             )));
         }
-        if !Path::new(&zkey_path).exists() {
-            return Err(PrivacyCashError::CircuitNotFound(format!(
-                "zkey file not found: {}. Please download circuit files from the Privacy Cash SDK.",
-                zkey_path
-            )));
-        }
 
-        log::info!("  [1/5] Loading zkey file ({})...", zkey_path);
-        let start = std::time::Instant::now();
-        
-        // 1. Load the proving key from .zkey file
-        let mut zkey_file = File::open(&zkey_path)?;
-        
-        let (params, matrices) = read_zkey(&mut zkey_file)
-            .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Failed to read zkey: {}", e)))?;
+        // 1. Load the proving key from .zkey file (cached after the first proof)
+        let (params, matrices) = self.load_keys()?;
+        self.check_abort()?;
         
         let num_inputs = matrices.num_instance_variables;
         let num_constraints = matrices.num_constraints;
         
-        log::info!("  [1/5] Loaded zkey in {:.2}s (inputs: {}, constraints: {})", 
-            start.elapsed().as_secs_f64(), num_inputs, num_constraints);
-        
         // 2. Prepare inputs for witness calculator
         log::info!("  [2/5] Building witness inputs...");
         let witness_inputs = self.build_witness_inputs(input)?;
@@ -92,6 +369,7 @@ impl RustProver {
         let mut wtns = WitnessCalculator::new(&wasm_path)
             .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Failed to create witness calculator: {}", e)))?;
         log::info!("  [3/5] WASM loaded in {:.2}s", start.elapsed().as_secs_f64());
+        self.check_abort()?;
         
         log::info!("  [4/5] Calculating witness...");
         let start = std::time::Instant::now();
@@ -100,6 +378,14 @@ impl RustProver {
             .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Witness calculation failed: {}", e)))?;
         log::info!("  [4/5] Witness calculated in {:.2}s ({} elements)", 
             start.elapsed().as_secs_f64(), full_assignment.len());
+
+        if self.check_constraints {
+            let start = std::time::Instant::now();
+            check_constraints(self.load_constraints()?, &full_assignment)?;
+            log::info!("  [4/5] Witness satisfies all constraints ({:.2}s)", start.elapsed().as_secs_f64());
+        }
+        // Last chance to stop: proof generation runs to completion
+        self.check_abort()?;
         
         // 4. Generate proof
         log::info!("  [5/5] Generating Groth16 proof (this may take 30-60 seconds)...");
@@ -110,10 +396,10 @@ impl RustProver {
         let s = Fr::rand(&mut rng);
         
         let proof = GrothBn::create_proof_with_reduction_and_matrices(
-            &params,
+            params,
             r,
             s,
-            &matrices,
+            matrices,
             num_inputs,
             num_constraints,
             full_assignment.as_slice(),
@@ -121,18 +407,17 @@ impl RustProver {
         .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Proof generation failed: {}", e)))?;
         log::info!("  [5/5] Proof generated in {:.2}s", start.elapsed().as_secs_f64());
         
+        self.check_abort()?;
+
         // Verify proof locally before returning
-        log::info!("  Verifying proof locally...");
-        use ark_crypto_primitives::snark::SNARK;
-        let pvk = GrothBn::process_vk(&params.vk)
-            .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Failed to process VK: {}", e)))?;
-        let public_inputs: Vec<Fr> = full_assignment[1..num_inputs].to_vec();
-        let verified = GrothBn::verify_with_processed_vk(&pvk, &public_inputs, &proof)
-            .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Proof verification failed: {}", e)))?;
-        if !verified {
-            return Err(PrivacyCashError::ProofGenerationError("Proof verification failed locally!".to_string()));
+        if self.verify_locally {
+            log::info!("  Verifying proof locally...");
+            let start = std::time::Instant::now();
+            verify_groth16(&params.vk, &full_assignment[1..num_inputs], &proof)?;
+            log::info!("  ✅ Proof verified locally in {:.2}s", start.elapsed().as_secs_f64());
+        } else {
+            log::debug!("  Skipping local proof verification");
         }
-        log::info!("  ✅ Proof verified locally!");
         
         // 5. Convert proof to snarkjs-compatible format
         let snarkjs_proof = self.format_proof_for_snarkjs(&proof)?;
@@ -157,6 +442,22 @@ impl RustProver {
         Ok((snarkjs_proof, public_signals))
     }
 
+    /// `Aborted` if the abort signal is set or snarkjs won the race
+    ///
+    /// Runs on blocking threads, so the signal is only peeked at; if it's
+    /// locked right now, the next check will see it.
+    fn check_abort(&self) -> Result<()> {
+        if self.race_lost.as_ref().is_some_and(|lost| lost.load(Ordering::SeqCst)) {
+            return Err(PrivacyCashError::Aborted);
+        }
+        match &self.abort_signal {
+            Some(signal) if signal.try_lock().map(|aborted| *aborted).unwrap_or(false) => {
+                Err(PrivacyCashError::Aborted)
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Build witness inputs HashMap from CircuitInput
     fn build_witness_inputs(&self, input: &CircuitInput) -> Result<HashMap<String, Vec<num_bigint::BigInt>>> {
         let mut witness_inputs: HashMap<String, Vec<num_bigint::BigInt>> = HashMap::new();
@@ -272,6 +573,257 @@ impl RustProver {
     }
 }
 
+/// Prover that produced a proof
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofSource {
+    /// The built-in ark-circom prover
+    #[default]
+    Rust,
+    /// The snarkjs CLI, after the Rust prover ran past its fallback time
+    Snarkjs,
+    /// Proved elsewhere and handed in by the caller (see
+    /// [`PrivacyCash::submit_withdraw_with_proof`](crate::PrivacyCash::submit_withdraw_with_proof))
+    External,
+}
+
+/// Output of a Rust prover task
+fn rust_output(
+    joined: std::result::Result<Result<(Proof, Vec<String>)>, tokio::task::JoinError>,
+) -> Result<(Proof, Vec<String>, ProofSource)> {
+    let (proof, public_signals) = joined
+        .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Prover task failed: {}", e)))??;
+    Ok((proof, public_signals, ProofSource::Rust))
+}
+
+/// Race snarkjs against the Rust prover once `after` has passed
+///
+/// Both run on blocking threads, each handed a flag that is set once it lost
+/// (or the race was dropped), so the loser stops instead of running to the
+/// end: the Rust prover at its next step, snarkjs by killing the process.
+async fn race_snarkjs(
+    after: Duration,
+    rust: impl FnOnce(Arc<AtomicBool>) -> Result<(Proof, Vec<String>)> + Send + 'static,
+    snarkjs: impl FnOnce(Arc<AtomicBool>) -> Result<(Proof, Vec<String>)> + Send + 'static,
+) -> Result<(Proof, Vec<String>, ProofSource)> {
+    let rust_lost = Arc::new(AtomicBool::new(false));
+    let snarkjs_lost = Arc::new(AtomicBool::new(false));
+    let _stop_losers = StopOnDrop(vec![Arc::clone(&rust_lost), Arc::clone(&snarkjs_lost)]);
+
+    let mut rust = tokio::task::spawn_blocking(move || rust(rust_lost));
+    if let Ok(joined) = tokio::time::timeout(after, &mut rust).await {
+        return rust_output(joined);
+    }
+    log::warn!(
+        "Rust prover still running after {:.0}s, racing snarkjs",
+        after.as_secs_f64()
+    );
+
+    let snarkjs = tokio::task::spawn_blocking(move || snarkjs(snarkjs_lost));
+    let snarkjs_result = tokio::select! {
+        joined = &mut rust => return rust_output(joined),
+        joined = snarkjs => joined,
+    };
+    match snarkjs_result {
+        Ok(Ok((proof, public_signals))) => {
+            log::info!("Proof produced by snarkjs");
+            Ok((from_snarkjs_order(proof), public_signals, ProofSource::Snarkjs))
+        }
+        Ok(Err(e)) => {
+            log::warn!("snarkjs fallback failed ({}), waiting for the Rust prover", e);
+            rust_output(rust.await)
+        }
+        Err(e) => {
+            log::warn!("snarkjs fallback task failed ({}), waiting for the Rust prover", e);
+            rust_output(rust.await)
+        }
+    }
+}
+
+/// Sets each flag when dropped
+struct StopOnDrop(Vec<Arc<AtomicBool>>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        for flag in &self.0 {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Resolve once `signal` is set to `true`
+async fn wait_for_abort(signal: &tokio::sync::Mutex<bool>) {
+    while !*signal.lock().await {
+        tokio::time::sleep(ABORT_POLL_INTERVAL).await;
+    }
+}
+
+/// A snarkjs proof with B's coordinates in the order [`RustProver`] uses
+///
+/// snarkjs writes each Fq2 coordinate as `[c0, c1]`; `parse_proof_to_bytes`
+/// expects `[c1, c0]`, as `RustProver` produces.
+fn from_snarkjs_order(mut proof: Proof) -> Proof {
+    for coord in proof.pi_b.iter_mut().take(2) {
+        coord.reverse();
+    }
+    proof
+}
+
+/// A fixed-size pool of warm provers for concurrent proof generation
+///
+/// Each prover keeps its own copy of the zkey in memory (roughly the size of
+/// the .zkey file, tens of MB for the transaction circuit), so a pool of `size`
+/// provers costs about `size` times that. Proofs run on tokio's blocking thread
+/// pool; at most `size` run at once and further callers wait for a free prover.
+pub struct ProverPool {
+    provers: Arc<Mutex<Vec<Arc<RustProver>>>>,
+    permits: Arc<Semaphore>,
+    size: usize,
+}
+
+impl ProverPool {
+    /// Create a pool of `size` provers, loading the zkey into each one
+    pub fn new(circuit_path: &str, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(PrivacyCashError::InvalidInput(
+                "Prover pool size must be at least 1".to_string(),
+            ));
+        }
+
+        let mut provers = Vec::with_capacity(size);
+        for _ in 0..size {
+            let prover = RustProver::new(circuit_path);
+            prover.warm_up()?;
+            provers.push(prover);
+        }
+        Ok(Self::from_provers(provers))
+    }
+
+    fn from_provers(provers: Vec<RustProver>) -> Self {
+        let size = provers.len();
+        Self {
+            provers: Arc::new(Mutex::new(provers.into_iter().map(Arc::new).collect())),
+            permits: Arc::new(Semaphore::new(size)),
+            size,
+        }
+    }
+
+    /// Number of provers in the pool
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Generate a proof on the next free prover
+    pub async fn prove(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        let input = input.clone();
+        self.run(move |prover| prover.prove_blocking(&input)).await
+    }
+
+    /// Run `task` on the next free prover, on tokio's blocking thread pool
+    ///
+    /// The blocking task owns the checked-out prover and its permit, so both
+    /// return to the pool when the task ends, even if the caller stopped
+    /// waiting for it.
+    async fn run<T: Send + 'static>(
+        &self,
+        task: impl FnOnce(&RustProver) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Prover pool closed: {}", e)))?;
+        let provers = self.provers.clone();
+
+        tokio::task::spawn_blocking(move || {
+            // Holding a permit guarantees a free prover
+            let prover = provers.lock().pop().expect("prover pool permit without a free prover");
+            let checkout = Checkout {
+                provers,
+                prover: Some(prover),
+                _permit: permit,
+            };
+            task(checkout.prover.as_ref().expect("checked-out prover"))
+        })
+        .await
+        .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Prover task failed: {}", e)))?
+    }
+}
+
+/// A prover taken from a [`ProverPool`], put back on drop (also when the
+/// proof panics) before its permit is released
+struct Checkout {
+    provers: Arc<Mutex<Vec<Arc<RustProver>>>>,
+    prover: Option<Arc<RustProver>>,
+    _permit: tokio::sync::OwnedSemaphorePermit,
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        if let Some(prover) = self.prover.take() {
+            self.provers.lock().push(prover);
+        }
+    }
+}
+
+/// Fail unless `witness` satisfies every `a * b = c` constraint
+///
+/// Each side is a linear combination of witness wires, as in the circuit's
+/// R1CS file; the witness calculator outputs wires in the same order.
+fn check_constraints(constraints: &[Constraints<Bn254>], witness: &[Fr]) -> Result<()> {
+    use ark_ff::Zero;
+
+    let evaluate = |terms: &[(usize, Fr)]| -> Result<Fr> {
+        terms.iter().try_fold(Fr::zero(), |sum, (wire, coeff)| {
+            let value = witness.get(*wire).ok_or_else(|| {
+                PrivacyCashError::ProofGenerationError(format!(
+                    "Constraint uses wire {} but the witness has {}",
+                    wire,
+                    witness.len()
+                ))
+            })?;
+            Ok(sum + *coeff * value)
+        })
+    };
+
+    for (index, (a, b, c)) in constraints.iter().enumerate() {
+        if evaluate(a)? * evaluate(b)? != evaluate(c)? {
+            return Err(PrivacyCashError::WitnessConstraintFailed {
+                constraint: index,
+                total: constraints.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Fail unless `proof` verifies for `public_inputs` under `vk`
+fn verify_groth16(
+    vk: &VerifyingKey<Bn254>,
+    public_inputs: &[Fr],
+    proof: &ark_groth16::Proof<Bn254>,
+) -> Result<()> {
+    use ark_crypto_primitives::snark::SNARK;
+    let pvk = GrothBn::process_vk(vk)
+        .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Failed to process VK: {}", e)))?;
+    let verified = GrothBn::verify_with_processed_vk(&pvk, public_inputs, proof)
+        .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Proof verification failed: {}", e)))?;
+    if !verified {
+        return Err(PrivacyCashError::ProofGenerationError("Proof verification failed locally!".to_string()));
+    }
+    Ok(())
+}
+
+/// Parse a decimal public signal, rejecting values outside the scalar field
+fn parse_fr(s: &str) -> Result<Fr> {
+    use ark_ff::PrimeField;
+    BigUint::parse_bytes(s.as_bytes(), 10)
+        .filter(|n| *n < BigUint::from(Fr::MODULUS))
+        .map(Fr::from)
+        .ok_or_else(|| PrivacyCashError::ProofGenerationError(format!("Invalid public signal: {}", s)))
+}
+
 /// Parse a decimal string to BigInt
 fn parse_bigint(s: &str) -> Result<num_bigint::BigInt> {
     num_bigint::BigInt::parse_bytes(s.as_bytes(), 10)
@@ -294,6 +846,95 @@ fn fr_to_decimal_string<F: ark_ff::PrimeField>(f: F) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::{FIELD_SIZE, MERKLE_TREE_DEPTH};
+    use proptest::prelude::*;
+
+    /// Field elements as the decimal strings `CircuitInput` carries
+    fn field_string() -> impl Strategy<Value = String> {
+        any::<[u8; 32]>().prop_map(|bytes| (BigUint::from_bytes_be(&bytes) % &*FIELD_SIZE).to_string())
+    }
+
+    fn field_pair() -> impl Strategy<Value = Vec<String>> {
+        prop::collection::vec(field_string(), 2)
+    }
+
+    /// Well-formed two-in/two-out circuit inputs with random field values
+    fn circuit_input() -> impl Strategy<Value = CircuitInput> {
+        let public = (field_string(), field_pair(), field_pair(), field_string(), any::<[u8; 32]>());
+        let inputs = (
+            field_pair(),
+            field_pair(),
+            field_pair(),
+            prop::collection::vec(any::<u64>(), 2),
+            prop::collection::vec(prop::collection::vec(field_string(), MERKLE_TREE_DEPTH), 2),
+        );
+        let outputs = (field_pair(), field_pair(), field_pair(), field_string());
+
+        (public, inputs, outputs).prop_map(
+            |(
+                (root, input_nullifier, output_commitment, public_amount, ext_data_hash),
+                (in_amount, in_private_key, in_blinding, in_path_indices, in_path_elements),
+                (out_amount, out_blinding, out_pubkey, mint_address),
+            )| {
+                let to_biguint = |v: Vec<String>| -> Vec<BigUint> {
+                    v.iter().map(|s| s.parse().unwrap()).collect()
+                };
+                CircuitInput {
+                    root,
+                    input_nullifier,
+                    output_commitment,
+                    public_amount,
+                    ext_data_hash: ext_data_hash.to_vec(),
+                    in_amount,
+                    in_private_key: to_biguint(in_private_key),
+                    in_blinding,
+                    in_path_indices,
+                    in_path_elements,
+                    out_amount,
+                    out_blinding,
+                    out_pubkey: to_biguint(out_pubkey),
+                    mint_address,
+                }
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_witness_inputs_have_circuit_arities(input in circuit_input()) {
+            let prover = RustProver::new("/nonexistent/transaction2");
+            let witness = prover.build_witness_inputs(&input).unwrap();
+
+            let expected = [
+                ("root", 1),
+                ("inputNullifier", 2),
+                ("outputCommitment", 2),
+                ("publicAmount", 1),
+                ("extDataHash", 1),
+                ("inAmount", 2),
+                ("inPrivateKey", 2),
+                ("inBlinding", 2),
+                ("inPathIndices", 2),
+                ("inPathElements", 2 * MERKLE_TREE_DEPTH),
+                ("outAmount", 2),
+                ("outBlinding", 2),
+                ("outPubkey", 2),
+                ("mintAddress", 1),
+            ];
+            prop_assert_eq!(witness.len(), expected.len());
+            for (name, arity) in expected {
+                prop_assert_eq!(witness[name].len(), arity, "arity of {}", name);
+            }
+
+            // Values pass through unchanged; extDataHash is read little-endian
+            prop_assert_eq!(witness["root"][0].to_string(), input.root);
+            prop_assert_eq!(witness["inPathElements"][MERKLE_TREE_DEPTH].to_string(), input.in_path_elements[1][0].clone());
+            prop_assert_eq!(
+                witness["extDataHash"][0].clone(),
+                biguint_to_bigint(&BigUint::from_bytes_le(&input.ext_data_hash))
+            );
+        }
+    }
     
     #[test]
     fn test_parse_bigint() {
@@ -308,4 +949,252 @@ mod tests {
         let bi = biguint_to_bigint(&bu);
         assert_eq!(bi, num_bigint::BigInt::from(12345u64));
     }
+
+    #[test]
+    fn test_check_constraints() {
+        // x * y = z over wires [1, x, y, z]
+        let constraints: Vec<Constraints<Bn254>> = vec![(
+            vec![(1, Fr::from(1u64))],
+            vec![(2, Fr::from(1u64))],
+            vec![(3, Fr::from(1u64))],
+        )];
+        let witness = |z: u64| vec![Fr::from(1u64), Fr::from(3u64), Fr::from(4u64), Fr::from(z)];
+
+        assert!(check_constraints(&constraints, &witness(12)).is_ok());
+        assert!(matches!(
+            check_constraints(&constraints, &witness(13)),
+            Err(PrivacyCashError::WitnessConstraintFailed { constraint: 0, total: 1 })
+        ));
+        assert!(matches!(
+            check_constraints(&constraints, &witness(12)[..3]),
+            Err(PrivacyCashError::ProofGenerationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_constraint_check_needs_r1cs() {
+        let prover = RustProver::new("/nonexistent/transaction2").with_constraint_check(true);
+        assert!(matches!(prover.load_constraints(), Err(PrivacyCashError::CircuitNotFound(_))));
+    }
+
+    #[test]
+    fn test_from_snarkjs_order() {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let proof = Proof {
+            pi_a: strings(&["1", "2", "1"]),
+            pi_b: vec![strings(&["10", "11"]), strings(&["20", "21"]), strings(&["1", "0"])],
+            pi_c: strings(&["3", "4", "1"]),
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+        };
+        let converted = from_snarkjs_order(proof);
+        assert_eq!(converted.pi_b[0], strings(&["11", "10"]));
+        assert_eq!(converted.pi_b[1], strings(&["21", "20"]));
+        assert_eq!(converted.pi_b[2], strings(&["1", "0"]));
+        assert_eq!(converted.pi_a, strings(&["1", "2", "1"]));
+    }
+
+    #[tokio::test]
+    async fn test_snarkjs_fallback_passes_through_fast_errors() {
+        // The Rust prover fails before the fallback time, so snarkjs never runs
+        let prover = RustProver::new("/nonexistent/transaction2")
+            .with_snarkjs_fallback(Some(Duration::from_secs(60)));
+        let input = CircuitInput::dummy().unwrap();
+        assert!(matches!(
+            prover.prove_with_source(&input).await,
+            Err(PrivacyCashError::CircuitNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_rejects_other_statements() {
+        let prover = RustProver::new("/nonexistent/transaction2");
+        let input = CircuitInput::dummy().unwrap();
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let proof = Proof {
+            pi_a: strings(&["1", "2", "1"]),
+            pi_b: vec![strings(&["1", "2"]), strings(&["3", "4"]), strings(&["1", "0"])],
+            pi_c: strings(&["1", "2", "1"]),
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+        };
+
+        // Checked before the zkey is needed
+        let mut signals = input.public_signals();
+        signals[2] = "1".to_string();
+        assert!(matches!(
+            prover.verify_for_input(&input, &proof, &signals),
+            Err(PrivacyCashError::ProofGenerationError(_))
+        ));
+        assert!(matches!(
+            prover.verify_for_input(&input, &proof, &input.public_signals()),
+            Err(PrivacyCashError::CircuitNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_fr() {
+        assert_eq!(parse_fr("5").unwrap(), Fr::from(5u64));
+        let modulus = FIELD_SIZE.to_string();
+        assert!(parse_fr(&modulus).is_err());
+        assert!(parse_fr("-1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_abort_signal_stops_proving() {
+        let signal = Arc::new(tokio::sync::Mutex::new(false));
+        let prover = RustProver::new("/nonexistent/transaction2")
+            .with_abort_signal(Some(Arc::clone(&signal)));
+        let input = CircuitInput::dummy().unwrap();
+
+        // Unset, the prover runs (and fails on the missing files)
+        assert!(matches!(
+            prover.prove(&input).await,
+            Err(PrivacyCashError::CircuitNotFound(_))
+        ));
+
+        *signal.lock().await = true;
+        assert!(matches!(prover.prove(&input).await, Err(PrivacyCashError::Aborted)));
+        assert!(matches!(prover.prove_blocking(&input), Err(PrivacyCashError::Aborted)));
+    }
+
+    #[tokio::test]
+    async fn test_snarkjs_race_stops_the_loser() {
+        // Spins until told it lost, then records that it stopped
+        fn until_lost(
+            stopped: Arc<AtomicBool>,
+        ) -> impl FnOnce(Arc<AtomicBool>) -> Result<(Proof, Vec<String>)> + Send + 'static {
+            move |lost| {
+                while !lost.load(Ordering::SeqCst) {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                stopped.store(true, Ordering::SeqCst);
+                Err(PrivacyCashError::Aborted)
+            }
+        }
+        fn finishes_after(
+            delay: Duration,
+        ) -> impl FnOnce(Arc<AtomicBool>) -> Result<(Proof, Vec<String>)> + Send + 'static {
+            move |_| {
+                std::thread::sleep(delay);
+                let point = vec!["1".to_string(), "2".to_string(), "1".to_string()];
+                let proof = Proof {
+                    pi_a: point.clone(),
+                    pi_b: vec![point.clone(), point.clone(), point.clone()],
+                    pi_c: point,
+                    protocol: "groth16".to_string(),
+                    curve: "bn128".to_string(),
+                };
+                Ok((proof, vec!["7".to_string()]))
+            }
+        }
+        async fn wait_until(flag: &AtomicBool) -> bool {
+            for _ in 0..200 {
+                if flag.load(Ordering::SeqCst) {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            false
+        }
+        let after = Duration::from_millis(20);
+
+        // snarkjs finishes while the Rust prover is still running
+        let rust_stopped = Arc::new(AtomicBool::new(false));
+        let rust = until_lost(Arc::clone(&rust_stopped));
+        let (_, signals, source) = race_snarkjs(after, rust, finishes_after(after)).await.unwrap();
+        assert_eq!(source, ProofSource::Snarkjs);
+        assert_eq!(signals, vec!["7".to_string()]);
+        assert!(wait_until(&rust_stopped).await);
+
+        // The Rust prover finishes while snarkjs is still running
+        let snarkjs_stopped = Arc::new(AtomicBool::new(false));
+        let snarkjs = until_lost(Arc::clone(&snarkjs_stopped));
+        let (_, _, source) = race_snarkjs(after, finishes_after(after * 3), snarkjs).await.unwrap();
+        assert_eq!(source, ProofSource::Rust);
+        assert!(wait_until(&snarkjs_stopped).await);
+    }
+
+    #[test]
+    fn test_race_lost_stops_rust_prover() {
+        let lost = Arc::new(AtomicBool::new(true));
+        let prover = RustProver {
+            race_lost: Some(lost),
+            ..RustProver::new("/nonexistent/transaction2")
+        };
+        let input = CircuitInput::dummy().unwrap();
+        assert!(matches!(prover.prove_blocking(&input), Err(PrivacyCashError::Aborted)));
+    }
+
+    #[test]
+    fn test_prover_pool_rejects_zero_size() {
+        assert!(matches!(
+            ProverPool::new("circuit/transaction2", 0),
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_prover_pool_survives_cancelled_proof() {
+        let pool = ProverPool::from_provers(vec![RustProver::new("/nonexistent/transaction2")]);
+
+        // The caller gives up while the proof is still running
+        let slow = pool.run(|_| {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(())
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(10), slow).await.is_err());
+
+        // The prover comes back once the abandoned proof finishes
+        let next = tokio::time::timeout(Duration::from_secs(5), pool.run(|_| Ok(7)));
+        assert_eq!(next.await.unwrap().unwrap(), 7);
+        assert_eq!(pool.provers.lock().len(), 1);
+
+        // Also after a panicking proof
+        assert!(pool.run(|_| -> Result<()> { panic!("prover crashed") }).await.is_err());
+        assert_eq!(pool.run(|_| Ok(8)).await.unwrap(), 8);
+    }
+
+    #[test]
+    fn test_prover_pool_missing_circuit() {
+        assert!(matches!(
+            ProverPool::new("/nonexistent/transaction2", 2),
+            Err(PrivacyCashError::CircuitNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_cached_uses_stored_value() {
+        let storage = Storage::memory();
+        let prover = RustProver::new("/nonexistent/transaction2");
+        storage.set(
+            &format!("{}{}", LSK_PROOF_BENCHMARK, "/nonexistent/transaction2"),
+            "42000",
+        );
+
+        let elapsed = prover.benchmark_cached(&storage).await.unwrap();
+        assert_eq!(elapsed, Duration::from_secs(42));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires circuit files and takes minutes"]
+    async fn test_skip_local_verification_still_proves() {
+        let key_base_path = format!("{}/circuit/transaction2", env!("CARGO_MANIFEST_DIR"));
+        let input = CircuitInput::dummy().unwrap();
+
+        let (verified, verified_signals) = RustProver::new(&key_base_path).prove(&input).await.unwrap();
+        let (unverified, unverified_signals) = RustProver::new(&key_base_path)
+            .with_local_verification(false)
+            .prove(&input)
+            .await
+            .unwrap();
+
+        // Same statement, and a proof of the same shape the instruction encodes
+        assert_eq!(unverified_signals, verified_signals);
+        assert_eq!(unverified_signals, input.public_signals());
+        assert_eq!(unverified.pi_a.len(), verified.pi_a.len());
+        assert_eq!(unverified.pi_b.len(), verified.pi_b.len());
+        assert_eq!(unverified.pi_c.len(), verified.pi_c.len());
+        assert!(crate::prover::parse_proof_to_bytes(&unverified).is_ok());
+    }
 }
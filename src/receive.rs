@@ -0,0 +1,241 @@
+//! Per-payment receive addresses
+//!
+//! A receive address is a sub-key of the wallet's V2 UTXO key (see
+//! [`ZkKeypair::derive_child`]) together with an encryption key derived for
+//! the same index. A payer who has the address, e.g.
+//! `pcrecv:7:<owner>:<key>`, pays it with `PrivacyCash::deposit_to`: the
+//! deposit creates a note owned by the sub-key, encrypted so the wallet finds
+//! it when scanning.
+//!
+//! # Privacy model
+//!
+//! Give each invoice its own index. Notes sent to different addresses have
+//! unrelated owners and encryption keys, so on-chain data doesn't link them
+//! to each other or to the wallet. The address does contain its encryption
+//! key: whoever holds it can read notes sent to that address (but never
+//! spend them, and never read notes sent elsewhere). Share it only with the
+//! payer of that invoice, and don't reuse it.
+//!
+//! # Scan cost
+//!
+//! The wallet only finds notes for the indices it scans for (see
+//! `PrivacyCash::set_receive_scan_indices`). Every output the wallet key
+//! can't open is tried against each of those keys, so a scan costs one
+//! AES-GCM tag check per output per index. A few hundred indices are cheap;
+//! for many thousands, retire paid invoices from the set.
+
+use crate::constants::pool_mint;
+use crate::encryption::encrypt_v2;
+use crate::error::{PrivacyCashError, Result};
+use crate::keypair::ZkKeypair;
+use crate::utxo::{Utxo, UtxoVersion};
+use num_bigint::BigUint;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+/// Prefix of a receive address string
+pub const RECEIVE_ADDRESS_PREFIX: &str = "pcrecv";
+
+/// Where a payer can send a private note
+#[derive(Clone, PartialEq, Eq)]
+pub struct ReceiveAddress {
+    /// Sub-key index
+    pub index: u32,
+
+    /// Public key of the sub-key that owns notes sent here
+    pub owner: BigUint,
+
+    /// Key that encrypts notes sent here
+    encryption_key: [u8; 32],
+}
+
+impl fmt::Debug for ReceiveAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReceiveAddress")
+            .field("index", &self.index)
+            .field("owner", &self.owner.to_string())
+            .finish()
+    }
+}
+
+impl ReceiveAddress {
+    /// Receive address `index` of the wallet with V2 UTXO key `keypair`
+    pub(crate) fn derive(keypair: &ZkKeypair, index: u32, encryption_key: [u8; 32]) -> Result<Self> {
+        Ok(Self {
+            index,
+            owner: keypair.derive_child(index)?.pubkey().clone(),
+            encryption_key,
+        })
+    }
+
+    /// Key that encrypts notes sent to this address
+    pub fn encryption_key(&self) -> &[u8; 32] {
+        &self.encryption_key
+    }
+
+    /// A new note of `amount` paying this address, to be placed at `leaf_index`
+    ///
+    /// `mint` is mapped with [`pool_mint`], so the wrapped SOL mint means
+    /// native SOL. The note has a random blinding and carries this address's index, so
+    /// the wallet spends it with the right sub-key. Payers don't build these
+    /// themselves: [`PrivacyCash::deposit_to`](crate::PrivacyCash::deposit_to)
+    /// places the note at the tree's next index.
+    pub(crate) fn note(&self, amount: impl Into<BigUint>, leaf_index: u64, mint: &Pubkey) -> Utxo {
+        let mut note = Utxo::new(
+            amount,
+            ZkKeypair::from_pubkey(self.owner.clone()),
+            leaf_index,
+            Some(&pool_mint(mint).to_string()),
+            Some(UtxoVersion::V2),
+        );
+        note.key_index = Some(self.index);
+        note
+    }
+
+    /// Commitment of a note paying this address
+    ///
+    /// commitment = Poseidon(amount, owner, blinding, mintAddressField), as
+    /// for any note with the default (zero) domain tags. The address string
+    /// doesn't carry [`DomainTags`](crate::keypair::DomainTags).
+    pub fn commitment(&self, amount: u64, blinding: &BigUint, mint: &Pubkey) -> Result<String> {
+        let mut note = self.note(amount, 0, mint);
+        note.blinding = blinding.clone();
+        note.get_commitment()
+    }
+
+    /// Encrypted output for `note`, a note paying this address
+    ///
+    /// Encrypted under this address's key in the format of
+    /// [`Utxo::serialize_for_encryption`], which the wallet's scan reads.
+    pub(crate) fn encrypt_note(&self, note: &Utxo) -> Result<Vec<u8>> {
+        if note.key_index != Some(self.index) || note.keypair.pubkey() != &self.owner {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Note isn't owned by receive address {}",
+                self.index
+            )));
+        }
+        encrypt_v2(&self.encryption_key, note.serialize_for_encryption().as_bytes())
+    }
+}
+
+impl fmt::Display for ReceiveAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            RECEIVE_ADDRESS_PREFIX,
+            self.index,
+            self.owner,
+            hex::encode(self.encryption_key)
+        )
+    }
+}
+
+impl FromStr for ReceiveAddress {
+    type Err = PrivacyCashError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid =
+            |msg: &str| PrivacyCashError::InvalidInput(format!("Invalid receive address: {}", msg));
+
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        let [prefix, index, owner, key] = parts.as_slice() else {
+            return Err(invalid("expected 4 fields"));
+        };
+        if *prefix != RECEIVE_ADDRESS_PREFIX {
+            return Err(invalid("missing pcrecv: prefix"));
+        }
+
+        let index = index.parse().map_err(|_| invalid("bad index"))?;
+        let owner = BigUint::parse_bytes(owner.as_bytes(), 10).ok_or_else(|| invalid("bad owner"))?;
+        let encryption_key = hex::decode(key)
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or_else(|| invalid("bad encryption key"))?;
+
+        Ok(Self {
+            index,
+            owner,
+            encryption_key,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NATIVE_SOL_MINT;
+    use crate::encryption::EncryptionService;
+    use crate::utxo::UtxoVersion;
+    use solana_sdk::signature::Keypair;
+
+    fn wallet() -> (EncryptionService, ZkKeypair) {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new());
+        let keypair = ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap();
+        (service, keypair)
+    }
+
+    fn receive_address(service: &EncryptionService, keypair: &ZkKeypair, index: u32) -> ReceiveAddress {
+        ReceiveAddress::derive(keypair, index, service.derive_receive_key(index).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_receive_address_roundtrip() {
+        let (service, keypair) = wallet();
+        let address = receive_address(&service, &keypair, 7);
+
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("pcrecv:7:"));
+        assert_eq!(encoded.parse::<ReceiveAddress>().unwrap(), address);
+        assert!("pcrecv:7:1".parse::<ReceiveAddress>().is_err());
+        let wrong_prefix = format!("privacycash:7:1:{}", hex::encode([0u8; 32]));
+        assert!(wrong_prefix.parse::<ReceiveAddress>().is_err());
+
+        // Deterministic per index, unrelated across indices
+        assert_eq!(receive_address(&service, &keypair, 7), address);
+        let other = receive_address(&service, &keypair, 8);
+        assert_ne!(other.owner, address.owner);
+        assert_ne!(other.encryption_key(), address.encryption_key());
+    }
+
+    #[test]
+    fn test_scan_finds_notes_for_receive_addresses() {
+        let (mut service, keypair) = wallet();
+        let address = receive_address(&service, &keypair, 3);
+        let paid = address.note(1_000, 42, &NATIVE_SOL_MINT);
+        let blinding = paid.blinding.clone();
+        let note = address.encrypt_note(&paid).unwrap();
+
+        // Not scanned for yet
+        assert!(service.try_decrypt_utxo(&note).unwrap().is_none());
+
+        service.set_receive_indices([5, 3, 3]).unwrap();
+        assert_eq!(service.receive_indices(), vec![3, 5]);
+        let utxo = service.try_decrypt_utxo(&note).unwrap().unwrap();
+        assert_eq!(utxo.amount, BigUint::from(1_000u32));
+        assert_eq!(utxo.index, 42);
+        assert_eq!(utxo.key_index, Some(3));
+        assert_eq!(utxo.version, UtxoVersion::V2);
+        assert_eq!(utxo.keypair.pubkey(), &address.owner);
+        let commitment = address.commitment(1_000, &blinding, &NATIVE_SOL_MINT).unwrap();
+        assert_eq!(utxo.get_commitment().unwrap(), commitment);
+        assert_eq!(paid.get_commitment().unwrap(), commitment);
+        // The wrapped SOL mint names the same pool
+        let wrapped = address.commitment(1_000, &blinding, &crate::constants::SOL_MINT).unwrap();
+        assert_eq!(wrapped, commitment);
+        // The payer's copy can't be spent
+        assert!(paid.get_nullifier().is_err());
+        assert!(utxo.get_nullifier().is_ok());
+
+        // Only notes owned by the address are encrypted for it
+        let other = receive_address(&service, &keypair, 4);
+        assert!(other.encrypt_note(&paid).is_err());
+
+        // A note claiming another sub-key isn't ours to spend
+        let serialized = format!("1000|12345|42|{}|4", *NATIVE_SOL_MINT);
+        let mismatched = encrypt_v2(address.encryption_key(), serialized.as_bytes()).unwrap();
+        assert!(service.try_decrypt_utxo(&mismatched).unwrap().is_none());
+    }
+}
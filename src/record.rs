@@ -0,0 +1,273 @@
+//! Audit records of deposits, withdrawals and private sends
+//!
+//! An [`OperationRecord`] is one serializable line for an audit trail: what
+//! was attempted, by which wallet, for how much, what it cost, which
+//! transactions it produced, how long it took and whether it succeeded. It
+//! never holds keys, blindings or note data.
+//!
+//! Register a sink with `PrivacyCash::set_operation_sink` to get a record
+//! after every deposit, withdrawal and V1 migration the client makes.
+
+use crate::deposit::DepositResult;
+use crate::deposit_spl::DepositSplResult;
+use crate::error::Result;
+use crate::withdraw::WithdrawResult;
+use crate::withdraw_spl::WithdrawSplResult;
+use crate::SendPrivatelyResult;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Receives a record after each operation
+pub(crate) type RecordSink = Arc<dyn Fn(&OperationRecord) + Send + Sync>;
+
+/// Kind of operation a record describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Deposit,
+    Withdraw,
+    SendPrivately,
+    /// Moving V1 notes to V2 keys; nothing enters or leaves the pool
+    Migration,
+}
+
+/// How an operation ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum OperationOutcome {
+    Succeeded,
+    Failed { error: String },
+}
+
+/// One deposit, withdrawal or private send, for audit logs
+///
+/// Amounts are in base units of `token`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OperationRecord {
+    pub operation: Operation,
+    /// Wallet that made the operation
+    pub wallet: String,
+    /// Token name, e.g. `"sol"` or `"usdc"`
+    pub token: String,
+    /// Amount requested
+    pub amount: u64,
+    /// Amount the recipient received, for withdrawals and sends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_received: Option<u64>,
+    /// Protocol fee charged, for withdrawals and sends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<u64>,
+    /// Recipient address, for withdrawals and sends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+    /// Transaction signatures in the order they were sent, including any
+    /// partner fee transfer
+    pub signatures: Vec<String>,
+    /// Start time, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// Wall-clock duration in milliseconds
+    pub duration_ms: u64,
+    pub outcome: OperationOutcome,
+}
+
+impl OperationRecord {
+    /// Whether the operation succeeded
+    pub fn succeeded(&self) -> bool {
+        self.outcome == OperationOutcome::Succeeded
+    }
+
+    /// Record as a single JSON line
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("operation records always serialize")
+    }
+
+    /// Record of an operation started at `started`, ending with `result`
+    pub(crate) fn from_result<T: RecordDetails>(
+        operation: Operation,
+        wallet: &Pubkey,
+        token: &str,
+        amount: u64,
+        started: &OperationTimer,
+        result: &Result<T>,
+    ) -> Self {
+        let mut record = OperationRecord {
+            operation,
+            wallet: wallet.to_string(),
+            token: token.to_string(),
+            amount,
+            amount_received: None,
+            fees: None,
+            recipient: None,
+            signatures: Vec::new(),
+            started_at_ms: started.started_at_ms,
+            duration_ms: started.elapsed_ms(),
+            outcome: OperationOutcome::Succeeded,
+        };
+        match result {
+            Ok(details) => details.fill(&mut record),
+            Err(e) => {
+                record.outcome = OperationOutcome::Failed {
+                    error: e.to_string(),
+                }
+            }
+        }
+        record
+    }
+}
+
+/// Start time of an operation
+pub(crate) struct OperationTimer {
+    started_at_ms: u64,
+    start: Instant,
+}
+
+impl OperationTimer {
+    pub(crate) fn start() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        OperationTimer {
+            started_at_ms: since_epoch.as_millis() as u64,
+            start: Instant::now(),
+        }
+    }
+
+    /// Timer for an operation that took `duration` and just finished
+    fn ended(duration: Duration) -> Self {
+        let mut timer = Self::start();
+        timer.started_at_ms = timer.started_at_ms.saturating_sub(duration.as_millis() as u64);
+        timer.start = timer.start.checked_sub(duration).unwrap_or(timer.start);
+        timer
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+/// Result types that fill in the details of a successful record
+pub(crate) trait RecordDetails {
+    fn fill(&self, record: &mut OperationRecord);
+}
+
+/// Push a signature unless it's empty (dry runs submit nothing)
+fn push_signature(record: &mut OperationRecord, signature: &str) {
+    if !signature.is_empty() {
+        record.signatures.push(signature.to_string());
+    }
+}
+
+impl RecordDetails for DepositResult {
+    fn fill(&self, record: &mut OperationRecord) {
+        push_signature(record, &self.signature);
+    }
+}
+
+impl RecordDetails for DepositSplResult {
+    fn fill(&self, record: &mut OperationRecord) {
+        push_signature(record, &self.signature);
+    }
+}
+
+impl RecordDetails for WithdrawResult {
+    fn fill(&self, record: &mut OperationRecord) {
+        record.amount_received = Some(self.amount_in_lamports);
+        record.fees = Some(self.fee_in_lamports);
+        record.recipient = Some(self.recipient.clone());
+        if let Some(signature) = &self.partner_fee_signature {
+            push_signature(record, signature);
+        }
+        push_signature(record, &self.signature);
+    }
+}
+
+impl RecordDetails for WithdrawSplResult {
+    fn fill(&self, record: &mut OperationRecord) {
+        record.amount_received = Some(self.base_units);
+        record.fees = Some(self.fee_base_units);
+        record.recipient = Some(self.recipient.clone());
+        if let Some(signature) = &self.partner_fee_signature {
+            push_signature(record, signature);
+        }
+        push_signature(record, &self.signature);
+    }
+}
+
+impl RecordDetails for SendPrivatelyResult {
+    fn fill(&self, record: &mut OperationRecord) {
+        record.amount_received = Some(self.amount_received);
+        record.fees = Some(self.total_fees);
+        record.recipient = Some(self.recipient.clone());
+        push_signature(record, &self.deposit_signature);
+        push_signature(record, &self.withdraw_signature);
+    }
+}
+
+impl SendPrivatelyResult {
+    /// Audit record of this send, made from `wallet`
+    pub fn to_record(&self, wallet: &Pubkey) -> OperationRecord {
+        OperationRecord::from_result(
+            Operation::SendPrivately,
+            wallet,
+            &self.token,
+            self.amount_deposited,
+            &OperationTimer::ended(self.total_duration),
+            &Ok(self.clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PrivacyCashError;
+
+    #[test]
+    fn test_operation_records() {
+        let wallet = Pubkey::new_unique();
+        let timer = OperationTimer::start();
+
+        let result: Result<WithdrawResult> = Ok(WithdrawResult {
+            signature: "withdraw-sig".to_string(),
+            recipient: "recipient".to_string(),
+            amount_in_lamports: 90,
+            fee_in_lamports: 10,
+            is_partial: false,
+            expected_remaining_lamports: 0,
+            dry_run: None,
+            partner_fee_signature: Some("fee-sig".to_string()),
+            proof_source: Default::default(),
+        });
+        let record = OperationRecord::from_result(Operation::Withdraw, &wallet, "sol", 100, &timer, &result);
+        assert!(record.succeeded());
+        assert_eq!(record.amount_received, Some(90));
+        assert_eq!(record.fees, Some(10));
+        assert_eq!(record.signatures, vec!["fee-sig", "withdraw-sig"]);
+
+        let json = record.to_json();
+        assert!(json.contains("\"operation\":\"withdraw\""));
+        assert!(json.contains("\"status\":\"succeeded\""));
+        let parsed: OperationRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, record);
+
+        let result: Result<DepositResult> = Err(PrivacyCashError::InsufficientBalance { need: 2, have: 1 });
+        let record = OperationRecord::from_result(Operation::Deposit, &wallet, "sol", 100, &timer, &result);
+        assert!(!record.succeeded());
+        assert!(record.signatures.is_empty());
+        assert!(record.to_json().contains("\"status\":\"failed\""));
+
+        // A V1 migration is a deposit of nothing, but isn't recorded as one
+        let result: Result<DepositResult> = Ok(DepositResult {
+            signature: "migration-sig".to_string(),
+            output_utxo: None,
+            dry_run: None,
+            proof_source: Default::default(),
+        });
+        let record = OperationRecord::from_result(Operation::Migration, &wallet, "sol", 100, &timer, &result);
+        assert!(record.to_json().contains("\"operation\":\"migration\""));
+        assert_eq!(record.signatures, vec!["migration-sig"]);
+    }
+}
@@ -0,0 +1,385 @@
+//! Throttled HTTP access to the relayer API
+//!
+//! All relayer requests go through a shared client and a process-wide token
+//! bucket, so batch sends and confirmation polling don't hammer the shared
+//! relayer. A `429 Too Many Requests` response is retried after the
+//! `Retry-After` delay (or an exponential backoff) and surfaces as
+//! `PrivacyCashError::RateLimited` if it persists. Every request carries the
+//! configured `User-Agent` and extra headers.
+
+use crate::constants::RELAYER_API_URL;
+use crate::error::{PrivacyCashError, Result};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::{Duration, Instant};
+
+/// Default sustained request rate to the relayer
+pub const DEFAULT_RELAYER_REQUESTS_PER_SECOND: f64 = 10.0;
+
+/// Default number of requests allowed in a burst
+pub const DEFAULT_RELAYER_BURST: u32 = 20;
+
+/// `User-Agent` sent to the relayer unless overridden
+pub const DEFAULT_USER_AGENT: &str = concat!("privacy-cash-rust-sdk/", env!("CARGO_PKG_VERSION"));
+
+/// Default time allowed to open a connection to the relayer
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time an unused pooled connection is kept open
+///
+/// Below the idle timeout of typical NAT gateways and load balancers
+/// (60s or more), so idle connections are closed by us rather than silently
+/// dropped in between, which would fail the next request with a reset.
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// TCP keep-alive interval for relayer connections
+const TCP_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// How many times a rate-limited request is retried
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Shared HTTP client (reuses connections across requests)
+static HTTP_CLIENT: Lazy<RwLock<reqwest::Client>> = Lazy::new(|| {
+    RwLock::new(
+        build_http_client(DEFAULT_CONNECT_TIMEOUT, DEFAULT_POOL_IDLE_TIMEOUT)
+            .expect("default relayer HTTP client"),
+    )
+});
+
+/// Process-wide limiter for relayer requests
+pub static RELAYER_RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(|| {
+    RateLimiter::new(DEFAULT_RELAYER_REQUESTS_PER_SECOND, DEFAULT_RELAYER_BURST)
+});
+
+/// Process-wide headers added to relayer requests
+pub static RELAYER_HEADERS: Lazy<RelayerHeaders> = Lazy::new(RelayerHeaders::new);
+
+/// Headers sent with every relayer request
+pub struct RelayerHeaders {
+    headers: RwLock<HeaderMap>,
+}
+
+impl RelayerHeaders {
+    /// Only the default `User-Agent`
+    pub fn new() -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+        Self {
+            headers: RwLock::new(headers),
+        }
+    }
+
+    /// Replace the `User-Agent`
+    pub fn set_user_agent(&self, user_agent: &str) -> Result<()> {
+        self.set(USER_AGENT.as_str(), user_agent)
+    }
+
+    /// Add or replace a header, e.g. an API key for a private relayer
+    ///
+    /// Values are marked sensitive so they're left out of debug output.
+    pub fn set(&self, name: &str, value: &str) -> Result<()> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid header name {}: {}", name, e)))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid value for header {}: {}", name, e)))?;
+        if name != USER_AGENT {
+            value.set_sensitive(true);
+        }
+        self.headers.write().insert(name, value);
+        Ok(())
+    }
+
+    /// Go back to only the default `User-Agent`
+    pub fn reset(&self) {
+        *self.headers.write() = Self::new().headers.into_inner();
+    }
+
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        request.headers(self.headers.read().clone())
+    }
+}
+
+impl Default for RelayerHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Token bucket rate limiter
+pub struct RateLimiter {
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing `requests_per_second` sustained and `burst` at once
+    ///
+    /// A rate of 0 (or less) disables limiting.
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            state: Mutex::new(Bucket {
+                capacity,
+                refill_per_sec: requests_per_second,
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Change the rate and burst size (the bucket starts full again)
+    pub fn set_rate(&self, requests_per_second: f64, burst: u32) {
+        let mut state = self.state.lock();
+        state.capacity = burst.max(1) as f64;
+        state.refill_per_sec = requests_per_second;
+        state.tokens = state.capacity;
+        state.last_refill = Instant::now();
+    }
+
+    /// Take a token, or return how long to wait until one is available
+    fn try_acquire(&self) -> std::result::Result<(), Duration> {
+        let mut state = self.state.lock();
+        if state.refill_per_sec <= 0.0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * state.refill_per_sec).min(state.capacity);
+        state.last_refill = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - state.tokens) / state.refill_per_sec))
+        }
+    }
+
+    /// Wait until a request may be made
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// HTTP client with the given connection timeouts
+fn build_http_client(
+    connect_timeout: Duration,
+    pool_idle_timeout: Duration,
+) -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(pool_idle_timeout)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .build()
+        .map_err(|e| PrivacyCashError::ConfigError(format!("Relayer HTTP client: {}", e)))
+}
+
+/// Limit how fast this process makes relayer requests
+///
+/// Requests are throttled with a token bucket: `burst` requests may go out
+/// at once, refilling at `requests_per_second`. Pass a rate of 0 to disable
+/// throttling. The limit is process-wide: every client shares it, since they
+/// all talk to the same relayer.
+pub fn set_rate_limit(requests_per_second: f64, burst: u32) {
+    RELAYER_RATE_LIMITER.set_rate(requests_per_second, burst);
+}
+
+/// Set the `User-Agent` this process sends to the relayer
+///
+/// Defaults to `privacy-cash-rust-sdk/<version>`. Process-wide, like the
+/// rate limit.
+pub fn set_user_agent(user_agent: &str) -> Result<()> {
+    RELAYER_HEADERS.set_user_agent(user_agent)
+}
+
+/// Send an extra header with every relayer request from this process
+///
+/// For private or self-hosted relayers behind an API key or a proxy that
+/// requires custom headers. Setting the same name again replaces the value.
+pub fn set_header(name: &str, value: &str) -> Result<()> {
+    RELAYER_HEADERS.set(name, value)
+}
+
+/// Replace the shared relayer client with one using these timeouts
+///
+/// Defaults to [`DEFAULT_CONNECT_TIMEOUT`] and [`DEFAULT_POOL_IDLE_TIMEOUT`].
+/// `pool_idle_timeout` should stay below the idle timeout of any NAT or load
+/// balancer between this process and the relayer, so the first request
+/// after a quiet period doesn't hit a reset connection. Applies to every
+/// client in the process; requests already in flight finish on the old
+/// client.
+pub fn set_http_timeouts(connect_timeout: Duration, pool_idle_timeout: Duration) -> Result<()> {
+    *HTTP_CLIENT.write() = build_http_client(connect_timeout, pool_idle_timeout)?;
+    Ok(())
+}
+
+/// Base URL of `relayer_url`, or of `RELAYER_API_URL` if `None`
+pub(crate) fn base_url(relayer_url: Option<&str>) -> &str {
+    relayer_url.unwrap_or(RELAYER_API_URL.as_str()).trim_end_matches('/')
+}
+
+/// GET request to the relayer
+pub(crate) fn get(url: &str) -> RequestBuilder {
+    RELAYER_HEADERS.apply(HTTP_CLIENT.read().get(url))
+}
+
+/// POST request to the relayer
+pub(crate) fn post(url: &str) -> RequestBuilder {
+    RELAYER_HEADERS.apply(HTTP_CLIENT.read().post(url))
+}
+
+/// Send a relayer request through the rate limiter
+///
+/// Transport failures become `ApiError` prefixed with `context`. Non-429
+/// error statuses are returned as-is for the caller to interpret.
+pub(crate) async fn send(mut request: RequestBuilder, context: &str) -> Result<Response> {
+    let mut attempt = 0;
+    #[cfg(feature = "testing")]
+    if let Some(response) = crate::testing::mock_response(&request) {
+        return Ok(response);
+    }
+
+    loop {
+        RELAYER_RATE_LIMITER.acquire().await;
+
+        // Keep a copy for retrying; bodies that can't be cloned aren't retried
+        let retry = request.try_clone();
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PrivacyCashError::ApiError(format!("{}: {}", context, e)))?;
+
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(response);
+        }
+
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+
+        let Some(next) = retry.filter(|_| attempt < MAX_RATE_LIMIT_RETRIES) else {
+            return Err(PrivacyCashError::RateLimited {
+                retry_after_secs: retry_after.map(|d| d.as_secs()),
+            });
+        };
+
+        let wait = retry_after.unwrap_or_else(|| Duration::from_secs(1 << attempt));
+        log::warn!("Relayer rate limited ({}), retrying in {:?}", context, wait);
+        tokio::time::sleep(wait).await;
+        request = next;
+        attempt += 1;
+    }
+}
+
+/// Parse a `Retry-After` header given in seconds
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_burst() {
+        let limiter = RateLimiter::new(1.0, 2);
+        assert!(limiter.try_acquire().is_ok());
+        assert!(limiter.try_acquire().is_ok());
+
+        let wait = limiter.try_acquire().unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_rate_limiter_disabled() {
+        let limiter = RateLimiter::new(0.0, 1);
+        for _ in 0..100 {
+            assert!(limiter.try_acquire().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_relayer_headers() {
+        let headers = RelayerHeaders::new();
+        let build = |headers: &RelayerHeaders| {
+            headers
+                .apply(HTTP_CLIENT.read().get("http://localhost/config"))
+                .build()
+                .unwrap()
+        };
+        assert_eq!(build(&headers).headers()[USER_AGENT], DEFAULT_USER_AGENT);
+        assert!(DEFAULT_USER_AGENT.starts_with("privacy-cash-rust-sdk/"));
+
+        headers.set_user_agent("my-wallet/1.0").unwrap();
+        headers.set("X-Api-Key", "secret").unwrap();
+        let request = build(&headers);
+        assert_eq!(request.headers()[USER_AGENT], "my-wallet/1.0");
+        assert_eq!(request.headers()["x-api-key"], "secret");
+        assert!(request.headers()["x-api-key"].is_sensitive());
+
+        assert!(headers.set("bad header", "x").is_err());
+        assert!(headers.set("X-Api-Key", "line\nbreak").is_err());
+
+        headers.reset();
+        let request = build(&headers);
+        assert_eq!(request.headers()[USER_AGENT], DEFAULT_USER_AGENT);
+        assert!(request.headers().get("x-api-key").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_idle_timeout() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Keep-alive server counting the connections it accepts
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/config", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {
+                        let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}";
+                        if socket.write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let client = build_http_client(Duration::from_secs(1), Duration::from_millis(100)).unwrap();
+        assert!(client.get(&url).send().await.unwrap().status().is_success());
+        assert!(client.get(&url).send().await.unwrap().status().is_success());
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        // The idle connection is dropped and the next request reconnects
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(client.get(&url).send().await.unwrap().status().is_success());
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("3"), Some(Duration::from_secs(3)));
+        // HTTP dates fall back to the default backoff
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! Environment diagnostics
+//!
+//! [`PrivacyCash::self_test`](crate::PrivacyCash::self_test) runs these
+//! checks; nothing here signs or submits a transaction.
+
+use crate::cluster::Cluster;
+use crate::config::Config;
+use crate::constants::{ALT_ADDRESS, RELAYER_API_URL};
+use crate::error::{PrivacyCashError, Result};
+use crate::prover_rust::RustProver;
+use crate::utils::{get_lookup_table, validate_program_accounts};
+use sha2::{Digest, Sha256};
+use solana_client::rpc_client::RpcClient;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Genesis hashes of the public clusters
+const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+const TESTNET_GENESIS_HASH: &str = "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY";
+
+/// SHA-256 of the circuit files the program's verifying key matches (see
+/// `circuit/README.md`)
+const CIRCUIT_SHA256: [(&str, &str); 2] = [
+    ("wasm", "a277631b7616c2c0bfd78a1648b069972ac6020e5509ae8f9bfc8772bdc70ec1"),
+    ("zkey", "4aa7aa5c1c28ed1f00fee84f49c1686f53210fd999ef7c8db6cfcd298af4e693"),
+];
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check succeeded
+    Pass,
+    /// The check failed; see the details
+    Fail,
+    /// Not run because a check it depends on failed
+    Skipped,
+}
+
+/// One line of a [`SelfTestReport`]
+#[derive(Debug, Clone)]
+pub struct SelfTestCheck {
+    /// Short identifier, e.g. `"rpc"` or `"circuit_files"`
+    pub name: &'static str,
+    /// Whether the check passed
+    pub status: CheckStatus,
+    /// What was found, or why it failed
+    pub details: String,
+    /// How long the check took
+    pub duration: Duration,
+}
+
+/// Results of [`PrivacyCash::self_test`](crate::PrivacyCash::self_test)
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    /// Checks in the order they ran
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    /// Whether no check failed (skipped checks don't count as failures)
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestCheck> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Fail)
+    }
+
+    /// Look up a check by name
+    pub fn check(&self, name: &str) -> Option<&SelfTestCheck> {
+        self.checks.iter().find(|c| c.name == name)
+    }
+
+    /// Run `check` and record its outcome, returning whether it passed
+    pub(crate) async fn run<F>(&mut self, name: &'static str, check: F) -> bool
+    where
+        F: std::future::Future<Output = Result<String>>,
+    {
+        let start = Instant::now();
+        let (status, details) = match check.await {
+            Ok(details) => (CheckStatus::Pass, details),
+            Err(e) => (CheckStatus::Fail, e.to_string()),
+        };
+        self.checks.push(SelfTestCheck { name, status, details, duration: start.elapsed() });
+        status == CheckStatus::Pass
+    }
+
+    /// Record a check that wasn't run
+    pub(crate) fn skip(&mut self, name: &'static str, reason: &str) {
+        self.checks.push(SelfTestCheck {
+            name,
+            status: CheckStatus::Skipped,
+            details: reason.to_string(),
+            duration: Duration::ZERO,
+        });
+    }
+}
+
+impl std::fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Fail => "FAIL",
+                CheckStatus::Skipped => "SKIP",
+            };
+            writeln!(
+                f,
+                "[{}] {:<14} {} ({:.2}s)",
+                status,
+                check.name,
+                check.details,
+                check.duration.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Cluster a genesis hash belongs to, if it's a public one
+pub(crate) fn cluster_for_genesis_hash(hash: &str) -> Option<Cluster> {
+    match hash {
+        MAINNET_GENESIS_HASH => Some(Cluster::MainnetBeta),
+        DEVNET_GENESIS_HASH => Some(Cluster::Devnet),
+        TESTNET_GENESIS_HASH => Some(Cluster::Testnet),
+        _ => None,
+    }
+}
+
+/// RPC answers and is on the cluster its URL suggests
+pub(crate) async fn check_rpc(connection: &RpcClient, expected: Cluster) -> Result<String> {
+    let version = connection.get_version()?;
+    let genesis = connection.get_genesis_hash()?.to_string();
+    let actual = cluster_for_genesis_hash(&genesis);
+
+    match (actual, expected) {
+        (Some(actual), expected) if actual != expected => Err(PrivacyCashError::ConfigError(format!(
+            "RPC {} is on {:?} but its URL looks like {:?}",
+            connection.url(),
+            actual,
+            expected
+        ))),
+        (None, Cluster::Localnet) | (Some(_), _) => Ok(format!(
+            "solana-core {} on {:?}",
+            version.solana_core, expected
+        )),
+        (None, expected) => Err(PrivacyCashError::ConfigError(format!(
+            "RPC {} has unknown genesis hash {}, expected {:?}",
+            connection.url(),
+            genesis,
+            expected
+        ))),
+    }
+}
+
+/// Program and lookup table exist, and the table deserializes
+pub(crate) async fn check_program_accounts(connection: &RpcClient) -> Result<String> {
+    validate_program_accounts(connection)?;
+    let table = get_lookup_table(connection).await?;
+    Ok(format!("lookup table {} has {} addresses", *ALT_ADDRESS, table.addresses.len()))
+}
+
+/// Relayer `/config` answers
+pub(crate) async fn check_relayer() -> Result<String> {
+    let config = Config::fetch().await?;
+    Ok(format!(
+        "{} (withdraw fee rate {}, {} token rent fees)",
+        RELAYER_API_URL.as_str(),
+        config.withdraw_fee_rate,
+        config.rent_fees.len()
+    ))
+}
+
+/// Circuit files are the pinned version and the zkey parses
+pub(crate) async fn check_circuit_files(prover: &RustProver, circuit_path: &str) -> Result<String> {
+    let mut sizes = Vec::new();
+    for (ext, expected) in CIRCUIT_SHA256 {
+        let path = format!("{}.{}", circuit_path, ext);
+        let bytes = std::fs::read(Path::new(&path))
+            .map_err(|e| PrivacyCashError::CircuitNotFound(format!("{}: {}", path, e)))?;
+        check_sha256(&path, &bytes, expected)?;
+        sizes.push(format!("{} {} bytes", ext, bytes.len()));
+    }
+    prover.warm_up()?;
+    Ok(format!("{}, hashes match", sizes.join(", ")))
+}
+
+/// Fail unless `bytes`, read from `path`, hash to `expected`
+fn check_sha256(path: &str, bytes: &[u8], expected: &str) -> Result<()> {
+    let actual = hex::encode(Sha256::digest(bytes));
+    if actual != expected {
+        return Err(PrivacyCashError::CircuitHashMismatch {
+            path: path.to_string(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// A dummy proof generates and verifies locally
+pub(crate) async fn check_proof(prover: &RustProver) -> Result<String> {
+    let elapsed = prover.benchmark().await?;
+    Ok(format!("dummy proof verified in {:.2}s", elapsed.as_secs_f64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_for_genesis_hash() {
+        assert_eq!(cluster_for_genesis_hash(MAINNET_GENESIS_HASH), Some(Cluster::MainnetBeta));
+        assert_eq!(cluster_for_genesis_hash(DEVNET_GENESIS_HASH), Some(Cluster::Devnet));
+        assert_eq!(cluster_for_genesis_hash("11111111111111111111111111111111"), None);
+    }
+
+    #[test]
+    fn test_circuit_hashes() {
+        for (ext, expected) in CIRCUIT_SHA256 {
+            let path = format!("circuit/transaction2.{}", ext);
+            let mut bytes = std::fs::read(&path).unwrap();
+            check_sha256(&path, &bytes, expected).unwrap();
+
+            // One flipped byte, same size: caught
+            bytes[0] ^= 1;
+            assert!(matches!(
+                check_sha256(&path, &bytes, expected),
+                Err(PrivacyCashError::CircuitHashMismatch { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_report_outcomes() {
+        let mut report = SelfTestReport::default();
+        assert!(report.run("ok", async { Ok("fine".to_string()) }).await);
+        report.skip("later", "ok failed");
+        assert!(report.passed());
+
+        let failed = report
+            .run("bad", async { Err(PrivacyCashError::ConfigError("nope".to_string())) })
+            .await;
+        assert!(!failed);
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(report.check("later").unwrap().status, CheckStatus::Skipped);
+        assert!(report.to_string().contains("[FAIL] bad"));
+    }
+}
@@ -1,10 +1,20 @@
 //! Local storage for caching UTXOs and offsets
 
 use crate::error::{PrivacyCashError, Result};
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Prefix of values encrypted at rest, followed by hex of IV and ciphertext
+const ENCRYPTED_VALUE_PREFIX: &str = "enc1:";
 
 /// Storage backend trait
 pub trait StorageBackend: Send + Sync {
@@ -12,12 +22,24 @@ pub trait StorageBackend: Send + Sync {
     fn set(&self, key: &str, value: &str);
     fn remove(&self, key: &str);
     fn clear(&self);
+
+    /// Make sure everything set so far is durably stored
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// File-based storage implementation
+///
+/// Every `set` is written through to disk and synced before it returns, via
+/// a temporary file and a rename so a crash never leaves a half-written
+/// entry. Writes that fail are retried by [`flush`](StorageBackend::flush),
+/// which also runs on drop.
 pub struct FileStorage {
     cache_dir: PathBuf,
     cache: RwLock<HashMap<String, String>>,
+    /// Keys whose latest value failed to reach the disk
+    unsaved: RwLock<HashSet<String>>,
 }
 
 impl FileStorage {
@@ -32,6 +54,7 @@ impl FileStorage {
         let storage = Self {
             cache_dir,
             cache: RwLock::new(HashMap::new()),
+            unsaved: RwLock::new(HashSet::new()),
         };
 
         // Load existing cache files
@@ -62,6 +85,11 @@ impl FileStorage {
 
         for entry in entries.flatten() {
             let path = entry.path();
+            // Leftovers of interrupted writes
+            if path.extension().is_some_and(|ext| ext == TEMP_EXTENSION) {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
             if path.is_file() {
                 if let Some(key) = path.file_name().and_then(|n| n.to_str()) {
                     if let Ok(value) = fs::read_to_string(&path) {
@@ -80,6 +108,31 @@ impl FileStorage {
         let safe_key = key.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
         self.cache_dir.join(safe_key)
     }
+
+    /// Write `value` for `key` to disk and sync it
+    fn persist(&self, key: &str, value: &str) -> std::io::Result<()> {
+        let path = self.key_path(key);
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".");
+        temp_name.push(TEMP_EXTENSION);
+        let temp_path = path.with_file_name(temp_name);
+
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(value.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)
+    }
+}
+
+/// Extension of files being written, renamed into place once synced
+const TEMP_EXTENSION: &str = "tmp";
+
+impl Drop for FileStorage {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::warn!("Cache entries could not be saved: {}", e);
+        }
+    }
 }
 
 impl StorageBackend for FileStorage {
@@ -95,9 +148,16 @@ impl StorageBackend for FileStorage {
             cache.insert(key.to_string(), value.to_string());
         }
 
-        // Persist to disk (ignore errors)
-        let path = self.key_path(key);
-        let _ = fs::write(path, value);
+        // Persist to disk; failures are retried on flush
+        match self.persist(key, value) {
+            Ok(()) => {
+                self.unsaved.write().remove(key);
+            }
+            Err(e) => {
+                log::warn!("Failed to write cache entry {}: {}", key, e);
+                self.unsaved.write().insert(key.to_string());
+            }
+        }
     }
 
     fn remove(&self, key: &str) {
@@ -106,6 +166,7 @@ impl StorageBackend for FileStorage {
             let mut cache = self.cache.write();
             cache.remove(key);
         }
+        self.unsaved.write().remove(key);
 
         // Remove from disk
         let path = self.key_path(key);
@@ -118,6 +179,7 @@ impl StorageBackend for FileStorage {
             let mut cache = self.cache.write();
             cache.clear();
         }
+        self.unsaved.write().clear();
 
         // Clear disk cache
         if self.cache_dir.exists() {
@@ -125,6 +187,31 @@ impl StorageBackend for FileStorage {
             let _ = fs::create_dir_all(&self.cache_dir);
         }
     }
+
+    fn flush(&self) -> Result<()> {
+        let keys: Vec<String> = self.unsaved.read().iter().cloned().collect();
+        let mut failed = Vec::new();
+        for key in keys {
+            let Some(value) = self.cache.read().get(&key).cloned() else {
+                self.unsaved.write().remove(&key);
+                continue;
+            };
+            match self.persist(&key, &value) {
+                Ok(()) => {
+                    self.unsaved.write().remove(&key);
+                }
+                Err(e) => failed.push(format!("{}: {}", key, e)),
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(PrivacyCashError::StorageError(format!(
+                "Failed to save cache entries: {}",
+                failed.join(", ")
+            )))
+        }
+    }
 }
 
 /// In-memory storage (for testing or ephemeral use)
@@ -165,38 +252,69 @@ impl StorageBackend for MemoryStorage {
 }
 
 /// Storage wrapper for the SDK
+///
+/// With a cache key (see [`with_cache_key`](Self::with_cache_key)), values
+/// are encrypted with AES-256-GCM before they reach the backend. Plaintext
+/// values written before encryption was turned on are still read, and are
+/// encrypted the next time they're written.
 pub struct Storage {
-    backend: Box<dyn StorageBackend>,
+    backend: Arc<dyn StorageBackend>,
+    cache_key: Option<[u8; 32]>,
 }
 
 impl Storage {
+    fn with_backend(backend: impl StorageBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            cache_key: None,
+        }
+    }
+
     /// Create storage with file backend
     pub fn file(cache_dir: PathBuf) -> Result<Self> {
-        Ok(Self {
-            backend: Box::new(FileStorage::new(cache_dir)?),
-        })
+        Ok(Self::with_backend(FileStorage::new(cache_dir)?))
     }
 
     /// Create storage with default file backend
     pub fn default_file() -> Result<Self> {
-        Ok(Self {
-            backend: Box::new(FileStorage::default_cache()?),
-        })
+        Ok(Self::with_backend(FileStorage::default_cache()?))
     }
 
     /// Create storage with memory backend
     pub fn memory() -> Self {
+        Self::with_backend(MemoryStorage::new())
+    }
+
+    /// Storage on the same backend that encrypts values with `cache_key`
+    ///
+    /// `None` stores plaintext again. Values encrypted under another key (or
+    /// read without one) count as missing, so an encrypted cache is rebuilt
+    /// from the relayer rather than misread.
+    pub fn with_cache_key(&self, cache_key: Option<[u8; 32]>) -> Self {
         Self {
-            backend: Box::new(MemoryStorage::new()),
+            backend: Arc::clone(&self.backend),
+            cache_key,
         }
     }
 
+    /// Whether values are encrypted at rest
+    pub fn is_encrypted(&self) -> bool {
+        self.cache_key.is_some()
+    }
+
     pub fn get(&self, key: &str) -> Option<String> {
-        self.backend.get(key)
+        let value = self.backend.get(key)?;
+        match value.strip_prefix(ENCRYPTED_VALUE_PREFIX) {
+            Some(encrypted) => self.decrypt_value(encrypted),
+            None => Some(value),
+        }
     }
 
     pub fn set(&self, key: &str, value: &str) {
-        self.backend.set(key, value);
+        match self.encrypt_value(value) {
+            Some(encrypted) => self.backend.set(key, &encrypted),
+            None => self.backend.set(key, value),
+        }
     }
 
     pub fn remove(&self, key: &str) {
@@ -206,10 +324,108 @@ impl Storage {
     pub fn clear(&self) {
         self.backend.clear();
     }
+
+    /// Make sure every entry set so far is on disk
+    ///
+    /// File storage already writes each entry through as it's set; this
+    /// retries any write that failed (e.g. on a full disk) and reports
+    /// entries that still can't be saved.
+    pub fn flush(&self) -> Result<()> {
+        self.backend.flush()
+    }
+}
+
+impl Storage {
+    /// Encrypt `value` if a cache key is set
+    fn encrypt_value(&self, value: &str) -> Option<String> {
+        let cipher = Aes256Gcm::new_from_slice(self.cache_key.as_ref()?).ok()?;
+        let mut iv = [0u8; 12];
+        rand::thread_rng().fill(&mut iv);
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&iv), value.as_bytes()).ok()?;
+        Some(format!(
+            "{}{}{}",
+            ENCRYPTED_VALUE_PREFIX,
+            hex::encode(iv),
+            hex::encode(ciphertext)
+        ))
+    }
+
+    /// Decrypt a value written by `encrypt_value`, if it was under this key
+    fn decrypt_value(&self, encrypted: &str) -> Option<String> {
+        let cipher = Aes256Gcm::new_from_slice(self.cache_key.as_ref()?).ok()?;
+        let bytes = hex::decode(encrypted).ok()?;
+        if bytes.len() < 12 {
+            return None;
+        }
+        let (iv, ciphertext) = bytes.split_at(12);
+        let plaintext = cipher.decrypt(Nonce::from_slice(iv), ciphertext).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
 }
 
 impl std::fmt::Debug for Storage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Storage").finish()
+        f.debug_struct("Storage")
+            .field("encrypted", &self.is_encrypted())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_cache_at_rest() {
+        let dir = std::env::temp_dir().join(format!("privacy-cash-storage-{}", rand::random::<u64>()));
+        let plain = Storage::file(dir.clone()).unwrap();
+        plain.set("legacy", "old-notes");
+
+        let storage = plain.with_cache_key(Some([7u8; 32]));
+        storage.set("encrypted_outputs", "[\"secret-note\"]");
+        assert_eq!(storage.get("encrypted_outputs").unwrap(), "[\"secret-note\"]");
+
+        // The bytes on disk don't contain the value
+        let on_disk = fs::read_to_string(dir.join("encrypted_outputs")).unwrap();
+        assert!(on_disk.starts_with(ENCRYPTED_VALUE_PREFIX));
+        assert!(!on_disk.contains("secret-note"));
+
+        // Without the key, or with another one, the value is missing
+        let reopened = Storage::file(dir.clone()).unwrap();
+        assert_eq!(reopened.get("encrypted_outputs"), None);
+        assert_eq!(reopened.with_cache_key(Some([8u8; 32])).get("encrypted_outputs"), None);
+        let reopened = reopened.with_cache_key(Some([7u8; 32]));
+        assert_eq!(reopened.get("encrypted_outputs").unwrap(), "[\"secret-note\"]");
+
+        // Plaintext written before encryption is still read, then re-encrypted
+        assert_eq!(reopened.get("legacy").unwrap(), "old-notes");
+        reopened.set("legacy", "old-notes");
+        assert!(!fs::read_to_string(dir.join("legacy")).unwrap().contains("old-notes"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_file_storage_persists_across_drop() {
+        let dir = std::env::temp_dir().join(format!("privacy-cash-persist-{}", rand::random::<u64>()));
+        {
+            let storage = Storage::file(dir.clone()).unwrap();
+            storage.set("fetch_offset", "120");
+            storage.set("encrypted_outputs", "[\"ab\",\"cd\"]");
+            storage.set("removed", "x");
+            storage.remove("removed");
+            storage.flush().unwrap();
+        }
+        // A write interrupted before its rename
+        fs::write(dir.join("fetch_offset.tmp"), "999").unwrap();
+
+        let storage = Storage::file(dir.clone()).unwrap();
+        assert_eq!(storage.get("fetch_offset").as_deref(), Some("120"));
+        assert_eq!(storage.get("encrypted_outputs").as_deref(), Some("[\"ab\",\"cd\"]"));
+        assert_eq!(storage.get("removed"), None);
+        assert_eq!(storage.get("fetch_offset.tmp"), None);
+        assert!(!dir.join("fetch_offset.tmp").exists());
+
+        let _ = fs::remove_dir_all(dir);
     }
 }
@@ -0,0 +1,26 @@
+//! Optional `tracing` instrumentation
+//!
+//! With the `tracing` feature, deposits, withdrawals and `send_privately` run
+//! inside spans carrying the token, amount and (once known) signature, and
+//! each phase emits an event. Spans skip all arguments, so keys, notes and
+//! circuit inputs never end up in fields. Without the feature these macros
+//! expand to nothing and the SDK logs through `log` only.
+
+/// Mark the start of an operation phase (e.g. `"prove"`, `"submit"`)
+macro_rules! phase {
+    ($name:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::info!(phase = $name);
+    };
+}
+
+/// Record a transaction signature on the current span
+macro_rules! record_signature {
+    ($field:literal, $signature:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, tracing::field::display(&$signature));
+    };
+}
+
+pub(crate) use phase;
+pub(crate) use record_signature;
@@ -0,0 +1,229 @@
+//! Test helpers (enabled with the `testing` feature)
+//!
+//! [`MockRelayer`] answers relayer requests with canned JSON so client logic
+//! can be exercised without a network. `PrivacyCash::for_testing` (or
+//! [`install_thread_mocks`]) installs it for the current thread only, so
+//! tests running in parallel each see their own; [`install_mock_relayer`]
+//! installs it for the whole process.
+//! [`install_mock_prover`] replaces proof generation with a placeholder
+//! proof, for tests that run deposits and withdrawals against a local
+//! relayer without circuit files.
+
+use crate::config::Config;
+use crate::prover::{CircuitInput, Proof};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::{RequestBuilder, Response};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Mock installed for all relayer requests in this process
+static MOCK_RELAYER: Lazy<RwLock<Option<MockRelayer>>> = Lazy::new(|| RwLock::new(None));
+
+thread_local! {
+    /// Relayer mock and relayer config for requests made on this thread
+    static THREAD_MOCKS: RefCell<Option<(MockRelayer, Config)>> = const { RefCell::new(None) };
+}
+
+/// Whether proofs in this process are placeholders
+static MOCK_PROVER: AtomicBool = AtomicBool::new(false);
+
+/// Canned relayer responses keyed by method and path
+///
+/// Requests without a matching response get a 404.
+#[derive(Debug, Clone, Default)]
+pub struct MockRelayer {
+    responses: HashMap<(String, String), (u16, serde_json::Value)>,
+}
+
+impl MockRelayer {
+    /// Create a mock with no responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `method path` (e.g. `"GET"`, `"/config"`) with `status` and `body`
+    ///
+    /// The query string is ignored when matching.
+    pub fn respond(mut self, method: &str, path: &str, status: u16, body: serde_json::Value) -> Self {
+        self.responses
+            .insert((method.to_uppercase(), path.to_string()), (status, body));
+        self
+    }
+
+    fn lookup(&self, method: &str, path: &str) -> (u16, serde_json::Value) {
+        self.responses
+            .get(&(method.to_string(), path.to_string()))
+            .cloned()
+            .unwrap_or_else(|| {
+                (404, serde_json::json!({ "error": format!("no mock for {} {}", method, path) }))
+            })
+    }
+}
+
+/// Route all relayer requests in this process to `mock`
+pub fn install_mock_relayer(mock: MockRelayer) {
+    *MOCK_RELAYER.write() = Some(mock);
+}
+
+/// Go back to the real relayer
+pub fn uninstall_mock_relayer() {
+    *MOCK_RELAYER.write() = None;
+}
+
+/// Route relayer requests made on this thread to `mock`, and use `config` as
+/// the relayer config there
+///
+/// Takes precedence over [`install_mock_relayer`] and the process-wide
+/// config cache, on this thread only. `#[tokio::test]` gives each test its
+/// own thread and a single-threaded runtime, so tests that install different
+/// mocks can run in parallel. Installing again replaces this thread's mocks.
+pub fn install_thread_mocks(mock: MockRelayer, mut config: Config) {
+    config.normalize();
+    THREAD_MOCKS.with(|mocks| *mocks.borrow_mut() = Some((mock, config)));
+}
+
+/// Remove this thread's mocks
+pub fn uninstall_thread_mocks() {
+    THREAD_MOCKS.with(|mocks| *mocks.borrow_mut() = None);
+}
+
+/// Relayer config installed for this thread, if any
+pub(crate) fn thread_config() -> Option<Config> {
+    THREAD_MOCKS.with(|mocks| mocks.borrow().as_ref().map(|(_, config)| config.clone()))
+}
+
+/// Mocked response for a relayer request, if a mock is installed
+///
+/// This thread's mock wins over the process-wide one.
+pub(crate) fn mock_response(request: &RequestBuilder) -> Option<Response> {
+    let mock = THREAD_MOCKS
+        .with(|mocks| mocks.borrow().as_ref().map(|(mock, _)| mock.clone()))
+        .or_else(|| MOCK_RELAYER.read().clone())?;
+    let request = request.try_clone()?.build().ok()?;
+
+    let (status, body) = mock.lookup(request.method().as_str(), request.url().path());
+    let response = http::Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .ok()?;
+    Some(Response::from(response))
+}
+
+/// Replace proof generation in this process with a placeholder proof
+///
+/// `RustProver` then returns a proof with well-formed points and the public
+/// signals of its input, without reading circuit files, so instruction
+/// encoding and relayer requests run as usual. The proof doesn't verify:
+/// only use it against a mock relayer.
+pub fn install_mock_prover() {
+    MOCK_PROVER.store(true, Ordering::SeqCst);
+}
+
+/// Go back to generating real proofs
+pub fn uninstall_mock_prover() {
+    MOCK_PROVER.store(false, Ordering::SeqCst);
+}
+
+/// Whether proofs in this process are placeholders, which don't verify
+pub(crate) fn mock_prover_installed() -> bool {
+    MOCK_PROVER.load(Ordering::SeqCst)
+}
+
+/// Placeholder proof for `input`, if the mock prover is installed
+pub(crate) fn mock_proof(input: &CircuitInput) -> Option<(Proof, Vec<String>)> {
+    if !mock_prover_installed() {
+        return None;
+    }
+
+    let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    let proof = Proof {
+        pi_a: strings(&["1", "2", "1"]),
+        pi_b: vec![strings(&["1", "2"]), strings(&["3", "4"]), strings(&["1", "0"])],
+        pi_c: strings(&["1", "2", "1"]),
+        protocol: "groth16".to_string(),
+        curve: "bn128".to_string(),
+    };
+
+    Some((proof, input.public_signals()))
+}
+
+#[cfg(test)]
+pub(crate) use crate::config::test_config;
+
+/// Relayer mock serving [`test_config`] and a few notes for the crate's
+/// unit tests
+#[cfg(test)]
+pub(crate) fn test_mock() -> MockRelayer {
+    let config_json = serde_json::to_value(test_config()).unwrap();
+    MockRelayer::new()
+        .respond("GET", "/config", 200, config_json)
+        .respond(
+            "GET",
+            "/utxos/range",
+            200,
+            serde_json::json!({ "encrypted_outputs": ["00", "01"], "hasMore": false }),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PrivacyCash;
+    use solana_sdk::signature::Keypair;
+
+    #[tokio::test]
+    async fn test_for_testing_client() {
+        let mut client = PrivacyCash::for_testing(Keypair::new(), test_mock(), test_config());
+        client.set_partner_fee(client.pubkey(), 0.01).unwrap();
+
+        let (pc_fee, partner_fee, total) =
+            client.estimate_withdraw_fees(1_000_000_000).await.unwrap();
+        assert_eq!(pc_fee, 3_500_000 + 6_000_000);
+        assert_eq!(partner_fee, 10_000_000);
+        assert_eq!(total, pc_fee + partner_fee);
+
+        // Fetching goes through the mock relayer
+        let fetched = Config::fetch().await.unwrap();
+        assert_eq!(fetched.withdraw_fee_rate, 0.0035);
+        assert!(crate::utils::query_remote_tree_state(None).await.is_err());
+    }
+
+    #[test]
+    fn test_thread_mocks_are_per_thread() {
+        // Each thread builds its own client with its own fee schedule
+        let handles: Vec<_> = [0.0035, 0.01]
+            .into_iter()
+            .map(|rate| {
+                std::thread::spawn(move || {
+                    let mut config = test_config();
+                    config.withdraw_fee_rate = rate;
+                    let mock = MockRelayer::new().respond(
+                        "GET",
+                        "/config",
+                        200,
+                        serde_json::to_value(&config).unwrap(),
+                    );
+                    let _client = PrivacyCash::for_testing(Keypair::new(), mock, config);
+
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap();
+                    runtime.block_on(async {
+                        let cached = Config::get_or_fetch().await.unwrap();
+                        let fetched = Config::fetch().await.unwrap();
+                        (cached.withdraw_fee_rate, fetched.withdraw_fee_rate)
+                    })
+                })
+            })
+            .collect();
+        let rates: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(rates, [(0.0035, 0.0035), (0.01, 0.01)]);
+
+        // Nothing was installed on this thread
+        assert!(thread_config().is_none());
+    }
+}
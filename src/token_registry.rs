@@ -0,0 +1,183 @@
+//! Read-through cache of token metadata
+//!
+//! SPL deposits and withdrawals resolve their mint through the client's
+//! [`TokenRegistry`]. The first lookup of a mint combines the static token
+//! table (name, prefix, decimals) with the on-chain mint account (owning
+//! program, actual decimals), so a mint overridden to the wrong address (e.g.
+//! `USDC_MINT` pointing at a mainnet mint on devnet) fails before any proof is
+//! generated. Later lookups are served from memory until
+//! [`TOKEN_METADATA_TTL`] expires.
+//!
+//! The relayer config lists tokens by name only, without mints or decimals,
+//! so it isn't a source here; fees and minimums stay in `Config`.
+
+use crate::constants::{find_token_by_mint, TokenInfo, SOL_MINT};
+use crate::error::{PrivacyCashError, Result};
+use parking_lot::RwLock;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use spl_token::solana_program::program_pack::Pack;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long resolved token metadata is reused
+pub const TOKEN_METADATA_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Everything the SDK needs to know about a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TokenMetadata {
+    /// Name, mint, relayer prefix and decimals
+    pub info: TokenInfo,
+    /// Program owning the mint; the system program for native SOL
+    pub token_program: Pubkey,
+}
+
+/// Token metadata cache, keyed by mint
+#[derive(Debug, Default)]
+pub struct TokenRegistry {
+    entries: RwLock<HashMap<Pubkey, (TokenMetadata, Instant)>>,
+}
+
+impl TokenRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Metadata for `mint`, reading the mint account on a cache miss
+    ///
+    /// Fails with `TokenNotSupported` for mints the SDK doesn't know, and
+    /// with `ConfigError` if the mint account doesn't match the token table.
+    pub fn resolve(&self, connection: &RpcClient, mint: &Pubkey) -> Result<TokenMetadata> {
+        self.resolve_with(mint, |mint| Ok(connection.get_account(mint)?))
+    }
+
+    fn resolve_with<F>(&self, mint: &Pubkey, fetch_account: F) -> Result<TokenMetadata>
+    where
+        F: FnOnce(&Pubkey) -> Result<Account>,
+    {
+        if let Some((metadata, resolved)) = self.entries.read().get(mint) {
+            if resolved.elapsed() < TOKEN_METADATA_TTL {
+                return Ok(metadata.clone());
+            }
+        }
+
+        let info = find_token_by_mint(mint)
+            .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint.to_string()))?;
+        let metadata = if *mint == *SOL_MINT {
+            TokenMetadata {
+                info,
+                token_program: solana_sdk::system_program::id(),
+            }
+        } else {
+            metadata_from_mint_account(info, &fetch_account(mint)?)?
+        };
+
+        self.entries
+            .write()
+            .insert(*mint, (metadata.clone(), Instant::now()));
+        Ok(metadata)
+    }
+
+    /// Drop all cached metadata
+    pub fn clear(&self) {
+        self.entries.write().clear();
+    }
+}
+
+/// Check a mint account against the token table
+fn metadata_from_mint_account(info: TokenInfo, account: &Account) -> Result<TokenMetadata> {
+    if account.owner != spl_token::id() {
+        return Err(PrivacyCashError::ConfigError(format!(
+            "{} mint {} is owned by {}, not the SPL Token program",
+            info.name, info.mint, account.owner
+        )));
+    }
+    let mint = spl_token::state::Mint::unpack(&account.data).map_err(|e| {
+        PrivacyCashError::ConfigError(format!("{} is not a mint account: {}", info.mint, e))
+    })?;
+    if mint.decimals != info.decimals() {
+        return Err(PrivacyCashError::ConfigError(format!(
+            "{} mint {} has {} decimals, expected {}",
+            info.name,
+            info.mint,
+            mint.decimals,
+            info.decimals()
+        )));
+    }
+
+    Ok(TokenMetadata {
+        info,
+        token_program: account.owner,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::USDC_MINT;
+    use std::cell::Cell;
+
+    fn mint_account(decimals: u8) -> Account {
+        let mint = spl_token::state::Mint {
+            decimals,
+            is_initialized: true,
+            ..Default::default()
+        };
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        Account {
+            owner: spl_token::id(),
+            data,
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_reads_mint_once() {
+        let registry = TokenRegistry::new();
+        let fetches = Cell::new(0);
+        let fetch = |_: &Pubkey| {
+            fetches.set(fetches.get() + 1);
+            Ok(mint_account(6))
+        };
+
+        let usdc = registry.resolve_with(&USDC_MINT, fetch).unwrap();
+        assert_eq!(usdc.info.name, "usdc");
+        assert_eq!(usdc.token_program, spl_token::id());
+        registry.resolve_with(&USDC_MINT, fetch).unwrap();
+        assert_eq!(fetches.get(), 1);
+
+        // SOL needs no account read
+        let sol = registry.resolve_with(&SOL_MINT, fetch).unwrap();
+        assert_eq!(sol.token_program, solana_sdk::system_program::id());
+        assert_eq!(fetches.get(), 1);
+
+        registry.clear();
+        registry.resolve_with(&USDC_MINT, fetch).unwrap();
+        assert_eq!(fetches.get(), 2);
+    }
+
+    #[test]
+    fn test_resolve_rejects_mismatched_mints() {
+        let registry = TokenRegistry::new();
+        let unknown = Pubkey::new_unique();
+        assert!(matches!(
+            registry.resolve_with(&unknown, |_| Ok(mint_account(6))),
+            Err(PrivacyCashError::TokenNotSupported(_))
+        ));
+        assert!(matches!(
+            registry.resolve_with(&USDC_MINT, |_| Ok(mint_account(9))),
+            Err(PrivacyCashError::ConfigError(_))
+        ));
+
+        let mut foreign = mint_account(6);
+        foreign.owner = Pubkey::new_unique();
+        assert!(registry.resolve_with(&USDC_MINT, |_| Ok(foreign)).is_err());
+        // Failures aren't cached
+        assert!(registry
+            .resolve_with(&USDC_MINT, |_| Ok(mint_account(6)))
+            .is_ok());
+    }
+}
@@ -1,15 +1,34 @@
 //! Utility functions for Privacy Cash SDK
 
-use crate::constants::{PROGRAM_ID, RELAYER_API_URL, FIELD_SIZE};
+use crate::constants::{
+    resolve_token, ALT_ADDRESS, NATIVE_SOL_MINT, NATIVE_SOL_MINT_STR, PROGRAM_ID, RELAYER_API_URL, FIELD_SIZE,
+    SIGNATURE_FEE_LAMPORTS,
+};
 #[allow(unused_imports)]
 use crate::error::{PrivacyCashError, Result};
-use crate::merkle_tree::MerklePath;
+use crate::merkle_tree::{MerklePath, MerkleTree};
+use crate::relayer;
+use crate::utxo::Utxo;
 use borsh::BorshSerialize;
+use futures::{StreamExt, TryStreamExt};
 use num_bigint::BigUint;
-use num_traits::ToPrimitive;
+use num_traits::{ToPrimitive, Zero};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::VersionedTransaction;
+use spl_token::solana_program::program_pack::Pack;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 /// External data for proof
 #[derive(Debug, Clone)]
@@ -70,9 +89,7 @@ pub async fn query_remote_tree_state(token_name: Option<&str>) -> Result<TreeSta
 
     log::debug!("Fetching Merkle root from: {}", url);
 
-    let response = reqwest::get(&url)
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch tree state: {}", e)))?;
+    let response = relayer::send(relayer::get(&url), "Failed to fetch tree state").await?;
 
     if !response.status().is_success() {
         return Err(PrivacyCashError::ApiError(format!(
@@ -91,6 +108,202 @@ pub async fn query_remote_tree_state(token_name: Option<&str>) -> Result<TreeSta
     Ok(state)
 }
 
+/// Check once whether the relayer has indexed an encrypted output
+///
+/// Used before re-submitting a deposit: if the note already exists, an
+/// earlier attempt landed and only its relayer response was lost.
+pub(crate) async fn check_utxo_exists(encrypted_output: &[u8], token_name: Option<&str>) -> Result<bool> {
+    let mut url = format!("{}/utxos/check/{}", *RELAYER_API_URL, hex::encode(encrypted_output));
+    if let Some(token) = token_name {
+        url = format!("{}?token={}", url, token);
+    }
+
+    let response = relayer::send(relayer::get(&url), "UTXO check failed").await?;
+    if !response.status().is_success() {
+        return Err(PrivacyCashError::ApiError(format!(
+            "UTXO check API returned status: {}",
+            response.status()
+        )));
+    }
+
+    let data: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse UTXO check: {}", e)))?;
+    Ok(data.get("exists").and_then(|v| v.as_bool()).unwrap_or(false))
+}
+
+/// First of `signatures` the cluster has a status for
+///
+/// Fails with `TransactionError` listing the signatures if none has one yet,
+/// rather than guessing which transaction landed.
+pub(crate) fn find_landed_signature(connection: &RpcClient, signatures: &[Signature]) -> Result<String> {
+    let statuses = connection.get_signature_statuses(signatures)?.value;
+    signatures
+        .iter()
+        .zip(statuses)
+        .find(|(_, status)| status.is_some())
+        .map(|(signature, _)| signature.to_string())
+        .ok_or_else(|| {
+            let signatures: Vec<String> = signatures.iter().map(ToString::to_string).collect();
+            PrivacyCashError::TransactionError(format!(
+                "Deposit landed, but none of its transactions has a status yet: {}",
+                signatures.join(", ")
+            ))
+        })
+}
+
+/// Whether any of `signatures` has landed without an error
+pub(crate) fn has_landed(connection: &RpcClient, signatures: &[Signature]) -> Result<bool> {
+    if signatures.is_empty() {
+        return Ok(false);
+    }
+    let statuses = connection.get_signature_statuses(signatures)?.value;
+    Ok(statuses.iter().flatten().any(|status| status.err.is_none()))
+}
+
+/// Whether `error` means the transaction's blockhash expired before it landed
+fn is_blockhash_expired(error: &PrivacyCashError) -> bool {
+    let message = error.to_string();
+    message.contains("block height exceeded")
+        || message.contains("expired")
+        || message.contains("expiration")
+}
+
+/// Submit a deposit, re-submitting when the blockhash expires
+///
+/// `attempt` signs a transaction with a fresh blockhash and returns its
+/// signature and the future that submits it. Any failed submission may
+/// still have landed with only the response lost, so before retrying or
+/// returning the error the relayer (for [`SubmitMode::Relayer`], by
+/// `encrypted_output`) or the RPC node (for [`SubmitMode::Direct`]) is asked
+/// whether it did: a landed deposit is neither sent twice nor reported as
+/// failed. Returns the landed transaction's signature.
+pub(crate) async fn submit_deposit<F, Fut>(
+    connection: &RpcClient,
+    submit_mode: SubmitMode,
+    encrypted_output: &[u8],
+    token_name: Option<&str>,
+    mut attempt: F,
+) -> Result<String>
+where
+    F: FnMut() -> Result<(Signature, Fut)>,
+    Fut: std::future::Future<Output = Result<String>>,
+{
+    const MAX_ATTEMPTS: usize = 3;
+    let mut submitted = Vec::new();
+    loop {
+        let (signature, submission) = attempt()?;
+        submitted.push(signature);
+        let error = match submission.await {
+            Ok(signature) => return Ok(signature),
+            Err(e) => e,
+        };
+
+        // Small delay to allow network conditions to stabilize
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let landed = match submit_mode {
+            SubmitMode::Relayer => check_utxo_exists(encrypted_output, token_name).await,
+            SubmitMode::Direct => has_landed(connection, &submitted),
+        };
+        match landed {
+            Ok(true) => {
+                log::warn!("Deposit landed despite the error ({}), not re-submitting", error);
+                return find_landed_signature(connection, &submitted);
+            }
+            Ok(false) => {}
+            // Re-submitting without knowing could deposit twice
+            Err(check_error) => {
+                log::warn!("Couldn't check whether the deposit landed: {}", check_error);
+                return Err(error);
+            }
+        }
+
+        if !is_blockhash_expired(&error) || submitted.len() == MAX_ATTEMPTS {
+            return Err(error);
+        }
+        log::warn!(
+            "Transaction blockhash expired, retrying (attempt {}/{}) with a fresh blockhash...",
+            submitted.len() + 1,
+            MAX_ATTEMPTS
+        );
+    }
+}
+
+/// Where signed deposit transactions are sent
+///
+/// With [`Relayer`](Self::Relayer), the default, the relayer forwards the
+/// transaction and credits the referrer. [`Direct`](Self::Direct) sends it
+/// straight to the RPC node and waits for it to confirm there; the relayer
+/// never sees the signed transaction, so no referral is recorded. The
+/// relayer still serves the tree state, Merkle proofs and config a deposit
+/// needs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubmitMode {
+    /// Submit through the relayer's `/deposit` endpoints
+    #[default]
+    Relayer,
+    /// Submit with `send_and_confirm_transaction` on the RPC node
+    Direct,
+}
+
+/// Send a signed transaction to the RPC node and wait for it to confirm
+pub(crate) fn submit_direct(
+    connection: &RpcClient,
+    transaction: &VersionedTransaction,
+) -> Result<String> {
+    connection
+        .send_and_confirm_transaction(transaction)
+        .map(|signature| signature.to_string())
+        .map_err(|e| PrivacyCashError::TransactionError(format!("Direct submission failed: {}", e)))
+}
+
+/// Wait until `signature` reaches `commitment`, polling its status
+///
+/// Fails with `TransactionError` if the transaction failed on-chain and
+/// with `CommitmentTimeout` if it isn't at `commitment` after `timeout`.
+pub async fn wait_for_commitment(
+    connection: &RpcClient,
+    signature: &str,
+    commitment: CommitmentLevel,
+    timeout: Duration,
+) -> Result<()> {
+    let parsed = Signature::from_str(signature)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid signature: {}", e)))?;
+    let target = CommitmentConfig { commitment };
+    let start = Instant::now();
+
+    loop {
+        let statuses = connection.get_signature_statuses(&[parsed])?.value;
+        let status = statuses.into_iter().next().flatten();
+        if let Some(status) = status {
+            if let Some(err) = status.err {
+                return Err(PrivacyCashError::TransactionError(format!(
+                    "Transaction {} failed: {}",
+                    signature, err
+                )));
+            }
+            if status.satisfies_commitment(target) {
+                return Ok(());
+            }
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(PrivacyCashError::CommitmentTimeout {
+                signature: signature.to_string(),
+                commitment: format!("{:?}", commitment).to_lowercase(),
+                timeout_secs: timeout.as_secs(),
+            });
+        }
+        log::debug!("Waiting for {} to reach {:?} commitment", signature, commitment);
+        tokio::time::sleep(COMMITMENT_POLL_INTERVAL.min(timeout)).await;
+    }
+}
+
+/// Delay between signature status checks in `wait_for_commitment`
+const COMMITMENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Merkle proof from API
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MerkleProofResponse {
@@ -118,9 +331,7 @@ pub async fn fetch_merkle_proof(commitment: &str, token_name: Option<&str>) -> R
 
     log::debug!("Fetching Merkle proof for: {}", commitment);
 
-    let response = reqwest::get(&url)
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch Merkle proof: {}", e)))?;
+    let response = relayer::send(relayer::get(&url), "Failed to fetch Merkle proof").await?;
 
     if !response.status().is_success() {
         return Err(PrivacyCashError::MerkleProofError(format!(
@@ -139,6 +350,64 @@ pub async fn fetch_merkle_proof(commitment: &str, token_name: Option<&str>) -> R
     Ok(proof.into())
 }
 
+/// Merkle proof requests [`fetch_merkle_proofs`] keeps in flight at once
+const MERKLE_PROOF_CONCURRENCY: usize = 8;
+
+/// Fetch the Merkle proofs of several commitments
+///
+/// The relayer has no multi-proof endpoint, so the proofs are fetched
+/// concurrently, at most `MERKLE_PROOF_CONCURRENCY` at a time, and returned
+/// in the order of `commitments`. A commitment given twice is fetched once.
+/// Proofs change as leaves are appended, so fetch them right before proving.
+pub async fn fetch_merkle_proofs(
+    commitments: &[String],
+    token_name: Option<&str>,
+) -> Result<Vec<MerklePath>> {
+    let mut seen = HashSet::with_capacity(commitments.len());
+    let unique: Vec<&str> = commitments
+        .iter()
+        .map(String::as_str)
+        .filter(|commitment| seen.insert(*commitment))
+        .collect();
+
+    let proofs: HashMap<&str, MerklePath> = futures::stream::iter(unique)
+        .map(|commitment| async move {
+            let proof = fetch_merkle_proof(commitment, token_name).await?;
+            Ok::<_, PrivacyCashError>((commitment, proof))
+        })
+        .buffer_unordered(MERKLE_PROOF_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    Ok(commitments
+        .iter()
+        .map(|commitment| proofs[commitment.as_str()].clone())
+        .collect())
+}
+
+/// Fetch the Merkle proofs of transaction inputs concurrently
+///
+/// Dummy inputs get the zero path without a request.
+pub(crate) async fn fetch_input_merkle_paths(
+    inputs: &[Utxo],
+    token_name: Option<&str>,
+) -> Result<Vec<MerklePath>> {
+    let commitments = inputs
+        .iter()
+        .filter(|utxo| !utxo.is_dummy())
+        .map(Utxo::get_commitment)
+        .collect::<Result<Vec<_>>>()?;
+    let mut proofs = fetch_merkle_proofs(&commitments, token_name).await?.into_iter();
+
+    Ok(inputs
+        .iter()
+        .map(|utxo| {
+            let proof = if utxo.is_dummy() { None } else { proofs.next() };
+            proof.unwrap_or_else(MerkleTree::zero_path)
+        })
+        .collect())
+}
+
 /// Derive program PDAs
 pub fn get_program_accounts() -> (Pubkey, Pubkey, Pubkey) {
     let (tree_account, _) = Pubkey::find_program_address(&[b"merkle_tree"], &PROGRAM_ID);
@@ -150,6 +419,132 @@ pub fn get_program_accounts() -> (Pubkey, Pubkey, Pubkey) {
     (tree_account, tree_token_account, global_config_account)
 }
 
+/// Check that the program and lookup table exist on the connected RPC
+///
+/// Pointing a devnet RPC at the mainnet constants (or vice versa) otherwise
+/// only fails mid-transaction with a bare "account not found".
+pub fn validate_program_accounts(connection: &RpcClient) -> Result<()> {
+    let accounts = connection.get_multiple_accounts(&[*PROGRAM_ID, *ALT_ADDRESS])?;
+    let rpc_url = connection.url();
+
+    match &accounts[0] {
+        Some(program) if program.executable => {}
+        Some(_) => {
+            return Err(PrivacyCashError::ConfigError(format!(
+                "Account {} on {} is not an executable program; check PROGRAM_ID",
+                *PROGRAM_ID, rpc_url
+            )))
+        }
+        None => {
+            return Err(PrivacyCashError::ConfigError(format!(
+                "Privacy Cash program {} not found on {}; the RPC is probably on a different \
+                 network than the program (set SOLANA_RPC_URL or PROGRAM_ID to match)",
+                *PROGRAM_ID, rpc_url
+            )))
+        }
+    }
+
+    if accounts[1].is_none() {
+        return Err(PrivacyCashError::ConfigError(format!(
+            "Address lookup table {} not found on {}; set ALT_ADDRESS for this network",
+            *ALT_ADDRESS, rpc_url
+        )));
+    }
+
+    Ok(())
+}
+
+/// How long a fetched address lookup table is reused
+///
+/// The table only changes when it's extended with new accounts, so a stale
+/// copy at worst makes a transaction slightly larger.
+pub const LOOKUP_TABLE_TTL: Duration = Duration::from_secs(600);
+
+/// Lookup tables fetched per RPC URL, with the time they were fetched
+static LOOKUP_TABLE_CACHE: Lazy<RwLock<HashMap<String, (AddressLookupTableAccount, Instant)>>> =
+    Lazy::new(Default::default);
+
+/// How many times a lookup table fetch is tried before giving up
+const LOOKUP_TABLE_FETCH_ATTEMPTS: u32 = 3;
+
+/// Delay before the first lookup table retry, doubled for each later one
+const LOOKUP_TABLE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The program's address lookup table (`ALT_ADDRESS`)
+///
+/// Cached per RPC URL for [`LOOKUP_TABLE_TTL`], so back-to-back deposits
+/// fetch it once.
+pub async fn get_lookup_table(connection: &RpcClient) -> Result<AddressLookupTableAccount> {
+    if let Some((table, fetched)) = LOOKUP_TABLE_CACHE.read().get(&connection.url()) {
+        if fetched.elapsed() < LOOKUP_TABLE_TTL {
+            return Ok(table.clone());
+        }
+    }
+    refresh_lookup_table(connection).await
+}
+
+/// Fetch the lookup table now and replace the cached copy
+///
+/// Transient RPC failures (connection errors, timeouts) are retried with
+/// backoff; a missing or malformed account fails right away.
+pub async fn refresh_lookup_table(connection: &RpcClient) -> Result<AddressLookupTableAccount> {
+    log::info!("Fetching Address Lookup Table...");
+    let account = retry_transient(LOOKUP_TABLE_FETCH_ATTEMPTS, LOOKUP_TABLE_RETRY_DELAY, || {
+        connection.get_account(&ALT_ADDRESS)
+    })
+    .await?;
+    let table = AddressLookupTableAccount {
+        key: *ALT_ADDRESS,
+        addresses: parse_alt_addresses(&account.data)?,
+    };
+    LOOKUP_TABLE_CACHE
+        .write()
+        .insert(connection.url(), (table.clone(), Instant::now()));
+    Ok(table)
+}
+
+/// Whether an RPC error is worth retrying (the request never got an answer)
+fn is_transient_rpc_error(error: &ClientError) -> bool {
+    matches!(error.kind(), ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_))
+}
+
+/// Run an RPC call, retrying transient failures with backoff
+///
+/// Waits between attempts without blocking the runtime's worker thread.
+async fn retry_transient<T>(
+    attempts: u32,
+    delay: Duration,
+    mut call: impl FnMut() -> std::result::Result<T, ClientError>,
+) -> std::result::Result<T, ClientError> {
+    let mut delay = delay;
+    for _ in 1..attempts {
+        match call() {
+            Err(e) if is_transient_rpc_error(&e) => {
+                log::warn!("RPC request failed ({}), retrying in {:?}", e, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    call()
+}
+
+/// Parse Address Lookup Table addresses from account data
+pub(crate) fn parse_alt_addresses(data: &[u8]) -> Result<Vec<Pubkey>> {
+    // ALT format: 56 bytes header + addresses (32 bytes each)
+    const HEADER_SIZE: usize = 56;
+
+    if data.len() < HEADER_SIZE {
+        return Err(PrivacyCashError::TransactionError("Invalid ALT account data".to_string()));
+    }
+
+    Ok(data[HEADER_SIZE..]
+        .chunks_exact(32)
+        .map(|chunk| Pubkey::new_from_array(chunk.try_into().expect("32-byte chunk")))
+        .collect())
+}
+
 /// Get SPL tree account PDA
 pub fn get_spl_tree_account(mint: &Pubkey) -> Pubkey {
     let (tree_account, _) =
@@ -180,13 +575,46 @@ pub fn find_cross_check_nullifier_pdas(nullifiers: &[[u8; 32]]) -> (Pubkey, Pubk
     (nullifier2_pda, nullifier3_pda)
 }
 
+/// Nullifier PDAs a withdrawal spending `inputs` creates or checks
+///
+/// `inputs` are the notes in circuit order (at most two, no dummies). With
+/// two notes this is `nullifier0..3` as the withdrawal passes them; with one,
+/// the two PDAs derived from its nullifier (a dummy second input gets a
+/// random nullifier, so its PDAs can't be known in advance).
+pub fn withdrawal_nullifier_pdas(inputs: &[Utxo]) -> Result<Vec<Pubkey>> {
+    let nullifiers = inputs
+        .iter()
+        .map(|utxo| string_to_circuit_bytes(&utxo.get_nullifier()?))
+        .collect::<Result<Vec<_>>>()?;
+
+    match nullifiers.as_slice() {
+        [] => Err(PrivacyCashError::NoUtxosAvailable),
+        [only] => {
+            let (nullifier0_pda, nullifier1_pda) = find_nullifier_pdas(&[*only, *only]);
+            Ok(vec![nullifier0_pda, nullifier1_pda])
+        }
+        [_, _] => {
+            let (nullifier0_pda, nullifier1_pda) = find_nullifier_pdas(&nullifiers);
+            let (nullifier2_pda, nullifier3_pda) = find_cross_check_nullifier_pdas(&nullifiers);
+            Ok(vec![nullifier0_pda, nullifier1_pda, nullifier2_pda, nullifier3_pda])
+        }
+        _ => Err(PrivacyCashError::InvalidInput(format!(
+            "A withdrawal spends at most 2 notes, got {}",
+            inputs.len()
+        ))),
+    }
+}
+
 /// Get mint address field for circuit
+///
+/// Native SOL ([`NATIVE_SOL_MINT`]) is the placeholder string as-is; SPL
+/// mints are their first 31 bytes as a number, so the value fits the field.
+/// The wrapped SOL mint is an SPL mint here: map a caller's mint with
+/// [`pool_mint`](crate::constants::pool_mint) first where wrapped SOL means
+/// native SOL.
 pub fn get_mint_address_field(mint: &Pubkey) -> String {
-    let mint_str = mint.to_string();
-
-    // Special case for SOL
-    if mint_str == "11111111111111111111111111111112" {
-        return mint_str;
+    if *mint == *NATIVE_SOL_MINT {
+        return NATIVE_SOL_MINT_STR.to_string();
     }
 
     // For SPL tokens: use first 31 bytes
@@ -194,6 +622,342 @@ pub fn get_mint_address_field(mint: &Pubkey) -> String {
     BigUint::from_bytes_be(mint_bytes).to_string()
 }
 
+/// Convert a failed relayer response into an error
+///
+/// Compute-budget failures reported in the simulation logs become
+/// `ComputeBudgetExceeded` (with unit counts when the logs include them);
+/// anything else is an `ApiError` prefixed with `context`.
+pub fn relayer_error(context: &str, error_text: &str) -> PrivacyCashError {
+    let is_budget_error = error_text.contains("exceeded CUs meter")
+        || error_text.contains("Computational budget exceeded")
+        || error_text.contains("ComputationalBudgetExceeded");
+
+    if is_budget_error {
+        let units = parse_compute_units(error_text);
+        return PrivacyCashError::ComputeBudgetExceeded {
+            consumed: units.map(|(consumed, _)| consumed),
+            limit: units.map(|(_, limit)| limit),
+        };
+    }
+
+    PrivacyCashError::ApiError(format!("{}: {}", context, error_text))
+}
+
+/// Find the last "consumed X of Y compute units" entry in program logs
+fn parse_compute_units(logs: &str) -> Option<(u64, u64)> {
+    let pos = logs.rfind(" compute units")?;
+    let words: Vec<&str> = logs[..pos].rsplit(' ').take(4).collect();
+    // Reversed: [Y, "of", X, "consumed"]
+    match words.as_slice() {
+        [limit, "of", consumed, "consumed"] => Some((consumed.parse().ok()?, limit.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// How a fractional fee is rounded to whole base units
+///
+/// The program checks withdrawal fees with integer division (rounding
+/// down), and the relayer and TypeScript SDK floor the same expression this
+/// SDK computes, so [`Floor`](Self::Floor) is the default and matches their
+/// fee exactly. `Ceil` and `Round` pay at most one base unit more.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeRounding {
+    /// Round down
+    #[default]
+    Floor,
+    /// Round up
+    Ceil,
+    /// Round to the nearest unit, halves up
+    Round,
+}
+
+impl FeeRounding {
+    /// Round `numerator / denominator` to a whole number
+    fn apply(self, numerator: &BigUint, denominator: &BigUint) -> BigUint {
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        let round_up = match self {
+            Self::Floor => false,
+            Self::Ceil => !remainder.is_zero(),
+            Self::Round => remainder * 2u32 >= *denominator,
+        };
+        if round_up {
+            quotient + 1u32
+        } else {
+            quotient
+        }
+    }
+}
+
+/// Calculate the Privacy Cash withdrawal fee in base units
+///
+/// The fee is `fee_rate` of the amount plus a flat `rent_fee` expressed in
+/// whole tokens, rounded down (see [`FeeRounding`]).
+///
+/// Fails with `InvalidInput` if the fee doesn't fit in a `u64`.
+pub fn calculate_withdraw_fee(
+    amount: u64,
+    fee_rate: f64,
+    rent_fee: f64,
+    units_per_token: u64,
+) -> Result<u64> {
+    calculate_withdraw_fee_rounded(amount, fee_rate, rent_fee, units_per_token, FeeRounding::Floor)
+}
+
+/// [`calculate_withdraw_fee`] with an explicit rounding mode
+///
+/// The rates are read at their shortest decimal representation (0.0035 is
+/// 35/10000, as the relayer's config JSON has it) and the fee is computed
+/// exactly before rounding. Floating-point math would put a fee of exactly
+/// 29 at 28.99..., one unit short.
+///
+/// Fails with `InvalidInput` if a rate is negative or not finite, or the
+/// fee doesn't fit in a `u64`.
+pub fn calculate_withdraw_fee_rounded(
+    amount: u64,
+    fee_rate: f64,
+    rent_fee: f64,
+    units_per_token: u64,
+    rounding: FeeRounding,
+) -> Result<u64> {
+    let out_of_range = || {
+        PrivacyCashError::InvalidInput(format!(
+            "Fee out of range: {} of {} plus {} x {} rent",
+            fee_rate, amount, rent_fee, units_per_token
+        ))
+    };
+    let (rate, rate_scale) = decimal_fraction(fee_rate).ok_or_else(out_of_range)?;
+    let (rent, rent_scale) = decimal_fraction(rent_fee).ok_or_else(out_of_range)?;
+
+    // Both terms over the common denominator 10^scale
+    let ten = BigUint::from(10u32);
+    let scale = rate_scale.max(rent_scale);
+    let numerator = BigUint::from(amount) * rate * ten.pow(scale - rate_scale)
+        + BigUint::from(units_per_token) * rent * ten.pow(scale - rent_scale);
+    rounding
+        .apply(&numerator, &ten.pow(scale))
+        .to_u64()
+        .ok_or_else(out_of_range)
+}
+
+/// `value` as `numerator / 10^scale`, from its shortest decimal form
+///
+/// `None` for negative or non-finite values.
+fn decimal_fraction(value: f64) -> Option<(BigUint, u32)> {
+    if !value.is_finite() || value < 0.0 {
+        return None;
+    }
+    if value == 0.0 {
+        return Some((BigUint::zero(), 0));
+    }
+    // Display never uses an exponent, e.g. "0.0035" or "1000"
+    let text = value.to_string();
+    let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let numerator = BigUint::parse_bytes(format!("{}{}", whole, fraction).as_bytes(), 10)?;
+    Some((numerator, fraction.len() as u32))
+}
+
+/// Calculate the Privacy Cash deposit fee in base units
+///
+/// Taken out of the deposited amount at the relayer's `deposit_fee_rate`,
+/// rounded down.
+pub fn calculate_deposit_fee(amount: u64, deposit_fee_rate: f64) -> Result<u64> {
+    calculate_withdraw_fee(amount, deposit_fee_rate, 0.0, 1)
+}
+
+/// Calculate the partner/platform fee in base units
+///
+/// Fails with `InvalidInput` if the fee doesn't fit in a `u64`.
+pub fn calculate_partner_fee(amount: u64, rate: f64) -> Result<u64> {
+    fee_to_base_units(amount as f64 * rate)
+}
+
+/// Round a fee down to whole base units, rejecting values outside `u64`
+fn fee_to_base_units(fee: f64) -> Result<u64> {
+    // 2^64: the smallest value that no longer fits
+    const U64_LIMIT: f64 = 18_446_744_073_709_551_616.0;
+
+    let fee = fee.floor();
+    if !(0.0..U64_LIMIT).contains(&fee) {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Fee out of range: {}",
+            fee
+        )));
+    }
+    Ok(fee as u64)
+}
+
+/// Add an amount and its fee, failing with `InvalidInput` on overflow
+pub fn checked_total(amount: u64, fee: u64) -> Result<u64> {
+    amount.checked_add(fee).ok_or_else(|| {
+        PrivacyCashError::InvalidInput(format!("Amount {} plus fee {} overflows u64", amount, fee))
+    })
+}
+
+/// Check that a balance leaves something to withdraw after fees
+pub fn ensure_balance_covers_fee(balance: u64, fee: u64) -> Result<()> {
+    if balance <= fee {
+        return Err(PrivacyCashError::BalanceTooSmallForFees { balance, fee });
+    }
+    Ok(())
+}
+
+/// USD value of `base_units` of a token priced at `price_usd` per whole token
+pub fn usd_value(base_units: u64, units_per_token: u64, price_usd: f64) -> f64 {
+    base_units as f64 / units_per_token as f64 * price_usd
+}
+
+/// Format base units as a decimal amount, trimming trailing zeros
+///
+/// `format_amount(1_500_000, 6)` gives `"1.5"`, `format_amount(2_000_000, 6)`
+/// gives `"2"`. Exact for any number of decimals (no float rounding).
+pub fn format_amount(base_units: u64, decimals: u8) -> String {
+    let digits = format!("{:0>width$}", base_units, width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Format base units of a token given by name, alias or mint address
+pub fn format_token_amount(base_units: u64, token: &str) -> Result<String> {
+    let token = resolve_token(token)?;
+    Ok(format_amount(base_units, token.decimals()))
+}
+
+/// Calculate the fee for a single-signature transaction in lamports
+///
+/// Base signature fee plus the priority fee for the requested compute units
+/// (`micro_lamports_per_cu` is rounded up to whole lamports).
+pub fn calculate_transaction_fee(compute_unit_limit: u32, micro_lamports_per_cu: u64) -> u64 {
+    // u128::div_ceil needs Rust 1.73; the crate supports 1.70
+    let priority_fee = (compute_unit_limit as u128 * micro_lamports_per_cu as u128 + 999_999) / 1_000_000;
+    SIGNATURE_FEE_LAMPORTS + priority_fee as u64
+}
+
+/// Fail unless a SOL deposit of `amount` took at least `amount + network_fee`
+/// from the public balance (`before` and `after`, in lamports)
+///
+/// Taking more is fine: the deposit also pays rent for its nullifier
+/// accounts. Catches deposits the relayer reported but that didn't move the
+/// funds.
+pub(crate) fn ensure_deposit_debited(
+    before: u64,
+    after: u64,
+    amount: u64,
+    network_fee: u64,
+    signature: &str,
+) -> Result<()> {
+    let debited = before.saturating_sub(after);
+    let expected = checked_total(amount, network_fee)?;
+    if debited < expected {
+        return Err(PrivacyCashError::TransactionError(format!(
+            "Deposit {} took {} lamports from the public balance, expected at least {}",
+            signature, debited, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Check that `token_account` is an SPL token account for `mint`
+///
+/// Returns the account's owner. Fails with `InvalidInput` if the account
+/// doesn't exist, isn't owned by the token program or holds another mint.
+pub fn token_account_owner(connection: &RpcClient, token_account: &Pubkey, mint: &Pubkey) -> Result<Pubkey> {
+    let account = connection
+        .get_account_with_commitment(token_account, connection.commitment())?
+        .value
+        .ok_or_else(|| {
+            PrivacyCashError::InvalidInput(format!("Token account {} not found", token_account))
+        })?;
+    check_token_account(&account, token_account, mint)
+}
+
+/// `token_account_owner` on an already fetched account
+fn check_token_account(
+    account: &solana_sdk::account::Account,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Pubkey> {
+    if account.owner != spl_token::id() {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "{} is not a token account (owned by {})",
+            token_account, account.owner
+        )));
+    }
+    let state = spl_token::state::Account::unpack(&account.data).map_err(|e| {
+        PrivacyCashError::InvalidInput(format!("Invalid token account {}: {}", token_account, e))
+    })?;
+    if state.mint != *mint {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Token account {} holds mint {}, expected {}",
+            token_account, state.mint, mint
+        )));
+    }
+    Ok(state.owner)
+}
+
+/// Check that the protocol has set up the pool for an SPL token
+///
+/// The pool needs the mint's tree account and the tree's token account (the
+/// global config's ATA for the mint). Without them the transaction fails
+/// on-chain with an opaque account error, so fail early with
+/// `TokenNotInitialized` instead.
+pub fn ensure_spl_pool_initialized(connection: &RpcClient, mint: &Pubkey, token_name: &str) -> Result<()> {
+    let tree_account = get_spl_tree_account(mint);
+    let (_, _, global_config_account) = get_program_accounts();
+    let tree_ata = spl_associated_token_account::get_associated_token_address(&global_config_account, mint);
+
+    let accounts = connection.get_multiple_accounts(&[tree_account, tree_ata])?;
+    match missing_pool_account(accounts[0].as_ref(), accounts[1].as_ref(), &tree_ata, mint) {
+        Some(missing) => Err(PrivacyCashError::TokenNotInitialized {
+            token: token_name.to_string(),
+            missing: missing.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Which pool account is missing or not set up, if any
+fn missing_pool_account(
+    tree_account: Option<&solana_sdk::account::Account>,
+    tree_ata_account: Option<&solana_sdk::account::Account>,
+    tree_ata: &Pubkey,
+    mint: &Pubkey,
+) -> Option<&'static str> {
+    if !matches!(tree_account, Some(a) if a.owner == *PROGRAM_ID && !a.data.is_empty()) {
+        return Some("tree account");
+    }
+    match tree_ata_account {
+        Some(account) if check_token_account(account, tree_ata, mint).is_ok() => None,
+        _ => Some("tree token account"),
+    }
+}
+
+/// Read the balance of an SPL token account
+///
+/// Returns `None` when the account doesn't exist, so callers can tell a
+/// missing ATA apart from a zero balance.
+pub fn get_token_account_amount(connection: &RpcClient, token_account: &Pubkey) -> Result<Option<u64>> {
+    let account = connection
+        .get_account_with_commitment(token_account, connection.commitment())?
+        .value;
+
+    let Some(account) = account else {
+        return Ok(None);
+    };
+
+    let state = spl_token::state::Account::unpack(&account.data).map_err(|e| {
+        PrivacyCashError::SerializationError(format!("Invalid token account {}: {}", token_account, e))
+    })?;
+    Ok(Some(state.amount))
+}
+
 /// Calculate public amount for circuit
 pub fn calculate_public_amount(ext_amount: i64, fee: u64) -> BigUint {
     let ext_bn = if ext_amount >= 0 {
@@ -226,6 +990,80 @@ pub fn biguint_to_bytes_be(n: &BigUint) -> [u8; 32] {
     result
 }
 
+/// Largest amount one deposit or withdrawal can move, in base units
+///
+/// `ext_amount` is an `i64` in the program's instruction data.
+pub const MAX_EXT_AMOUNT: u64 = i64::MAX as u64;
+
+/// Bits the circuit allows for an output note amount
+///
+/// Output notes hold at most two input notes plus one deposit, all `u64`,
+/// so real amounts stay below 2^66. The check only turns a bug into an
+/// error instead of an unsatisfiable witness.
+pub const NOTE_AMOUNT_BITS: u64 = 248;
+
+/// `ext_amount` for moving `amount` base units into (`deposit`) or out of the pool
+///
+/// Fails for amounts above [`MAX_EXT_AMOUNT`], which would otherwise wrap
+/// around to the opposite sign.
+pub fn ext_amount_for(amount: u64, deposit: bool) -> Result<i64> {
+    let ext_amount = i64::try_from(amount).map_err(|_| {
+        PrivacyCashError::InvalidInput(format!(
+            "Amount {} exceeds the maximum of {} base units per transaction",
+            amount, MAX_EXT_AMOUNT
+        ))
+    })?;
+    Ok(if deposit { ext_amount } else { -ext_amount })
+}
+
+/// Check that output note amounts fit the circuit's range check
+pub fn ensure_output_amounts_in_range(outputs: &[Utxo]) -> Result<()> {
+    match outputs.iter().find(|u| u.amount.bits() > NOTE_AMOUNT_BITS) {
+        Some(output) => Err(PrivacyCashError::InvalidInput(format!(
+            "Output note amount {} exceeds the circuit's {}-bit limit",
+            output.amount, NOTE_AMOUNT_BITS
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Length of a Solana keypair's secret key (seed followed by public key)
+pub const KEYPAIR_LEN: usize = 64;
+
+/// Parse a wallet private key
+///
+/// Accepts the 64-byte secret key as base58 (as exported by most wallets)
+/// or as the JSON byte array of a Solana CLI keypair file (e.g. the contents
+/// of `~/.config/solana/id.json`). Surrounding whitespace is ignored.
+pub fn parse_keypair(private_key: &str) -> Result<Keypair> {
+    let private_key = private_key.trim();
+    let key_bytes: Vec<u8> = if private_key.starts_with('[') {
+        serde_json::from_str(private_key).map_err(|e| {
+            PrivacyCashError::InvalidInput(format!("Invalid JSON private key: {}", e))
+        })?
+    } else {
+        bs58::decode(private_key)
+            .into_vec()
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid private key: {}", e)))?
+    };
+    if key_bytes.len() != KEYPAIR_LEN {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Invalid keypair: expected {} bytes, got {}",
+            KEYPAIR_LEN,
+            key_bytes.len()
+        )));
+    }
+    let keypair = Keypair::try_from(key_bytes.as_slice())
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid keypair: {}", e)))?;
+    // The second half must be the public key of the first
+    if keypair.pubkey().to_bytes()[..] != key_bytes[32..] {
+        return Err(PrivacyCashError::InvalidInput(
+            "Invalid keypair: public key doesn't match the secret key".to_string(),
+        ));
+    }
+    Ok(keypair)
+}
+
 /// Convert BigUint to 32-byte array (little-endian)
 pub fn biguint_to_bytes_le(n: &BigUint) -> [u8; 32] {
     let bytes = n.to_bytes_le();
@@ -250,6 +1088,306 @@ pub fn string_to_circuit_bytes(s: &str) -> Result<[u8; 32]> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "testing")]
+    use crate::testing::{install_thread_mocks, test_config, test_mock};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wait_for_commitment() {
+        let signature = Signature::new_unique().to_string();
+        let timeout = Duration::from_millis(10);
+
+        // The mock reports every transaction as finalized
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        wait_for_commitment(&connection, &signature, CommitmentLevel::Finalized, timeout)
+            .await
+            .unwrap();
+
+        let connection = RpcClient::new_mock("sig_not_found".to_string());
+        assert!(matches!(
+            wait_for_commitment(&connection, &signature, CommitmentLevel::Confirmed, timeout).await,
+            Err(PrivacyCashError::CommitmentTimeout { commitment, .. }) if commitment == "confirmed"
+        ));
+
+        let connection = RpcClient::new_mock("instruction_error".to_string());
+        assert!(matches!(
+            wait_for_commitment(&connection, &signature, CommitmentLevel::Confirmed, timeout).await,
+            Err(PrivacyCashError::TransactionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_has_landed() {
+        let submitted = [Signature::new_unique()];
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        assert!(has_landed(&connection, &submitted).unwrap());
+        assert!(!has_landed(&connection, &[]).unwrap());
+
+        let connection = RpcClient::new_mock("sig_not_found".to_string());
+        assert!(!has_landed(&connection, &submitted).unwrap());
+        // Landed but failed: re-submitting is safe
+        let connection = RpcClient::new_mock("instruction_error".to_string());
+        assert!(!has_landed(&connection, &submitted).unwrap());
+    }
+
+    #[test]
+    fn test_parse_keypair_formats() {
+        let keypair = Keypair::new();
+        let bytes = keypair.to_bytes();
+
+        // Base58 secret key
+        let base58 = bs58::encode(bytes).into_string();
+        assert_eq!(parse_keypair(&base58).unwrap().pubkey(), keypair.pubkey());
+        assert_eq!(parse_keypair(&format!("  {}\n", base58)).unwrap().pubkey(), keypair.pubkey());
+
+        // Solana CLI keypair file
+        let json = serde_json::to_string(&bytes.to_vec()).unwrap();
+        assert_eq!(parse_keypair(&format!("{}\n", json)).unwrap().pubkey(), keypair.pubkey());
+
+        // Wrong length, in either format
+        assert!(parse_keypair(&bs58::encode(&bytes[..32]).into_string()).is_err());
+        assert!(parse_keypair("[1, 2, 3]").is_err());
+
+        // A secret key whose public half doesn't match its seed
+        let mut mismatched = bytes;
+        mismatched[40] ^= 1;
+        assert!(parse_keypair(&bs58::encode(mismatched).into_string()).is_err());
+
+        // Not a key at all
+        assert!(parse_keypair("not-a-key!").is_err());
+        assert!(parse_keypair("[1, 2,").is_err());
+        assert!(parse_keypair("").is_err());
+    }
+
+    #[test]
+    fn test_calculate_deposit_fee() {
+        assert_eq!(calculate_deposit_fee(1_000_000_000, 0.0).unwrap(), 0);
+        assert_eq!(calculate_deposit_fee(1_000_000_000, 0.0025).unwrap(), 2_500_000);
+        assert_eq!(calculate_deposit_fee(399, 0.0025).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_calculate_partner_fee() {
+        assert_eq!(calculate_partner_fee(1_000_000, 0.01).unwrap(), 10_000);
+        assert_eq!(calculate_partner_fee(1_000_000, 0.0).unwrap(), 0);
+        assert_eq!(calculate_partner_fee(99, 0.01).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(2_000_000, 6), "2");
+        assert_eq!(format_amount(1, 9), "0.000000001");
+        assert_eq!(format_amount(0, 9), "0");
+        assert_eq!(format_amount(42, 0), "42");
+        assert_eq!(format_amount(u64::MAX, 11), "184467440.73709551615");
+    }
+
+    #[test]
+    fn test_usd_value() {
+        assert_eq!(usd_value(1_500_000_000, 1_000_000_000, 150.0), 225.0);
+        assert_eq!(usd_value(2_500_000, 1_000_000, 1.0), 2.5);
+        assert_eq!(usd_value(0, 100_000_000, 40.0), 0.0);
+    }
+
+    #[test]
+    fn test_format_token_amount() {
+        assert_eq!(format_token_amount(250_000_000, "sol").unwrap(), "0.25");
+        assert_eq!(format_token_amount(123_450_000_000, "ORE").unwrap(), "1.2345");
+        assert!(format_token_amount(1, "doge").is_err());
+    }
+
+    #[test]
+    fn test_calculate_transaction_fee() {
+        assert_eq!(calculate_transaction_fee(1_000_000, 0), 5_000);
+        assert_eq!(calculate_transaction_fee(1_000_000, 1_000), 6_000);
+        // Fractional lamports round up
+        assert_eq!(calculate_transaction_fee(200_000, 1), 5_001);
+    }
+
+    #[test]
+    fn test_ensure_deposit_debited() {
+        assert!(ensure_deposit_debited(1_000_000, 489_000, 500_000, 5_000, "sig").is_ok());
+        assert!(ensure_deposit_debited(1_000_000, 495_000, 500_000, 5_000, "sig").is_ok());
+        // A no-op deposit only paid the network fee
+        let err = ensure_deposit_debited(1_000_000, 995_000, 500_000, 5_000, "sig").unwrap_err();
+        assert!(matches!(err, PrivacyCashError::TransactionError(_)));
+        assert!(err.to_string().contains("took 5000 lamports"));
+        // The balance grew meanwhile
+        assert!(ensure_deposit_debited(1_000_000, 2_000_000, 500_000, 5_000, "sig").is_err());
+    }
+
+    #[test]
+    fn test_relayer_error_compute_budget() {
+        let logs = "Transaction simulation failed: Program 9fhQ invoke [1], \
+            Program 9fhQ consumed 1000000 of 1000000 compute units, \
+            Program 9fhQ failed: exceeded CUs meter at BPF instruction #1234";
+
+        match relayer_error("Withdraw failed", logs) {
+            PrivacyCashError::ComputeBudgetExceeded { consumed, limit } => {
+                assert_eq!(consumed, Some(1_000_000));
+                assert_eq!(limit, Some(1_000_000));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        assert!(matches!(
+            relayer_error("Withdraw failed", "Computational budget exceeded"),
+            PrivacyCashError::ComputeBudgetExceeded { consumed: None, limit: None }
+        ));
+    }
+
+    #[test]
+    fn test_relayer_error_other() {
+        match relayer_error("Withdraw failed", "Invalid proof") {
+            PrivacyCashError::ApiError(msg) => assert_eq!(msg, "Withdraw failed: Invalid proof"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_calculate_withdraw_fee() {
+        // 0.35% of 1 SOL + 0.006 SOL rent
+        let fee = calculate_withdraw_fee(1_000_000_000, 0.0035, 0.006, 1_000_000_000).unwrap();
+        assert_eq!(fee, 9_500_000);
+
+        // 0.35% of 10 USDC + 0.85 USDC rent
+        let fee = calculate_withdraw_fee(10_000_000, 0.0035, 0.85, 1_000_000).unwrap();
+        assert_eq!(fee, 885_000);
+    }
+
+    #[test]
+    fn test_withdraw_fee_exact_at_whole_units() {
+        // In f64 these products land just below a whole number (28.99...,
+        // 62.99..., 8176.99...), so flooring them would come out one unit
+        // short of the program's integer fee
+        let sol = 1_000_000_000;
+        for (amount, rate, bps) in [(10_000u64, 0.0029, 29u64), (30_000, 0.0021, 21), (4_810_000, 0.0017, 17)] {
+            let program_fee = amount * bps / 10_000;
+            assert_eq!(calculate_withdraw_fee(amount, rate, 0.0, sol).unwrap(), program_fee);
+            assert_eq!(
+                calculate_withdraw_fee(amount, rate, 0.006, sol).unwrap(),
+                program_fee + 6_000_000
+            );
+            for rounding in [FeeRounding::Ceil, FeeRounding::Round] {
+                assert_eq!(
+                    calculate_withdraw_fee_rounded(amount, rate, 0.0, sol, rounding).unwrap(),
+                    program_fee
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_withdraw_fee_rounding_modes() {
+        let sol = 1_000_000_000;
+        let fee = |amount, rounding| {
+            calculate_withdraw_fee_rounded(amount, 0.0035, 0.006, sol, rounding).unwrap()
+        };
+
+        // 3.5 + rent: exactly half a unit
+        assert_eq!(fee(1_000, FeeRounding::Floor), 6_000_003);
+        assert_eq!(fee(1_000, FeeRounding::Ceil), 6_000_004);
+        assert_eq!(fee(1_000, FeeRounding::Round), 6_000_004);
+
+        // 3500.0035 + rent: just above a whole unit
+        assert_eq!(fee(1_000_001, FeeRounding::Floor), 6_003_500);
+        assert_eq!(fee(1_000_001, FeeRounding::Ceil), 6_003_501);
+        assert_eq!(fee(1_000_001, FeeRounding::Round), 6_003_500);
+
+        // 3.4965 + rent: just below half
+        assert_eq!(fee(999, FeeRounding::Round), 6_000_003);
+
+        // Floor is the program's integer division
+        for amount in [1u64, 999, 1_000, 2_857, 1_000_001, 123_456_789] {
+            assert_eq!(fee(amount, FeeRounding::Floor), amount * 35 / 10_000 + 6_000_000);
+        }
+        assert_eq!(FeeRounding::default(), FeeRounding::Floor);
+        assert!(calculate_withdraw_fee(1, -0.1, 0.0, sol).is_err());
+        assert!(calculate_withdraw_fee(1, f64::NAN, 0.0, sol).is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_nullifier_pdas() {
+        let keypair = crate::keypair::ZkKeypair::generate().unwrap();
+        let first = Utxo::new(5u64, keypair.clone(), 3, None, None);
+        let second = Utxo::new(2u64, keypair, 4, None, None);
+        let bytes = |u: &Utxo| string_to_circuit_bytes(&u.get_nullifier().unwrap()).unwrap();
+
+        let pdas = withdrawal_nullifier_pdas(&[first.clone(), second.clone()]).unwrap();
+        let (n0, n1) = find_nullifier_pdas(&[bytes(&first), bytes(&second)]);
+        let (n2, n3) = find_cross_check_nullifier_pdas(&[bytes(&first), bytes(&second)]);
+        assert_eq!(pdas, vec![n0, n1, n2, n3]);
+
+        // Both PDAs of a lone note: the ones a two-note spend puts at 0 and 3
+        let single = withdrawal_nullifier_pdas(std::slice::from_ref(&first)).unwrap();
+        assert_eq!(single, vec![n0, n3]);
+
+        assert!(withdrawal_nullifier_pdas(&[]).is_err());
+        assert!(withdrawal_nullifier_pdas(&[first.clone(), first.clone(), first]).is_err());
+    }
+
+    #[test]
+    fn test_check_token_account() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let state = spl_token::state::Account {
+            mint,
+            owner,
+            amount: 5,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(state, &mut data).unwrap();
+        let account = solana_sdk::account::Account {
+            lamports: 2_039_280,
+            data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        };
+
+        assert_eq!(check_token_account(&account, &token_account, &mint).unwrap(), owner);
+        assert!(check_token_account(&account, &token_account, &Pubkey::new_unique()).is_err());
+
+        let wallet = solana_sdk::account::Account {
+            owner: solana_sdk::system_program::id(),
+            data: vec![],
+            ..account.clone()
+        };
+        assert!(check_token_account(&wallet, &token_account, &mint).is_err());
+
+        let truncated = solana_sdk::account::Account {
+            data: vec![0u8; 10],
+            ..account
+        };
+        assert!(check_token_account(&truncated, &token_account, &mint).is_err());
+    }
+
+    #[test]
+    fn test_fee_math_near_u64_max() {
+        // 2^63 is exactly representable and still fits
+        assert_eq!(calculate_partner_fee(u64::MAX, 0.5).unwrap(), 1 << 63);
+        assert!(calculate_partner_fee(u64::MAX, 1.0).is_err());
+        assert!(calculate_partner_fee(1, -1.0).is_err());
+        assert!(calculate_withdraw_fee(u64::MAX, 0.0035, 0.006, 1_000_000_000).is_ok());
+        assert!(calculate_withdraw_fee(u64::MAX, 1.0, 1.0, 1_000_000_000).is_err());
+
+        assert_eq!(checked_total(u64::MAX - 1, 1).unwrap(), u64::MAX);
+        assert!(checked_total(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_ensure_balance_covers_fee_boundary() {
+        assert!(matches!(
+            ensure_balance_covers_fee(6_000_000, 6_000_000),
+            Err(PrivacyCashError::BalanceTooSmallForFees { balance: 6_000_000, fee: 6_000_000 })
+        ));
+        assert!(ensure_balance_covers_fee(5_999_999, 6_000_000).is_err());
+        assert!(ensure_balance_covers_fee(0, 0).is_err());
+        assert!(ensure_balance_covers_fee(6_000_001, 6_000_000).is_ok());
+    }
 
     #[test]
     fn test_public_amount_positive() {
@@ -273,4 +1411,258 @@ mod tests {
         assert_ne!(token, Pubkey::default());
         assert_ne!(config, Pubkey::default());
     }
+
+    #[test]
+    fn test_amount_limits() {
+        assert_eq!(ext_amount_for(MAX_EXT_AMOUNT, true).unwrap(), i64::MAX);
+        assert_eq!(ext_amount_for(MAX_EXT_AMOUNT, false).unwrap(), -i64::MAX);
+        assert!(ext_amount_for(MAX_EXT_AMOUNT + 1, true).is_err());
+        assert!(ext_amount_for(u64::MAX, false).is_err());
+
+        let keypair = crate::keypair::ZkKeypair::generate().unwrap();
+        let note = |amount: BigUint| Utxo::new(amount, keypair.clone(), 0, None, None);
+        let max_note = (BigUint::from(1u8) << NOTE_AMOUNT_BITS) - 1u8;
+        assert!(ensure_output_amounts_in_range(&[note(max_note.clone()), note(BigUint::from(0u8))]).is_ok());
+        assert!(ensure_output_amounts_in_range(&[note(max_note + 1u8)]).is_err());
+    }
+
+    #[test]
+    fn test_missing_pool_account() {
+        use solana_sdk::account::Account;
+
+        let mint = Pubkey::new_unique();
+        let tree_ata = Pubkey::new_unique();
+        let tree = Account { owner: *PROGRAM_ID, data: vec![1; 8], ..Account::default() };
+        let state = spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(state, &mut data).unwrap();
+        let ata = Account { owner: spl_token::id(), data, ..Account::default() };
+
+        assert_eq!(missing_pool_account(Some(&tree), Some(&ata), &tree_ata, &mint), None);
+        assert_eq!(missing_pool_account(None, Some(&ata), &tree_ata, &mint), Some("tree account"));
+        assert_eq!(
+            missing_pool_account(Some(&tree), None, &tree_ata, &mint),
+            Some("tree token account")
+        );
+        // Token account for another mint
+        assert_eq!(
+            missing_pool_account(Some(&tree), Some(&ata), &tree_ata, &Pubkey::new_unique()),
+            Some("tree token account")
+        );
+    }
+
+    #[test]
+    fn test_validate_program_accounts() {
+        use solana_client::rpc_request::RpcRequest;
+
+        // RPC answering getMultipleAccounts for [PROGRAM_ID, ALT_ADDRESS]
+        let rpc = |program: serde_json::Value, alt: serde_json::Value| {
+            let mut mocks = HashMap::new();
+            mocks.insert(
+                RpcRequest::GetMultipleAccounts,
+                serde_json::json!({ "context": { "slot": 1 }, "value": [program, alt] }),
+            );
+            RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+        };
+        let account = |owner: &str, executable: bool| {
+            serde_json::json!({
+                "data": ["", "base64"],
+                "lamports": 1_000_000,
+                "owner": owner,
+                "executable": executable,
+                "rentEpoch": 0,
+                "space": 0,
+            })
+        };
+        let program = account("BPFLoaderUpgradeab1e11111111111111111111111", true);
+        let alt = account("AddressLookupTab1e1111111111111111111111111", false);
+
+        // Program and lookup table deployed on the RPC's network
+        assert!(validate_program_accounts(&rpc(program.clone(), alt.clone())).is_ok());
+
+        // RPC on another network: neither account exists there
+        match validate_program_accounts(&rpc(serde_json::Value::Null, serde_json::Value::Null)) {
+            Err(PrivacyCashError::ConfigError(message)) => {
+                assert!(message.contains(&PROGRAM_ID.to_string()));
+                assert!(message.contains("different network"));
+            }
+            other => panic!("expected a network mismatch, got {:?}", other),
+        }
+
+        // PROGRAM_ID pointing at a non-program account
+        let result = validate_program_accounts(&rpc(alt.clone(), alt));
+        assert!(matches!(result, Err(PrivacyCashError::ConfigError(m)) if m.contains("not an executable")));
+
+        // Program found but ALT_ADDRESS is from another network
+        let result = validate_program_accounts(&rpc(program, serde_json::Value::Null));
+        assert!(matches!(result, Err(PrivacyCashError::ConfigError(m)) if m.contains("ALT_ADDRESS")));
+    }
+
+    #[test]
+    fn test_parse_alt_addresses() {
+        let first = Pubkey::new_unique();
+        let second = Pubkey::new_unique();
+        let mut data = vec![0u8; 56];
+        data.extend_from_slice(first.as_ref());
+        data.extend_from_slice(second.as_ref());
+        // A trailing partial entry is ignored
+        data.extend_from_slice(&[1, 2, 3]);
+
+        assert_eq!(parse_alt_addresses(&data).unwrap(), vec![first, second]);
+        assert!(parse_alt_addresses(&data[..55]).unwrap_err().to_string().contains("ALT"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient() {
+        use solana_client::rpc_request::RpcError;
+
+        let transient =
+            || ClientError::from(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out"));
+
+        // Recovers once the transient failures stop
+        let mut calls = 0;
+        let result = retry_transient(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 {
+                Err(transient())
+            } else {
+                Ok(calls)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        // Gives up after the last attempt
+        let mut calls = 0;
+        let result: std::result::Result<(), _> = retry_transient(3, Duration::ZERO, || {
+            calls += 1;
+            Err(transient())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+
+        // An answer from the node isn't retried
+        let mut calls = 0;
+        let result: std::result::Result<(), _> = retry_transient(3, Duration::ZERO, || {
+            calls += 1;
+            Err(ClientError::from(RpcError::ForUser("AccountNotFound".to_string())))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_fetch_merkle_proofs_keeps_order() {
+        let relayer = test_mock()
+            .respond(
+                "GET",
+                "/merkle/proof/111",
+                200,
+                serde_json::json!({ "pathElements": ["1"], "pathIndices": [0] }),
+            )
+            .respond(
+                "GET",
+                "/merkle/proof/222",
+                200,
+                serde_json::json!({ "pathElements": ["2"], "pathIndices": [1] }),
+            );
+        install_thread_mocks(relayer, test_config());
+
+        let commitments = ["222", "111", "222"].map(String::from);
+        let proofs = fetch_merkle_proofs(&commitments, Some("usdc"))
+            .await
+            .unwrap();
+        let elements: Vec<&str> = proofs.iter().map(|p| p.path_elements[0].as_str()).collect();
+        assert_eq!(elements, ["2", "1", "2"]);
+        assert_eq!(proofs[1].path_indices, [0]);
+
+        let missing = ["111".to_string(), "333".to_string()];
+        assert!(fetch_merkle_proofs(&missing, None).await.is_err());
+        assert!(fetch_merkle_proofs(&[], None).await.unwrap().is_empty());
+    }
+
+    /// Relayer mock that has indexed the note with encrypted output `abcd`
+    /// but not the one with `ef01`
+    #[cfg(feature = "testing")]
+    fn utxo_check_mock() -> crate::testing::MockRelayer {
+        test_mock()
+            .respond("GET", "/utxos/check/abcd", 200, serde_json::json!({ "exists": true }))
+            .respond("GET", "/utxos/check/ef01", 200, serde_json::json!({ "exists": false }))
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deposit_retry_detects_landed_note() {
+        install_thread_mocks(utxo_check_mock(), test_config());
+
+        // The relayer response was lost but the note was indexed: don't re-submit
+        assert!(check_utxo_exists(&[0xab, 0xcd], None).await.unwrap());
+        // The note never landed: safe to re-submit
+        assert!(!check_utxo_exists(&[0xef, 0x01], Some("usdc")).await.unwrap());
+        // No answer from the relayer: fail instead of risking a double deposit
+        assert!(check_utxo_exists(&[0x00], None).await.is_err());
+
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        let submitted = [Signature::new_unique()];
+        let landed = find_landed_signature(&connection, &submitted).unwrap();
+        assert_eq!(landed, submitted[0].to_string());
+        // The relayer indexed the note but no transaction has a status yet
+        let connection = RpcClient::new_mock("sig_not_found".to_string());
+        assert!(matches!(
+            find_landed_signature(&connection, &submitted),
+            Err(PrivacyCashError::TransactionError(e)) if e.contains(&submitted[0].to_string())
+        ));
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deposit_submission_checks_for_lost_responses() {
+        use std::cell::RefCell;
+
+        install_thread_mocks(utxo_check_mock(), test_config());
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        // Submits `encrypted_output`'s deposit, failing every attempt with `error`
+        let submit = |encrypted_output: &'static [u8], error: &'static str| {
+            let attempts = RefCell::new(Vec::new());
+            let connection = &connection;
+            async move {
+                let result = submit_deposit(connection, SubmitMode::Relayer, encrypted_output, None, || {
+                    let signature = Signature::new_unique();
+                    attempts.borrow_mut().push(signature);
+                    let error = PrivacyCashError::TransactionError(error.to_string());
+                    Ok((signature, async move { Err(error) }))
+                })
+                .await;
+                (result, attempts.into_inner())
+            }
+        };
+
+        // The relayer took the deposit but the response was lost
+        let (result, attempts) = submit(&[0xab, 0xcd], "connection reset").await;
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(result.unwrap(), attempts[0].to_string());
+
+        // Rejected and not indexed: the error is reported without a retry
+        let (result, attempts) = submit(&[0xef, 0x01], "connection reset").await;
+        assert_eq!(attempts.len(), 1);
+        assert!(matches!(result, Err(PrivacyCashError::TransactionError(e)) if e == "connection reset"));
+
+        // Expired blockhashes are retried with fresh transactions
+        let (result, attempts) = submit(&[0xef, 0x01], "blockhash expired").await;
+        assert_eq!(attempts.len(), 3);
+        assert!(result.is_err());
+
+        // Unknown whether it landed: don't risk a second deposit
+        let (result, attempts) = submit(&[0x00], "blockhash expired").await;
+        assert_eq!(attempts.len(), 1);
+        assert!(matches!(result, Err(PrivacyCashError::TransactionError(e)) if e == "blockhash expired"));
+    }
 }
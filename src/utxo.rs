@@ -2,9 +2,10 @@
 //!
 //! Based on Tornado Cash Nova's UTXO model.
 
-use crate::constants::{FIELD_SIZE, SOL_MINT};
+use crate::constants::{FIELD_SIZE, NATIVE_SOL_MINT_STR};
 use crate::error::{PrivacyCashError, Result};
-use crate::keypair::ZkKeypair;
+use crate::keypair::{DomainTags, ZkKeypair};
+use borsh::{BorshDeserialize, BorshSerialize};
 use num_bigint::BigUint;
 use num_traits::Zero;
 use rand::Rng;
@@ -13,6 +14,19 @@ use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 
 /// UTXO version
+///
+/// The version determines how a note is encrypted and which keypair owns it.
+/// Both are derived from the same wallet signature, so a wallet can always
+/// spend notes of either version.
+///
+/// - `V1`: AES-128-CTR + HMAC encryption, owner key derived with SHA-256.
+///   Written by early deployments of Privacy Cash; wallets that used the
+///   protocol before the V2 upgrade may still hold V1 notes.
+/// - `V2`: AES-256-GCM encryption, owner key derived with Keccak-256. All new
+///   outputs created by this SDK are V2.
+///
+/// V1 notes remain spendable; `PrivacyCash::migrate_v1_to_v2` sweeps them
+/// into V2 notes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UtxoVersion {
     V1,
@@ -25,6 +39,32 @@ impl Default for UtxoVersion {
     }
 }
 
+/// Format tag of [`Utxo::to_bytes`] output without domain tags, still read
+const UTXO_BYTES_FORMAT_V1: u8 = 1;
+
+/// Format tag written at the start of [`Utxo::to_bytes`] output
+const UTXO_BYTES_FORMAT_V2: u8 = 2;
+
+/// Wire layout of format 1 (after the format tag)
+#[derive(BorshSerialize, BorshDeserialize)]
+struct UtxoBytesV1 {
+    amount: Vec<u8>,
+    blinding: Vec<u8>,
+    privkey: Vec<u8>,
+    index: u64,
+    mint_address: String,
+    version: u8,
+    key_index: Option<u32>,
+}
+
+/// Wire layout of format 2: format 1 followed by the key's domain tags
+#[derive(BorshSerialize, BorshDeserialize)]
+struct UtxoBytesV2 {
+    note: UtxoBytesV1,
+    /// Little-endian keypair, commitment, signature and nullifier tags
+    domain_tags: [Vec<u8>; 4],
+}
+
 /// UTXO (Unspent Transaction Output)
 #[derive(Clone)]
 pub struct Utxo {
@@ -45,6 +85,10 @@ pub struct Utxo {
 
     /// UTXO version
     pub version: UtxoVersion,
+
+    /// Sub-key index when the note is owned by a derived key (see
+    /// [`ZkKeypair::derive_child`]); `None` for the wallet's base key
+    pub key_index: Option<u32>,
 }
 
 impl std::fmt::Debug for Utxo {
@@ -54,6 +98,7 @@ impl std::fmt::Debug for Utxo {
             .field("index", &self.index)
             .field("mint_address", &self.mint_address)
             .field("version", &self.version)
+            .field("key_index", &self.key_index)
             .finish()
     }
 }
@@ -76,13 +121,19 @@ impl Utxo {
             keypair,
             index,
             mint_address: mint_address
-                .unwrap_or("11111111111111111111111111111112")
+                .unwrap_or(NATIVE_SOL_MINT_STR)
                 .to_string(),
             version: version.unwrap_or_default(),
+            key_index: None,
         }
     }
 
     /// Create a new UTXO with specific blinding factor
+    ///
+    /// Commitment and nullifier depend only on the fields, so the same
+    /// inputs always give the same note. Use this for test vectors or to
+    /// recompute a known note; real deposits should use [`Utxo::new`], since
+    /// a guessable blinding makes the commitment linkable.
     pub fn with_blinding(
         amount: impl Into<BigUint>,
         blinding: impl Into<BigUint>,
@@ -97,9 +148,10 @@ impl Utxo {
             keypair,
             index,
             mint_address: mint_address
-                .unwrap_or("11111111111111111111111111111112")
+                .unwrap_or(NATIVE_SOL_MINT_STR)
                 .to_string(),
             version: version.unwrap_or_default(),
+            key_index: None,
         }
     }
 
@@ -108,6 +160,17 @@ impl Utxo {
         Self::new(0u64, keypair, 0, mint_address, Some(UtxoVersion::V2))
     }
 
+    /// Hand the note to sub-key `key_index` of its current key
+    ///
+    /// `None` leaves the note with its current key.
+    pub fn with_key_index(mut self, key_index: Option<u32>) -> Result<Self> {
+        if let Some(index) = key_index {
+            self.keypair = self.keypair.derive_child(index)?;
+            self.key_index = Some(index);
+        }
+        Ok(self)
+    }
+
     /// Get the amount as u64
     pub fn amount_u64(&self) -> u64 {
         use num_traits::ToPrimitive;
@@ -121,59 +184,66 @@ impl Utxo {
 
     /// Calculate the commitment for this UTXO
     ///
-    /// commitment = Poseidon(amount, pubkey, blinding, mintAddressField)
+    /// commitment = Poseidon(amount, pubkey, blinding, mintAddressField),
+    /// under the keypair's commitment domain tag
     pub fn get_commitment(&self) -> Result<String> {
         let mint_field = self.get_mint_address_field()?;
 
-        ZkKeypair::poseidon_hash_strings(&[
-            &self.amount.to_string(),
-            &self.keypair.pubkey_string(),
-            &self.blinding.to_string(),
-            &mint_field,
-        ])
+        ZkKeypair::poseidon_hash_strings_with_domain(
+            &[
+                &self.amount.to_string(),
+                &self.keypair.pubkey_string(),
+                &self.blinding.to_string(),
+                &mint_field,
+            ],
+            &self.keypair.domain_tags().commitment,
+        )
     }
 
     /// Calculate the nullifier for this UTXO
     ///
     /// nullifier = Poseidon(commitment, index, signature)
-    /// where signature = keypair.sign(commitment, index)
+    /// where signature = keypair.sign(commitment, index), under the
+    /// keypair's nullifier domain tag
     pub fn get_nullifier(&self) -> Result<String> {
         let commitment = self.get_commitment()?;
         let index_str = self.index.to_string();
         let signature = self.keypair.sign(&commitment, &index_str)?;
 
-        ZkKeypair::poseidon_hash_strings(&[&commitment, &index_str, &signature])
+        ZkKeypair::poseidon_hash_strings_with_domain(
+            &[&commitment, &index_str, &signature],
+            &self.keypair.domain_tags().nullifier,
+        )
     }
 
     /// Get the mint address field for circuit computation
     ///
-    /// For SOL: returns the mint string as-is
-    /// For SPL: returns first 31 bytes of mint as BigUint
+    /// Same value as [`get_mint_address_field`](crate::utils::get_mint_address_field)
+    /// for the note's mint.
     fn get_mint_address_field(&self) -> Result<String> {
-        // Special case for SOL
-        if self.mint_address == "11111111111111111111111111111112" {
-            return Ok(self.mint_address.clone());
-        }
-
-        // For SPL tokens: use first 31 bytes
         let mint = Pubkey::from_str(&self.mint_address)
             .map_err(|e| PrivacyCashError::InvalidKeypair(format!("Invalid mint: {}", e)))?;
-
-        let mint_bytes = &mint.to_bytes()[..31];
-        let field_value = BigUint::from_bytes_be(mint_bytes);
-
-        Ok(field_value.to_string())
+        Ok(crate::utils::get_mint_address_field(&mint))
     }
 
     /// Serialize UTXO to a pipe-delimited string for encryption
+    ///
+    /// Notes owned by a sub-key carry the key index as a fifth field.
     pub fn serialize_for_encryption(&self) -> String {
-        format!(
+        let serialized = format!(
             "{}|{}|{}|{}",
             self.amount, self.blinding, self.index, self.mint_address
-        )
+        );
+        match self.key_index {
+            Some(index) => format!("{}|{}", serialized, index),
+            None => serialized,
+        }
     }
 
     /// Deserialize UTXO from a pipe-delimited string
+    ///
+    /// `keypair` is the wallet's base key; notes with a key index are
+    /// assigned the matching sub-key.
     pub fn deserialize_from_encryption(
         data: &str,
         keypair: ZkKeypair,
@@ -181,7 +251,7 @@ impl Utxo {
     ) -> Result<Self> {
         let parts: Vec<&str> = data.split('|').collect();
 
-        if parts.len() != 4 {
+        if parts.len() != 4 && parts.len() != 5 {
             return Err(PrivacyCashError::DecryptionError(
                 "Invalid UTXO format".to_string(),
             ));
@@ -199,13 +269,110 @@ impl Utxo {
 
         let mint_address = parts[3].to_string();
 
-        Ok(Self {
+        let key_index = match parts.get(4) {
+            Some(part) => Some(part.parse().map_err(|_| {
+                PrivacyCashError::DecryptionError("Invalid key index".to_string())
+            })?),
+            None => None,
+        };
+
+        Self {
             amount,
             blinding,
             keypair,
             index,
             mint_address,
             version,
+            key_index: None,
+        }
+        .with_key_index(key_index)
+    }
+
+    /// Serialize the full note, including its private key, for backup
+    ///
+    /// The output is a format tag followed by a Borsh payload. It contains
+    /// everything needed to spend the note, so store it like a private key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let note = UtxoBytesV1 {
+            amount: self.amount.to_bytes_le(),
+            blinding: self.blinding.to_bytes_le(),
+            privkey: self.keypair.privkey().to_bytes_le(),
+            index: self.index,
+            mint_address: self.mint_address.clone(),
+            version: match self.version {
+                UtxoVersion::V1 => 1,
+                UtxoVersion::V2 => 2,
+            },
+            key_index: self.key_index,
+        };
+        let tags = self.keypair.domain_tags();
+        let payload = UtxoBytesV2 {
+            note,
+            domain_tags: [
+                tags.keypair.to_bytes_le(),
+                tags.commitment.to_bytes_le(),
+                tags.signature.to_bytes_le(),
+                tags.nullifier.to_bytes_le(),
+            ],
+        };
+
+        let mut bytes = vec![UTXO_BYTES_FORMAT_V2];
+        // Writing into a Vec can't fail
+        bytes.extend(borsh::to_vec(&payload).expect("in-memory serialization"));
+        bytes
+    }
+
+    /// Restore a note written by [`to_bytes`](Self::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (format, payload) = bytes.split_first().ok_or_else(|| {
+            PrivacyCashError::SerializationError("Empty UTXO bytes".to_string())
+        })?;
+        let invalid =
+            |e: borsh::io::Error| PrivacyCashError::SerializationError(format!("Invalid UTXO bytes: {}", e));
+        let (payload, domain_tags) = match *format {
+            UTXO_BYTES_FORMAT_V1 => {
+                let note = UtxoBytesV1::try_from_slice(payload).map_err(invalid)?;
+                (note, DomainTags::default())
+            }
+            UTXO_BYTES_FORMAT_V2 => {
+                let UtxoBytesV2 { note, domain_tags } =
+                    UtxoBytesV2::try_from_slice(payload).map_err(invalid)?;
+                let [keypair, commitment, signature, nullifier] = domain_tags;
+                let domain_tags = DomainTags {
+                    keypair: BigUint::from_bytes_le(&keypair),
+                    commitment: BigUint::from_bytes_le(&commitment),
+                    signature: BigUint::from_bytes_le(&signature),
+                    nullifier: BigUint::from_bytes_le(&nullifier),
+                };
+                (note, domain_tags)
+            }
+            other => {
+                return Err(PrivacyCashError::SerializationError(format!(
+                    "Unsupported UTXO bytes format: {}",
+                    other
+                )))
+            }
+        };
+        let version = match payload.version {
+            1 => UtxoVersion::V1,
+            2 => UtxoVersion::V2,
+            other => {
+                return Err(PrivacyCashError::SerializationError(format!(
+                    "Unknown UTXO version: {}",
+                    other
+                )))
+            }
+        };
+        let privkey = BigUint::from_bytes_le(&payload.privkey);
+
+        Ok(Self {
+            amount: BigUint::from_bytes_le(&payload.amount),
+            blinding: BigUint::from_bytes_le(&payload.blinding),
+            keypair: ZkKeypair::from_bytes(&privkey.to_bytes_be())?.with_domain_tags(domain_tags)?,
+            index: payload.index,
+            mint_address: payload.mint_address,
+            version,
+            key_index: payload.key_index,
         })
     }
 
@@ -283,9 +450,55 @@ pub fn get_balance_from_utxos_spl(utxos: &[Utxo], units_per_token: u64) -> SplBa
     SplBalance::new(total, units_per_token)
 }
 
+/// Expected private balance after spending the first `spent` UTXOs
+///
+/// `change` is the change output of the transaction. The result is a local
+/// estimate: it holds once the transaction confirms and the change note is
+/// indexed.
+pub fn expected_balance_after_spend(unspent: &[Utxo], spent: usize, change: u64) -> u64 {
+    let untouched = unspent.get(spent..).unwrap_or_default();
+    change + get_balance_from_utxos(untouched).lamports
+}
+
+/// Check that caller-picked inputs can be spent by this wallet
+///
+/// Each note must be on `v1`, `v2` or the sub-key of `v2` named by its key
+/// index, hold `mint`, and appear only once. Whether the notes are still
+/// unspent is checked on-chain separately.
+pub(crate) fn ensure_own_inputs(notes: &[Utxo], v1: &ZkKeypair, v2: &ZkKeypair, mint: &str) -> Result<()> {
+    let mut nullifiers = std::collections::HashSet::with_capacity(notes.len());
+    for note in notes {
+        let owner = match note.key_index {
+            Some(index) => v2.derive_child(index)?,
+            None if note.version == UtxoVersion::V1 => v1.clone(),
+            None => v2.clone(),
+        };
+        if note.keypair.pubkey() != owner.pubkey() {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Input note {} doesn't belong to this wallet",
+                note.index
+            )));
+        }
+        if note.mint_address != mint {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Input note {} holds mint {}, expected {}",
+                note.index, note.mint_address, mint
+            )));
+        }
+        if !nullifiers.insert(note.get_nullifier()?) {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Input note {} is given twice",
+                note.index
+            )));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::SOL_MINT;
 
     #[test]
     fn test_utxo_creation() {
@@ -306,6 +519,86 @@ mod tests {
         assert_eq!(utxo.amount_u64(), 0);
     }
 
+    #[test]
+    fn test_sub_key_serialization_roundtrip() {
+        let base = ZkKeypair::generate().unwrap();
+        let utxo = Utxo::new(1000u64, base.clone(), 3, None, None)
+            .with_key_index(Some(7))
+            .unwrap();
+
+        let serialized = utxo.serialize_for_encryption();
+        assert!(serialized.ends_with("|7"));
+
+        let restored =
+            Utxo::deserialize_from_encryption(&serialized, base.clone(), UtxoVersion::V2).unwrap();
+        assert_eq!(restored.key_index, Some(7));
+        assert_eq!(restored.get_commitment().unwrap(), utxo.get_commitment().unwrap());
+        assert_ne!(restored.keypair.pubkey(), base.pubkey());
+
+        // Notes without a key index keep the four-field format
+        let plain = Utxo::new(1000u64, base.clone(), 3, None, None);
+        assert_eq!(plain.serialize_for_encryption().split('|').count(), 4);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let utxo = Utxo::new(
+            123_456u64,
+            keypair,
+            42,
+            Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+            Some(UtxoVersion::V1),
+        )
+        .with_key_index(Some(3))
+        .unwrap();
+
+        let restored = Utxo::from_bytes(&utxo.to_bytes()).unwrap();
+        assert_eq!(restored.get_commitment().unwrap(), utxo.get_commitment().unwrap());
+        assert_eq!(restored.get_nullifier().unwrap(), utxo.get_nullifier().unwrap());
+        assert_eq!(restored.version, UtxoVersion::V1);
+        assert_eq!(restored.key_index, Some(3));
+        assert_eq!(restored.mint_address, utxo.mint_address);
+
+        assert!(Utxo::from_bytes(&[]).is_err());
+        assert!(Utxo::from_bytes(&[9]).is_err());
+        assert!(Utxo::from_bytes(&utxo.to_bytes()[..10]).is_err());
+    }
+
+    #[test]
+    fn test_bytes_roundtrip_domain_tags() {
+        let tags = DomainTags {
+            keypair: BigUint::from(1u8),
+            commitment: BigUint::from(2u8),
+            signature: BigUint::from(3u8),
+            nullifier: BigUint::from(4u8),
+        };
+        let keypair = ZkKeypair::generate().unwrap().with_domain_tags(tags.clone()).unwrap();
+        let utxo = Utxo::new(123_456u64, keypair, 42, None, None);
+
+        let restored = Utxo::from_bytes(&utxo.to_bytes()).unwrap();
+        assert_eq!(restored.keypair.domain_tags(), &tags);
+        assert_eq!(restored.keypair.pubkey(), utxo.keypair.pubkey());
+        assert_eq!(restored.get_commitment().unwrap(), utxo.get_commitment().unwrap());
+        assert_eq!(restored.get_nullifier().unwrap(), utxo.get_nullifier().unwrap());
+
+        // Backups written before tags were stored restore with the default tags
+        let legacy = UtxoBytesV1 {
+            amount: utxo.amount.to_bytes_le(),
+            blinding: utxo.blinding.to_bytes_le(),
+            privkey: utxo.keypair.privkey().to_bytes_le(),
+            index: 42,
+            mint_address: utxo.mint_address.clone(),
+            version: 2,
+            key_index: None,
+        };
+        let mut bytes = vec![UTXO_BYTES_FORMAT_V1];
+        bytes.extend(borsh::to_vec(&legacy).unwrap());
+        let restored = Utxo::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.keypair.domain_tags(), &DomainTags::default());
+        assert_eq!(restored.amount, utxo.amount);
+    }
+
     #[test]
     fn test_commitment_calculation() {
         let keypair = ZkKeypair::generate().unwrap();
@@ -319,6 +612,30 @@ mod tests {
         assert_eq!(commitment, commitment2);
     }
 
+    #[test]
+    fn test_commitment_deterministic_with_blinding() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = |blinding: u64| {
+            Utxo::with_blinding(1000u64, blinding, keypair.clone(), 3, None, Some(UtxoVersion::V2))
+        };
+
+        assert_eq!(note(42).get_commitment().unwrap(), note(42).get_commitment().unwrap());
+        assert_eq!(note(42).get_nullifier().unwrap(), note(42).get_nullifier().unwrap());
+        assert_ne!(note(42).get_commitment().unwrap(), note(43).get_commitment().unwrap());
+    }
+
+    #[test]
+    fn test_expected_balance_after_spend() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let utxos: Vec<Utxo> = [500u64, 300, 200]
+            .iter()
+            .map(|&a| Utxo::new(a, keypair.clone(), 0, None, None))
+            .collect();
+
+        assert_eq!(expected_balance_after_spend(&utxos, 2, 100), 300);
+        assert_eq!(expected_balance_after_spend(&utxos[..1], 2, 40), 40);
+    }
+
     #[test]
     fn test_serialization() {
         let keypair = ZkKeypair::generate().unwrap();
@@ -333,4 +650,79 @@ mod tests {
         assert_eq!(utxo.index, deserialized.index);
         assert_eq!(utxo.mint_address, deserialized.mint_address);
     }
+
+    #[test]
+    fn test_ensure_own_inputs() {
+        let v1 = ZkKeypair::generate().unwrap();
+        let v2 = ZkKeypair::generate().unwrap();
+        let sol = NATIVE_SOL_MINT_STR;
+        let old = Utxo::new(10u64, v1.clone(), 1, None, Some(UtxoVersion::V1));
+        let change = Utxo::new(20u64, v2.clone(), 2, None, Some(UtxoVersion::V2))
+            .with_key_index(Some(3))
+            .unwrap();
+        ensure_own_inputs(&[old.clone(), change.clone()], &v1, &v2, sol).unwrap();
+
+        let foreign = Utxo::new(30u64, ZkKeypair::generate().unwrap(), 3, None, None);
+        assert!(ensure_own_inputs(&[old.clone(), foreign], &v1, &v2, sol).is_err());
+        // A V1 note on the V2 key isn't one the wallet created
+        let misfiled = Utxo::new(30u64, v2.clone(), 4, None, Some(UtxoVersion::V1));
+        assert!(ensure_own_inputs(&[misfiled], &v1, &v2, sol).is_err());
+        assert!(ensure_own_inputs(&[change.clone(), change], &v1, &v2, sol).is_err());
+        // Wrapped SOL notes are a different pool
+        assert!(ensure_own_inputs(&[old], &v1, &v2, &SOL_MINT.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_sol_note_mint_field() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = Utxo::new(1u64, keypair.clone(), 0, None, None);
+        assert_eq!(note.mint_address, NATIVE_SOL_MINT_STR);
+        assert_eq!(note.get_mint_address_field().unwrap(), "11111111111111111111111111111112");
+
+        // A note labelled with the wrapped SOL mint is in a different pool
+        // and commits to a different field
+        let wrapped_mint = SOL_MINT.to_string();
+        let wrapped =
+            Utxo::with_blinding(1u64, note.blinding.clone(), keypair, 0, Some(&wrapped_mint), None);
+        assert_ne!(wrapped.get_mint_address_field().unwrap(), NATIVE_SOL_MINT_STR);
+        assert_ne!(wrapped.get_commitment().unwrap(), note.get_commitment().unwrap());
+    }
+
+    #[test]
+    fn test_commitment_and_nullifier_pinned() {
+        let keypair = ZkKeypair::from_hex("0x1").unwrap();
+        let note = Utxo::with_blinding(1000u64, 12345u64, keypair.clone(), 3, None, None);
+        assert_eq!(
+            note.get_commitment().unwrap(),
+            "7184444811215116501090144094268518375109594839859746171892905847822799375342"
+        );
+        assert_eq!(
+            note.get_nullifier().unwrap(),
+            "3034443290125521563389194441848688266005114725938978243146894433286266792289"
+        );
+
+        // Each hash uses its own tag
+        let tags = crate::keypair::DomainTags {
+            keypair: BigUint::from(1u8),
+            commitment: BigUint::from(2u8),
+            signature: BigUint::from(3u8),
+            nullifier: BigUint::from(4u8),
+        };
+        let tagged = Utxo::with_blinding(
+            1000u64,
+            12345u64,
+            keypair.with_domain_tags(tags).unwrap(),
+            3,
+            None,
+            None,
+        );
+        assert_eq!(
+            tagged.get_commitment().unwrap(),
+            "14415526450732606837366389450121429670531045656620421318543539966934416376468"
+        );
+        assert_eq!(
+            tagged.get_nullifier().unwrap(),
+            "14529513354204390732867362822431179154676847012265603762896004645792673270312"
+        );
+    }
 }
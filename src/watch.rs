@@ -0,0 +1,179 @@
+//! Polling API for detecting incoming private payments
+//!
+//! Periodically re-runs the UTXO scan and yields notes that have not been
+//! reported before, so a merchant can react to payments arriving at their
+//! shielded account.
+
+use crate::constants::LSK_SEEN_COMMITMENTS;
+use crate::encryption::EncryptionService;
+use crate::error::Result;
+use crate::get_utxos::{get_utxos, localstorage_key};
+use crate::storage::Storage;
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+/// A newly observed private note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingNote {
+    /// Note commitment (decimal string)
+    pub commitment: String,
+
+    /// Amount in base units (lamports for SOL)
+    pub amount: u64,
+
+    /// Leaf index in the Merkle tree
+    pub index: u64,
+
+    /// Mint address of the note
+    pub mint_address: String,
+}
+
+/// Internal state carried between polls
+struct WatchState<'a> {
+    connection: &'a RpcClient,
+    public_key: Pubkey,
+    encryption_service: &'a EncryptionService,
+    storage: &'a Storage,
+    interval: Duration,
+    pending: VecDeque<IncomingNote>,
+    first_poll: bool,
+}
+
+/// Watch for incoming private notes
+///
+/// Every `interval` the UTXO scan is re-run (reusing the cached fetch offset,
+/// so only the relayer delta is downloaded) and any unspent note whose
+/// commitment hasn't been reported before is yielded. Reported commitments are
+/// persisted in `storage`, so a restarted watcher doesn't repeat itself.
+///
+/// Scan errors are logged and retried on the next tick; the stream never ends.
+pub fn watch_incoming<'a>(
+    connection: &'a RpcClient,
+    public_key: Pubkey,
+    encryption_service: &'a EncryptionService,
+    storage: &'a Storage,
+    interval: Duration,
+) -> impl Stream<Item = IncomingNote> + 'a {
+    let state = WatchState {
+        connection,
+        public_key,
+        encryption_service,
+        storage,
+        interval,
+        pending: VecDeque::new(),
+        first_poll: true,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(note) = state.pending.pop_front() {
+                return Some((note, state));
+            }
+
+            if !state.first_poll {
+                tokio::time::sleep(state.interval).await;
+            }
+            state.first_poll = false;
+
+            match poll_incoming(&state).await {
+                Ok(notes) => state.pending.extend(notes),
+                Err(e) => log::warn!("Incoming payment scan failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Run one scan and return the notes that haven't been reported yet
+async fn poll_incoming(state: &WatchState<'_>) -> Result<Vec<IncomingNote>> {
+    let utxos = get_utxos(
+        state.connection,
+        &state.public_key,
+        state.encryption_service,
+        state.storage,
+        None,
+    )
+    .await?;
+
+    let mut candidates = Vec::with_capacity(utxos.len());
+    for utxo in &utxos {
+        candidates.push(IncomingNote {
+            commitment: utxo.get_commitment()?,
+            amount: utxo.amount_u64(),
+            index: utxo.index,
+            mint_address: utxo.mint_address.clone(),
+        });
+    }
+
+    let storage_key = localstorage_key(&state.public_key);
+    Ok(take_unseen_notes(state.storage, &storage_key, candidates))
+}
+
+/// Filter out already-reported notes and remember the new ones
+fn take_unseen_notes(
+    storage: &Storage,
+    storage_key: &str,
+    candidates: Vec<IncomingNote>,
+) -> Vec<IncomingNote> {
+    let key = format!("{}{}", LSK_SEEN_COMMITMENTS, storage_key);
+
+    let mut seen: HashSet<String> = storage
+        .get(&key)
+        .and_then(|s| serde_json::from_str::<Vec<String>>(&s).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
+    let mut new_notes: Vec<IncomingNote> = candidates
+        .into_iter()
+        .filter(|note| seen.insert(note.commitment.clone()))
+        .collect();
+
+    if !new_notes.is_empty() {
+        let seen: Vec<_> = seen.into_iter().collect();
+        storage.set(&key, &serde_json::to_string(&seen).unwrap_or_default());
+    }
+
+    new_notes.sort_by_key(|note| note.index);
+    new_notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(commitment: &str, index: u64) -> IncomingNote {
+        IncomingNote {
+            commitment: commitment.to_string(),
+            amount: 1000,
+            index,
+            mint_address: crate::constants::NATIVE_SOL_MINT_STR.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_take_unseen_notes_deduplicates() {
+        let storage = Storage::memory();
+
+        let first = take_unseen_notes(&storage, "key", vec![note("2", 7), note("1", 3)]);
+        assert_eq!(first, vec![note("1", 3), note("2", 7)]);
+
+        let second = take_unseen_notes(&storage, "key", vec![note("1", 3), note("2", 7)]);
+        assert!(second.is_empty());
+
+        let third = take_unseen_notes(&storage, "key", vec![note("1", 3), note("3", 9)]);
+        assert_eq!(third, vec![note("3", 9)]);
+    }
+
+    #[test]
+    fn test_take_unseen_notes_scoped_per_key() {
+        let storage = Storage::memory();
+
+        take_unseen_notes(&storage, "alice", vec![note("1", 3)]);
+        let other = take_unseen_notes(&storage, "bob", vec![note("1", 3)]);
+        assert_eq!(other.len(), 1);
+    }
+}
@@ -2,22 +2,26 @@
 
 use crate::config::Config;
 use crate::constants::{
-    ALT_ADDRESS, FEE_RECIPIENT, LAMPORTS_PER_SOL, PROGRAM_ID,
-    RELAYER_API_URL, TRANSACT_IX_DISCRIMINATOR,
+    ALT_ADDRESS, FEE_RECIPIENT, LAMPORTS_PER_SOL, NATIVE_SOL_MINT, NATIVE_SOL_MINT_STR, PROGRAM_ID,
+    TRANSACT_IX_DISCRIMINATOR,
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::get_utxos;
-use crate::keypair::ZkKeypair;
-use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
-use crate::prover_rust::RustProver;
+use crate::get_utxos::{ensure_unspent, get_utxos};
+use crate::prover::{
+    input_nullifiers, output_commitments, parse_proof_to_bytes, CircuitInput, CircuitSpec, DryRunOutput,
+    Proof, PublicSignals,
+};
+use crate::prover_rust::{ProofSource, RustProver};
+use crate::relayer;
 use crate::storage::Storage;
-use crate::utxo::{Utxo, UtxoVersion};
+use crate::telemetry;
+use crate::utxo::{ensure_own_inputs, expected_balance_after_spend, Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, query_remote_tree_state,
-    ExtData,
+    calculate_public_amount, calculate_withdraw_fee_rounded, checked_total, ensure_balance_covers_fee,
+    ensure_output_amounts_in_range, ext_amount_for, fetch_input_merkle_paths,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_mint_address_field,
+    get_program_accounts, query_remote_tree_state, relayer_error, ExtData, FeeRounding,
 };
 use num_bigint::BigUint;
 use num_traits::Zero;
@@ -25,6 +29,8 @@ use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
 use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 /// Withdrawal result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +49,112 @@ pub struct WithdrawResult {
 
     /// Whether this was a partial withdrawal
     pub is_partial: bool,
+
+    /// Expected private balance once this withdrawal confirms (change plus
+    /// untouched UTXOs), computed locally without re-scanning
+    #[serde(default)]
+    pub expected_remaining_lamports: u64,
+
+    /// Set by a dry run, which stops after proving and submits nothing
+    ///
+    /// `signature` is empty in that case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunOutput>,
+
+    /// Signature of the separate partner fee transfer, if one was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partner_fee_signature: Option<String>,
+
+    /// Prover that produced the proof
+    #[serde(default)]
+    pub proof_source: ProofSource,
+}
+
+impl WithdrawResult {
+    /// Whether the withdrawal was actually submitted (i.e. not a dry run)
+    pub fn is_submitted(&self) -> bool {
+        self.dry_run.is_none()
+    }
+}
+
+/// A withdrawal built up to, but not including, the proof
+///
+/// Returned by [`prepare_withdraw`] so the proof can be generated on another
+/// (e.g. air-gapped) machine: prove `circuit_input` there with
+/// [`RustProver::prove`], then hand the context and proof to
+/// [`submit_withdraw`] on the online machine.
+///
+/// **WARNING**: `circuit_input` holds the input notes' private keys and
+/// blindings, and the output notes' blindings. Anyone holding a context can
+/// spend the input notes. Move it only between your own machines, never
+/// log it, and delete every copy once the withdrawal has been submitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawContext {
+    /// Everything the prover needs, including secrets
+    pub circuit_input: CircuitInput,
+
+    /// Wallet that prepared the withdrawal
+    pub sender: String,
+
+    /// Recipient address
+    pub recipient: String,
+
+    /// Referrer passed to the relayer
+    pub referrer: Option<String>,
+
+    /// Amount withdrawn (after fees)
+    pub amount_in_lamports: u64,
+
+    /// Fee charged
+    pub fee_in_lamports: u64,
+
+    /// Whether this is a partial withdrawal
+    pub is_partial: bool,
+
+    /// Expected private balance once this withdrawal confirms
+    pub expected_remaining_lamports: u64,
+
+    /// Signed amount leaving the pool
+    pub ext_amount: i64,
+
+    /// Encrypted change note
+    pub encrypted_output1: Vec<u8>,
+
+    /// Encrypted zero-value note
+    pub encrypted_output2: Vec<u8>,
+
+    /// Relayer the withdrawal is submitted to; `None` is `RELAYER_API_URL`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relayer_url: Option<String>,
+}
+
+impl WithdrawContext {
+    /// External data the proof commits to
+    fn ext_data(&self) -> Result<ExtData> {
+        let recipient = Pubkey::from_str(&self.recipient)
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid recipient: {}", e)))?;
+        Ok(ExtData {
+            recipient,
+            ext_amount: self.ext_amount,
+            encrypted_output1: self.encrypted_output1.clone(),
+            encrypted_output2: self.encrypted_output2.clone(),
+            fee: self.fee_in_lamports,
+            fee_recipient: *FEE_RECIPIENT,
+            mint_address: *NATIVE_SOL_MINT,
+        })
+    }
+
+    /// [`ext_data`](Self::ext_data), failing with `InvalidInput` if the
+    /// context was changed so that it no longer matches the circuit input
+    fn checked_ext_data(&self) -> Result<ExtData> {
+        let ext_data = self.ext_data()?;
+        if ext_data.hash().as_slice() != self.circuit_input.ext_data_hash.as_slice() {
+            return Err(PrivacyCashError::InvalidInput(
+                "Withdraw context doesn't match its circuit input".to_string(),
+            ));
+        }
+        Ok(ext_data)
+    }
 }
 
 /// Parameters for withdrawal
@@ -55,10 +167,71 @@ pub struct WithdrawParams<'a> {
     pub recipient: &'a Pubkey,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+
+    /// Sub-key for the change note, `None` for the base key (see [`PrivacyCash::set_change_key_index`](crate::PrivacyCash::set_change_key_index))
+    pub change_key_index: Option<u32>,
+
+    /// Spend these notes instead of scanning for the wallet's unspent ones
+    ///
+    /// Exactly these notes are spent, in the given order; the expected
+    /// remaining balance then only counts the change. At most as many notes
+    /// as the circuit spends (two) may be given. The notes must be the
+    /// wallet's own, of the withdrawn token, distinct and unspent on-chain;
+    /// anything else fails with `InvalidInput` before a proof is generated.
+    pub input_utxos: Option<Vec<Utxo>>,
+
+    /// Stop after generating and locally verifying the proof, returning it in
+    /// [`WithdrawResult::dry_run`] without submitting anything
+    pub dry_run: bool,
+
+    /// Relayer config to charge the fee by; `None` uses the cached config
+    /// of `relayer_url`
+    pub config: Option<&'a Config>,
+
+    /// Relayer to submit the withdrawal to; `None` uses `RELAYER_API_URL`
+    pub relayer_url: Option<&'a str>,
+
+    /// How the fee is rounded to whole base units
+    pub fee_rounding: FeeRounding,
+
+    /// Stop scanning and proving once this is set to `true` (see
+    /// [`RustProver::with_abort_signal`]), failing with `Aborted`
+    pub abort_signal: Option<Arc<Mutex<bool>>>,
 }
 
 /// Execute a withdrawal
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "withdraw",
+        skip_all,
+        fields(token = "sol", amount = params.amount_in_lamports, signature = tracing::field::Empty)
+    )
+)]
 pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
+    let key_base_path = params.key_base_path;
+    let dry_run = params.dry_run;
+    let abort_signal = params.abort_signal.clone();
+    let context = prepare_withdraw(params).await?;
+
+    // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
+    telemetry::phase!("prove");
+    log::info!("Generating ZK proof using pure Rust prover...");
+    #[cfg(feature = "debug-export")]
+    crate::prover::export_circuit_input_if_enabled(&context.circuit_input, "withdraw");
+    let prover = RustProver::new(key_base_path).with_abort_signal(abort_signal);
+    let (proof, public_signals, proof_source) =
+        prover.prove_with_source(&context.circuit_input).await?;
+
+    finish_withdraw(context, &proof, public_signals, proof_source, dry_run).await
+}
+
+/// Build a withdrawal up to the proof (see [`WithdrawContext`])
+///
+/// Selects the inputs, fetches their Merkle paths and creates the encrypted
+/// outputs, exactly as [`withdraw`] does before proving. `key_base_path` and
+/// `dry_run` are not used.
+pub async fn prepare_withdraw(params: WithdrawParams<'_>) -> Result<WithdrawContext> {
     let WithdrawParams {
         connection,
         keypair,
@@ -66,19 +239,34 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         storage,
         mut amount_in_lamports,
         recipient,
-        key_base_path,
+        key_base_path: _,
         referrer,
+        change_key_index,
+        input_utxos,
+        dry_run: _,
+        config,
+        relayer_url,
+        fee_rounding,
+        abort_signal,
     } = params;
 
     let public_key = keypair.pubkey();
 
     // Get fee configuration
-    let withdraw_fee_rate = Config::get_withdraw_fee_rate().await?;
-    let withdraw_rent_fee = Config::get_withdraw_rent_fee().await?;
+    let config = match config {
+        Some(config) => config.clone(),
+        None => Config::get_from(relayer::base_url(relayer_url)).await?,
+    };
+    let withdraw_fee_rate = config.withdraw_fee_rate;
+    let withdraw_rent_fee = config.withdraw_rent_fee;
 
-    let fee_in_lamports =
-        (amount_in_lamports as f64 * withdraw_fee_rate + LAMPORTS_PER_SOL as f64 * withdraw_rent_fee)
-            as u64;
+    let fee_in_lamports = calculate_withdraw_fee_rounded(
+        amount_in_lamports,
+        withdraw_fee_rate,
+        withdraw_rent_fee,
+        LAMPORTS_PER_SOL,
+        fee_rounding,
+    )?;
 
     // Note: We do NOT subtract fee from amount here.
     // The user requests X lamports to withdraw, and the fee is taken from their balance.
@@ -92,45 +280,47 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         fee_in_lamports
     );
 
-    let (tree_account, tree_token_account, global_config_account) = get_program_accounts();
-
     // Get tree state
     let tree_state = query_remote_tree_state(None).await?;
 
     // Get UTXO keypairs
-    let utxo_private_key_v1 = encryption_service.get_utxo_private_key_v1()?;
-    let utxo_keypair_v1 = ZkKeypair::from_hex(&utxo_private_key_v1)?;
-
-    let utxo_private_key_v2 = encryption_service.get_utxo_private_key_v2()?;
-    let utxo_keypair_v2 = ZkKeypair::from_hex(&utxo_private_key_v2)?;
-
-    // Fetch existing UTXOs
-    let mut unspent_utxos =
-        get_utxos(connection, &public_key, encryption_service, storage, None).await?;
+    let utxo_keypair_v1 = encryption_service.utxo_keypair(UtxoVersion::V1)?;
+    let utxo_keypair_v2 = encryption_service.utxo_keypair(UtxoVersion::V2)?;
+
+    // Fetch existing UTXOs, unless the caller picked the inputs
+    let circuit = CircuitSpec::default();
+    let unspent_utxos = match input_utxos {
+        Some(utxos) => {
+            // Spent as given, in the caller's order
+            circuit.ensure_input_count(utxos.len())?;
+            ensure_own_inputs(&utxos, &utxo_keypair_v1, &utxo_keypair_v2, NATIVE_SOL_MINT_STR)?;
+            ensure_unspent(connection, &utxos).await?;
+            utxos
+        }
+        None => {
+            let mut utxos =
+                get_utxos(connection, &public_key, encryption_service, storage, abort_signal.clone())
+                    .await?;
+            // Spend the largest notes, as many as the circuit takes
+            utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
+            utxos
+        }
+    };
 
     if unspent_utxos.is_empty() {
         return Err(PrivacyCashError::NoUtxosAvailable);
     }
 
-    // Sort by amount descending
-    unspent_utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
-
-    let first_input = unspent_utxos[0].clone();
-    let second_input = if unspent_utxos.len() > 1 {
-        unspent_utxos[1].clone()
-    } else {
-        Utxo::dummy(utxo_keypair_v1.clone(), None)
-    };
-
-    let inputs = vec![first_input.clone(), second_input.clone()];
-    let total_input_amount = first_input.amount.clone() + second_input.amount.clone();
+    let spent = unspent_utxos.iter().take(circuit.inputs).cloned().collect();
+    let inputs = circuit.pad_inputs(spent, &utxo_keypair_v1, None)?;
+    let total_input_amount = inputs.iter().fold(BigUint::zero(), |total, u| total + &u.amount);
 
     if total_input_amount.is_zero() {
         return Err(PrivacyCashError::NoUtxosAvailable);
     }
 
     // Check if partial withdrawal
-    let required = BigUint::from(amount_in_lamports + fee_in_lamports);
+    let required = BigUint::from(checked_total(amount_in_lamports, fee_in_lamports)?);
     if total_input_amount < required {
         is_partial = true;
         // In partial withdrawal, we withdraw everything minus the fee
@@ -141,12 +331,7 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
             .unwrap_or(0);
         
         // If balance is less than fee, we can't withdraw anything
-        if total_as_u64 <= fee_in_lamports {
-            return Err(PrivacyCashError::InsufficientBalance {
-                have: total_as_u64,
-                need: fee_in_lamports,
-            });
-        }
+        ensure_balance_covers_fee(total_as_u64, fee_in_lamports)?;
         
         amount_in_lamports = total_as_u64.saturating_sub(fee_in_lamports);
     }
@@ -163,21 +348,11 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         change_amount
     );
 
+    let expected_remaining_lamports =
+        expected_balance_after_spend(&unspent_utxos, circuit.inputs, change_amount.to_u64_safe());
+
     // Fetch Merkle proofs
-    let input_merkle_paths = vec![
-        if first_input.is_dummy() {
-            MerkleTree::zero_path()
-        } else {
-            let commitment = first_input.get_commitment()?;
-            fetch_merkle_proof(&commitment, None).await?
-        },
-        if second_input.is_dummy() {
-            MerkleTree::zero_path()
-        } else {
-            let commitment = second_input.get_commitment()?;
-            fetch_merkle_proof(&commitment, None).await?
-        },
-    ];
+    let input_merkle_paths = fetch_input_merkle_paths(&inputs, None).await?;
 
     // Create outputs with V2 keypair
     let outputs = vec![
@@ -187,7 +362,8 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
             tree_state.next_index,
             None,
             Some(UtxoVersion::V2),
-        ),
+        )
+        .with_key_index(change_key_index)?,
         Utxo::new(
             0u64,
             utxo_keypair_v2.clone(),
@@ -196,22 +372,21 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
             Some(UtxoVersion::V2),
         ),
     ];
+    ensure_output_amounts_in_range(&outputs)?;
 
     // For withdrawal, ext_amount is negative
-    let ext_amount = -(amount_in_lamports as i64);
+    let ext_amount = ext_amount_for(amount_in_lamports, false)?;
     let public_amount = calculate_public_amount(ext_amount, fee_in_lamports);
 
     // Generate nullifiers and commitments
-    let input_nullifiers = vec![inputs[0].get_nullifier()?, inputs[1].get_nullifier()?];
-    let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
+    let input_nullifiers = input_nullifiers(&inputs)?;
+    let output_commitments = output_commitments(&outputs)?;
 
     // Encrypt outputs
     let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
     let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
 
     // Create ExtData
-    let sol_mint = Pubkey::from_str("11111111111111111111111111111112").unwrap();
-
     let ext_data = ExtData {
         recipient: *recipient,
         ext_amount,
@@ -219,7 +394,7 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         encrypted_output2: encrypted_output2.clone(),
         fee: fee_in_lamports,
         fee_recipient: *FEE_RECIPIENT,
-        mint_address: sol_mint,
+        mint_address: *NATIVE_SOL_MINT,
     };
 
     let ext_data_hash = ext_data.hash();
@@ -245,26 +420,127 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         out_blinding: outputs.iter().map(|u| u.blinding.to_string()).collect(),
         out_pubkey: outputs.iter().map(|u| u.keypair.pubkey().clone()).collect(),
 
-        mint_address: get_mint_address_field(&sol_mint),
+        mint_address: get_mint_address_field(&NATIVE_SOL_MINT),
     };
+    circuit.check_input(&circuit_input)?;
 
-    // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
-    log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    Ok(WithdrawContext {
+        circuit_input,
+        sender: public_key.to_string(),
+        recipient: recipient.to_string(),
+        referrer: referrer.map(str::to_string),
+        amount_in_lamports,
+        fee_in_lamports,
+        is_partial,
+        expected_remaining_lamports,
+        ext_amount,
+        encrypted_output1,
+        encrypted_output2,
+        relayer_url: relayer_url.map(str::to_string),
+    })
+}
+
+/// Submit a withdrawal proved elsewhere
+///
+/// `proof` must be a proof of `context.circuit_input`, e.g. from
+/// [`RustProver::prove`] on an offline machine. Its public signals are
+/// recomputed from the context and the proof is checked against them with
+/// the verifying key under `key_base_path` before anything is sent. Fails
+/// with `ProofGenerationError` if it doesn't verify, and with `InvalidInput`
+/// if the context's external data doesn't match the hash the proof commits
+/// to.
+pub async fn submit_withdraw(
+    context: WithdrawContext,
+    proof: &Proof,
+    key_base_path: &str,
+) -> Result<WithdrawResult> {
+    let public_signals = verify_external_proof(&context, proof, key_base_path).await?;
+    submit_verified_withdraw(context, proof, public_signals).await
+}
+
+/// Check `proof` against `context` as [`submit_withdraw`] does, returning
+/// the public signals it verified against
+pub(crate) async fn verify_external_proof(
+    context: &WithdrawContext,
+    proof: &Proof,
+    key_base_path: &str,
+) -> Result<Vec<String>> {
+    context.checked_ext_data()?;
+    let public_signals = context.circuit_input.public_signals();
+
+    let verifier = RustProver::new(key_base_path);
+    let task_proof = proof.clone();
+    let task_signals = public_signals.clone();
+    tokio::task::spawn_blocking(move || verifier.verify(&task_proof, &task_signals))
+        .await
+        .map_err(|e| PrivacyCashError::ProofGenerationError(format!("Proof verification panicked: {}", e)))??;
+    Ok(public_signals)
+}
+
+/// Submit a withdrawal whose proof [`verify_external_proof`] accepted
+pub(crate) async fn submit_verified_withdraw(
+    context: WithdrawContext,
+    proof: &Proof,
+    public_signals: Vec<String>,
+) -> Result<WithdrawResult> {
+    finish_withdraw(context, proof, public_signals, ProofSource::External, false).await
+}
+
+/// Serialize a proved withdrawal and submit it, unless `dry_run`
+async fn finish_withdraw(
+    context: WithdrawContext,
+    proof: &Proof,
+    public_signals: Vec<String>,
+    proof_source: ProofSource,
+    dry_run: bool,
+) -> Result<WithdrawResult> {
+    let ext_data = context.checked_ext_data()?;
+    let WithdrawContext {
+        circuit_input,
+        sender,
+        recipient,
+        referrer,
+        amount_in_lamports,
+        fee_in_lamports,
+        is_partial,
+        expected_remaining_lamports,
+        ext_amount,
+        encrypted_output1,
+        encrypted_output2,
+        relayer_url,
+    } = context;
+    let relayer_url = relayer::base_url(relayer_url.as_deref());
+    let (tree_account, tree_token_account, global_config_account) = get_program_accounts();
 
     // Parse proof to bytes
-    let proof_bytes = parse_proof_to_bytes(&proof)?;
-    let signals_bytes = parse_public_signals_to_bytes(&public_signals)?;
+    let proof_bytes = parse_proof_to_bytes(proof)?;
+    let signals = PublicSignals::parse(&public_signals)?;
 
     // Find nullifier PDAs
     let (nullifier0_pda, nullifier1_pda) =
-        find_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
+        find_nullifier_pdas(&signals.nullifiers());
     let (nullifier2_pda, nullifier3_pda) =
-        find_cross_check_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
+        find_cross_check_nullifier_pdas(&signals.nullifiers());
 
     // Serialize proof
-    let serialized_proof = serialize_withdraw_proof(&proof_bytes, &signals_bytes, &ext_data);
+    let serialized_proof = serialize_withdraw_proof(&proof_bytes, &signals, &ext_data)?;
+    #[cfg(feature = "audit")]
+    crate::audit::emit("withdraw", dry_run, &serialized_proof);
+
+    if dry_run {
+        log::info!("Dry run: proof generated, not submitting withdrawal");
+        return Ok(WithdrawResult {
+            signature: String::new(),
+            recipient,
+            amount_in_lamports,
+            fee_in_lamports,
+            is_partial,
+            expected_remaining_lamports,
+            dry_run: Some(DryRunOutput::new(&proof_bytes, public_signals, serialized_proof)),
+            partner_fee_signature: None,
+            proof_source,
+        });
+    }
 
     // Build withdraw parameters for backend
     use base64::Engine;
@@ -279,52 +555,55 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         "nullifier3PDA": nullifier3_pda.to_string(),
         "treeTokenAccount": tree_token_account.to_string(),
         "globalConfigAccount": global_config_account.to_string(),
-        "recipient": recipient.to_string(),
+        "recipient": recipient,
         "feeRecipientAccount": FEE_RECIPIENT.to_string(),
         "extAmount": ext_amount,
         "encryptedOutput1": b64.encode(&encrypted_output1),
         "encryptedOutput2": b64.encode(&encrypted_output2),
         "fee": fee_in_lamports,
         "lookupTableAddress": ALT_ADDRESS.to_string(),
-        "senderAddress": public_key.to_string(),
+        "senderAddress": sender,
         "referralWalletAddress": referrer
     });
     
     log::debug!("Withdraw params: {:?}", withdraw_params);
 
     // Submit to backend
+    telemetry::phase!("submit");
     log::info!("Submitting withdrawal to relayer...");
-    let signature = submit_withdraw_to_indexer(withdraw_params).await?;
+    let signature = submit_withdraw_to_indexer(withdraw_params, relayer_url).await?;
+
+    telemetry::record_signature!("signature", signature);
 
     // Wait for confirmation
+    telemetry::phase!("confirm");
     log::info!("Waiting for confirmation...");
-    wait_for_confirmation(&encrypted_output1, None).await?;
+    wait_for_confirmation(&encrypted_output1, None, relayer_url).await?;
 
     Ok(WithdrawResult {
         signature,
-        recipient: recipient.to_string(),
+        recipient,
         amount_in_lamports,
         fee_in_lamports,
         is_partial,
+        expected_remaining_lamports,
+        dry_run: None,
+        partner_fee_signature: None,
+        proof_source,
     })
 }
 
 /// Submit withdrawal to indexer backend
-async fn submit_withdraw_to_indexer(params: serde_json::Value) -> Result<String> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/withdraw", *RELAYER_API_URL))
-        .json(&params)
-        .send()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Withdraw submit failed: {}", e)))?;
+async fn submit_withdraw_to_indexer(params: serde_json::Value, relayer_url: &str) -> Result<String> {
+    let response = relayer::send(
+        relayer::post(&format!("{}/withdraw", relayer_url)).json(&params),
+        "Withdraw submit failed",
+    )
+    .await?;
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        return Err(PrivacyCashError::ApiError(format!(
-            "Withdraw failed: {}",
-            error_text
-        )));
+        return Err(relayer_error("Withdraw failed", &error_text));
     }
 
     #[derive(Deserialize)]
@@ -341,7 +620,11 @@ async fn submit_withdraw_to_indexer(params: serde_json::Value) -> Result<String>
 }
 
 /// Wait for transaction confirmation
-async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>) -> Result<()> {
+async fn wait_for_confirmation(
+    encrypted_output: &[u8],
+    token_name: Option<&str>,
+    relayer_url: &str,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
@@ -349,12 +632,12 @@ async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>
     loop {
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
-        let mut url = format!("{}/utxos/check/{}", *RELAYER_API_URL, encrypted_hex);
+        let mut url = format!("{}/utxos/check/{}", relayer_url, encrypted_hex);
         if let Some(token) = token_name {
             url = format!("{}?token={}", url, token);
         }
 
-        let response = reqwest::get(&url).await;
+        let response = relayer::send(relayer::get(&url), "Confirmation check").await;
 
         if let Ok(resp) = response {
             if let Ok(data) = resp.json::<serde_json::Value>().await {
@@ -376,9 +659,11 @@ async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>
 /// Serialize withdrawal proof
 fn serialize_withdraw_proof(
     proof_bytes: &crate::prover::ProofBytes,
-    signals: &[[u8; 32]],
+    signals: &PublicSignals,
     ext_data: &ExtData,
-) -> Vec<u8> {
+) -> Result<Vec<u8>> {
+    proof_bytes.validate()?;
+
     let mut data = Vec::new();
 
     // Discriminator
@@ -390,9 +675,7 @@ fn serialize_withdraw_proof(
     data.extend_from_slice(&proof_bytes.proof_c);
 
     // Public signals
-    for signal in signals.iter().take(7) {
-        data.extend_from_slice(signal);
-    }
+    data.extend_from_slice(&signals.to_bytes());
 
     // ExtData (minified)
     data.extend_from_slice(&ext_data.ext_amount.to_le_bytes());
@@ -404,7 +687,7 @@ fn serialize_withdraw_proof(
     data.extend_from_slice(&(ext_data.encrypted_output2.len() as u32).to_le_bytes());
     data.extend_from_slice(&ext_data.encrypted_output2);
 
-    data
+    Ok(data)
 }
 
 // Re-export BigUint conversion for withdraw
@@ -418,3 +701,111 @@ impl BigUintExt for BigUint {
         self.to_u64().unwrap_or(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prover::{ProofBytes, PROOF_A_LEN, PROOF_B_LEN, PROOF_C_LEN, TRANSACT_IX_FIXED_LEN};
+
+    #[test]
+    fn test_serialized_proof_length() {
+        let mut proof = ProofBytes {
+            proof_a: vec![1; PROOF_A_LEN],
+            proof_b: vec![2; PROOF_B_LEN],
+            proof_c: vec![3; PROOF_C_LEN],
+        };
+        let signals = PublicSignals::from_bytes(&[[4; 32]; 7]).unwrap();
+        let ext_data = ExtData {
+            recipient: Pubkey::new_unique(),
+            ext_amount: -1_000,
+            encrypted_output1: vec![5; 90],
+            encrypted_output2: vec![6; 100],
+            fee: 10,
+            fee_recipient: Pubkey::new_unique(),
+            mint_address: Pubkey::new_unique(),
+        };
+
+        let data = serialize_withdraw_proof(&proof, &signals, &ext_data).unwrap();
+        assert_eq!(TRANSACT_IX_FIXED_LEN, 504);
+        assert_eq!(data.len(), TRANSACT_IX_FIXED_LEN + 4 + 90 + 4 + 100);
+
+        // A truncated point B would shift every field after it
+        proof.proof_b.pop();
+        assert!(matches!(
+            serialize_withdraw_proof(&proof, &signals, &ext_data),
+            Err(PrivacyCashError::SerializationError(_))
+        ));
+    }
+
+    /// Context for a zero-value transaction, consistent with its ext data
+    fn test_context() -> WithdrawContext {
+        let mut context = WithdrawContext {
+            circuit_input: CircuitInput::dummy().unwrap(),
+            sender: Pubkey::new_unique().to_string(),
+            recipient: Pubkey::new_unique().to_string(),
+            referrer: None,
+            amount_in_lamports: 1_000,
+            fee_in_lamports: 10,
+            is_partial: false,
+            expected_remaining_lamports: 0,
+            ext_amount: -1_000,
+            encrypted_output1: vec![1; 90],
+            encrypted_output2: vec![2; 90],
+            relayer_url: None,
+        };
+        context.circuit_input.ext_data_hash = context.ext_data().unwrap().hash().to_vec();
+        context
+    }
+
+    #[test]
+    fn test_withdraw_context_round_trip() {
+        let context = test_context();
+        let json = serde_json::to_string(&context).unwrap();
+        let restored: WithdrawContext = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.circuit_input.public_signals(), context.circuit_input.public_signals());
+        assert_eq!(restored.circuit_input.in_private_key, context.circuit_input.in_private_key);
+        assert_eq!(restored.circuit_input.out_blinding, context.circuit_input.out_blinding);
+        assert_eq!(restored.ext_data().unwrap().hash(), context.ext_data().unwrap().hash());
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejects_changed_context() {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let proof = Proof {
+            pi_a: strings(&["1", "2", "1"]),
+            pi_b: vec![strings(&["1", "2"]), strings(&["3", "4"]), strings(&["1", "0"])],
+            pi_c: strings(&["1", "2", "1"]),
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+        };
+
+        // A different recipient no longer matches the proved ext data hash
+        let mut context = test_context();
+        context.recipient = Pubkey::new_unique().to_string();
+        assert!(matches!(
+            submit_withdraw(context, &proof, "./circuit/transaction2").await,
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_submit_verifies_proof() {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let proof = Proof {
+            pi_a: strings(&["1", "2", "1"]),
+            pi_b: vec![strings(&["1", "2"]), strings(&["3", "4"]), strings(&["1", "0"])],
+            pi_c: strings(&["1", "2", "1"]),
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+        };
+
+        // Nothing is sent without a verifying key to check the proof against
+        let dir = std::env::temp_dir().join(format!("privacy-cash-no-keys-{}", std::process::id()));
+        let key_base_path = dir.join("transaction2");
+        assert!(matches!(
+            submit_withdraw(test_context(), &proof, key_base_path.to_str().unwrap()).await,
+            Err(PrivacyCashError::CircuitNotFound(_))
+        ));
+    }
+}
@@ -1,23 +1,27 @@
 //! Withdrawal functionality for SPL tokens
 
 use crate::config::Config;
-use crate::constants::{
-    find_token_by_mint, ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, RELAYER_API_URL,
-    TRANSACT_SPL_IX_DISCRIMINATOR,
-};
+use crate::constants::{ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, TRANSACT_SPL_IX_DISCRIMINATOR};
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::ensure_unspent;
 use crate::get_utxos_spl::get_utxos_spl;
-use crate::keypair::ZkKeypair;
-use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
-use crate::prover_rust::RustProver;
+use crate::prover::{
+    input_nullifiers, output_commitments, parse_proof_to_bytes, CircuitInput, CircuitSpec, DryRunOutput,
+    PublicSignals,
+};
+use crate::prover_rust::{ProofSource, RustProver};
+use crate::relayer;
 use crate::storage::Storage;
-use crate::utxo::{Utxo, UtxoVersion};
+use crate::telemetry;
+use crate::token_registry::TokenMetadata;
+use crate::utxo::{ensure_own_inputs, expected_balance_after_spend, Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, get_spl_tree_account,
-    query_remote_tree_state, ExtData,
+    calculate_public_amount, calculate_withdraw_fee_rounded, checked_total, ensure_output_amounts_in_range,
+    ensure_spl_pool_initialized, ext_amount_for, fetch_input_merkle_paths,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_mint_address_field,
+    get_program_accounts, get_spl_tree_account, query_remote_tree_state, relayer_error,
+    token_account_owner, ExtData, FeeRounding,
 };
 use num_bigint::BigUint;
 use num_traits::{ToPrimitive, Zero};
@@ -25,6 +29,8 @@ use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
 use spl_associated_token_account::get_associated_token_address;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 /// SPL Withdrawal result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +40,30 @@ pub struct WithdrawSplResult {
     pub base_units: u64,
     pub fee_base_units: u64,
     pub is_partial: bool,
+    /// Expected private balance once this withdrawal confirms (change plus
+    /// untouched UTXOs), computed locally without re-scanning
+    #[serde(default)]
+    pub expected_remaining_base_units: u64,
+    /// Signature of the separate partner fee transfer, if one was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partner_fee_signature: Option<String>,
+
+    /// Set by a dry run, which stops after proving and submits nothing
+    ///
+    /// `signature` is empty in that case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunOutput>,
+
+    /// Prover that produced the proof
+    #[serde(default)]
+    pub proof_source: ProofSource,
+}
+
+impl WithdrawSplResult {
+    /// Whether the withdrawal was actually submitted (i.e. not a dry run)
+    pub fn is_submitted(&self) -> bool {
+        self.dry_run.is_none()
+    }
 }
 
 /// Parameters for SPL withdrawal
@@ -43,13 +73,63 @@ pub struct WithdrawSplParams<'a> {
     pub encryption_service: &'a EncryptionService,
     pub storage: &'a Storage,
     pub base_units: u64,
-    pub mint_address: &'a Pubkey,
+    /// Token to withdraw, as resolved by [`PrivacyCash::token_metadata`](crate::PrivacyCash::token_metadata)
+    pub token: &'a TokenMetadata,
     pub recipient: &'a Pubkey,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+
+    /// Sub-key for the change note, `None` for the base key (see [`PrivacyCash::set_change_key_index`](crate::PrivacyCash::set_change_key_index))
+    pub change_key_index: Option<u32>,
+
+    /// Spend these notes instead of scanning for the wallet's unspent ones
+    ///
+    /// Exactly these notes are spent, in the given order; the expected
+    /// remaining balance then only counts the change. At most as many notes
+    /// as the circuit spends (two) may be given. The notes must be the
+    /// wallet's own, of the withdrawn token, distinct and unspent on-chain;
+    /// anything else fails with `InvalidInput` before a proof is generated.
+    pub input_utxos: Option<Vec<Utxo>>,
+
+    /// Token account to pay out to instead of `recipient`'s ATA
+    ///
+    /// For recipients such as exchanges that use non-associated token
+    /// accounts. Must be a token account for `mint_address` owned by
+    /// `recipient`; checked before any proof is generated.
+    pub recipient_token_account: Option<&'a Pubkey>,
+
+    /// Relayer config to charge the fee by; `None` uses the cached config
+    /// of `relayer_url`
+    pub config: Option<&'a Config>,
+
+    /// Relayer to submit the withdrawal to; `None` uses `RELAYER_API_URL`
+    pub relayer_url: Option<&'a str>,
+
+    /// How the fee is rounded to whole base units
+    pub fee_rounding: FeeRounding,
+
+    /// Stop scanning and proving once this is set to `true` (see
+    /// [`RustProver::with_abort_signal`]), failing with `Aborted`
+    pub abort_signal: Option<Arc<Mutex<bool>>>,
+
+    /// Stop after generating and locally verifying the proof, returning it in
+    /// [`WithdrawSplResult::dry_run`] without submitting anything
+    pub dry_run: bool,
 }
 
 /// Execute an SPL token withdrawal
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "withdraw_spl",
+        skip_all,
+        fields(
+            token = %params.token.info.mint,
+            amount = params.base_units,
+            signature = tracing::field::Empty,
+        )
+    )
+)]
 pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplResult> {
     let WithdrawSplParams {
         connection,
@@ -57,14 +137,21 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         encryption_service,
         storage,
         mut base_units,
-        mint_address,
+        token: TokenMetadata { info: token, .. },
         recipient,
         key_base_path,
         referrer,
+        change_key_index,
+        input_utxos,
+        recipient_token_account,
+        config,
+        relayer_url,
+        fee_rounding,
+        abort_signal,
+        dry_run,
     } = params;
-
-    let token = find_token_by_mint(mint_address)
-        .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
+    let relayer_url = relayer::base_url(relayer_url);
+    let mint_address = &token.mint;
 
     log::info!(
         "Starting {} withdrawal of {} base units",
@@ -75,12 +162,20 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
     let public_key = keypair.pubkey();
 
     // Get fee configuration
-    let withdraw_fee_rate = Config::get_withdraw_fee_rate().await?;
-    let token_rent_fee = Config::get_token_rent_fee(token.name).await?;
+    let config = match config {
+        Some(config) => config.clone(),
+        None => Config::get_from(relayer_url).await?,
+    };
+    let withdraw_fee_rate = config.withdraw_fee_rate;
+    let token_rent_fee = config.token_rent_fee(token.name)?;
 
-    let fee_base_units =
-        (base_units as f64 * withdraw_fee_rate + token.units_per_token as f64 * token_rent_fee)
-            as u64;
+    let fee_base_units = calculate_withdraw_fee_rounded(
+        base_units,
+        withdraw_fee_rate,
+        token_rent_fee,
+        token.units_per_token,
+        fee_rounding,
+    )?;
 
     base_units = base_units.saturating_sub(fee_base_units);
     let mut is_partial = false;
@@ -92,7 +187,19 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
     }
 
     // Get token accounts
-    let recipient_ata = get_associated_token_address(recipient, mint_address);
+    let recipient_ata = match recipient_token_account {
+        Some(token_account) => {
+            let owner = token_account_owner(connection, token_account, mint_address)?;
+            if owner != *recipient {
+                return Err(PrivacyCashError::InvalidInput(format!(
+                    "Token account {} is owned by {}, not the recipient {}",
+                    token_account, owner, recipient
+                )));
+            }
+            *token_account
+        }
+        None => get_associated_token_address(recipient, mint_address),
+    };
     let fee_recipient_token_account = get_associated_token_address(&FEE_RECIPIENT, mint_address);
 
     // Get tree account
@@ -102,51 +209,55 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
     // Get global config for tree ATA
     let (global_config_pda, _) = Pubkey::find_program_address(&[b"global_config"], &PROGRAM_ID);
     let tree_ata = get_associated_token_address(&global_config_pda, mint_address);
+    ensure_spl_pool_initialized(connection, mint_address, token.name)?;
 
     // Get tree state
     let tree_state = query_remote_tree_state(Some(token.name)).await?;
 
     // Get UTXO keypairs
-    let utxo_private_key_v1 = encryption_service.get_utxo_private_key_v1()?;
-    let utxo_keypair_v1 = ZkKeypair::from_hex(&utxo_private_key_v1)?;
-
-    let utxo_private_key_v2 = encryption_service.get_utxo_private_key_v2()?;
-    let utxo_keypair_v2 = ZkKeypair::from_hex(&utxo_private_key_v2)?;
-
-    // Fetch existing UTXOs
-    let mut unspent_utxos = get_utxos_spl(
-        connection,
-        &public_key,
-        encryption_service,
-        storage,
-        mint_address,
-        None,
-    )
-    .await?;
+    let utxo_keypair_v1 = encryption_service.utxo_keypair(UtxoVersion::V1)?;
+    let utxo_keypair_v2 = encryption_service.utxo_keypair(UtxoVersion::V2)?;
+
+    // Fetch existing UTXOs, unless the caller picked the inputs
+    let circuit = CircuitSpec::default();
+    let unspent_utxos = match input_utxos {
+        Some(utxos) => {
+            // Spent as given, in the caller's order
+            circuit.ensure_input_count(utxos.len())?;
+            ensure_own_inputs(&utxos, &utxo_keypair_v1, &utxo_keypair_v2, &mint_address.to_string())?;
+            ensure_unspent(connection, &utxos).await?;
+            utxos
+        }
+        None => {
+            let mut utxos = get_utxos_spl(
+                connection,
+                &public_key,
+                encryption_service,
+                storage,
+                mint_address,
+                abort_signal.clone(),
+            )
+            .await?;
+            // Spend the largest notes, as many as the circuit takes
+            utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
+            utxos
+        }
+    };
 
     if unspent_utxos.is_empty() {
         return Err(PrivacyCashError::NoUtxosAvailable);
     }
 
-    // Sort by amount descending
-    unspent_utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
-
-    let first_input = unspent_utxos[0].clone();
-    let second_input = if unspent_utxos.len() > 1 {
-        unspent_utxos[1].clone()
-    } else {
-        Utxo::dummy(utxo_keypair_v1.clone(), Some(&mint_address.to_string()))
-    };
-
-    let inputs = vec![first_input.clone(), second_input.clone()];
-    let total_input_amount = first_input.amount.clone() + second_input.amount.clone();
+    let spent = unspent_utxos.iter().take(circuit.inputs).cloned().collect();
+    let inputs = circuit.pad_inputs(spent, &utxo_keypair_v1, Some(&mint_address.to_string()))?;
+    let total_input_amount = inputs.iter().fold(BigUint::zero(), |total, u| total + &u.amount);
 
     if total_input_amount.is_zero() {
         return Err(PrivacyCashError::NoUtxosAvailable);
     }
 
     // Check if partial withdrawal
-    let required = BigUint::from(base_units + fee_base_units);
+    let required = BigUint::from(checked_total(base_units, fee_base_units)?);
     if total_input_amount < required {
         is_partial = true;
         base_units = total_input_amount
@@ -165,21 +276,11 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         change_amount
     );
 
+    let expected_remaining_base_units =
+        expected_balance_after_spend(&unspent_utxos, circuit.inputs, change_amount.to_u64().unwrap_or(0));
+
     // Fetch Merkle proofs
-    let input_merkle_paths = vec![
-        if first_input.is_dummy() {
-            MerkleTree::zero_path()
-        } else {
-            let commitment = first_input.get_commitment()?;
-            fetch_merkle_proof(&commitment, Some(token.name)).await?
-        },
-        if second_input.is_dummy() {
-            MerkleTree::zero_path()
-        } else {
-            let commitment = second_input.get_commitment()?;
-            fetch_merkle_proof(&commitment, Some(token.name)).await?
-        },
-    ];
+    let input_merkle_paths = fetch_input_merkle_paths(&inputs, Some(token.name)).await?;
 
     // Create outputs with V2 keypair
     let outputs = vec![
@@ -189,7 +290,8 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
             tree_state.next_index,
             Some(&mint_address.to_string()),
             Some(UtxoVersion::V2),
-        ),
+        )
+        .with_key_index(change_key_index)?,
         Utxo::new(
             0u64,
             utxo_keypair_v2.clone(),
@@ -198,12 +300,13 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
             Some(UtxoVersion::V2),
         ),
     ];
+    ensure_output_amounts_in_range(&outputs)?;
 
-    let ext_amount = -(base_units as i64);
+    let ext_amount = ext_amount_for(base_units, false)?;
     let public_amount = calculate_public_amount(ext_amount, fee_base_units);
 
-    let input_nullifiers = vec![inputs[0].get_nullifier()?, inputs[1].get_nullifier()?];
-    let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
+    let input_nullifiers = input_nullifiers(&inputs)?;
+    let output_commitments = output_commitments(&outputs)?;
 
     let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
     let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
@@ -242,21 +345,42 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
 
         mint_address: get_mint_address_field(mint_address),
     };
+    circuit.check_input(&circuit_input)?;
 
     // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
+    telemetry::phase!("prove");
     log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    #[cfg(feature = "debug-export")]
+    crate::prover::export_circuit_input_if_enabled(&circuit_input, "withdraw_spl");
+    let prover = RustProver::new(key_base_path).with_abort_signal(abort_signal);
+    let (proof, public_signals, proof_source) = prover.prove_with_source(&circuit_input).await?;
 
     let proof_bytes = parse_proof_to_bytes(&proof)?;
-    let signals_bytes = parse_public_signals_to_bytes(&public_signals)?;
+    let signals = PublicSignals::parse(&public_signals)?;
 
     let (nullifier0_pda, nullifier1_pda) =
-        find_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
+        find_nullifier_pdas(&signals.nullifiers());
     let (nullifier2_pda, nullifier3_pda) =
-        find_cross_check_nullifier_pdas(&[signals_bytes[3], signals_bytes[4]]);
-
-    let serialized_proof = serialize_spl_proof(&proof_bytes, &signals_bytes, &ext_data);
+        find_cross_check_nullifier_pdas(&signals.nullifiers());
+
+    let serialized_proof = serialize_spl_proof(&proof_bytes, &signals, &ext_data)?;
+    #[cfg(feature = "audit")]
+    crate::audit::emit("withdraw_spl", dry_run, &serialized_proof);
+
+    if dry_run {
+        log::info!("Dry run: proof generated, not submitting {} withdrawal", token.name);
+        return Ok(WithdrawSplResult {
+            signature: String::new(),
+            recipient: recipient.to_string(),
+            base_units,
+            fee_base_units,
+            is_partial,
+            expected_remaining_base_units,
+            partner_fee_signature: None,
+            dry_run: Some(DryRunOutput::new(&proof_bytes, public_signals, serialized_proof)),
+            proof_source,
+        });
+    }
 
     let withdraw_params = serde_json::json!({
         "serializedProof": base64::encode(&serialized_proof),
@@ -280,11 +404,15 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         "referralWalletAddress": referrer
     });
 
+    telemetry::phase!("submit");
     log::info!("Submitting SPL withdrawal to relayer...");
-    let signature = submit_spl_withdraw_to_indexer(withdraw_params).await?;
+    let signature = submit_spl_withdraw_to_indexer(withdraw_params, relayer_url).await?;
 
+    telemetry::record_signature!("signature", signature);
+
+    telemetry::phase!("confirm");
     log::info!("Waiting for confirmation...");
-    wait_for_spl_confirmation(&encrypted_output1, token.name).await?;
+    wait_for_spl_confirmation(&encrypted_output1, token.name, relayer_url).await?;
 
     Ok(WithdrawSplResult {
         signature,
@@ -292,14 +420,20 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         base_units,
         fee_base_units,
         is_partial,
+        expected_remaining_base_units,
+        partner_fee_signature: None,
+        dry_run: None,
+        proof_source,
     })
 }
 
 fn serialize_spl_proof(
     proof_bytes: &crate::prover::ProofBytes,
-    signals: &[[u8; 32]],
+    signals: &PublicSignals,
     ext_data: &ExtData,
-) -> Vec<u8> {
+) -> Result<Vec<u8>> {
+    proof_bytes.validate()?;
+
     let mut data = Vec::new();
 
     data.extend_from_slice(&TRANSACT_SPL_IX_DISCRIMINATOR);
@@ -308,9 +442,7 @@ fn serialize_spl_proof(
     data.extend_from_slice(&proof_bytes.proof_b);
     data.extend_from_slice(&proof_bytes.proof_c);
 
-    for signal in signals.iter().take(7) {
-        data.extend_from_slice(signal);
-    }
+    data.extend_from_slice(&signals.to_bytes());
 
     data.extend_from_slice(&ext_data.ext_amount.to_le_bytes());
     data.extend_from_slice(&ext_data.fee.to_le_bytes());
@@ -320,24 +452,19 @@ fn serialize_spl_proof(
     data.extend_from_slice(&(ext_data.encrypted_output2.len() as u32).to_le_bytes());
     data.extend_from_slice(&ext_data.encrypted_output2);
 
-    data
+    Ok(data)
 }
 
-async fn submit_spl_withdraw_to_indexer(params: serde_json::Value) -> Result<String> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/withdraw/spl", *RELAYER_API_URL))
-        .json(&params)
-        .send()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("SPL withdraw submit failed: {}", e)))?;
+async fn submit_spl_withdraw_to_indexer(params: serde_json::Value, relayer_url: &str) -> Result<String> {
+    let response = relayer::send(
+        relayer::post(&format!("{}/withdraw/spl", relayer_url)).json(&params),
+        "SPL withdraw submit failed",
+    )
+    .await?;
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        return Err(PrivacyCashError::ApiError(format!(
-            "SPL withdraw failed: {}",
-            error_text
-        )));
+        return Err(relayer_error("SPL withdraw failed", &error_text));
     }
 
     #[derive(Deserialize)]
@@ -353,7 +480,11 @@ async fn submit_spl_withdraw_to_indexer(params: serde_json::Value) -> Result<Str
     Ok(result.signature)
 }
 
-async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) -> Result<()> {
+async fn wait_for_spl_confirmation(
+    encrypted_output: &[u8],
+    token_name: &str,
+    relayer_url: &str,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
@@ -363,10 +494,10 @@ async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) ->
 
         let url = format!(
             "{}/utxos/check/{}?token={}",
-            *RELAYER_API_URL, encrypted_hex, token_name
+            relayer_url, encrypted_hex, token_name
         );
 
-        let response = reqwest::get(&url).await;
+        let response = relayer::send(relayer::get(&url), "Confirmation check").await;
 
         if let Ok(resp) = response {
             if let Ok(data) = resp.json::<serde_json::Value>().await {
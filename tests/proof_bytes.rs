@@ -0,0 +1,79 @@
+//! On-chain proof encoding checked against reference bytes
+//!
+//! `fixtures/proof.json` and `fixtures/public.json` are a transaction2 proof
+//! and its public signals in snarkjs' output format. `fixtures/proof_bytes.json`
+//! holds the bytes the program's verifier reads for them.
+
+use privacy_cash::prover::{parse_public_signals_to_bytes, Proof, ProofBytes, PublicSignals};
+use privacy_cash::prover_rust::RustProver;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ExpectedBytes {
+    proof_a: String,
+    proof_b: String,
+    proof_c: String,
+    public_signals: Vec<String>,
+}
+
+fn snarkjs_proof() -> serde_json::Value {
+    serde_json::from_str(include_str!("fixtures/proof.json")).unwrap()
+}
+
+fn public_signals() -> Vec<String> {
+    serde_json::from_str(include_str!("fixtures/public.json")).unwrap()
+}
+
+fn expected() -> ExpectedBytes {
+    serde_json::from_str(include_str!("fixtures/proof_bytes.json")).unwrap()
+}
+
+#[test]
+fn proof_bytes_match_reference() {
+    let bytes = ProofBytes::from_snarkjs_json(&snarkjs_proof()).unwrap();
+    let expected = expected();
+
+    assert_eq!(hex::encode(&bytes.proof_a), expected.proof_a);
+    assert_eq!(hex::encode(&bytes.proof_b), expected.proof_b);
+    assert_eq!(hex::encode(&bytes.proof_c), expected.proof_c);
+
+    // And back to what snarkjs wrote
+    assert_eq!(bytes.to_snarkjs_json().unwrap(), snarkjs_proof());
+}
+
+#[test]
+fn public_signal_bytes_match_reference() {
+    let signals = public_signals();
+    let bytes = parse_public_signals_to_bytes(&signals).unwrap();
+    let encoded: Vec<String> = bytes.iter().map(hex::encode).collect();
+    assert_eq!(encoded, expected().public_signals);
+
+    let parsed = PublicSignals::parse(&signals).unwrap();
+    assert_eq!(parsed.to_bytes().to_vec(), bytes.concat());
+}
+
+#[test]
+fn proof_bytes_have_onchain_sizes() {
+    let bytes = ProofBytes::from_snarkjs_json(&snarkjs_proof()).unwrap();
+
+    assert_eq!(bytes.proof_a.len(), 64);
+    assert_eq!(bytes.proof_b.len(), 128);
+    assert_eq!(bytes.proof_c.len(), 64);
+}
+
+#[test]
+#[ignore = "loads the circuit's zkey"]
+fn fixture_proof_verifies() {
+    let mut proof: Proof = serde_json::from_value(snarkjs_proof()).unwrap();
+    // RustProver takes B's coordinates as [c1, c0]
+    for coord in proof.pi_b.iter_mut().take(2) {
+        coord.reverse();
+    }
+    let prover = RustProver::new(&format!("{}/circuit/transaction2", env!("CARGO_MANIFEST_DIR")));
+
+    let mut signals = public_signals();
+    prover.verify(&proof, &signals).unwrap();
+
+    signals[1] = "1".to_string();
+    assert!(prover.verify(&proof, &signals).is_err());
+}
@@ -0,0 +1,328 @@
+//! Deposit and withdrawal against a local server speaking the relayer and
+//! Solana RPC protocols
+//!
+//! Unlike the `MockRelayer` unit tests, requests go through the real HTTP
+//! client, so this checks what the SDK puts on the wire: the signed deposit
+//! transaction, the withdraw params JSON and the confirmation polling, and
+//! that a dry run never reaches the submit route.
+//! Proofs are placeholders (see `install_mock_prover`), so no circuit files
+//! are needed. Needs the `testing` feature.
+
+use base64::Engine;
+use privacy_cash::constants::{get_supported_tokens, ALT_ADDRESS, SOL_MINT};
+use privacy_cash::deposit::{deposit, DepositParams};
+use privacy_cash::encryption::EncryptionService;
+use privacy_cash::storage::Storage;
+use privacy_cash::testing::install_mock_prover;
+use privacy_cash::utils::get_program_accounts;
+use privacy_cash::{PrivacyCash, SubmitMode};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::VersionedTransaction;
+use wiremock::matchers::{body_partial_json, method, path, path_regex};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+const DEPOSIT_LAMPORTS: u64 = 1_000_000_000;
+
+/// JSON-RPC success response
+fn rpc_result(result: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+}
+
+/// JSON-RPC response for a call returning a value with a context
+fn rpc_value(value: Value) -> ResponseTemplate {
+    rpc_result(json!({ "context": { "slot": 1 }, "value": value }))
+}
+
+/// An account holding `data`, as `getAccountInfo` returns it
+fn account(data: &[u8], owner: &Pubkey) -> Value {
+    json!({
+        "data": [base64::engine::general_purpose::STANDARD.encode(data), "base64"],
+        "executable": false,
+        "lamports": 1_000_000,
+        "owner": owner.to_string(),
+        "rentEpoch": 0,
+        "space": data.len(),
+    })
+}
+
+/// Answer the RPC calls deposits and withdrawals make
+async fn mount_rpc(server: &MockServer, wallet: &Pubkey) {
+    let rpc_call = |name: &str| {
+        Mock::given(method("POST"))
+            .and(path("/"))
+            .and(body_partial_json(json!({ "method": name })))
+    };
+    let (tree_account, _, _) = get_program_accounts();
+    let program = Pubkey::new_unique();
+
+    rpc_call("getVersion")
+        .respond_with(rpc_result(
+            json!({ "solana-core": "2.0.0", "feature-set": 1 }),
+        ))
+        .mount(server)
+        .await;
+    rpc_call("getLatestBlockhash")
+        .respond_with(rpc_value(json!({
+            "blockhash": Hash::new_unique().to_string(),
+            "lastValidBlockHeight": 1_000,
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/"))
+        .and(body_partial_json(
+            json!({ "method": "getBalance", "params": [wallet.to_string()] }),
+        ))
+        .respond_with(rpc_value(json!(10 * DEPOSIT_LAMPORTS)))
+        .mount(server)
+        .await;
+    // Tree account without a deposit limit
+    Mock::given(method("POST"))
+        .and(path("/"))
+        .and(body_partial_json(
+            json!({ "method": "getAccountInfo", "params": [tree_account.to_string()] }),
+        ))
+        .respond_with(rpc_value(account(&[0; 64], &program)))
+        .mount(server)
+        .await;
+    // Empty lookup table: 56-byte header, no addresses
+    Mock::given(method("POST"))
+        .and(path("/"))
+        .and(body_partial_json(
+            json!({ "method": "getAccountInfo", "params": [ALT_ADDRESS.to_string()] }),
+        ))
+        .respond_with(rpc_value(account(&[0; 56], &program)))
+        .mount(server)
+        .await;
+    // No nullifier accounts: nothing is spent
+    rpc_call("getMultipleAccounts")
+        .respond_with(rpc_value(json!([null, null])))
+        .mount(server)
+        .await;
+}
+
+/// Answer the relayer endpoints with what the real relayer returns for a
+/// fresh tree
+async fn mount_relayer(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "withdraw_fee_rate": 0.0035,
+            "withdraw_rent_fee": 0.006,
+            "deposit_fee_rate": 0.0,
+            "rent_fees": {},
+            "minimum_withdrawal": { "sol": 0.01 },
+            "prices": { "sol": 150.0 },
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/merkle/root"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "root": "12345", "nextIndex": 0 })),
+        )
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/merkle/proof/[0-9]+$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "pathElements": vec!["0"; 26],
+            "pathIndices": vec![0; 26],
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/utxos/range"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "count": 0,
+            "total": 0,
+            "encrypted_outputs": [],
+            "hasMore": false,
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/utxos/check/[0-9a-f]+$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "exists": true })))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/utxos/indices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "indices": [0] })))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/deposit"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "signature": "deposit-signature" })),
+        )
+        .expect(1)
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/withdraw"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "signature": "withdraw-signature" })),
+        )
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+/// JSON body of the only request made to `path`
+async fn posted_json(server: &MockServer, path: &str) -> Value {
+    let requests: Vec<Request> = server.received_requests().await.unwrap();
+    let mut bodies = requests.iter().filter(|r| r.url.path() == path);
+    let body = bodies
+        .next()
+        .unwrap_or_else(|| panic!("no request to {}", path))
+        .body_json()
+        .unwrap();
+    assert!(bodies.next().is_none(), "more than one request to {}", path);
+    body
+}
+
+// One test per binary: RELAYER_API_URL is read once per process
+#[tokio::test(flavor = "multi_thread")]
+async fn deposit_then_withdraw_against_local_relayer() {
+    let server = MockServer::start().await;
+    std::env::set_var("RELAYER_API_URL", server.uri());
+    install_mock_prover();
+
+    let keypair = Keypair::new();
+    let wallet = keypair.pubkey();
+    mount_rpc(&server, &wallet).await;
+    mount_relayer(&server).await;
+
+    // A dry run proves the same deposit but never reaches the submit route
+    let connection = RpcClient::new(server.uri());
+    let mut encryption_service = EncryptionService::new();
+    encryption_service.derive_encryption_key_from_wallet(&keypair);
+    let dry_run = deposit(DepositParams {
+        connection: &connection,
+        keypair: &keypair,
+        encryption_service: &encryption_service,
+        storage: &Storage::memory(),
+        amount_in_lamports: DEPOSIT_LAMPORTS,
+        key_base_path: "circuit",
+        referrer: None,
+        input_utxos: None,
+        receive_address: None,
+        dry_run: true,
+        config: None,
+        relayer_url: None,
+        submit_mode: SubmitMode::Relayer,
+        abort_signal: None,
+    })
+    .await
+    .unwrap();
+    assert!(!dry_run.is_submitted());
+    assert!(dry_run.signature.is_empty());
+    assert!(dry_run.output_utxo.is_none());
+    let requests: Vec<Request> = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|r| r.url.path() != "/deposit"));
+
+    let cache_dir = std::env::temp_dir().join(format!(
+        "privacy-cash-relayer-contract-{}",
+        std::process::id()
+    ));
+    // The server's 127.0.0.1 URL makes this a localnet client
+    let mut client = PrivacyCash::with_rpc_client(
+        RpcClient::new(server.uri()),
+        keypair,
+        Some(cache_dir.clone()),
+        None,
+    )
+    .unwrap();
+    let referrer = Pubkey::new_unique().to_string();
+    client.set_referrer(Some(&referrer));
+    client.set_partner_fee(Pubkey::new_unique(), 0.0).unwrap();
+
+    // Prewarming reads the tree state of every SPL token as well as SOL's
+    client.prewarm().await.unwrap();
+    let requests: Vec<Request> = server.received_requests().await.unwrap();
+    let tree_queries: Vec<Option<&str>> = requests
+        .iter()
+        .filter(|r| r.url.path() == "/merkle/root")
+        .map(|r| r.url.query())
+        .collect();
+    for token in get_supported_tokens().iter().filter(|t| t.mint != *SOL_MINT) {
+        let query = format!("token={}", token.name);
+        assert!(tree_queries.contains(&Some(query.as_str())), "{} tree not prewarmed", token.name);
+    }
+
+    // Deposit: a signed v0 transaction from the wallet, relayed as base64
+    let deposit = client.deposit(DEPOSIT_LAMPORTS).await.unwrap();
+    assert_eq!(deposit.signature, "deposit-signature");
+    let note = deposit
+        .output_utxo
+        .expect("the relayer reported the leaf index");
+    assert_eq!(note.index, 0);
+    assert_eq!(note.amount_u64(), DEPOSIT_LAMPORTS);
+
+    let body = posted_json(&server, "/deposit").await;
+    assert_eq!(body["senderAddress"], wallet.to_string());
+    assert_eq!(body["referralWalletAddress"], referrer);
+    let tx_bytes = base64::engine::general_purpose::STANDARD
+        .decode(body["signedTransaction"].as_str().unwrap())
+        .unwrap();
+    let tx: VersionedTransaction = bincode::deserialize(&tx_bytes).unwrap();
+    assert!(tx.verify_with_results().iter().all(|ok| *ok));
+    assert_eq!(tx.message.static_account_keys()[0], wallet);
+
+    // Withdraw the new note without a scan
+    let recipient = Pubkey::new_unique();
+    let withdrawal = client
+        .withdraw_notes(vec![note], Some(&recipient))
+        .await
+        .unwrap();
+    assert_eq!(withdrawal.signature, "withdraw-signature");
+    assert_eq!(
+        withdrawal.amount_in_lamports + withdrawal.fee_in_lamports,
+        DEPOSIT_LAMPORTS
+    );
+
+    let params = posted_json(&server, "/withdraw").await;
+    let (tree_account, tree_token_account, global_config_account) = get_program_accounts();
+    assert_eq!(params["recipient"], recipient.to_string());
+    assert_eq!(params["senderAddress"], wallet.to_string());
+    assert_eq!(params["referralWalletAddress"], referrer);
+    assert_eq!(params["treeAccount"], tree_account.to_string());
+    assert_eq!(params["treeTokenAccount"], tree_token_account.to_string());
+    assert_eq!(
+        params["globalConfigAccount"],
+        global_config_account.to_string()
+    );
+    assert_eq!(params["lookupTableAddress"], ALT_ADDRESS.to_string());
+    assert_eq!(params["extAmount"], -(withdrawal.amount_in_lamports as i64));
+    assert_eq!(params["fee"], withdrawal.fee_in_lamports);
+    for key in [
+        "nullifier0PDA",
+        "nullifier1PDA",
+        "nullifier2PDA",
+        "nullifier3PDA",
+        "feeRecipientAccount",
+    ] {
+        assert!(
+            params[key].as_str().unwrap().parse::<Pubkey>().is_ok(),
+            "{} is not a pubkey",
+            key
+        );
+    }
+    for key in ["serializedProof", "encryptedOutput1", "encryptedOutput2"] {
+        let encoded = params[key].as_str().unwrap();
+        assert!(
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .is_ok(),
+            "{} is not base64",
+            key
+        );
+    }
+
+    let _ = std::fs::remove_dir_all(cache_dir);
+}